@@ -1,6 +1,7 @@
 .extern kernel_main
 
 .global _start
+.global stack_bottom
 .global stack_top
 
 .set MB_MAGIC, 0x1BADB002          
@@ -0,0 +1,157 @@
+use spin::Mutex;
+
+use crate::{
+    diag::{self, Counter},
+    time,
+};
+
+/// Pitch and length of the speaker bell, also the defaults of the `beep` command.
+pub const BELL_FREQUENCY_HZ: u32 = 880;
+pub const BELL_DURATION_MS: u32 = 50;
+
+/// Minimum number of ticks between two audible/visible bells.
+pub const BELL_INTERVAL_TICKS: u64 = 10;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BellMode {
+    /// Beeps through the PC speaker.
+    Speaker,
+    /// Briefly inverts the colors of the display.
+    Visual,
+    /// Ignores bells entirely.
+    Off,
+}
+
+impl BellMode {
+    pub fn from_name(name: &[u8]) -> Option<BellMode> {
+        match name {
+            b"speaker" => Some(BellMode::Speaker),
+            b"visual" => Some(BellMode::Visual),
+            b"off" => Some(BellMode::Off),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BellMode::Speaker => "speaker",
+            BellMode::Visual => "visual",
+            BellMode::Off => "off",
+        }
+    }
+}
+
+/// Rate-limiting state of the terminal bell.
+pub struct Bell {
+    pub mode: BellMode,
+    last_ring: Option<u64>,
+    /// Number of bells that were actually sounded. The dropped ones are counted by
+    /// `Counter::SuppressedBells`.
+    pub rung: u32,
+}
+
+impl Bell {
+    pub const fn new() -> Self {
+        Bell {
+            mode: BellMode::Speaker,
+            last_ring: None,
+            rung: 0,
+        }
+    }
+
+    /// Registers a bell request at tick `now` and returns `true` if it should be sounded, i.e. if
+    /// the bell is enabled and the last sounded bell is at least `BELL_INTERVAL_TICKS` old.
+    pub fn request(&mut self, now: u64) -> bool {
+        if self.mode == BellMode::Off {
+            return false;
+        }
+        if let Some(last) = self.last_ring {
+            if now.wrapping_sub(last) < BELL_INTERVAL_TICKS {
+                diag::bump(Counter::SuppressedBells);
+                return false;
+            }
+        }
+        self.last_ring = Some(now);
+        self.rung = self.rung.wrapping_add(1);
+        true
+    }
+}
+
+pub static BELL: Mutex<Bell> = Mutex::new(Bell::new());
+
+/// Rings the terminal bell through the configured `BellMode`, subject to rate limiting.
+pub fn ring() {
+    let mode = {
+        let mut bell = BELL.lock();
+        if !bell.request(time::ticks()) {
+            return;
+        }
+        bell.mode
+    };
+
+    sound(mode);
+}
+
+#[cfg(not(test))]
+fn sound(mode: BellMode) {
+    const BELL_DURATION_TICKS: u64 = 5;
+
+    match mode {
+        BellMode::Speaker => crate::speaker::beep(BELL_FREQUENCY_HZ, BELL_DURATION_MS),
+        BellMode::Visual => crate::terminal::vga::flash(BELL_DURATION_TICKS),
+        BellMode::Off => {}
+    }
+}
+
+#[cfg(test)]
+fn sound(_mode: BellMode) {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::terminal::Screen;
+
+    fn ring_at(bell: &mut Bell, ticks: &[u64]) -> usize {
+        ticks.iter().filter(|&&t| bell.request(t)).count()
+    }
+
+    #[test]
+    fn bells_are_rate_limited() {
+        let mut bell = Bell::new();
+        let suppressed = diag::get(Counter::SuppressedBells);
+        assert_eq!(ring_at(&mut bell, &[0, 1, 2, 9, 10, 11, 25]), 3);
+        assert_eq!(bell.rung, 3);
+        // Other tests may ring the global bell meanwhile.
+        assert!(diag::get(Counter::SuppressedBells) - suppressed >= 4);
+    }
+
+    #[test]
+    fn burst_of_bells_rings_once() {
+        let mut bell = Bell::new();
+        assert_eq!(ring_at(&mut bell, &[500; 8]), 1);
+        assert_eq!(bell.rung, 1);
+    }
+
+    #[test]
+    fn bell_bytes_are_not_displayed() {
+        *BELL.lock() = Bell::new();
+        let mut s = Screen::default();
+
+        s.write_bytes(b"a\x07\x07\x07b");
+
+        // `ticks` does not advance in tests, so only the first bell is sounded.
+        assert_eq!(BELL.lock().rung, 1);
+        assert_eq!(s.last_entry_index, 2);
+        assert_eq!(s.cursor, 2);
+        assert_eq!(s.buffer[0].ch(), b'a');
+        assert_eq!(s.buffer[1].ch(), b'b');
+    }
+
+    #[test]
+    fn off_never_rings() {
+        let mut bell = Bell::new();
+        bell.mode = BellMode::Off;
+        assert_eq!(ring_at(&mut bell, &[0, 100, 200]), 0);
+        assert_eq!(bell.rung, 0);
+    }
+}
@@ -0,0 +1,26 @@
+use crate::io::{inb, outb};
+
+const CMOS_INDEX_PORT: u16 = 0x70;
+const CMOS_DATA_PORT: u16 = 0x71;
+/// Set on the index to keep NMIs disabled while the CMOS is accessed.
+const NMI_DISABLE: u8 = 0x80;
+
+/// Register holding the keyboard layout chosen on the first boot. It lies after the range
+/// covered by the standard CMOS checksum (`0x10..=0x2D`), so writing it does not invalidate it.
+pub const KEYMAP_REGISTER: u8 = 0x3A;
+
+/// Reads the CMOS `register`.
+pub fn read(register: u8) -> u8 {
+    unsafe {
+        outb(CMOS_INDEX_PORT, NMI_DISABLE | register);
+        inb(CMOS_DATA_PORT)
+    }
+}
+
+/// Writes `value` to the CMOS `register`.
+pub fn write(register: u8, value: u8) {
+    unsafe {
+        outb(CMOS_INDEX_PORT, NMI_DISABLE | register);
+        outb(CMOS_DATA_PORT, value);
+    }
+}
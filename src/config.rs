@@ -0,0 +1,32 @@
+/// Memory-related tunables. The `minimal` cargo feature selects smaller values for targets with
+/// little memory; everything else must only depend on these constants, never on their values.
+#[cfg(not(feature = "minimal"))]
+mod profile {
+    /// Name of the active profile, displayed by `sysinfo` and `selftest`.
+    pub const NAME: &str = "default";
+    /// Number of cells of the scrollback of a screen.
+    pub const SCREEN_BUFFER_SIZE: usize = 50000;
+    /// Minimum number of cells freed when a full scrollback discards its oldest rows.
+    pub const SCREEN_DISCARD_CELLS: usize = 4000;
+    /// Number of messages kept by the kernel log.
+    pub const LOG_CAPACITY: usize = 32;
+    /// Number of files of the ramfs.
+    pub const RAMFS_MAX_FILES: usize = 8;
+    /// Size of a single ramfs file in bytes.
+    pub const RAMFS_MAX_FILE_SIZE: usize = 4096;
+    /// Number of lines `sort` can order.
+    pub const SORT_MAX_LINES: usize = 200;
+}
+
+#[cfg(feature = "minimal")]
+mod profile {
+    pub const NAME: &str = "minimal";
+    pub const SCREEN_BUFFER_SIZE: usize = 8000;
+    pub const SCREEN_DISCARD_CELLS: usize = 800;
+    pub const LOG_CAPACITY: usize = 8;
+    pub const RAMFS_MAX_FILES: usize = 4;
+    pub const RAMFS_MAX_FILE_SIZE: usize = 1024;
+    pub const SORT_MAX_LINES: usize = 64;
+}
+
+pub use profile::*;
@@ -1,3 +1,5 @@
+use crate::print::u64_to_base;
+
 /// Converts a slice of bytes into a `usize`, assuming hexadecimal format, skipping leading and
 /// trailing whitespaces.
 ///
@@ -34,3 +36,129 @@ pub fn hextou(bytes: &[u8]) -> Option<usize> {
     }
     Some(result / 2)
 }
+
+/// Converts a slice of binary digits into a `u32`, ignoring `_` separators.
+///
+/// Returns `None` on an empty slice, an invalid digit, or if the value does not fit in a `u32`.
+pub fn btou(bytes: &[u8]) -> Option<u32> {
+    digits_to_u32(bytes, 2)
+}
+
+/// Converts a slice of decimal digits into a `u32`, ignoring `_` separators.
+///
+/// Returns `None` on an empty slice, an invalid digit, or if the value does not fit in a `u32`.
+pub fn dtou(bytes: &[u8]) -> Option<u32> {
+    digits_to_u32(bytes, 10)
+}
+
+/// Converts a slice of hexadecimal digits into a `u32`, ignoring `_` separators.
+///
+/// Returns `None` on an empty slice, an invalid digit, or if the value does not fit in a `u32`.
+pub fn htou(bytes: &[u8]) -> Option<u32> {
+    digits_to_u32(bytes, 16)
+}
+
+fn digits_to_u32(bytes: &[u8], base: u32) -> Option<u32> {
+    let mut result: u32 = 0;
+    let mut digits = 0;
+
+    for &byte in bytes {
+        if byte == b'_' {
+            continue;
+        }
+        let digit = (byte as char).to_digit(base)?;
+        result = result.checked_mul(base)?.checked_add(digit)?;
+        digits += 1;
+    }
+
+    if digits == 0 {
+        return None;
+    }
+    Some(result)
+}
+
+/// Parses a numeric literal in one of the forms `0x1F` (hexadecimal), `0b1010` (binary), `'c'`
+/// (ASCII character) or `42` (decimal).
+///
+/// Returns `None` if `bytes` is malformed or the value does not fit in a `u32`.
+pub fn parse_literal(bytes: &[u8]) -> Option<u32> {
+    match bytes {
+        [b'0', b'x' | b'X', rest @ ..] => htou(rest),
+        [b'0', b'b' | b'B', rest @ ..] => btou(rest),
+        [b'\'', c, b'\''] => Some(*c as u32),
+        _ => dtou(bytes),
+    }
+}
+
+/// Length of the longest `i32` written by `itoa`, `-2147483648`.
+pub const I32_MAX_LENGTH: usize = 11;
+
+/// Writes `value` in decimal at the end of `buf` and returns the part of `buf` holding it.
+///
+/// Panics if `buf` is too short for `value`, which cannot happen with `I32_MAX_LENGTH` bytes.
+pub fn itoa(value: i32, buf: &mut [u8]) -> &[u8] {
+    // `unsigned_abs` because `i32::MIN` cannot be negated.
+    let (digits, len) = u64_to_base(value.unsigned_abs() as u64, 10).unwrap();
+    let end = buf.len();
+    let start = end - len - (value < 0) as usize;
+    buf[end - len..].copy_from_slice(&digits[digits.len() - len..]);
+    if value < 0 {
+        buf[start] = b'-';
+    }
+    &buf[start..]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decimal() {
+        assert_eq!(parse_literal(b"0"), Some(0));
+        assert_eq!(parse_literal(b"42"), Some(42));
+        assert_eq!(parse_literal(b"4294967295"), Some(u32::MAX));
+        assert_eq!(parse_literal(b"4294967296"), None);
+        assert_eq!(parse_literal(b"12a"), None);
+    }
+
+    #[test]
+    fn hexadecimal() {
+        assert_eq!(parse_literal(b"0xff"), Some(0xFF));
+        assert_eq!(parse_literal(b"0XDEAD_BEEF"), Some(0xDEADBEEF));
+        assert_eq!(parse_literal(b"0x100000000"), None);
+        assert_eq!(parse_literal(b"0x"), None);
+        assert_eq!(parse_literal(b"0xg"), None);
+    }
+
+    #[test]
+    fn binary() {
+        assert_eq!(parse_literal(b"0b1010"), Some(10));
+        assert_eq!(parse_literal(b"0b1111_0000"), Some(0xF0));
+        assert_eq!(parse_literal(b"0b102"), None);
+        assert_eq!(parse_literal(b"0b"), None);
+    }
+
+    #[test]
+    fn character() {
+        assert_eq!(parse_literal(b"'A'"), Some(0x41));
+        assert_eq!(parse_literal(b"' '"), Some(0x20));
+        assert_eq!(parse_literal(b"'AB'"), None);
+        assert_eq!(parse_literal(b"''"), None);
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(parse_literal(b""), None);
+        assert_eq!(parse_literal(b"_"), None);
+    }
+
+    #[test]
+    fn signed_decimal() {
+        let mut buf = [0; I32_MAX_LENGTH];
+        assert_eq!(itoa(0, &mut buf), b"0");
+        assert_eq!(itoa(-1, &mut buf), b"-1");
+        assert_eq!(itoa(1234, &mut buf), b"1234");
+        assert_eq!(itoa(i32::MAX, &mut buf), b"2147483647");
+        assert_eq!(itoa(i32::MIN, &mut buf), b"-2147483648");
+    }
+}
@@ -0,0 +1,146 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::terminal::Screen;
+
+/// Events that are handled by dropping or ignoring something. They are harmless one at a time but
+/// worth a look after a crash, so they are counted instead of logged.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Counter {
+    DroppedCells,
+    SuppressedBells,
+    LogOverwrites,
+    Ps2Acks,
+    Ps2Echoes,
+    Ps2SelfTests,
+    Ps2Resends,
+    Ps2Errors,
+    ScancodesRead,
+    UnknownScancodes,
+    ScancodeOverruns,
+}
+
+impl Counter {
+    pub const ALL: [Counter; 11] = [
+        Counter::DroppedCells,
+        Counter::SuppressedBells,
+        Counter::LogOverwrites,
+        Counter::Ps2Acks,
+        Counter::Ps2Echoes,
+        Counter::Ps2SelfTests,
+        Counter::Ps2Resends,
+        Counter::Ps2Errors,
+        Counter::ScancodesRead,
+        Counter::UnknownScancodes,
+        Counter::ScancodeOverruns,
+    ];
+
+    /// Counters of the keyboard protocol bytes that arrive among the scancodes.
+    pub const PS2_PROTOCOL: [Counter; 5] = [
+        Counter::Ps2Acks,
+        Counter::Ps2Echoes,
+        Counter::Ps2SelfTests,
+        Counter::Ps2Resends,
+        Counter::Ps2Errors,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Counter::DroppedCells => "dropped-cells",
+            Counter::SuppressedBells => "suppressed-bells",
+            Counter::LogOverwrites => "log-overwrites",
+            Counter::Ps2Acks => "ps2-acks",
+            Counter::Ps2Echoes => "ps2-echoes",
+            Counter::Ps2SelfTests => "ps2-self-tests",
+            Counter::Ps2Resends => "ps2-resends",
+            Counter::Ps2Errors => "ps2-errors",
+            Counter::ScancodesRead => "scancodes-read",
+            Counter::UnknownScancodes => "unknown-scancodes",
+            Counter::ScancodeOverruns => "scancode-overruns",
+        }
+    }
+
+    /// Returns `true` for the counters that also grow in normal operation, which `selftest` does
+    /// not take as a sign of trouble.
+    pub fn is_informational(&self) -> bool {
+        matches!(
+            self,
+            Counter::SuppressedBells | Counter::Ps2Acks | Counter::Ps2Echoes | Counter::Ps2SelfTests | Counter::ScancodesRead
+        )
+    }
+
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            Counter::DroppedCells => "cells lost to a full screen buffer",
+            Counter::SuppressedBells => "bells dropped by the rate limiter",
+            Counter::LogOverwrites => "kernel log messages overwritten by newer ones",
+            Counter::Ps2Acks => "acknowledgements sent by the keyboard",
+            Counter::Ps2Echoes => "echo bytes sent by the keyboard",
+            Counter::Ps2SelfTests => "keyboard self-tests passed, e.g. after it was plugged in",
+            Counter::Ps2Resends => "resend requests sent by the keyboard",
+            Counter::Ps2Errors => "key detection errors or overruns reported by the keyboard",
+            Counter::ScancodesRead => "scancodes read from the keyboard",
+            Counter::UnknownScancodes => "scancodes of keys missing from the keymap",
+            Counter::ScancodeOverruns => "scancodes dropped because the input queue was full",
+        }
+    }
+}
+
+/// Values of the counters, indexed by `Counter`. Atomics, so that interrupt handlers can count too.
+static COUNTERS: [AtomicU32; Counter::ALL.len()] = [const { AtomicU32::new(0) }; Counter::ALL.len()];
+
+pub fn bump(counter: Counter) {
+    COUNTERS[counter as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn get(counter: Counter) -> u32 {
+    COUNTERS[counter as usize].load(Ordering::Relaxed)
+}
+
+pub fn reset() {
+    for counter in COUNTERS.iter() {
+        counter.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Resets only `counter`, for the commands that show a subset of the counters.
+pub fn reset_counter(counter: Counter) {
+    COUNTERS[counter as usize].store(0, Ordering::Relaxed);
+}
+
+/// Iterates over the counters that are not zero, with their value.
+pub fn nonzero() -> impl Iterator<Item = (Counter, u32)> {
+    Counter::ALL.into_iter().map(|c| (c, get(c))).filter(|&(_, value)| value != 0)
+}
+
+/// Prints one compact `diag: name=value` line per non-zero counter, for the panic screen.
+pub fn write_compact(s: &mut Screen) {
+    for (counter, value) in nonzero() {
+        s.write_str("diag: ");
+        s.write_str(counter.name());
+        s.write_str("=");
+        s.write_dec(value);
+        s.write_str("\n");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names_are_unique() {
+        for (i, a) in Counter::ALL.iter().enumerate() {
+            assert_eq!(*a as usize, i);
+            assert!(Counter::ALL[i + 1..].iter().all(|b| a.name() != b.name()));
+        }
+    }
+
+    #[test]
+    fn suppressed_bells_are_informational() {
+        assert!(Counter::SuppressedBells.is_informational());
+        assert!(!Counter::DroppedCells.is_informational());
+        assert!(!Counter::ScancodeOverruns.is_informational());
+        assert!(Counter::Ps2Acks.is_informational());
+        assert!(!Counter::Ps2Errors.is_informational());
+    }
+}
@@ -0,0 +1,329 @@
+/// Maximum number of bytes a single formatted row can take.
+pub const ROW_MAX_LENGTH: usize = 192;
+
+/// Maximum number of bytes displayed per row.
+pub const MAX_COLUMNS: usize = 32;
+
+/// Marker emitted in place of a run of skipped zeroed rows.
+pub const SKIP_MARKER: &[u8] = b"*";
+
+const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Layout of the rows produced by `HexRows`.
+#[derive(Clone, Copy)]
+pub struct HexRowOptions {
+    /// Number of bytes displayed per row, clamped to `1..=MAX_COLUMNS`.
+    pub columns: usize,
+    /// Number of bytes per `0x`-prefixed group, clamped to `1..=columns`.
+    pub group_size: usize,
+    /// Appends a `|...|` column with the printable ASCII representation of the row.
+    pub ascii: bool,
+    /// Replaces runs of zeroed rows with a single `SKIP_MARKER` row.
+    pub skip_zero_rows: bool,
+}
+
+impl HexRowOptions {
+    pub const fn default() -> Self {
+        HexRowOptions {
+            columns: 16,
+            group_size: 4,
+            ascii: false,
+            skip_zero_rows: true,
+        }
+    }
+}
+
+/// Formats a byte slice into hexdump rows without allocating.
+///
+/// Each row looks like `0x00001000-0x0000100f: 0x00010203 0x04050607 0x08090a0b 0x0c0d0e0f`,
+/// where the addresses are computed from `base_addr`, followed by `  |................|` when
+/// `ascii` is enabled.
+///
+/// ### Example
+/// ```
+/// let mut rows = HexRows::new(addr, bytes, HexRowOptions::default());
+/// let mut buf = [0u8; ROW_MAX_LENGTH];
+/// while let Some(row) = rows.next_row(&mut buf) {
+///     s.write_bytes(row);
+/// }
+/// ```
+pub struct HexRows<'a> {
+    base_addr: usize,
+    bytes: &'a [u8],
+    offset: usize,
+    options: HexRowOptions,
+    in_zero_run: bool,
+}
+
+impl<'a> HexRows<'a> {
+    pub fn new(base_addr: usize, bytes: &'a [u8], mut options: HexRowOptions) -> Self {
+        options.columns = options.columns.clamp(1, MAX_COLUMNS);
+        options.group_size = options.group_size.clamp(1, options.columns);
+
+        HexRows {
+            base_addr,
+            bytes,
+            offset: 0,
+            options,
+            in_zero_run: false,
+        }
+    }
+
+    /// Formats the next row into `buf` and returns the written part of it, or `None` once every
+    /// byte has been consumed.
+    pub fn next_row<'b>(&mut self, buf: &'b mut [u8; ROW_MAX_LENGTH]) -> Option<&'b [u8]> {
+        loop {
+            if self.offset >= self.bytes.len() {
+                return None;
+            }
+
+            let end = (self.offset + self.options.columns).min(self.bytes.len());
+            let row = &self.bytes[self.offset..end];
+            let row_offset = self.offset;
+            self.offset = end;
+
+            if self.options.skip_zero_rows && row.iter().all(|&b| b == 0) {
+                if self.in_zero_run {
+                    continue;
+                }
+                self.in_zero_run = true;
+                buf[..SKIP_MARKER.len()].copy_from_slice(SKIP_MARKER);
+                return Some(&buf[..SKIP_MARKER.len()]);
+            }
+            self.in_zero_run = false;
+
+            let len = self.format_row(row, self.base_addr.wrapping_add(row_offset), buf);
+            return Some(&buf[..len]);
+        }
+    }
+
+    fn format_row(&self, row: &[u8], addr: usize, buf: &mut [u8; ROW_MAX_LENGTH]) -> usize {
+        let mut w = RowWriter { buf, len: 0 };
+
+        w.push_slice(b"0x");
+        w.push_hex(addr as u32);
+        w.push_slice(b"-0x");
+        w.push_hex(addr.wrapping_add(self.options.columns - 1) as u32);
+        w.push_slice(b":");
+
+        for group in row.chunks(self.options.group_size) {
+            w.push_slice(b" 0x");
+            for byte in group {
+                w.push_byte(*byte);
+            }
+        }
+
+        if self.options.ascii {
+            let missing = self.options.columns - row.len();
+            let missing_groups = self.options.columns.div_ceil(self.options.group_size) - row.len().div_ceil(self.options.group_size);
+            for _ in 0..(missing * 2 + missing_groups * 3) {
+                w.push(b' ');
+            }
+
+            w.push_slice(b"  |");
+            for &byte in row {
+                w.push(if (0x20..0x7F).contains(&byte) { byte } else { b'.' });
+            }
+            w.push(b'|');
+        }
+
+        w.len
+    }
+}
+
+struct RowWriter<'b> {
+    buf: &'b mut [u8; ROW_MAX_LENGTH],
+    len: usize,
+}
+
+impl RowWriter<'_> {
+    fn push(&mut self, byte: u8) {
+        if self.len < ROW_MAX_LENGTH {
+            self.buf[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn push_slice(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.push(b);
+        }
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        self.push(DIGITS[(byte >> 4) as usize]);
+        self.push(DIGITS[(byte & 0xF) as usize]);
+    }
+
+    fn push_hex(&mut self, val: u32) {
+        for i in (0..8).rev() {
+            self.push(DIGITS[((val >> (i * 4)) & 0xF) as usize]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn collect(rows: &mut HexRows, out: &mut [[u8; ROW_MAX_LENGTH]; 8], lens: &mut [usize; 8]) -> usize {
+        let mut count = 0;
+        let mut buf = [0u8; ROW_MAX_LENGTH];
+        while let Some(row) = rows.next_row(&mut buf) {
+            out[count][..row.len()].copy_from_slice(row);
+            lens[count] = row.len();
+            count += 1;
+        }
+        count
+    }
+
+    fn sequence() -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = i as u8 + 0x40;
+        }
+        bytes
+    }
+
+    #[test]
+    fn default_layout() {
+        let bytes = sequence();
+        let mut rows = HexRows::new(0x1000, &bytes, HexRowOptions::default());
+        let mut out = [[0u8; ROW_MAX_LENGTH]; 8];
+        let mut lens = [0usize; 8];
+
+        assert_eq!(collect(&mut rows, &mut out, &mut lens), 2);
+        assert_eq!(&out[0][..lens[0]], b"0x00001000-0x0000100f: 0x40414243 0x44454647 0x48494a4b 0x4c4d4e4f");
+        assert_eq!(&out[1][..lens[1]], b"0x00001010-0x0000101f: 0x50515253 0x54555657 0x58595a5b 0x5c5d5e5f");
+    }
+
+    #[test]
+    fn ascii_column() {
+        let bytes = [b'h', b'i', 0x00, 0x7F, b'!', b' ', 0xFF, b'~'];
+        let options = HexRowOptions {
+            columns: 8,
+            group_size: 2,
+            ascii: true,
+            skip_zero_rows: false,
+        };
+        let mut rows = HexRows::new(0, &bytes, options);
+        let mut buf = [0u8; ROW_MAX_LENGTH];
+
+        assert_eq!(
+            rows.next_row(&mut buf).unwrap(),
+            b"0x00000000-0x00000007: 0x6869 0x007f 0x2120 0xff7e  |hi..! .~|"
+        );
+        assert!(rows.next_row(&mut buf).is_none());
+    }
+
+    #[test]
+    fn ascii_column_is_aligned_on_short_row() {
+        let bytes = *b"abc";
+        let options = HexRowOptions {
+            columns: 4,
+            group_size: 2,
+            ascii: true,
+            skip_zero_rows: false,
+        };
+        let mut rows = HexRows::new(0x10, &bytes, options);
+        let mut buf = [0u8; ROW_MAX_LENGTH];
+
+        assert_eq!(rows.next_row(&mut buf).unwrap(), b"0x00000010-0x00000013: 0x6162 0x63    |abc|");
+    }
+
+    #[test]
+    fn zero_rows_are_collapsed() {
+        let mut bytes = [0u8; 64];
+        bytes[0] = 1;
+        bytes[63] = 2;
+        let mut rows = HexRows::new(0x2000, &bytes, HexRowOptions::default());
+        let mut out = [[0u8; ROW_MAX_LENGTH]; 8];
+        let mut lens = [0usize; 8];
+
+        assert_eq!(collect(&mut rows, &mut out, &mut lens), 3);
+        assert_eq!(&out[0][..lens[0]], b"0x00002000-0x0000200f: 0x01000000 0x00000000 0x00000000 0x00000000");
+        assert_eq!(&out[1][..lens[1]], SKIP_MARKER);
+        assert_eq!(&out[2][..lens[2]], b"0x00002030-0x0000203f: 0x00000000 0x00000000 0x00000000 0x00000002");
+    }
+
+    #[test]
+    fn zero_rows_are_kept_when_not_skipping() {
+        let bytes = [0u8; 32];
+        let options = HexRowOptions {
+            skip_zero_rows: false,
+            ..HexRowOptions::default()
+        };
+        let mut rows = HexRows::new(0, &bytes, options);
+        let mut out = [[0u8; ROW_MAX_LENGTH]; 8];
+        let mut lens = [0usize; 8];
+
+        assert_eq!(collect(&mut rows, &mut out, &mut lens), 2);
+        assert_eq!(&out[1][..lens[1]], b"0x00000010-0x0000001f: 0x00000000 0x00000000 0x00000000 0x00000000");
+    }
+
+    #[test]
+    fn group_sizes() {
+        let bytes = sequence();
+        let options = HexRowOptions {
+            columns: 4,
+            group_size: 1,
+            ascii: false,
+            skip_zero_rows: false,
+        };
+        let mut rows = HexRows::new(0, &bytes[..4], options);
+        let mut buf = [0u8; ROW_MAX_LENGTH];
+        assert_eq!(rows.next_row(&mut buf).unwrap(), b"0x00000000-0x00000003: 0x40 0x41 0x42 0x43");
+
+        let options = HexRowOptions {
+            columns: 4,
+            group_size: 3,
+            ..options
+        };
+        let mut rows = HexRows::new(0, &bytes[..4], options);
+        assert_eq!(rows.next_row(&mut buf).unwrap(), b"0x00000000-0x00000003: 0x404142 0x43");
+    }
+
+    #[test]
+    fn options_are_clamped() {
+        let bytes = sequence();
+        let options = HexRowOptions {
+            columns: 0,
+            group_size: 0,
+            ascii: false,
+            skip_zero_rows: false,
+        };
+        let mut rows = HexRows::new(0, &bytes[..2], options);
+        let mut buf = [0u8; ROW_MAX_LENGTH];
+        assert_eq!(rows.next_row(&mut buf).unwrap(), b"0x00000000-0x00000000: 0x40");
+        assert_eq!(rows.next_row(&mut buf).unwrap(), b"0x00000001-0x00000001: 0x41");
+        assert!(rows.next_row(&mut buf).is_none());
+
+        let options = HexRowOptions {
+            columns: 64,
+            group_size: 64,
+            ..options
+        };
+        let long = [0xAAu8; 64];
+        let mut rows = HexRows::new(0, &long, options);
+        let row = rows.next_row(&mut buf).unwrap();
+        assert_eq!(row.len(), 22 + 3 + MAX_COLUMNS * 2);
+    }
+
+    #[test]
+    fn empty_input() {
+        let mut rows = HexRows::new(0, &[], HexRowOptions::default());
+        let mut buf = [0u8; ROW_MAX_LENGTH];
+        assert!(rows.next_row(&mut buf).is_none());
+    }
+
+    #[test]
+    fn address_wraps() {
+        let bytes = [1u8; 16];
+        let mut rows = HexRows::new(0xFFFF_FFF0, &bytes, HexRowOptions::default());
+        let mut buf = [0u8; ROW_MAX_LENGTH];
+        assert_eq!(
+            rows.next_row(&mut buf).unwrap(),
+            b"0xfffffff0-0xffffffff: 0x01010101 0x01010101 0x01010101 0x01010101"
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use spin::Mutex;
+
+use crate::{
+    gdt::set_gdt,
+    rand, serial, settings,
+    terminal::{ps2, vga::Color, Screen},
+    time,
+};
+
+/// Maximum number of stages a `BootLog` can hold.
+pub const MAX_STAGES: usize = 16;
+
+/// A single step of the kernel bring-up.
+///
+/// Stages are executed in the order they appear in `STAGES`. A failing `critical` stage aborts the
+/// boot, while failures in non-critical stages are logged and the remaining stages still run.
+pub struct Stage {
+    pub name: &'static str,
+    pub run: fn() -> Result<(), &'static str>,
+    pub critical: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StageStatus {
+    Ok,
+    Failed(&'static str),
+}
+
+#[derive(Clone, Copy)]
+pub struct StageResult {
+    pub name: &'static str,
+    pub status: StageStatus,
+}
+
+/// Results of every stage that was executed, in execution order.
+#[derive(Clone, Copy)]
+pub struct BootLog {
+    results: [Option<StageResult>; MAX_STAGES],
+    len: usize,
+    /// Set when a critical stage failed and the remaining stages were skipped.
+    pub aborted: bool,
+}
+
+impl BootLog {
+    pub const fn new() -> Self {
+        BootLog {
+            results: [None; MAX_STAGES],
+            len: 0,
+            aborted: false,
+        }
+    }
+
+    fn push(&mut self, result: StageResult) {
+        if self.len < MAX_STAGES {
+            self.results[self.len] = Some(result);
+            self.len += 1;
+        }
+    }
+
+    pub fn results(&self) -> impl Iterator<Item = &StageResult> {
+        self.results[..self.len].iter().flatten()
+    }
+
+    pub fn failures(&self) -> usize {
+        self.results().filter(|r| r.status != StageStatus::Ok).count()
+    }
+}
+
+/// The kernel's initialization stages, in execution order.
+pub static STAGES: &[Stage] = &[
+    Stage {
+        name: "gdt",
+        run: gdt_stage,
+        critical: true,
+    },
+    Stage {
+        name: "timer",
+        run: time::calibrate,
+        critical: false,
+    },
+    Stage {
+        name: "safe mode",
+        run: settings::detect_safe_mode,
+        critical: false,
+    },
+    Stage {
+        name: "ps2",
+        run: ps2::init,
+        critical: false,
+    },
+    Stage {
+        name: "serial",
+        run: serial::init,
+        critical: false,
+    },
+    Stage {
+        name: "rand",
+        run: rand::seed_from_hardware,
+        critical: false,
+    },
+];
+
+/// Results of the last boot, replayed by the `bootlog` command.
+pub static BOOT_LOG: Mutex<BootLog> = Mutex::new(BootLog::new());
+
+fn gdt_stage() -> Result<(), &'static str> {
+    set_gdt();
+    Ok(())
+}
+
+/// Runs `stages` in order, printing a status line for each of them to `s`.
+///
+/// Execution stops at the first failing critical stage, in which case the returned log is marked
+/// as `aborted`.
+pub fn run(stages: &[Stage], s: &mut Screen) -> BootLog {
+    let mut log = BootLog::new();
+
+    for stage in stages {
+        let result = StageResult {
+            name: stage.name,
+            status: match (stage.run)() {
+                Ok(()) => StageStatus::Ok,
+                Err(e) => StageStatus::Failed(e),
+            },
+        };
+        write_result(&result, s);
+        log.push(result);
+
+        if stage.critical && result.status != StageStatus::Ok {
+            log.aborted = true;
+            break;
+        }
+    }
+
+    log
+}
+
+/// Runs the kernel's `STAGES`, stores the results for `bootlog` and prints a summary.
+///
+/// Panics if a critical stage failed.
+pub fn boot(s: &mut Screen) {
+    let log = run(STAGES, s);
+    *BOOT_LOG.lock() = log;
+    write_summary(&log, s);
+
+    if log.aborted {
+        panic!("init: critical stage failed");
+    }
+}
+
+/// Prints every result stored in `log`, followed by a summary line.
+pub fn report(log: &BootLog, s: &mut Screen) {
+    for result in log.results() {
+        write_result(result, s);
+    }
+    write_summary(log, s);
+}
+
+fn write_result(result: &StageResult, s: &mut Screen) {
+    match result.status {
+        StageStatus::Ok => {
+            s.write_str("[ OK ] ");
+            s.write_str(result.name);
+        }
+        StageStatus::Failed(reason) => {
+            s.write_color_str("[FAIL]", Color::Error as u8);
+            s.write_str(" ");
+            s.write_str(result.name);
+            s.write_str(": ");
+            s.write_str(reason);
+        }
+    }
+    s.write_str("\n");
+}
+
+fn write_summary(log: &BootLog, s: &mut Screen) {
+    if log.aborted {
+        s.write_color_str("init: aborted on critical stage failure", Color::Error as u8);
+        s.write_str("\n");
+    } else if log.failures() == 0 {
+        s.write_str("init: all stages ok\n");
+    } else {
+        s.write_str("init: completed with failures\n");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ok() -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    fn fail() -> Result<(), &'static str> {
+        Err("self-test timeout")
+    }
+
+    #[test]
+    fn stages_run_in_order() {
+        let mut s = Screen::default();
+        let stages = [
+            Stage {
+                name: "first",
+                run: ok,
+                critical: true,
+            },
+            Stage {
+                name: "second",
+                run: ok,
+                critical: false,
+            },
+        ];
+        let log = run(&stages, &mut s);
+
+        let mut names = log.results().map(|r| r.name);
+        assert_eq!(names.next(), Some("first"));
+        assert_eq!(names.next(), Some("second"));
+        assert_eq!(names.next(), None);
+        assert!(!log.aborted);
+        assert_eq!(log.failures(), 0);
+    }
+
+    #[test]
+    fn non_critical_failure_is_not_fatal() {
+        let mut s = Screen::default();
+        let stages = [
+            Stage {
+                name: "ps2",
+                run: fail,
+                critical: false,
+            },
+            Stage {
+                name: "shell",
+                run: ok,
+                critical: true,
+            },
+        ];
+        let log = run(&stages, &mut s);
+
+        assert!(!log.aborted);
+        assert_eq!(log.failures(), 1);
+        assert_eq!(log.results().count(), 2);
+        assert_eq!(log.results().next().unwrap().status, StageStatus::Failed("self-test timeout"));
+    }
+
+    #[test]
+    fn critical_failure_aborts() {
+        let mut s = Screen::default();
+        let stages = [
+            Stage {
+                name: "gdt",
+                run: fail,
+                critical: true,
+            },
+            Stage {
+                name: "shell",
+                run: ok,
+                critical: false,
+            },
+        ];
+        let log = run(&stages, &mut s);
+
+        assert!(log.aborted);
+        assert_eq!(log.results().count(), 1);
+    }
+
+    #[test]
+    fn status_lines() {
+        let mut s = Screen::with_capture();
+        let stages = [
+            Stage {
+                name: "gdt",
+                run: ok,
+                critical: true,
+            },
+            Stage {
+                name: "ps2",
+                run: fail,
+                critical: false,
+            },
+        ];
+        run(&stages, &mut s);
+
+        assert_eq!(s.captured(), "[ OK ] gdt\n[FAIL] ps2: self-test timeout\n");
+    }
+}
@@ -0,0 +1,112 @@
+#[cfg(not(test))]
+use core::arch::asm;
+
+#[cfg(test)]
+pub use mock::{inb, outb};
+
+/// Reads a byte from the I/O `port`.
+///
+/// ## SAFETY
+/// Reading from some ports has side effects on the device behind them (e.g. popping a byte from the
+/// PS/2 output buffer). The caller is responsible for knowing what `port` is wired to.
+#[cfg(not(test))]
+pub unsafe fn inb(port: u16) -> u8 {
+    let res: u8;
+
+    asm!(
+        "in al, dx",
+        in("dx") port,
+        out("al") res,
+    );
+
+    res
+}
+
+/// Writes `value` to the I/O `port`.
+///
+/// ## SAFETY
+/// Writing to an arbitrary port can reconfigure or reset hardware. The caller is responsible for
+/// knowing what `port` is wired to.
+#[cfg(not(test))]
+pub unsafe fn outb(port: u16, value: u8) {
+    asm!(
+        "out dx, al",
+        in("dx") port,
+        in("al") value,
+    );
+}
+
+/// Port I/O replacement for host tests: accesses are recorded per thread instead of reaching the
+/// hardware, and reads return the values queued with `queue_inputs`, then `0` unless another value
+/// was set with `set_input`.
+#[cfg(test)]
+pub mod mock {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use std::vec::Vec;
+
+    std::thread_local! {
+        static WRITES: RefCell<Vec<(u16, u8)>> = const { RefCell::new(Vec::new()) };
+        static READS: RefCell<Vec<u16>> = const { RefCell::new(Vec::new()) };
+        static INPUTS: RefCell<Vec<(u16, u8)>> = const { RefCell::new(Vec::new()) };
+        static QUEUED: RefCell<Vec<(u16, u8)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn inb(port: u16) -> u8 {
+        READS.with(|r| r.borrow_mut().push(port));
+        let queued = QUEUED.with(|q| {
+            let mut q = q.borrow_mut();
+            q.iter().position(|(p, _)| *p == port).map(|i| q.remove(i).1)
+        });
+        if let Some(value) = queued {
+            return value;
+        }
+        INPUTS.with(|i| i.borrow().iter().rev().find(|(p, _)| *p == port).map_or(0, |&(_, value)| value))
+    }
+
+    /// Makes reads from `port` on this thread return `value`.
+    pub fn set_input(port: u16, value: u8) {
+        INPUTS.with(|i| i.borrow_mut().push((port, value)));
+    }
+
+    /// Makes the next reads from `port` on this thread return `values`, in order, before falling
+    /// back to the value set with `set_input`.
+    pub fn queue_inputs(port: u16, values: &[u8]) {
+        QUEUED.with(|q| q.borrow_mut().extend(values.iter().map(|&value| (port, value))));
+    }
+
+    /// Bytes written to `port` on this thread, in order.
+    pub fn bytes_to(port: u16) -> Vec<u8> {
+        WRITES.with(|w| w.borrow().iter().filter(|(p, _)| *p == port).map(|&(_, value)| value).collect())
+    }
+
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn outb(port: u16, value: u8) {
+        WRITES.with(|w| w.borrow_mut().push((port, value)));
+    }
+
+    /// Number of writes to `port` recorded on this thread.
+    pub fn writes_to(port: u16) -> usize {
+        WRITES.with(|w| w.borrow().iter().filter(|(p, _)| *p == port).count())
+    }
+
+    /// Every write recorded on this thread, as `(port, value)` pairs in order.
+    pub fn writes() -> Vec<(u16, u8)> {
+        WRITES.with(|w| w.borrow().clone())
+    }
+
+    /// Number of reads from `port` recorded on this thread.
+    pub fn reads_from(port: u16) -> usize {
+        READS.with(|r| r.borrow().iter().filter(|&&p| p == port).count())
+    }
+
+    /// Forgets every access recorded on this thread.
+    pub fn clear() {
+        WRITES.with(|w| w.borrow_mut().clear());
+        READS.with(|r| r.borrow_mut().clear());
+        INPUTS.with(|i| i.borrow_mut().clear());
+        QUEUED.with(|q| q.borrow_mut().clear());
+    }
+}
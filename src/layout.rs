@@ -0,0 +1,121 @@
+use core::ops::Range;
+
+use crate::terminal::Screen;
+
+/// Addresses of the symbols defined by `boot.s` and `linker.ld`.
+struct Symbols {
+    kernel_start: u32,
+    kernel_end: u32,
+    stack_bottom: u32,
+    stack_top: u32,
+    heap_start: u32,
+    heap_end: u32,
+}
+
+/// This is the only place where linker-provided symbols are accessed. The symbols carry no data,
+/// only their addresses are meaningful, so they are declared as `u8` and never read.
+#[cfg(not(test))]
+fn symbols() -> Symbols {
+    use core::ptr::addr_of;
+
+    extern "C" {
+        static kernel_start: u8;
+        static kernel_end: u8;
+        static stack_bottom: u8;
+        static stack_top: u8;
+        static heap_start: u8;
+        static heap_end: u8;
+    }
+
+    Symbols {
+        kernel_start: addr_of!(kernel_start) as u32,
+        kernel_end: addr_of!(kernel_end) as u32,
+        stack_bottom: addr_of!(stack_bottom) as u32,
+        stack_top: addr_of!(stack_top) as u32,
+        heap_start: addr_of!(heap_start) as u32,
+        heap_end: addr_of!(heap_end) as u32,
+    }
+}
+
+/// The test binary is not linked with `linker.ld`, so it gets a plausible fixed layout instead.
+#[cfg(test)]
+fn symbols() -> Symbols {
+    Symbols {
+        kernel_start: 0x0010_0000,
+        kernel_end: 0x0020_8000,
+        stack_bottom: 0x0010_8000,
+        stack_top: 0x0020_8000,
+        heap_start: 0x0020_8000,
+        heap_end: 0x0060_8000,
+    }
+}
+
+/// Builds the range between two symbols, tolerating symbols given in the wrong order.
+fn span(a: u32, b: u32) -> Range<u32> {
+    a.min(b)..a.max(b)
+}
+
+/// The kernel stack, growing down from its `end`.
+pub fn stack() -> Range<u32> {
+    let s = symbols();
+    span(s.stack_bottom, s.stack_top)
+}
+
+/// The whole kernel image, from the start of `.text` to the end of `.bss`.
+pub fn kernel_image() -> Range<u32> {
+    let s = symbols();
+    span(s.kernel_start, s.kernel_end)
+}
+
+/// The memory reserved for the kernel heap, right after the kernel image.
+pub fn heap() -> Range<u32> {
+    let s = symbols();
+    span(s.heap_start, s.heap_end)
+}
+
+/// Every named region of the kernel's memory layout.
+pub fn regions() -> [(&'static str, Range<u32>); 3] {
+    [("kernel", kernel_image()), ("stack", stack()), ("heap", heap())]
+}
+
+/// Writes `range` as `name: 0xSTART-0xEND (N KiB)`, with `name` padded to align the ranges.
+pub fn write_region(name: &str, range: &Range<u32>, s: &mut Screen) {
+    s.write_str(name);
+    s.write_str(":");
+    for _ in name.len()..8 {
+        s.write(b' ');
+    }
+    s.write_hex_prefixed(range.start, 8);
+    s.write(b'-');
+    s.write_hex_prefixed(range.end, 8);
+    s.write_str(" (");
+    s.write_dec(range.len() as u32 / 1024);
+    s.write_str(" KiB)\n");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn span_is_ordered() {
+        assert_eq!(span(0x2000, 0x1000), 0x1000..0x2000);
+        assert_eq!(span(0x1000, 0x2000), 0x1000..0x2000);
+        assert!(span(0x1000, 0x1000).is_empty());
+    }
+
+    #[test]
+    fn regions() {
+        assert_eq!(stack(), 0x0010_8000..0x0020_8000);
+        assert_eq!(stack().len(), 1024 * 1024);
+        assert!(kernel_image().contains(&stack().start));
+        assert_eq!(heap().start, kernel_image().end);
+    }
+
+    #[test]
+    fn region_line() {
+        let mut s = Screen::with_capture();
+        write_region("stack", &(0x0010_8000..0x0020_8000), &mut s);
+        assert_eq!(s.captured(), "stack:   0x00108000-0x00208000 (1024 KiB)\n");
+    }
+}
@@ -1,18 +1,41 @@
 #![no_std]
 
-use gdt::set_gdt;
 use terminal::Screen;
 
+mod bell;
+#[cfg_attr(test, allow(dead_code))]
+mod cmos;
+mod config;
 mod conv;
+mod diag;
 mod gdt;
+mod hexrow;
+mod init;
+mod io;
+mod layout;
+mod log;
 mod panic;
 mod print;
+mod ramfs;
+mod rand;
+mod serial;
+mod settings;
 mod shell;
+#[cfg_attr(test, allow(dead_code))]
+mod speaker;
 mod terminal;
+mod time;
 
 #[no_mangle]
 pub extern "C" fn kernel_main() {
-    set_gdt();
     let mut s = Screen::default();
+    init::boot(&mut s);
+    terminal::splash(&mut s);
+    s.serial_mirror = serial::is_present();
+    if settings::is_safe_mode() {
+        s.write_color_str(settings::SAFE_MODE_BANNER, terminal::vga::Color::Error as u8);
+        s.write_str("\n");
+    }
+    terminal::keymap::first_boot(&mut s);
     shell::launch(&mut s);
 }
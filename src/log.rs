@@ -0,0 +1,120 @@
+use spin::Mutex;
+
+use crate::{
+    config,
+    diag::{self, Counter},
+    terminal::Screen,
+    time,
+};
+
+/// Number of messages kept by the kernel log before the oldest ones are overwritten.
+pub const LOG_CAPACITY: usize = config::LOG_CAPACITY;
+
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub tick: u64,
+    pub message: &'static str,
+}
+
+/// Fixed-size ring of kernel notices.
+pub struct Log {
+    entries: [Option<Entry>; LOG_CAPACITY],
+    /// Total number of messages ever pushed.
+    written: usize,
+    /// Value of `written` when the messages were last displayed.
+    displayed: usize,
+}
+
+impl Log {
+    pub const fn new() -> Self {
+        Log {
+            entries: [None; LOG_CAPACITY],
+            written: 0,
+            displayed: 0,
+        }
+    }
+
+    pub fn push(&mut self, tick: u64, message: &'static str) {
+        if self.written >= LOG_CAPACITY {
+            diag::bump(Counter::LogOverwrites);
+        }
+        self.entries[self.written % LOG_CAPACITY] = Some(Entry { tick, message });
+        self.written += 1;
+    }
+
+    /// Iterates over the retained messages, oldest first, starting at the `from`-th message ever
+    /// written.
+    fn since(&self, from: usize) -> impl Iterator<Item = &Entry> {
+        let first = from.max(self.written.saturating_sub(LOG_CAPACITY));
+        (first..self.written).filter_map(|i| self.entries[i % LOG_CAPACITY].as_ref())
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.since(0)
+    }
+
+    /// Returns the messages pushed since the last call and marks them as displayed.
+    pub fn take_new(&mut self) -> impl Iterator<Item = &Entry> {
+        let from = self.displayed;
+        self.displayed = self.written;
+        self.since(from)
+    }
+}
+
+pub static LOG: Mutex<Log> = Mutex::new(Log::new());
+
+/// Appends `message` to the kernel log.
+pub fn notice(message: &'static str) {
+    LOG.lock().push(time::ticks(), message);
+}
+
+/// Returns `true` if messages were logged since the last call to `print_new`.
+pub fn has_new() -> bool {
+    let log = LOG.lock();
+    log.displayed != log.written
+}
+
+/// Prints the messages logged since the last call.
+pub fn print_new(s: &mut Screen) {
+    for entry in LOG.lock().take_new() {
+        write_entry(entry, s);
+    }
+}
+
+pub fn write_entry(entry: &Entry, s: &mut Screen) {
+    s.write_str("[");
+    s.write_dec_u64(entry.tick);
+    s.write_str("] ");
+    s.write_str(entry.message);
+    s.write_str("\n");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn oldest_messages_are_overwritten() {
+        let mut log = Log::new();
+        for i in 0..LOG_CAPACITY as u64 + 3 {
+            log.push(i, "m");
+        }
+        let mut entries = log.entries();
+        assert_eq!(entries.next().unwrap().tick, 3);
+        assert_eq!(entries.count(), LOG_CAPACITY - 1);
+    }
+
+    #[test]
+    fn new_messages_are_taken_once() {
+        let mut log = Log::new();
+        log.push(1, "a");
+        log.push(2, "b");
+        assert_eq!(log.take_new().count(), 2);
+        assert_eq!(log.take_new().count(), 0);
+
+        log.push(3, "c");
+        let mut new = log.take_new();
+        assert_eq!(new.next().unwrap().message, "c");
+        assert!(new.next().is_none());
+    }
+}
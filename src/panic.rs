@@ -6,11 +6,18 @@ use crate::terminal::vga::Color;
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
-    use crate::terminal::{vga::Buffer, Screen};
+    use crate::terminal::{
+        vga::{self, Buffer},
+        Screen,
+    };
 
+    vga::emergency_reset();
     let mut s = Screen::default();
-    s.write_color_str("Panicked!", Color::Error as u8);
+    s.write_centered(0, "Panicked!", Color::Error as u8);
+    s.write_str("\n");
+    crate::diag::write_compact(&mut s);
+    s.set_cursor_visible(false);
     let b = Buffer::from_screen(&s);
-    b.flush();
+    b.flush_immediate();
     loop {}
 }
@@ -1,4 +1,75 @@
-use core::str;
+use core::{
+    fmt::{self, Write},
+    str,
+};
+
+use spin::Mutex;
+
+use crate::terminal::Screen;
+
+/// Bytes of text `print!` and `println!` can hold until it is shown.
+const PENDING_SIZE: usize = 1024;
+
+/// Text written by `print!` and `println!` and not shown yet. The macros cannot reach the screen,
+/// which its owner holds mutably; the owner shows the text with `show_pending` instead.
+struct Pending {
+    bytes: [u8; PENDING_SIZE],
+    len: usize,
+}
+
+impl Write for Pending {
+    /// Appends what fits of `string`, cut on a character boundary.
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        let mut len = string.len().min(PENDING_SIZE - self.len);
+        while !string.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.bytes[self.len..self.len + len].copy_from_slice(&string.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+/// Not to be used from interrupt handlers, which could find it locked by the code they interrupted.
+static PENDING: Mutex<Pending> = Mutex::new(Pending {
+    bytes: [0; PENDING_SIZE],
+    len: 0,
+});
+
+/// Queues `args` for the next `show_pending`. Used by `print!` and `println!`.
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    let _ = PENDING.lock().write_fmt(args);
+}
+
+/// Writes the text printed since the last call to `s`.
+pub fn show_pending(s: &mut Screen) {
+    let mut pending = PENDING.lock();
+    s.write_str(str::from_utf8(&pending.bytes[..pending.len]).unwrap_or(""));
+    pending.len = 0;
+}
+
+/// Serializes the tests that print, and drops the text they left behind.
+#[cfg(test)]
+pub fn reset_for_test() -> spin::MutexGuard<'static, ()> {
+    static PRINT_TESTS: Mutex<()> = Mutex::new(());
+    let guard = PRINT_TESTS.lock();
+    PENDING.lock().len = 0;
+    guard
+}
+
+/// Writes formatted text to the screen, once its owner calls `show_pending`.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::print::_print(format_args!($($arg)*)));
+}
+
+/// Like `print!`, followed by a line break.
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
 
 #[derive(Debug)]
 pub struct ParseError;
@@ -43,6 +114,37 @@ pub fn u64_to_base(mut addr: u64, base: u8) -> Result<([u8; 65], usize), ()> {
     Ok((buf, len))
 }
 
+#[cfg(test)]
+mod print_test {
+    use super::*;
+
+    #[test]
+    fn println_shows_up_on_the_next_show_pending() {
+        let _print = reset_for_test();
+        crate::print!("ESP: {:#010x}", 0x1234);
+        crate::println!(" {}", -7);
+        crate::println!();
+
+        let mut s = Screen::with_capture();
+        show_pending(&mut s);
+        assert_eq!(s.captured(), "ESP: 0x00001234 -7\n\n");
+        show_pending(&mut s);
+        assert_eq!(s.captured(), "ESP: 0x00001234 -7\n\n");
+    }
+
+    #[test]
+    fn overflow_is_cut_on_a_character_boundary() {
+        let mut pending = Pending {
+            bytes: [0; PENDING_SIZE],
+            len: PENDING_SIZE - 1,
+        };
+        let _ = pending.write_str("\u{e9}");
+        assert_eq!(pending.len, PENDING_SIZE - 1);
+        let _ = pending.write_str("ab");
+        assert_eq!(pending.len, PENDING_SIZE);
+    }
+}
+
 #[cfg(test)]
 mod u64_to_base_test {
     use super::*;
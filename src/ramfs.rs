@@ -0,0 +1,166 @@
+use spin::Mutex;
+
+use crate::config;
+
+/// Maximum number of files the ramfs can hold.
+pub const MAX_FILES: usize = config::RAMFS_MAX_FILES;
+
+/// Maximum size of a single file in bytes.
+pub const MAX_FILE_SIZE: usize = config::RAMFS_MAX_FILE_SIZE;
+
+/// Maximum length of a file name in bytes.
+pub const MAX_NAME_LENGTH: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RamFsError {
+    /// Every file slot is in use.
+    NoSpace,
+    /// The file reached `MAX_FILE_SIZE`.
+    FileFull,
+    /// The name is empty or longer than `MAX_NAME_LENGTH`.
+    InvalidName,
+}
+
+/// A fixed-capacity in-memory file.
+#[derive(Clone, Copy)]
+pub struct RamFile {
+    name: [u8; MAX_NAME_LENGTH],
+    name_len: usize,
+    data: [u8; MAX_FILE_SIZE],
+    len: usize,
+}
+
+impl RamFile {
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Appends `bytes` to the file. Nothing is written if the whole slice does not fit.
+    pub fn append(&mut self, bytes: &[u8]) -> Result<(), RamFsError> {
+        if self.len + bytes.len() > MAX_FILE_SIZE {
+            return Err(RamFsError::FileFull);
+        }
+        self.data[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    /// Shortens the file to `len` bytes. Does nothing if the file is already shorter.
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+}
+
+/// A flat, allocation-free file store living in kernel memory.
+pub struct RamFs {
+    files: [Option<RamFile>; MAX_FILES],
+}
+
+impl RamFs {
+    pub const fn new() -> Self {
+        RamFs { files: [None; MAX_FILES] }
+    }
+
+    /// Creates an empty file called `name`, replacing any existing file with the same name.
+    pub fn create(&mut self, name: &[u8]) -> Result<&mut RamFile, RamFsError> {
+        if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+            return Err(RamFsError::InvalidName);
+        }
+        self.remove(name);
+
+        let slot = self.files.iter_mut().find(|f| f.is_none()).ok_or(RamFsError::NoSpace)?;
+        let mut file = RamFile {
+            name: [0; MAX_NAME_LENGTH],
+            name_len: name.len(),
+            data: [0; MAX_FILE_SIZE],
+            len: 0,
+        };
+        file.name[..name.len()].copy_from_slice(name);
+
+        Ok(slot.insert(file))
+    }
+
+    pub fn find(&self, name: &[u8]) -> Option<&RamFile> {
+        self.files.iter().flatten().find(|f| f.name() == name)
+    }
+
+    pub fn find_mut(&mut self, name: &[u8]) -> Option<&mut RamFile> {
+        self.files.iter_mut().flatten().find(|f| f.name() == name)
+    }
+
+    /// Deletes the file called `name`. Returns `false` if it did not exist.
+    pub fn remove(&mut self, name: &[u8]) -> bool {
+        match self.files.iter_mut().find(|f| f.as_ref().is_some_and(|f| f.name() == name)) {
+            Some(slot) => {
+                *slot = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &RamFile> {
+        self.files.iter().flatten()
+    }
+}
+
+pub static RAMFS: Mutex<RamFs> = Mutex::new(RamFs::new());
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn create_and_append() {
+        let mut fs = RamFs::new();
+        let file = fs.create(b"log").unwrap();
+        file.append(b"hello ").unwrap();
+        file.append(b"world").unwrap();
+
+        assert_eq!(fs.find(b"log").unwrap().data(), b"hello world");
+        assert!(fs.find(b"missing").is_none());
+    }
+
+    #[test]
+    fn create_replaces_existing_file() {
+        let mut fs = RamFs::new();
+        fs.create(b"a").unwrap().append(b"old").unwrap();
+        fs.create(b"a").unwrap();
+
+        assert_eq!(fs.find(b"a").unwrap().data(), b"");
+        assert_eq!(fs.files().count(), 1);
+    }
+
+    #[test]
+    fn limits() {
+        let mut fs = RamFs::new();
+        assert_eq!(fs.create(b"").err(), Some(RamFsError::InvalidName));
+        assert_eq!(fs.create(&[b'x'; MAX_NAME_LENGTH + 1]).err(), Some(RamFsError::InvalidName));
+
+        let file = fs.create(b"big").unwrap();
+        file.append(&[1; MAX_FILE_SIZE - 1]).unwrap();
+        assert_eq!(file.append(b"ab"), Err(RamFsError::FileFull));
+        assert_eq!(file.data().len(), MAX_FILE_SIZE - 1);
+
+        for i in 1..MAX_FILES {
+            fs.create(&[b'0' + i as u8]).unwrap();
+        }
+        assert_eq!(fs.create(b"full").err(), Some(RamFsError::NoSpace));
+    }
+
+    #[test]
+    fn remove_and_truncate() {
+        let mut fs = RamFs::new();
+        fs.create(b"a").unwrap().append(b"abcdef").unwrap();
+        fs.find_mut(b"a").unwrap().truncate(2);
+        assert_eq!(fs.find(b"a").unwrap().data(), b"ab");
+
+        assert!(fs.remove(b"a"));
+        assert!(!fs.remove(b"a"));
+        assert!(fs.find(b"a").is_none());
+    }
+}
@@ -0,0 +1,135 @@
+use spin::Mutex;
+
+use crate::{cmos, time};
+
+const MULTIPLIER: u64 = 6364136223846793005;
+/// Stream selected by `Pcg32::from_seed`.
+const DEFAULT_STREAM: u64 = 54;
+
+/// PCG32 (XSH RR) pseudo-random number generator, reproducible for a given seed.
+#[derive(Clone, Copy)]
+pub struct Pcg32 {
+    state: u64,
+    inc: u64,
+}
+
+impl Pcg32 {
+    /// Creates a generator from `seed` on the sequence selected by `stream`.
+    pub const fn new(seed: u64, stream: u64) -> Self {
+        let inc = (stream << 1) | 1;
+        let state = inc.wrapping_add(seed).wrapping_mul(MULTIPLIER).wrapping_add(inc);
+        Pcg32 { state, inc }
+    }
+
+    pub const fn from_seed(seed: u64) -> Self {
+        Pcg32::new(seed, DEFAULT_STREAM)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    /// Fills `bytes` with random bytes.
+    #[allow(dead_code)]
+    pub fn fill(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(4) {
+            let random = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&random[..chunk.len()]);
+        }
+    }
+
+    /// Returns a number uniformly distributed in `lo..hi`, or `lo` if the range is empty.
+    ///
+    /// Outputs below `2^32 % (hi - lo)` are rejected, so that every value of the range is reached
+    /// by the same number of outputs and the reduction has no modulo bias.
+    pub fn range(&mut self, lo: u32, hi: u32) -> u32 {
+        if hi <= lo {
+            return lo;
+        }
+        let bound = hi - lo;
+        let threshold = bound.wrapping_neg() % bound;
+        loop {
+            let r = self.next_u32();
+            if r >= threshold {
+                return lo + r % bound;
+            }
+        }
+    }
+}
+
+/// The kernel's generator, seeded at boot by `seed_from_hardware` or by the `srand` command.
+pub static RNG: Mutex<Pcg32> = Mutex::new(Pcg32::from_seed(0));
+
+/// Reads the RTC date and time registers into a single value.
+fn rtc_seed() -> u64 {
+    [0x00, 0x02, 0x04, 0x07, 0x08, 0x09]
+        .iter()
+        .fold(0, |seed, &register| (seed << 8) | cmos::read(register) as u64)
+}
+
+/// Seeds `RNG` from the TSC xor the RTC, so that every boot produces a different sequence.
+pub fn seed_from_hardware() -> Result<(), &'static str> {
+    *RNG.lock() = Pcg32::from_seed(time::rdtsc() ^ rtc_seed());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn known_answer() {
+        // First outputs of the reference pcg32-demo for seed 42 on stream 54.
+        let mut rng = Pcg32::new(42, 54);
+        let expected = [0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e];
+        for value in expected {
+            assert_eq!(rng.next_u32(), value);
+        }
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Pcg32::from_seed(7);
+        let mut b = Pcg32::from_seed(7);
+        let mut c = Pcg32::from_seed(8);
+        assert!((0..16).all(|_| a.next_u32() == b.next_u32()));
+        assert!((0..16).any(|_| a.next_u32() != c.next_u32()));
+    }
+
+    #[test]
+    fn fill_covers_partial_words() {
+        let mut rng = Pcg32::new(42, 54);
+        let mut bytes = [0u8; 6];
+        rng.fill(&mut bytes);
+        assert_eq!(bytes, [0xb7, 0x02, 0x5c, 0xa1, 0x09, 0xf4]);
+    }
+
+    #[test]
+    fn range_bounds() {
+        let mut rng = Pcg32::from_seed(1);
+        assert!((0..1000).all(|_| (10..13).contains(&rng.range(10, 13))));
+        assert_eq!(rng.range(5, 6), 5);
+        assert_eq!(rng.range(5, 5), 5);
+        assert_eq!(rng.range(9, 2), 9);
+        assert!(rng.range(0, u32::MAX) < u32::MAX);
+    }
+
+    #[test]
+    fn range_is_uniform() {
+        const BUCKETS: usize = 10;
+        const SAMPLES: u32 = 10_000;
+        let mut rng = Pcg32::from_seed(2024);
+        let mut counts = [0u32; BUCKETS];
+        for _ in 0..SAMPLES {
+            counts[rng.range(0, BUCKETS as u32) as usize] += 1;
+        }
+
+        let expected = (SAMPLES / BUCKETS as u32) as f64;
+        let chi_square: f64 = counts.iter().map(|&c| (c as f64 - expected) * (c as f64 - expected) / expected).sum();
+        // 99.9th percentile of the chi-square distribution with 9 degrees of freedom.
+        assert!(chi_square < 27.88, "chi-square {chi_square} for {counts:?}");
+    }
+}
@@ -0,0 +1,94 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::io::{inb, outb};
+
+/// First port of the COM1 UART, its data register.
+pub const COM1: u16 = 0x3F8;
+const INTERRUPT_ENABLE: u16 = COM1 + 1;
+const FIFO_CONTROL: u16 = COM1 + 2;
+const LINE_CONTROL: u16 = COM1 + 3;
+const MODEM_CONTROL: u16 = COM1 + 4;
+pub const LINE_STATUS: u16 = COM1 + 5;
+const SCRATCH: u16 = COM1 + 7;
+
+/// Set in `LINE_STATUS` when the transmitter can take another byte.
+pub const TRANSMIT_EMPTY: u8 = 0x20;
+/// Gives the divisor latch access to the first two registers.
+const DIVISOR_LATCH: u8 = 0x80;
+/// 115200 / 3 = 38400 baud.
+const DIVISOR: u8 = 3;
+const TRANSMIT_MAX_POLLS: u32 = 100_000;
+
+/// Set by `init` when a UART answered on COM1.
+static PRESENT: AtomicBool = AtomicBool::new(false);
+
+/// Configures COM1 for 38400 baud, 8N1, after checking that a UART is there through its scratch
+/// register.
+pub fn init() -> Result<(), &'static str> {
+    unsafe {
+        outb(SCRATCH, 0xAE);
+        if inb(SCRATCH) != 0xAE {
+            return Err("no uart on com1");
+        }
+        outb(INTERRUPT_ENABLE, 0x00);
+        outb(LINE_CONTROL, DIVISOR_LATCH);
+        outb(COM1, DIVISOR);
+        outb(INTERRUPT_ENABLE, 0x00);
+        outb(LINE_CONTROL, 0x03);
+        outb(FIFO_CONTROL, 0xC7);
+        outb(MODEM_CONTROL, 0x03);
+    }
+    PRESENT.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn is_present() -> bool {
+    PRESENT.load(Ordering::Relaxed)
+}
+
+/// Sends `byte`, waiting a bounded time for the transmitter. Line breaks are sent as CR LF.
+pub fn write_byte(byte: u8) {
+    if byte == b'\n' {
+        transmit(b'\r');
+    }
+    transmit(byte);
+}
+
+fn transmit(byte: u8) {
+    for _ in 0..TRANSMIT_MAX_POLLS {
+        if unsafe { inb(LINE_STATUS) } & TRANSMIT_EMPTY != 0 {
+            unsafe { outb(COM1, byte) };
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::mock;
+
+    #[test]
+    fn line_breaks_are_sent_as_crlf() {
+        mock::clear();
+        mock::set_input(LINE_STATUS, TRANSMIT_EMPTY);
+        for &b in b"ok\n" {
+            write_byte(b);
+        }
+        assert_eq!(mock::bytes_to(COM1), b"ok\r\n");
+    }
+
+    #[test]
+    fn busy_transmitter_drops_bytes() {
+        mock::clear();
+        write_byte(b'x');
+        assert!(mock::bytes_to(COM1).is_empty());
+        assert_eq!(mock::reads_from(LINE_STATUS), TRANSMIT_MAX_POLLS as usize);
+    }
+
+    #[test]
+    fn missing_uart() {
+        mock::clear();
+        assert_eq!(init(), Err("no uart on com1"));
+    }
+}
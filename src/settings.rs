@@ -0,0 +1,94 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+    cmos,
+    terminal::ps2,
+    time::{self, TICKS_PER_SECOND},
+};
+
+/// CMOS registers holding settings persisted across boots.
+pub const PERSISTED_REGISTERS: &[u8] = &[cmos::KEYMAP_REGISTER];
+
+/// How long the boot waits for a held Space key.
+const SAFE_MODE_WINDOW_TICKS: u64 = TICKS_PER_SECOND / 5;
+/// Number of polls used instead of the window when the timer is not calibrated.
+const SAFE_MODE_MAX_POLLS: u32 = 200_000;
+
+const SPACE: u8 = 0x39;
+const BREAK_BIT: u8 = 0x80;
+
+pub const SAFE_MODE_BANNER: &str = "SAFE MODE: stored settings ignored";
+
+/// Set at boot when Space was held: persisted settings are ignored and the defaults are used.
+static SAFE_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn is_safe_mode() -> bool {
+    SAFE_MODE.load(Ordering::Relaxed)
+}
+
+/// Updates whether Space is held after `code`: a make code, repeated by the typematic stream while
+/// the key is down, sets it and the break code clears it.
+fn space_held_after(held: bool, code: u8) -> bool {
+    match code {
+        SPACE => true,
+        c if c == SPACE | BREAK_BIT => false,
+        _ => held,
+    }
+}
+
+/// Boot stage entering safe mode if Space is held at the end of a `SAFE_MODE_WINDOW_TICKS` window,
+/// so that a normal boot without any key press is barely delayed.
+pub fn detect_safe_mode() -> Result<(), &'static str> {
+    let deadline = time::ticks() + SAFE_MODE_WINDOW_TICKS;
+    let mut held = false;
+    for _ in 0..SAFE_MODE_MAX_POLLS {
+        if let Some(code) = ps2::read_scancode_if_ready() {
+            held = space_held_after(held, code);
+        }
+        if time::is_calibrated() && time::ticks() >= deadline {
+            break;
+        }
+    }
+    SAFE_MODE.store(held, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Clears every persisted setting, so that the next boot starts from the defaults.
+pub fn reset() {
+    for &register in PERSISTED_REGISTERS {
+        cmos::write(register, 0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::mock;
+
+    fn held(codes: &[u8]) -> bool {
+        codes.iter().fold(false, |held, &code| space_held_after(held, code))
+    }
+
+    #[test]
+    fn held_space_is_detected() {
+        assert!(held(&[0x39]));
+        assert!(held(&[0x39, 0x39, 0x39, 0x39]));
+        assert!(held(&[0x1E, 0x39, 0x9E, 0x39]));
+    }
+
+    #[test]
+    fn released_or_absent_space_is_ignored() {
+        assert!(!held(&[]));
+        assert!(!held(&[0x39, 0x39, 0xB9]));
+        assert!(!held(&[0x1E, 0x9E]));
+        assert!(!held(&[0xFA, 0xAA]));
+    }
+
+    #[test]
+    fn reset_clears_the_persisted_registers() {
+        mock::clear();
+        reset();
+        let expected = PERSISTED_REGISTERS.iter().flat_map(|&register| [(0x70, 0x80 | register), (0x71, 0)]);
+        assert!(mock::writes().into_iter().eq(expected));
+    }
+}
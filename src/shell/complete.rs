@@ -0,0 +1,221 @@
+use crate::{
+    bell::BellMode,
+    terminal::{cursor::CursorStyle, keymap::Layout, vga::Hue},
+};
+
+use super::registry;
+
+/// Source of completion candidates: calls `visit` with every candidate.
+pub type Candidates = fn(visit: &mut dyn FnMut(&'static str));
+
+/// Result of completing a word.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Completion {
+    /// No candidate starts with the word.
+    NoMatch,
+    /// Bytes to append to the word. `complete` is set when they finish the only candidate, in
+    /// which case a space should follow.
+    Insert { rest: &'static str, complete: bool },
+    /// Several candidates start with the word and share nothing more, they should be listed.
+    Ambiguous,
+}
+
+/// Completes `word` from the `candidates` starting with it.
+pub fn complete_word(word: &[u8], candidates: Candidates) -> Completion {
+    let mut first: Option<&'static str> = None;
+    let mut common = 0;
+    let mut matches = 0;
+    candidates(&mut |candidate| {
+        if !candidate.as_bytes().starts_with(word) {
+            return;
+        }
+        matches += 1;
+        match first {
+            None => {
+                first = Some(candidate);
+                common = candidate.len();
+            }
+            Some(first) => {
+                let shared = first.bytes().zip(candidate.bytes()).take_while(|(a, b)| a == b).count();
+                common = common.min(shared);
+            }
+        }
+    });
+
+    match first {
+        None => Completion::NoMatch,
+        Some(candidate) if matches == 1 => Completion::Insert {
+            rest: &candidate[word.len()..],
+            complete: true,
+        },
+        Some(candidate) if common > word.len() => Completion::Insert {
+            rest: &candidate[word.len()..common],
+            complete: false,
+        },
+        Some(_) => Completion::Ambiguous,
+    }
+}
+
+/// Calls `visit` with every candidate starting with `word`.
+pub fn matching(word: &[u8], candidates: Candidates, visit: &mut dyn FnMut(&'static str)) {
+    candidates(&mut |candidate| {
+        if candidate.as_bytes().starts_with(word) {
+            visit(candidate);
+        }
+    });
+}
+
+/// Returns the word at the end of the command `line` and where to complete it from: command names
+/// for the first word, the command's `complete_arg` hook for its first argument.
+pub fn split_line(line: &[u8]) -> Option<(&[u8], Candidates)> {
+    let line = &line[line.iter().position(|&c| c != b' ').unwrap_or(line.len())..];
+    let Some(space) = line.iter().position(|&c| c == b' ') else {
+        return Some((line, command_names));
+    };
+
+    let arg = &line[space..];
+    let arg = &arg[arg.iter().position(|&c| c != b' ').unwrap_or(arg.len())..];
+    if arg.contains(&b' ') {
+        return None;
+    }
+    let candidates = registry::find(&line[..space])?.complete_arg?;
+    Some((arg, candidates))
+}
+
+pub fn command_names(visit: &mut dyn FnMut(&'static str)) {
+    registry::commands().iter().for_each(|c| visit(c.name));
+}
+
+pub fn bell_modes(visit: &mut dyn FnMut(&'static str)) {
+    [BellMode::Speaker, BellMode::Visual, BellMode::Off].iter().for_each(|m| visit(m.name()));
+}
+
+pub fn setting_names(visit: &mut dyn FnMut(&'static str)) {
+    ["AUTOSCROLL", "HINTS", "SAVER"].into_iter().for_each(visit);
+}
+
+pub fn on_off(visit: &mut dyn FnMut(&'static str)) {
+    ["on", "off"].into_iter().for_each(visit);
+}
+
+pub fn cursor_args(visit: &mut dyn FnMut(&'static str)) {
+    ["on", "off", "style"].into_iter().for_each(&mut *visit);
+    CursorStyle::ALL.iter().for_each(|style| visit(style.name()));
+}
+
+pub fn demo_names(visit: &mut dyn FnMut(&'static str)) {
+    visit("highlight");
+}
+
+pub fn text_modes(visit: &mut dyn FnMut(&'static str)) {
+    ["25", "50"].into_iter().for_each(visit);
+}
+
+pub fn color_names(visit: &mut dyn FnMut(&'static str)) {
+    hue_names(visit);
+    visit("reset");
+}
+
+pub fn hue_names(visit: &mut dyn FnMut(&'static str)) {
+    Hue::ALL.iter().for_each(|h| visit(h.name()));
+}
+
+pub fn bench_names(visit: &mut dyn FnMut(&'static str)) {
+    visit("scroll");
+}
+
+pub fn saver_actions(visit: &mut dyn FnMut(&'static str)) {
+    visit("demo");
+}
+
+pub fn diag_actions(visit: &mut dyn FnMut(&'static str)) {
+    visit("reset");
+}
+
+pub fn kbdstat_actions(visit: &mut dyn FnMut(&'static str)) {
+    visit("reset");
+}
+
+pub fn keymap_names(visit: &mut dyn FnMut(&'static str)) {
+    for layout in Layout::ALL {
+        visit(layout.name());
+    }
+}
+
+pub fn serial_actions(visit: &mut dyn FnMut(&'static str)) {
+    visit("dump");
+}
+
+pub fn settings_actions(visit: &mut dyn FnMut(&'static str)) {
+    visit("reset");
+}
+
+pub fn record_sources(visit: &mut dyn FnMut(&'static str)) {
+    visit("input");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn words(visit: &mut dyn FnMut(&'static str)) {
+        ["lightgray", "lightgreen", "red"].into_iter().for_each(visit);
+    }
+
+    #[test]
+    fn unique_match_is_completed() {
+        assert_eq!(complete_word(b"r", words), Completion::Insert { rest: "ed", complete: true });
+        assert_eq!(complete_word(b"lightgr", words), Completion::Ambiguous);
+        assert_eq!(complete_word(b"lightgre", words), Completion::Insert { rest: "en", complete: true });
+        assert_eq!(complete_word(b"red", words), Completion::Insert { rest: "", complete: true });
+    }
+
+    #[test]
+    fn common_prefix_is_inserted() {
+        assert_eq!(
+            complete_word(b"l", words),
+            Completion::Insert {
+                rest: "ightgr",
+                complete: false
+            }
+        );
+        assert_eq!(complete_word(b"", words), Completion::Ambiguous);
+        assert_eq!(complete_word(b"blue", words), Completion::NoMatch);
+    }
+
+    #[test]
+    fn matching_lists_candidates() {
+        let mut found = [""; 3];
+        let mut n = 0;
+        matching(b"light", words, &mut |c| {
+            found[n] = c;
+            n += 1;
+        });
+        assert_eq!(&found[..n], ["lightgray", "lightgreen"]);
+    }
+
+    #[test]
+    fn line_splitting() {
+        let (word, candidates) = split_line(b"  sel").unwrap();
+        assert_eq!(word, b"sel");
+        assert_eq!(complete_word(word, candidates), Completion::Insert { rest: "ftest", complete: true });
+
+        let (word, candidates) = split_line(b"bell  v").unwrap();
+        assert_eq!(word, b"v");
+        assert_eq!(complete_word(word, candidates), Completion::Insert { rest: "isual", complete: true });
+
+        assert!(split_line(b"echo x").is_none());
+        assert!(split_line(b"nope x").is_none());
+        assert!(split_line(b"set HINTS 1").is_none());
+    }
+
+    #[test]
+    fn hooks_only_offer_accepted_values() {
+        bell_modes(&mut |name| assert!(BellMode::from_name(name.as_bytes()).is_some()));
+        for command in registry::commands() {
+            if let Some(candidates) = command.complete_arg {
+                candidates(&mut |name| assert!(!name.is_empty() && !name.contains(' ')));
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{
+    ramfs::{RamFsError, MAX_FILES, MAX_FILE_SIZE},
+    terminal::{vga::Color, Screen},
+};
+
+use super::replay::ReplayError;
+
+/// Reasons for which a shell command can fail.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CmdError {
+    /// The arguments could not be parsed; the message describes what was expected.
+    BadArgs(&'static str),
+    /// The address does not point to memory that can be accessed.
+    InvalidAddress(u32),
+    /// A numeric argument exceeded its maximum value.
+    OutOfRange { what: &'static str, max: u32 },
+    /// The named device did not respond in time, for the given reason.
+    DeviceTimeout { device: &'static str, reason: &'static str },
+    /// The named device cannot be used at all, for the given reason.
+    DeviceUnavailable { device: &'static str, reason: &'static str },
+    /// The requested item does not exist.
+    NotFound,
+    /// There is no active item of the named kind to act on.
+    NotActive(&'static str),
+    /// The command was interrupted by the user.
+    Aborted,
+    /// A self-check of the named subsystem failed; the command printed the details.
+    CheckFailed(&'static str),
+}
+
+impl CmdError {
+    /// Prints the error as a single line prefixed with a red `error:` marker.
+    pub fn render(&self, s: &mut Screen) {
+        s.write_color_str("error:", Color::Error as u8);
+        s.write_str(" ");
+        match *self {
+            CmdError::BadArgs(expected) => {
+                s.write_str("bad arguments: ");
+                s.write_str(expected);
+            }
+            CmdError::InvalidAddress(addr) => {
+                s.write_str("invalid address ");
+                s.write_hex_prefixed(addr, 8);
+            }
+            CmdError::OutOfRange { what, max } => {
+                s.write_str(what);
+                s.write_str(" out of range (max ");
+                s.write_dec(max);
+                s.write_str(")");
+            }
+            CmdError::DeviceTimeout { device, reason } => {
+                s.write_str(device);
+                s.write_str(": device timeout (");
+                s.write_str(reason);
+                s.write_str(")");
+            }
+            CmdError::DeviceUnavailable { device, reason } => {
+                s.write_str(device);
+                s.write_str(": device unavailable (");
+                s.write_str(reason);
+                s.write_str(")");
+            }
+            CmdError::NotFound => s.write_str("not found"),
+            CmdError::NotActive(what) => {
+                s.write_str("no ");
+                s.write_str(what);
+                s.write_str(" is active");
+            }
+            CmdError::Aborted => s.write_str("aborted"),
+            CmdError::CheckFailed(what) => {
+                s.write_str(what);
+                s.write_str(" check failed");
+            }
+        }
+        s.write_str("\n");
+    }
+}
+
+impl From<RamFsError> for CmdError {
+    fn from(e: RamFsError) -> Self {
+        match e {
+            RamFsError::NoSpace => CmdError::OutOfRange {
+                what: "file count",
+                max: MAX_FILES as u32,
+            },
+            RamFsError::FileFull => CmdError::OutOfRange {
+                what: "file size",
+                max: MAX_FILE_SIZE as u32,
+            },
+            RamFsError::InvalidName => CmdError::BadArgs("file names are 1 to 16 bytes long"),
+        }
+    }
+}
+
+impl From<ReplayError> for CmdError {
+    fn from(_: ReplayError) -> Self {
+        CmdError::BadArgs("corrupt recording")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_renders(e: CmdError, expected: &str) {
+        let mut s = Screen::with_capture();
+        e.render(&mut s);
+        assert_eq!(s.captured(), expected);
+        assert_eq!(s.buffer[0].color(), Color::Error as u8);
+    }
+
+    #[test]
+    fn bad_args() {
+        assert_renders(CmdError::BadArgs("expected <address>"), "error: bad arguments: expected <address>\n");
+    }
+
+    #[test]
+    fn invalid_address() {
+        assert_renders(CmdError::InvalidAddress(0xB8000), "error: invalid address 0x000b8000\n");
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_renders(CmdError::OutOfRange { what: "rate", max: 31 }, "error: rate out of range (max 31)\n");
+    }
+
+    #[test]
+    fn device_timeout() {
+        assert_renders(
+            CmdError::DeviceTimeout {
+                device: "ps2",
+                reason: "no response",
+            },
+            "error: ps2: device timeout (no response)\n",
+        );
+    }
+
+    #[test]
+    fn device_unavailable() {
+        assert_renders(
+            CmdError::DeviceUnavailable {
+                device: "serial",
+                reason: "not present",
+            },
+            "error: serial: device unavailable (not present)\n",
+        );
+    }
+
+    #[test]
+    fn not_active() {
+        assert_renders(CmdError::NotActive("recording"), "error: no recording is active\n");
+    }
+
+    #[test]
+    fn not_found() {
+        assert_renders(CmdError::NotFound, "error: not found\n");
+    }
+
+    #[test]
+    fn aborted() {
+        assert_renders(CmdError::Aborted, "error: aborted\n");
+    }
+
+    #[test]
+    fn check_failed() {
+        assert_renders(CmdError::CheckFailed("registry"), "error: registry check failed\n");
+    }
+}
@@ -0,0 +1,2056 @@
+use core::arch::asm;
+
+use spin::Mutex;
+
+use crate::{
+    bell::{self, BellMode},
+    config,
+    conv::{self, hextou},
+    diag,
+    hexrow::{HexRowOptions, HexRows, ROW_MAX_LENGTH},
+    init, layout, log, print,
+    ramfs::{self, RAMFS},
+    rand::{self, Pcg32},
+    serial, settings, speaker,
+    terminal::{
+        cursor::CursorStyle,
+        indicator::{self, MOD_INDICATOR, STATUS_BAR},
+        keymap::{self, Layout},
+        ps2::{self, mouse, read_if_ready, Key},
+        saver::{self, SaverMode, SAVER},
+        splash,
+        terminal::{emergency_action, Emergency},
+        vga::{self, mode::TextMode, Buffer, Color, Hue},
+        AutoScroll, Screen, HIDDEN_INPUT_SIZE,
+    },
+    time::{self, TICKS_PER_SECOND},
+};
+
+mod complete;
+mod error;
+mod registry;
+mod replay;
+mod sort;
+
+use complete::Completion;
+pub use error::CmdError;
+use replay::{Player, Recorder, PLAYER, RECORDER};
+use sort::{LineTable, SortOptions};
+
+const PROMPT_MAX_LENGTH: usize = 1000;
+
+/// Outcome of the last executed command, displayed by `status` and the prompt failure marker.
+static LAST_STATUS: Mutex<Result<(), CmdError>> = Mutex::new(Ok(()));
+
+pub fn launch(s: &mut Screen) {
+    let mut prompt_start: usize;
+    // Whatever ran before the shell may have hidden the cursor.
+    s.set_cursor_visible(true);
+    print::show_pending(s);
+    let mut last_input = time::ticks();
+
+    loop {
+        write_prompt(s);
+        flush(s);
+
+        prompt_start = s.cursor;
+
+        loop {
+            if log::has_new() {
+                print_new_log(s, &mut prompt_start);
+                flush(s);
+            }
+            if let Some(mods) = ps2::take_modifier_change() {
+                let mut indicator = MOD_INDICATOR.lock();
+                if indicator.update(mods) && indicator.is_shown() {
+                    drop(indicator);
+                    flush(s);
+                }
+            }
+            if mouse::POINTER.lock().take_moved() {
+                flush(s);
+            }
+            if saver::is_due(last_input) {
+                let _ = saver::run(s);
+                flush(s);
+                last_input = time::ticks();
+            }
+            if let Some(done) = process_next_key(s, &mut prompt_start) {
+                last_input = time::ticks();
+                flush(s);
+                if done {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Reads the next key and applies it, see `next_key` and `edit_prompt`. The Ctrl+Alt chords are
+/// handled first, whatever is typed. Returns `None` if no key was pressed, otherwise `true` once the
+/// prompt line is done with.
+fn process_next_key(s: &mut Screen, prompt_start: &mut usize) -> Option<bool> {
+    let key = next_key(s)?;
+    Some(handle_emergency(key, s) || edit_prompt(key, s, prompt_start))
+}
+
+/// Runs the emergency action of `key`: Ctrl+Alt+Del reboots and Ctrl+Alt+Backspace starts the
+/// screen afresh, keeping its serial mirror and text mode. Returns `true` if `key` was consumed.
+fn handle_emergency(key: Key, s: &mut Screen) -> bool {
+    match emergency_action(key, ps2::ctrl_alt_held()) {
+        Some(Emergency::Reboot) => ps2::reboot(),
+        Some(Emergency::ResetScreen) => {
+            let (serial_mirror, view_height) = (s.serial_mirror, s.view_height);
+            *s = Screen::default();
+            s.serial_mirror = serial_mirror;
+            s.view_height = view_height;
+        }
+        None => return false,
+    }
+    true
+}
+
+/// Applies `key` to the prompt line starting at `prompt_start`. Returns `true` once the line is
+/// done with, either executed or cancelled.
+fn edit_prompt(key: Key, s: &mut Screen, prompt_start: &mut usize) -> bool {
+    let discarded = s.cells_discarded;
+    let done = apply_key(key, s, prompt_start);
+    follow_discarded(s, prompt_start, discarded);
+    done
+}
+
+/// Moves `index` left by the cells `s` discarded from the start of its buffer since the count was
+/// `discarded`, so that it stays on the same entry. An entry that was discarded itself ends up on
+/// the first one.
+fn follow_discarded(s: &Screen, index: &mut usize, discarded: usize) {
+    *index = index.saturating_sub(s.cells_discarded.wrapping_sub(discarded));
+}
+
+/// The key bindings of `edit_prompt`.
+fn apply_key(key: Key, s: &mut Screen, prompt_start: &mut usize) -> bool {
+    match key {
+        Key::Enter => {
+            let mut prompt: [u8; PROMPT_MAX_LENGTH] = [0; PROMPT_MAX_LENGTH];
+            s.move_cursor_to_end();
+            for (place, data) in prompt.iter_mut().zip(s.buffer[*prompt_start..s.cursor].iter()) {
+                *place = data.ch()
+            }
+            s.handle_key(key);
+            prompt_execute(&prompt, s);
+            return true;
+        }
+        Key::ArrowLeft | Key::Backspace => {
+            if *prompt_start < s.cursor {
+                s.handle_key(key);
+            }
+        }
+        Key::Home | Key::CtrlA => s.set_cursor(*prompt_start),
+        Key::End | Key::CtrlE => s.move_cursor_to_end(),
+        Key::Delete => {
+            if *prompt_start <= s.cursor {
+                s.handle_key(key);
+            }
+        }
+        Key::Escape => {
+            let _ = reboot_cmd(&[], s);
+        }
+        Key::Tab => complete_prompt(s, prompt_start),
+        Key::CtrlC => {
+            cancel_prompt(s);
+            return true;
+        }
+        Key::CtrlL => redraw_prompt(s, prompt_start),
+        Key::CtrlArrowLeft => jump_word(s, *prompt_start, false),
+        Key::CtrlArrowRight => jump_word(s, *prompt_start, true),
+        Key::CtrlU => s.erase_range(*prompt_start, s.cursor),
+        Key::CtrlK => s.clear_to_eol(),
+        _ => s.handle_key(key),
+    }
+    false
+}
+
+/// Abandons the prompt line without executing it. The line stays visible, marked with `^C`.
+fn cancel_prompt(s: &mut Screen) {
+    s.move_cursor_to_end();
+    s.write_str("^C\n");
+}
+
+/// Clears the screen and redraws the prompt line being typed at its top, cursor included.
+fn redraw_prompt(s: &mut Screen, prompt_start: &mut usize) {
+    let mut line = [0u8; PROMPT_MAX_LENGTH];
+    let len = read_prompt_line(s, *prompt_start, &mut line);
+    let column = s.cursor - *prompt_start;
+
+    s.clear();
+    write_prompt(s);
+    *prompt_start = s.cursor;
+    s.write_bytes(&line[..len]);
+    s.set_cursor(*prompt_start + column);
+}
+
+/// Prints the messages logged since the last call below the prompt line starting at
+/// `prompt_start`, then writes a new prompt below them with the text typed so far, the cursor at
+/// the same column.
+fn print_new_log(s: &mut Screen, prompt_start: &mut usize) {
+    let mut line = [0u8; PROMPT_MAX_LENGTH];
+    let len = read_prompt_line(s, *prompt_start, &mut line);
+    let column = s.cursor - *prompt_start;
+
+    s.move_cursor_to_end();
+    s.write_str("\n");
+    log::print_new(s);
+    write_prompt(s);
+    *prompt_start = s.cursor;
+    let discarded = s.cells_discarded;
+    s.write_bytes(&line[..len]);
+    follow_discarded(s, prompt_start, discarded);
+    s.set_cursor(*prompt_start + column);
+}
+
+/// Copies the typed text of the prompt line starting at `prompt_start` to `line` and returns its
+/// length.
+fn read_prompt_line(s: &Screen, prompt_start: usize, line: &mut [u8; PROMPT_MAX_LENGTH]) -> usize {
+    let len = (s.last_entry_index - prompt_start).min(PROMPT_MAX_LENGTH);
+    for (place, cell) in line.iter_mut().zip(s.buffer[prompt_start..].iter().take(len)) {
+        *place = cell.ch();
+    }
+    len
+}
+
+/// Moves the cursor to the start of the previous word of the prompt line, or past the end of the
+/// next one if `forward`.
+fn jump_word(s: &mut Screen, prompt_start: usize, forward: bool) {
+    let mut line = [0u8; PROMPT_MAX_LENGTH];
+    let len = read_prompt_line(s, prompt_start, &mut line);
+    let column = (s.cursor - prompt_start).min(len);
+    let column = if forward {
+        next_word_end(&line[..len], column)
+    } else {
+        previous_word_start(&line[..len], column)
+    };
+    s.set_cursor(prompt_start + column);
+}
+
+/// Position after the run of non-space bytes following the spaces at `pos`.
+fn next_word_end(line: &[u8], pos: usize) -> usize {
+    let pos = pos + line[pos..].iter().take_while(|&&c| c == b' ').count();
+    pos + line[pos..].iter().take_while(|&&c| c != b' ').count()
+}
+
+/// Position of the start of the run of non-space bytes preceding the spaces before `pos`.
+fn previous_word_start(line: &[u8], pos: usize) -> usize {
+    let pos = pos - line[..pos].iter().rev().take_while(|&&c| c == b' ').count();
+    pos - line[..pos].iter().rev().take_while(|&&c| c != b' ').count()
+}
+
+fn write_prompt(s: &mut Screen) {
+    if LAST_STATUS.lock().is_ok() {
+        s.write_str("sh> ");
+    } else {
+        s.write_color_str("sh> ", Color::Error as u8);
+    }
+}
+
+/// Completes the word before the cursor, which must be at the end of the prompt. A unique match
+/// is inserted with a trailing space, and the prefix shared by several matches is inserted. If no
+/// prefix is shared, the matches are listed and the prompt is redrawn below them.
+fn complete_prompt(s: &mut Screen, prompt_start: &mut usize) {
+    if s.cursor != s.last_entry_index {
+        return;
+    }
+    let mut line = [0u8; PROMPT_MAX_LENGTH];
+    let len = (s.cursor - *prompt_start).min(PROMPT_MAX_LENGTH);
+    for (place, cell) in line.iter_mut().zip(s.buffer[*prompt_start..].iter().take(len)) {
+        *place = cell.ch();
+    }
+    let line = &line[..len];
+    let Some((word, candidates)) = complete::split_line(line) else {
+        return;
+    };
+
+    match complete::complete_word(word, candidates) {
+        Completion::NoMatch => {}
+        Completion::Insert { rest, complete } => {
+            s.write_str(rest);
+            if complete {
+                s.write(b' ');
+            }
+        }
+        Completion::Ambiguous => {
+            s.write(b'\n');
+            complete::matching(word, candidates, &mut |candidate| {
+                s.write_str(candidate);
+                s.write_str("  ");
+            });
+            s.write(b'\n');
+            write_prompt(s);
+            *prompt_start = s.cursor;
+            s.write_bytes(line);
+        }
+    }
+}
+
+/// Returns the next key to process. Replayed keys take precedence over the keyboard, and keys read
+/// from the keyboard are appended to the active recording.
+fn next_key(s: &mut Screen) -> Option<Key> {
+    {
+        let mut player = PLAYER.lock();
+        if let Some(p) = player.as_mut() {
+            let polled = p.poll(time::ticks());
+            if p.is_finished() {
+                *player = None;
+            }
+            return match polled {
+                Some(Ok(key)) => Some(key),
+                Some(Err(e)) => {
+                    *player = None;
+                    CmdError::from(e).render(s);
+                    None
+                }
+                None => None,
+            };
+        }
+    }
+
+    let key = ps2::read_if_ready()?;
+
+    let mut recorder = RECORDER.lock();
+    if let Some(r) = recorder.as_mut() {
+        if let Err(e) = r.record(key, time::ticks()) {
+            *recorder = None;
+            s.write_str("\nrecording stopped: ");
+            CmdError::from(e).render(s);
+        }
+    }
+
+    Some(key)
+}
+
+fn flush(s: &mut Screen) {
+    let mut b: Buffer = s.render();
+    indicator::draw_scrollback(s.rows_scrolled, &mut b);
+    {
+        let status_bar = STATUS_BAR.lock();
+        let indicator = MOD_INDICATOR.lock();
+        status_bar.draw(0, 1, &indicator, &mut b);
+        // The status bar shows the indicator itself, and the scrollback banner takes the top row.
+        if !status_bar.enabled && s.rows_scrolled == 0 {
+            indicator.draw(&mut b);
+        }
+    }
+    mouse::POINTER.lock().draw(&mut b);
+    indicator::draw_pending_lines(s.pending_lines, &mut b);
+    // The scrollback banner takes the top row whenever the marker can show.
+    indicator::draw_discarded(s.lines_discarded, usize::from(s.rows_scrolled > 0), &mut b);
+    b.flush();
+}
+
+/// Maximum number of ticks between two Escape presses for them to abort the running command.
+const ABORT_WINDOW_TICKS: u64 = TICKS_PER_SECOND / 2;
+
+/// Recognizes the Escape double-press used to cancel long-running commands.
+struct AbortDetector {
+    last_escape: Option<u64>,
+}
+
+impl AbortDetector {
+    const fn new() -> Self {
+        AbortDetector { last_escape: None }
+    }
+
+    /// Registers `key` as pressed at tick `now` and returns `true` if it completes a double-press.
+    fn on_key(&mut self, key: Key, now: u64) -> bool {
+        if key != Key::Escape {
+            return false;
+        }
+        match self.last_escape {
+            Some(last) if now.wrapping_sub(last) <= ABORT_WINDOW_TICKS => {
+                self.last_escape = None;
+                true
+            }
+            _ => {
+                self.last_escape = Some(now);
+                false
+            }
+        }
+    }
+}
+
+static ABORT_DETECTOR: Mutex<AbortDetector> = Mutex::new(AbortDetector::new());
+
+/// Drains pending keyboard input and returns `true` if Escape was pressed twice within half a second.
+///
+/// Long-running commands are expected to call this inside their loops and stop when it fires. Keys
+/// other than Escape are discarded so that they do not leak into the next prompt.
+pub fn should_abort() -> bool {
+    let mut detector = ABORT_DETECTOR.lock();
+    while let Some(key) = read_if_ready() {
+        if detector.on_key(key, time::ticks()) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Number of empty lines entered between two hints.
+const HINT_INTERVAL: usize = 5;
+
+static HINTS_TABLE: &[&str] = &[
+    "hint: type 'help' for commands",
+    "hint: press Escape twice to abort a running command",
+    "hint: type 'set' to display the settings",
+    "hint: type 'dmesg' to display the kernel log",
+];
+
+/// Rotation of the hints displayed on empty lines.
+struct Hints {
+    enabled: bool,
+    empty_lines: usize,
+    next: usize,
+}
+
+impl Hints {
+    const fn new() -> Self {
+        Hints {
+            enabled: true,
+            empty_lines: 0,
+            next: 0,
+        }
+    }
+
+    /// Called for every empty line, returns the hint to display, if any.
+    fn on_empty_line(&mut self) -> Option<&'static str> {
+        if !self.enabled {
+            return None;
+        }
+        self.empty_lines += 1;
+        if !self.empty_lines.is_multiple_of(HINT_INTERVAL) {
+            return None;
+        }
+        let hint = HINTS_TABLE[self.next];
+        self.next = (self.next + 1) % HINTS_TABLE.len();
+        Some(hint)
+    }
+}
+
+static HINTS: Mutex<Hints> = Mutex::new(Hints::new());
+
+fn prompt_execute(prompt: &[u8], s: &mut Screen) {
+    let prompt = &prompt[prompt.iter().position(|&c| c != b' ').unwrap_or(prompt.len())..];
+    if prompt.first().is_none_or(|&c| c == 0) {
+        if let Some(hint) = HINTS.lock().on_empty_line() {
+            s.write_str(hint);
+            s.write_str("\n");
+        }
+        return;
+    }
+
+    let cmd_end = match prompt.iter().position(|&c| c == b' ' || c == 0) {
+        Some(pos) => pos,
+        None => prompt.len(),
+    };
+    // TODO: add a way to get the total prompt length from the prompt (`prompt.len()` does not work since the prompt
+    // is padded with trailing zeros).
+    let prompt_len = match prompt.iter().position(|&c| c == 0) {
+        Some(pos) => pos,
+        None => prompt.len(),
+    };
+
+    let cmd = &prompt[..cmd_end];
+
+    if let Some(command) = registry::find(cmd) {
+        let args = if cmd_end < prompt_len { &prompt[cmd_end + 1..] } else { &[] };
+        let status = command.run(args, s);
+        print::show_pending(s);
+        if let Err(e) = status {
+            e.render(s);
+        }
+        *LAST_STATUS.lock() = status;
+        return;
+    }
+    s.write_str("'");
+    for byte in &cmd[..cmd_end] {
+        s.write(*byte);
+    }
+    s.write_str("': command not found\n");
+    *LAST_STATUS.lock() = Err(CmdError::NotFound);
+}
+
+#[allow(unused)]
+fn help_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    s.write_str("\nAvailable commands:\n\n");
+    for command in registry::commands() {
+        s.write_str("    ");
+        s.write_str(command.usage);
+        for _ in command.usage.len()..21 {
+            s.write(b' ');
+        }
+        s.write_str(command.help);
+        if command.is_dangerous() {
+            s.write_str(" \x1b[91m(!)\x1b[0m");
+        }
+        s.write_str("\n");
+    }
+    s.write_str("\n");
+    Ok(())
+}
+
+#[allow(unused)]
+fn selftest_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    match registry::check(registry::commands()) {
+        Ok(()) => s.write_str("[ OK ] registry\n"),
+        Err(reason) => {
+            s.write_color_str("[FAIL]", Color::Error as u8);
+            s.write_str(" registry: ");
+            s.write_str(reason);
+            s.write_str("\n");
+            return Err(CmdError::CheckFailed("registry"));
+        }
+    }
+    // A healthy boot never drops anything. Bells are dropped by design.
+    if let Some((counter, _)) = diag::nonzero().find(|(counter, _)| !counter.is_informational()) {
+        s.write_color_str("[FAIL]", Color::Error as u8);
+        s.write_str(" diagnostics: ");
+        s.write_str(counter.name());
+        s.write_str(" is not zero, see diag\n");
+        return Err(CmdError::CheckFailed("diagnostics"));
+    }
+    s.write_str("[ OK ] diagnostics\n");
+    s.write_str("profile: ");
+    s.write_str(config::NAME);
+    s.write_str(" (run the unit tests with and without --features minimal)\n");
+    Ok(())
+}
+
+fn print_stack_slice(addr: usize, s: &mut Screen) -> Result<(), CmdError> {
+    let bytes = unsafe { core::slice::from_raw_parts(addr as *const u8, 1024) };
+    let mut rows = HexRows::new(addr, bytes, HexRowOptions::default());
+    let mut row_buf = [0u8; ROW_MAX_LENGTH];
+
+    while let Some(row) = rows.next_row(&mut row_buf) {
+        if should_abort() {
+            return Err(CmdError::Aborted);
+        }
+        s.write_line(row);
+        flush(s);
+    }
+
+    s.write_str("\n1024 bytes from ");
+    s.write_hex_prefixed(addr as u32, 8);
+    s.write_str(" displayed by rows of 16. Zeroed out rows collapsed into '*'.\n");
+    Ok(())
+}
+
+fn prints_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let sp: usize;
+    #[cfg(not(test))]
+    unsafe {
+        asm!(
+            "mov {0}, esp",
+            out(reg) sp,
+        )
+    }
+    #[cfg(test)]
+    unsafe {
+        asm!(
+            "mov {0}, rsp",
+            out(reg) sp,
+        )
+    }
+
+    if args.is_empty() || args.iter().all(|&c| c == b' ' || c == 0) {
+        crate::println!("ESP: {:#010x} STACK_TOP: {:#010x}", sp as u32, layout::stack().end);
+        Ok(())
+    } else {
+        let addr = hextou(args).ok_or(CmdError::BadArgs("no valid hex found in input"))?;
+        if addr.checked_add(1024).is_none() {
+            return Err(CmdError::InvalidAddress(addr as u32));
+        }
+        print_stack_slice(addr, s)
+    }
+}
+
+/// Number of lines written by `bench scroll`.
+const BENCH_SCROLL_LINES: usize = 1000;
+
+fn bench_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    if trim_args(args) != b"scroll" {
+        return Err(CmdError::BadArgs("expected scroll"));
+    }
+
+    let start = time::ticks();
+    for _ in 0..BENCH_SCROLL_LINES {
+        s.write_str("bench\n");
+    }
+    flush(s);
+    let elapsed = time::ticks() - start;
+
+    s.write_str("bench scroll: ");
+    s.write_dec(BENCH_SCROLL_LINES as u32);
+    s.write_str(" lines in ");
+    s.write_dec_u64(elapsed);
+    s.write_str(" ticks\n");
+    Ok(())
+}
+
+#[allow(unused)]
+fn bootlog_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let log = *init::BOOT_LOG.lock();
+    init::report(&log, s);
+    Ok(())
+}
+
+/// Returns `args` up to the zero padding, with leading and trailing spaces removed.
+fn trim_args(args: &[u8]) -> &[u8] {
+    let len = args.iter().position(|&c| c == 0).unwrap_or(args.len());
+    let args = &args[..len];
+    let start = args.iter().position(|&c| c != b' ').unwrap_or(args.len());
+    let end = args.iter().rposition(|&c| c != b' ').map_or(start, |pos| pos + 1);
+    &args[start..end]
+}
+
+fn bell_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let args = trim_args(args);
+    if args.is_empty() {
+        s.write_str("bell: ");
+        s.write_str(bell::BELL.lock().mode.name());
+        s.write_str("\n");
+        return Ok(());
+    }
+    bell::BELL.lock().mode = BellMode::from_name(args).ok_or(CmdError::BadArgs("expected speaker, visual or off"))?;
+    Ok(())
+}
+
+#[allow(unused)]
+fn banner_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    splash(s);
+    flush(s);
+    Ok(())
+}
+
+/// Longest beep accepted by the `beep` command, in milliseconds.
+const BEEP_MAX_MS: u32 = 5000;
+
+fn beep_cmd(args: &[u8], _s: &mut Screen) -> Result<(), CmdError> {
+    const BAD_BEEP: CmdError = CmdError::BadArgs("expected [freq] [ms] as numbers");
+
+    let mut args = split_args(args).map(conv::parse_literal);
+    let freq = args.next().map_or(Some(bell::BELL_FREQUENCY_HZ), |f| f).ok_or(BAD_BEEP)?;
+    let ms = args.next().map_or(Some(bell::BELL_DURATION_MS), |ms| ms).ok_or(BAD_BEEP)?;
+    if freq == 0 {
+        return Err(BAD_BEEP);
+    }
+    if ms > BEEP_MAX_MS {
+        return Err(CmdError::OutOfRange {
+            what: "duration",
+            max: BEEP_MAX_MS,
+        });
+    }
+    speaker::beep(freq, ms);
+    Ok(())
+}
+
+#[allow(unused)]
+fn sysinfo_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let rung = bell::BELL.lock().rung;
+    s.write_str("profile:          ");
+    s.write_str(config::NAME);
+    s.write_str("\nbells rung:       ");
+    s.write_dec(rung);
+    s.write_str("\nbells suppressed: ");
+    s.write_dec(diag::get(diag::Counter::SuppressedBells));
+    s.write_str("\nlines written:    ");
+    s.write_dec_u64(s.lines_written);
+    s.write_str("\nlines discarded:  ");
+    s.write_dec_u64(s.lines_discarded);
+    s.write_str("\ncells flushed:    ");
+    s.write_dec(vga::cells_written());
+    s.write_str("\n");
+    Ok(())
+}
+
+#[allow(unused)]
+fn status_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    match *LAST_STATUS.lock() {
+        Ok(()) => s.write_str("last command succeeded\n"),
+        Err(e) => e.render(s),
+    }
+    Ok(())
+}
+
+fn modind_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let enabled = match trim_args(args) {
+        b"on" => true,
+        b"off" => false,
+        _ => return Err(CmdError::BadArgs("expected on or off")),
+    };
+    MOD_INDICATOR.lock().enabled = enabled;
+    flush(s);
+    Ok(())
+}
+
+fn statusbar_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let enabled = match trim_args(args) {
+        b"on" => true,
+        b"off" => false,
+        _ => return Err(CmdError::BadArgs("expected on or off")),
+    };
+    STATUS_BAR.lock().enabled = enabled;
+    // The bottom row belongs to the status bar while it is shown.
+    s.view_height = vga::view_height() - enabled as usize;
+    flush(s);
+    Ok(())
+}
+
+#[allow(unused)]
+fn secret_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    s.write_str("secret: ");
+    s.set_echo(false);
+    flush(s);
+    loop {
+        match ps2::read_if_ready() {
+            Some(Key::Enter) => break,
+            Some(key) => {
+                s.handle_key(key);
+                flush(s);
+            }
+            None => core::hint::spin_loop(),
+        }
+    }
+    s.set_echo(true);
+
+    let mut secret = [0u8; HIDDEN_INPUT_SIZE];
+    let len = s.take_captured(&mut secret);
+    s.write_str("\nlength: ");
+    s.write_dec(len as u32);
+    s.write_str("\n");
+    Ok(())
+}
+
+/// Width of the name column of the `kbdstat` table.
+const KBDSTAT_NAME_WIDTH: usize = 20;
+
+fn kbdstat_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    match trim_args(args) {
+        b"" => {
+            for (name, value) in ps2::input_stats() {
+                s.write_str(name);
+                for _ in name.len()..KBDSTAT_NAME_WIDTH {
+                    s.write(b' ');
+                }
+                s.write_dec_padded(value as u64, 10);
+                s.write_str("\n");
+            }
+        }
+        b"reset" => ps2::reset_input_stats(),
+        _ => return Err(CmdError::BadArgs("expected reset")),
+    }
+    Ok(())
+}
+
+fn palette_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    const BAD_COLOR: CmdError = CmdError::BadArgs("expected a color name or 0..15, run color for the list");
+
+    let mut args = split_args(args);
+    match (args.next(), args.next()) {
+        (None, _) => {
+            for hue in Hue::ALL {
+                let (r, g, b) = vga::palette_entry(hue as u8).map_err(|_| BAD_COLOR)?;
+                s.write_str(hue.name());
+                for _ in hue.name().len()..PALETTE_NAME_WIDTH {
+                    s.write(b' ');
+                }
+                for component in [r, g, b] {
+                    s.write_hex_byte(component);
+                }
+                s.write_str("\n");
+            }
+        }
+        (Some(name), Some(rgb)) => {
+            let hue = Hue::from_name(name).ok_or(BAD_COLOR)?;
+            let rgb = parse_rgb(rgb).ok_or(CmdError::BadArgs("expected the color as rrggbb in hex"))?;
+            let [_, r, g, b] = rgb.to_be_bytes();
+            vga::set_palette_entry(hue as u8, r, g, b).map_err(|_| BAD_COLOR)?;
+        }
+        (Some(_), None) => return Err(CmdError::BadArgs("expected a color and rrggbb")),
+    }
+    Ok(())
+}
+
+/// Width of the name column of the `palette` listing.
+const PALETTE_NAME_WIDTH: usize = 14;
+
+/// Parses exactly six hexadecimal digits, optionally preceded by `#`.
+fn parse_rgb(bytes: &[u8]) -> Option<u32> {
+    let digits = bytes.strip_prefix(b"#").unwrap_or(bytes);
+    if digits.len() != 6 {
+        return None;
+    }
+    conv::htou(digits)
+}
+
+#[allow(unused)]
+fn ps2stats_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    for counter in diag::Counter::PS2_PROTOCOL {
+        s.write_str(counter.name());
+        s.write_str(": ");
+        s.write_dec(diag::get(counter));
+        s.write_str("\n");
+    }
+    Ok(())
+}
+
+#[allow(unused)]
+fn showkeys_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    MOD_INDICATOR.lock().suppressed = true;
+    s.write_str("press Escape to stop\n");
+    flush(s);
+    loop {
+        let Some((code, key)) = ps2::read_raw_if_ready() else {
+            core::hint::spin_loop();
+            continue;
+        };
+        write_raw_key(code, key, ps2::sequence_pending(), s);
+        flush(s);
+        if key == Some(Key::Escape) {
+            MOD_INDICATOR.lock().suppressed = false;
+            return Ok(());
+        }
+    }
+}
+
+/// Shows `code` for `showkeys`, followed by the key it completes. The bytes of a multi-byte
+/// sequence, `pending` until its last one, share a line.
+fn write_raw_key(code: u8, key: Option<Key>, pending: bool, s: &mut Screen) {
+    s.write_hex_byte(code);
+    if pending {
+        s.write(b' ');
+        return;
+    }
+    if let Some(key) = key {
+        s.write_str("  ");
+        match key.name() {
+            Some(name) => s.write_str(name),
+            None => {
+                s.write(b'\'');
+                s.write(key as u8);
+                s.write(b'\'');
+            }
+        }
+    }
+    s.write(b'\n');
+}
+
+fn mode_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let mode = TextMode::from_name(trim_args(args)).ok_or(CmdError::BadArgs("expected 25 or 50"))?;
+    vga::mode::set(mode, &s.render());
+    s.view_height = mode.rows() - STATUS_BAR.lock().enabled as usize;
+    s.clear();
+    Ok(())
+}
+
+fn mouse_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let toggle = match trim_args(args) {
+        b"on" => mouse::enable,
+        b"off" => mouse::disable,
+        _ => return Err(CmdError::BadArgs("expected on or off")),
+    };
+    toggle().map_err(|e| CmdError::DeviceTimeout {
+        device: "mouse",
+        reason: e.message(),
+    })?;
+    flush(s);
+    Ok(())
+}
+
+fn blink_cmd(args: &[u8], _s: &mut Screen) -> Result<(), CmdError> {
+    match trim_args(args) {
+        b"on" => vga::set_blink(true),
+        b"off" => vga::set_blink(false),
+        _ => return Err(CmdError::BadArgs("expected on or off")),
+    }
+    Ok(())
+}
+
+#[allow(unused)]
+fn clear_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    s.clear();
+    Ok(())
+}
+
+fn color_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    const BAD_COLOR: CmdError = CmdError::BadArgs("expected a color name or 0..15, run color for the list");
+
+    let mut args = split_args(args);
+    match (args.next(), args.next()) {
+        (None, _) => {
+            for (i, hue) in Hue::ALL.into_iter().enumerate() {
+                // Black text would not show on the black background.
+                let preview = if hue == Hue::Black { hue.on(Hue::LightGray) } else { hue.on(Hue::Black) };
+                s.write_color_str(hue.name(), preview);
+                s.write_str(if i % 8 == 7 { "\n" } else { " " });
+            }
+        }
+        (Some(b"reset"), None) => s.set_color(Color::Default as u8),
+        (Some(foreground), background) => {
+            let foreground = Hue::from_name(foreground).ok_or(BAD_COLOR)? as u8;
+            let background = match background {
+                Some(name) => (Hue::from_name(name).ok_or(BAD_COLOR)? as u8) << 4,
+                None => s.color() & 0xF0,
+            };
+            s.set_color(background | foreground);
+        }
+    }
+    Ok(())
+}
+
+fn cursor_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let mut args = split_args(args);
+    match (args.next(), args.next()) {
+        (Some(b"on"), None) => s.set_cursor_visible(true),
+        (Some(b"off"), None) => s.set_cursor_visible(false),
+        (Some(b"style"), Some(name)) => {
+            let style = CursorStyle::from_name(name).ok_or(CmdError::BadArgs("expected underline, block, halfblock or hidden"))?;
+            s.set_cursor_style(style);
+        }
+        _ => return Err(CmdError::BadArgs("expected on, off or style <name>")),
+    }
+    Ok(())
+}
+
+fn conv_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let value = conv::parse_literal(trim_args(args)).ok_or(CmdError::BadArgs("expected a 0x.., 0b.., 'c' or decimal literal fitting in 32 bits"))?;
+
+    s.write_str("unsigned: ");
+    s.write_dec(value);
+    s.write_str("\nsigned:   ");
+    s.write_i32(value as i32);
+    s.write_str("\nhex:      ");
+    s.write_hex_prefixed(value, 8);
+    s.write_str("\nbinary:   0b");
+    s.write_bin(value);
+    s.write_str("\noctal:    0o");
+    s.write_base(value as u64, 8);
+    s.write_str("\n");
+    if (0x20..=0x7E).contains(&value) {
+        s.write_str("ascii:    '");
+        s.write(value as u8);
+        s.write_str("'\n");
+    }
+    Ok(())
+}
+
+#[allow(unused)]
+fn symbols_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    for (name, range) in layout::regions() {
+        layout::write_region(name, &range, s);
+    }
+    Ok(())
+}
+
+/// Splits `args` into words separated by spaces.
+fn split_args(args: &[u8]) -> impl Iterator<Item = &[u8]> {
+    trim_args(args).split(|&c| c == b' ').filter(|word| !word.is_empty())
+}
+
+fn record_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let mut args = split_args(args);
+    let (Some(b"input"), Some(file), None) = (args.next(), args.next(), args.next()) else {
+        return Err(CmdError::BadArgs("expected input <file>"));
+    };
+
+    let mut recorder = RECORDER.lock();
+    if recorder.is_some() {
+        return Err(CmdError::BadArgs("already recording, use stop first"));
+    }
+    *recorder = Some(Recorder::start(file, time::ticks())?);
+    s.write_str("recording, type 'stop' to end\n");
+    Ok(())
+}
+
+#[allow(unused)]
+fn stop_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let recorder = RECORDER.lock().take().ok_or(CmdError::NotActive("recording"))?;
+    recorder.stop();
+    Ok(())
+}
+
+#[allow(unused)]
+fn replay_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let mut args = split_args(args);
+    let file = args.next().ok_or(CmdError::BadArgs("expected <file> [speed]"))?;
+    let speed = match args.next() {
+        Some(speed) => conv::parse_literal(speed)
+            .filter(|&n| n > 0)
+            .ok_or(CmdError::BadArgs("speed must be a positive number"))?,
+        None => 1,
+    };
+    if RECORDER.lock().is_some() {
+        return Err(CmdError::BadArgs("cannot replay while recording"));
+    }
+
+    let fs = RAMFS.lock();
+    let recording = fs.find(file).ok_or(CmdError::NotFound)?;
+    *PLAYER.lock() = Some(Player::new(recording.data(), speed as u64, time::ticks())?);
+    Ok(())
+}
+
+#[allow(unused)]
+fn files_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    for file in RAMFS.lock().files() {
+        s.write_bytes(file.name());
+        for _ in file.name().len()..ramfs::MAX_NAME_LENGTH + 2 {
+            s.write(b' ');
+        }
+        s.write_dec(file.data().len() as u32);
+        s.write_str(" bytes\n");
+    }
+    Ok(())
+}
+
+#[allow(unused)]
+fn dmesg_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    for entry in log::LOG.lock().entries() {
+        log::write_entry(entry, s);
+    }
+    Ok(())
+}
+
+fn dumpscreen_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let mut args = split_args(args);
+    let (Some(name), None) = (args.next(), args.next()) else {
+        return Err(CmdError::BadArgs("expected <file>"));
+    };
+
+    let mut fs = RAMFS.lock();
+    let file = fs.create(name)?;
+    for line in s.lines() {
+        for &entry in line {
+            file.append(&[entry.ch()])?;
+        }
+        file.append(b"\n")?;
+    }
+    Ok(())
+}
+
+fn random_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let mut args = split_args(args).map(conv::parse_literal);
+    let value = match (args.next(), args.next()) {
+        (None, _) => rand::RNG.lock().next_u32(),
+        (Some(Some(lo)), Some(Some(hi))) if lo < hi => rand::RNG.lock().range(lo, hi),
+        _ => return Err(CmdError::BadArgs("expected <lo> <hi> with lo < hi")),
+    };
+    s.write_dec(value);
+    s.write_str("\n");
+    Ok(())
+}
+
+#[allow(unused)]
+fn srand_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let seed = conv::parse_literal(trim_args(args)).ok_or(CmdError::BadArgs("expected a 0x.., 0b.., 'c' or decimal seed"))?;
+    *rand::RNG.lock() = Pcg32::from_seed(seed as u64);
+    Ok(())
+}
+
+fn sort_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let mut options = SortOptions::default();
+    let mut name = None;
+    for arg in split_args(args) {
+        match arg {
+            [b'-', flags @ ..] if !flags.is_empty() => {
+                for flag in flags {
+                    match flag {
+                        b'r' => options.reverse = true,
+                        b'u' => options.unique = true,
+                        _ => return Err(CmdError::BadArgs("expected [-ru] <file>")),
+                    }
+                }
+            }
+            _ if name.is_none() => name = Some(arg),
+            _ => return Err(CmdError::BadArgs("expected [-ru] <file>")),
+        }
+    }
+    let Some(name) = name else {
+        return Err(CmdError::BadArgs("expected [-ru] <file>"));
+    };
+
+    let fs = RAMFS.lock();
+    let text = fs.find(name).ok_or(CmdError::NotFound)?.data();
+    let mut table = LineTable::new(text).ok_or(CmdError::OutOfRange {
+        what: "line count",
+        max: sort::MAX_LINES as u32,
+    })?;
+    table.sort(text, options);
+    for line in table.lines(text) {
+        s.write_bytes(line);
+        s.write(b'\n');
+    }
+    Ok(())
+}
+
+fn saver_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    if trim_args(args) != b"demo" {
+        return Err(CmdError::BadArgs("expected demo"));
+    }
+    saver::run(s).map_err(|reason| CmdError::DeviceUnavailable { device: "timer", reason })?;
+    flush(s);
+    Ok(())
+}
+
+fn serial_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    if trim_args(args) != b"dump" {
+        return Err(CmdError::BadArgs("expected dump"));
+    }
+    if !serial::is_present() {
+        return Err(CmdError::DeviceUnavailable {
+            device: "serial",
+            reason: "not present",
+        });
+    }
+    for line in s.lines() {
+        for cell in line {
+            serial::write_byte(cell.ch());
+        }
+        serial::write_byte(b'\n');
+    }
+    Ok(())
+}
+
+fn demo_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    if trim_args(args) != b"highlight" {
+        return Err(CmdError::BadArgs("expected highlight"));
+    }
+    const TEXT: &str = "this region is shown in reverse video and runs on past the end of the row";
+    s.write_str("highlight: ");
+    let start = s.cursor;
+    s.write_str(TEXT);
+    s.invert_region(start, TEXT.len());
+    s.write_str("\n");
+    Ok(())
+}
+
+fn diag_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    match trim_args(args) {
+        b"" => {
+            let mut any = false;
+            for (counter, value) in diag::nonzero() {
+                s.write_str(counter.name());
+                s.write_str(": ");
+                s.write_dec(value);
+                s.write_str(" (");
+                s.write_str(counter.explanation());
+                s.write_str(")\n");
+                any = true;
+            }
+            if !any {
+                s.write_str("no events dropped\n");
+            }
+            Ok(())
+        }
+        b"reset" => {
+            diag::reset();
+            Ok(())
+        }
+        _ => Err(CmdError::BadArgs("expected reset")),
+    }
+}
+
+fn setkeymap_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let name = trim_args(args);
+    if name.is_empty() {
+        s.write_str("keymap: ");
+        s.write_str(ps2::layout().name());
+        s.write_str("\navailable:");
+        for layout in Layout::ALL {
+            s.write_str(" ");
+            s.write_str(layout.name());
+        }
+        s.write_str("\n");
+        return Ok(());
+    }
+    let layout = Layout::from_name(name).ok_or(CmdError::BadArgs("expected qwerty, azerty or qwertz"))?;
+    keymap::select(layout);
+    Ok(())
+}
+
+fn kbrate_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let mut args = split_args(args).map(conv::parse_literal);
+    let (Some(Some(rate)), Some(Some(delay))) = (args.next(), args.next()) else {
+        return Err(CmdError::BadArgs("expected <rate> <delay>"));
+    };
+    if rate > ps2::MAX_TYPEMATIC_RATE as u32 {
+        return Err(CmdError::OutOfRange {
+            what: "rate",
+            max: ps2::MAX_TYPEMATIC_RATE as u32,
+        });
+    }
+    if delay > ps2::MAX_TYPEMATIC_DELAY as u32 {
+        return Err(CmdError::OutOfRange {
+            what: "delay",
+            max: ps2::MAX_TYPEMATIC_DELAY as u32,
+        });
+    }
+    let (rate, delay) = (rate as u8, delay as u8);
+    ps2::set_typematic(rate, delay).map_err(|e| CmdError::DeviceTimeout {
+        device: "keyboard",
+        reason: e.message(),
+    })?;
+
+    s.write_str("keys repeat every ");
+    s.write_dec(ps2::typematic_period_ms(rate));
+    s.write_str(" ms after ");
+    s.write_dec(ps2::typematic_delay_ms(delay));
+    s.write_str(" ms\n");
+    Ok(())
+}
+
+#[allow(unused)]
+fn settings_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    if trim_args(args) != b"reset" {
+        return Err(CmdError::BadArgs("expected reset"));
+    }
+    settings::reset();
+    Ok(())
+}
+
+fn set_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let mut args = split_args(args);
+    match (args.next(), args.next(), args.next()) {
+        (None, _, _) => {
+            s.write_str("AUTOSCROLL=");
+            s.write_str(s.autoscroll.name());
+            s.write_str("\nHINTS=");
+            s.write_str(if HINTS.lock().enabled { "1" } else { "0" });
+            s.write_str("\nSAVER=");
+            s.write_str(SAVER.lock().name());
+            s.write_str("\n");
+            Ok(())
+        }
+        (Some(b"SAVER"), Some(value), None) => {
+            *SAVER.lock() = SaverMode::from_name(value).ok_or(CmdError::BadArgs("expected matrix or off"))?;
+            Ok(())
+        }
+        (Some(b"HINTS"), Some(value), None) => {
+            HINTS.lock().enabled = match value {
+                b"1" => true,
+                b"0" => false,
+                _ => return Err(CmdError::BadArgs("expected 0 or 1")),
+            };
+            Ok(())
+        }
+        (Some(b"AUTOSCROLL"), Some(value), None) => {
+            s.autoscroll = AutoScroll::from_name(value).ok_or(CmdError::BadArgs("expected always, input-only or never"))?;
+            Ok(())
+        }
+        (Some(b"AUTOSCROLL" | b"HINTS" | b"SAVER"), _, _) => Err(CmdError::BadArgs("expected <name> <value>")),
+        _ => Err(CmdError::NotFound),
+    }
+}
+
+#[allow(unused)]
+fn echo_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    let args_len = match args.iter().position(|&c| c == 0) {
+        Some(pos) => pos,
+        None => args.len(),
+    };
+
+    for byte in &args[..args_len] {
+        s.write(*byte);
+    }
+    s.write_str("\n");
+    Ok(())
+}
+
+fn reboot_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    ps2::reboot();
+    halt_cmd(args, s)
+}
+
+#[allow(unused)]
+fn halt_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    unsafe { asm!("hlt") }
+    Ok(())
+}
+
+#[allow(unused)]
+fn panic_cmd(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+    panic!()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(cmd: fn(&[u8], &mut Screen) -> Result<(), CmdError>, args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+        let mut padded = [0u8; PROMPT_MAX_LENGTH];
+        padded[..args.len()].copy_from_slice(args);
+        cmd(&padded, s)
+    }
+
+    #[test]
+    fn dumpscreen_trims_lines() {
+        let mut s = Screen::default();
+        s.write_line(b"size    ");
+        s.write_str("sh>   \n");
+        assert_eq!(run(dumpscreen_cmd, b"dump-213", &mut s), Ok(()));
+
+        let mut fs = RAMFS.lock();
+        assert_eq!(fs.find(b"dump-213").unwrap().data(), b"size\nsh>\n");
+        fs.remove(b"dump-213");
+    }
+
+    #[test]
+    fn diag_lists_nonzero_counters() {
+        diag::bump(diag::Counter::Ps2Errors);
+        let mut s = Screen::with_capture();
+        assert_eq!(run(diag_cmd, b"", &mut s), Ok(()));
+        assert!(s.captured().contains("ps2-errors: "));
+        assert!(s.captured().contains(" (key detection errors or overruns reported by the keyboard)\n"));
+        assert_eq!(run(diag_cmd, b"clear", &mut s), Err(CmdError::BadArgs("expected reset")));
+
+        let mut s = Screen::with_capture();
+        diag::write_compact(&mut s);
+        assert!(s.captured().contains("diag: ps2-errors="));
+
+        // The only test that resets, so that the others can rely on counters not decreasing.
+        for _ in 0..10 {
+            diag::bump(diag::Counter::LogOverwrites);
+        }
+        assert_eq!(run(diag_cmd, b"reset", &mut s), Ok(()));
+        assert!(diag::get(diag::Counter::LogOverwrites) < 10);
+    }
+
+    #[test]
+    fn setkeymap_switches_layouts() {
+        let _keyboard = ps2::reset_for_test();
+        let mut s = Screen::with_capture();
+        assert_eq!(run(setkeymap_cmd, b"azerty", &mut s), Ok(()));
+        assert_eq!(ps2::layout(), Layout::Azerty);
+        assert_eq!(run(setkeymap_cmd, b"", &mut s), Ok(()));
+        assert_eq!(s.captured(), "keymap: azerty\navailable: qwerty azerty qwertz\n");
+
+        assert_eq!(
+            run(setkeymap_cmd, b"dvorak", &mut s),
+            Err(CmdError::BadArgs("expected qwerty, azerty or qwertz"))
+        );
+        assert_eq!(run(setkeymap_cmd, b"qwerty", &mut s), Ok(()));
+        assert_eq!(ps2::layout(), Layout::Qwerty);
+    }
+
+    #[test]
+    fn prints_shows_the_stack_pointers() {
+        let _print = print::reset_for_test();
+        let mut s = Screen::with_capture();
+        prompt_execute(b"prints", &mut s);
+        let line = s.captured();
+        assert!(line.starts_with("ESP: 0x"), "{line}");
+        assert_eq!(line.len(), "ESP: 0x12345678 STACK_TOP: 0x12345678\n".len());
+        assert_eq!(&line[15..28], " STACK_TOP: 0");
+    }
+
+    #[test]
+    fn kbdstat_prints_a_table() {
+        let mut s = Screen::with_capture();
+        assert_eq!(run(kbdstat_cmd, b"", &mut s), Ok(()));
+        let mut lines = s.captured().lines();
+        for (name, _) in ps2::input_stats() {
+            let line = lines.next().unwrap();
+            assert!(line.starts_with(name));
+            assert_eq!(line.len(), KBDSTAT_NAME_WIDTH + 10);
+        }
+        assert!(lines.next().is_none());
+        assert_eq!(run(kbdstat_cmd, b"clear", &mut s), Err(CmdError::BadArgs("expected reset")));
+    }
+
+    #[test]
+    fn ps2stats_lists_every_protocol_byte() {
+        let mut s = Screen::with_capture();
+        assert_eq!(run(ps2stats_cmd, b"", &mut s), Ok(()));
+        let mut lines = s.captured().lines();
+        for counter in diag::Counter::PS2_PROTOCOL {
+            assert!(lines.next().unwrap().starts_with(counter.name()));
+        }
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn raw_keys_keep_sequences_on_one_line() {
+        let mut s = Screen::with_capture();
+        let mut modifiers = ps2::ModifierState::new();
+        // a pressed and released, then the extended Home.
+        for code in [0x1E, 0x9E, 0xE0, 0x47] {
+            let key = ps2::decode_scancode(code, &mut modifiers);
+            write_raw_key(code, key, modifiers.sequence_pending(), &mut s);
+        }
+        assert_eq!(s.captured(), "1e  'a'\n9e\ne0 47  Home\n");
+    }
+
+    #[test]
+    fn mouse_reports_a_missing_device() {
+        let mut s = Screen::with_capture();
+        assert_eq!(run(mouse_cmd, b"maybe", &mut s), Err(CmdError::BadArgs("expected on or off")));
+
+        crate::io::mock::clear();
+        assert_eq!(
+            run(mouse_cmd, b"on", &mut s),
+            Err(CmdError::DeviceTimeout {
+                device: "mouse",
+                reason: "no response"
+            })
+        );
+        assert_eq!(crate::io::mock::bytes_to(ps2::PS2_DATA_PORT), [0xF4]);
+        assert!(!mouse::POINTER.lock().enabled);
+    }
+
+    #[test]
+    fn kbrate_validates_and_reports() {
+        let _keyboard = ps2::reset_for_test();
+        let mut s = Screen::with_capture();
+        assert_eq!(run(kbrate_cmd, b"32 0", &mut s), Err(CmdError::OutOfRange { what: "rate", max: 31 }));
+        assert_eq!(run(kbrate_cmd, b"0 4", &mut s), Err(CmdError::OutOfRange { what: "delay", max: 3 }));
+        assert_eq!(run(kbrate_cmd, b"fast 0", &mut s), Err(CmdError::BadArgs("expected <rate> <delay>")));
+
+        crate::io::mock::clear();
+        assert_eq!(
+            run(kbrate_cmd, b"0 0", &mut s),
+            Err(CmdError::DeviceTimeout {
+                device: "keyboard",
+                reason: "no response"
+            })
+        );
+        crate::io::mock::set_input(ps2::PS2_STATUS_PORT, ps2::PS2_INPUT_BUFFER_STATUS_BIT);
+        assert_eq!(
+            run(kbrate_cmd, b"0 0", &mut s),
+            Err(CmdError::DeviceTimeout {
+                device: "keyboard",
+                reason: "controller not ready"
+            })
+        );
+        crate::io::mock::clear();
+
+        crate::io::mock::set_input(ps2::PS2_STATUS_PORT, ps2::PS2_OUTPUT_BUFFER_STATUS_BIT);
+        crate::io::mock::set_input(ps2::PS2_DATA_PORT, 0xFA);
+        assert_eq!(run(kbrate_cmd, b"0 1", &mut s), Ok(()));
+        assert_eq!(s.captured(), "keys repeat every 33 ms after 500 ms\n");
+    }
+
+    #[test]
+    fn sysinfo_counts_in_decimal() {
+        let mut s = Screen::with_capture();
+        for _ in 0..12 {
+            s.write_str("line\n");
+        }
+        assert_eq!(run(sysinfo_cmd, b"", &mut s), Ok(()));
+        // The 12 lines, then the 3 lines of the report above the count.
+        let out = s.captured();
+        assert!(out.contains("\nlines written:    15\nlines discarded:  0\n"), "{out}");
+    }
+
+    #[test]
+    fn stop_without_recording() {
+        let mut s = Screen::default();
+        assert_eq!(run(stop_cmd, b"", &mut s), Err(CmdError::NotActive("recording")));
+    }
+
+    #[test]
+    fn sort_file() {
+        RAMFS.lock().create(b"sort-229").unwrap().append(b"b\na\nb\n").unwrap();
+
+        let mut s = Screen::with_capture();
+        assert_eq!(run(sort_cmd, b"sort-229", &mut s), Ok(()));
+        assert_eq!(run(sort_cmd, b"-ru sort-229", &mut s), Ok(()));
+        assert_eq!(s.captured(), "a\nb\nb\nb\na\n");
+
+        assert_eq!(run(sort_cmd, b"missing-229", &mut s), Err(CmdError::NotFound));
+        assert!(run(sort_cmd, b"-u", &mut s).is_err());
+        assert!(run(sort_cmd, b"-x sort-229", &mut s).is_err());
+        RAMFS.lock().remove(b"sort-229");
+    }
+
+    #[test]
+    fn random_and_srand() {
+        let mut s = Screen::with_capture();
+        assert_eq!(run(random_cmd, b"7 8", &mut s), Ok(()));
+        assert_eq!(s.captured(), "7\n");
+        assert!(run(random_cmd, b"8 7", &mut s).is_err());
+        assert!(run(random_cmd, b"1", &mut s).is_err());
+
+        // Both commands share the global generator, so srand is tested here to avoid races.
+        assert_eq!(run(srand_cmd, b"0x2a", &mut s), Ok(()));
+        let first = rand::RNG.lock().next_u32();
+        assert_eq!(run(srand_cmd, b"42", &mut s), Ok(()));
+        assert_eq!(rand::RNG.lock().next_u32(), first);
+        assert_eq!(first, Pcg32::from_seed(42).next_u32());
+        assert!(run(srand_cmd, b"seed", &mut s).is_err());
+    }
+
+    fn type_prompt(s: &mut Screen, prompt_start: &mut usize, keys: &str) {
+        for &c in keys.as_bytes() {
+            if c == b'\t' {
+                complete_prompt(s, prompt_start);
+            } else {
+                s.write(c);
+            }
+        }
+    }
+
+    fn prompt_line(s: &Screen, prompt_start: usize) -> [u8; 32] {
+        let mut line = [0u8; 32];
+        for (place, cell) in line.iter_mut().zip(s.buffer[prompt_start..s.last_entry_index].iter()) {
+            *place = cell.ch();
+        }
+        line
+    }
+
+    #[test]
+    fn tab_completes_command_and_argument() {
+        let mut s = Screen::default();
+        s.write_str("sh> ");
+        let mut prompt_start = s.cursor;
+
+        type_prompt(&mut s, &mut prompt_start, "be\tl\tv\t");
+        assert_eq!(&prompt_line(&s, prompt_start)[..12], b"bell visual ");
+    }
+
+    /// Types the keys produced by `scancodes` at a fresh prompt, until the line is done with.
+    fn type_scancodes(s: &mut Screen, scancodes: &[u8]) {
+        let mut modifiers = ps2::ModifierState::new();
+        let mut source = scancodes.iter();
+        s.write_str("sh> ");
+        let mut prompt_start = s.cursor;
+        while let Some(key) = ps2::decode_from(&mut source, &mut modifiers) {
+            if edit_prompt(key, s, &mut prompt_start) {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn ctrl_alt_backspace_resets_the_screen() {
+        use crate::io::mock;
+        let _keyboard = ps2::reset_for_test();
+        let mut s = Screen::default();
+        s.view_height = 24;
+        s.write_str("sh> ab");
+        let mut prompt_start = 4;
+        // Ctrl and Alt pressed, then Backspace.
+        mock::queue_inputs(ps2::PS2_STATUS_PORT, &[ps2::PS2_OUTPUT_BUFFER_STATUS_BIT; 3]);
+        mock::queue_inputs(ps2::PS2_DATA_PORT, &[0x1D, 0x38, 0x0E]);
+        assert_eq!(process_next_key(&mut s, &mut prompt_start), Some(true));
+        assert_eq!((s.last_entry_index, s.view_height), (0, 24));
+
+        // Once Alt is released, Backspace edits the prompt again.
+        s.write_str("sh> ab");
+        mock::queue_inputs(ps2::PS2_STATUS_PORT, &[ps2::PS2_OUTPUT_BUFFER_STATUS_BIT; 2]);
+        mock::queue_inputs(ps2::PS2_DATA_PORT, &[0xB8, 0x0E]);
+        assert_eq!(process_next_key(&mut s, &mut prompt_start), Some(false));
+        assert_eq!(prompt_line(&s, prompt_start)[..2], *b"a\0");
+        assert_eq!(process_next_key(&mut s, &mut prompt_start), None);
+    }
+
+    #[test]
+    fn log_messages_keep_the_typed_text() {
+        let mut s = Screen::default();
+        s.write_str("sh> ");
+        let mut prompt_start = s.cursor;
+        s.write_str("echo hi");
+        s.set_cursor(prompt_start + 4);
+
+        log::notice("typed text test");
+        let old_start = prompt_start;
+        print_new_log(&mut s, &mut prompt_start);
+
+        assert!(prompt_start > old_start);
+        assert_eq!(&prompt_line(&s, prompt_start)[..8], b"echo hi\0");
+        assert_eq!(s.cursor, prompt_start + 4);
+        let is = |line: &[vga::Cell], text: &[u8]| line.iter().map(|c| c.ch()).eq(text.iter().copied());
+        assert!(s.lines().any(|line| is(line, b"[0] typed text test")));
+        assert!(is(s.lines().last().unwrap(), b"sh> echo hi"));
+    }
+
+    #[test]
+    fn line_typed_into_a_full_buffer_is_executed() {
+        let mut s = Screen::default();
+        while s.last_entry_index < crate::terminal::BUFFER_SIZE - 8 {
+            s.write_str("filler\n");
+        }
+        // "echo hi", then Enter.
+        type_scancodes(
+            &mut s,
+            &[0x12, 0x92, 0x2E, 0xAE, 0x23, 0xA3, 0x18, 0x98, 0x39, 0xB9, 0x23, 0xA3, 0x17, 0x97, 0x1C, 0x9C],
+        );
+
+        assert!(s.cells_discarded > 0);
+        let is = |line: &[vga::Cell], text: &[u8]| line.iter().map(|c| c.ch()).eq(text.iter().copied());
+        let mut lines = s.lines().skip(s.lines().count() - 2);
+        assert!(is(lines.next().unwrap(), b"sh> echo hi"));
+        assert!(is(lines.next().unwrap(), b"hi"));
+    }
+
+    #[test]
+    fn typed_command_is_executed() {
+        let mut s = Screen::with_capture();
+        // "echo hi", then Enter.
+        type_scancodes(
+            &mut s,
+            &[0x12, 0x92, 0x2E, 0xAE, 0x23, 0xA3, 0x18, 0x98, 0x39, 0xB9, 0x23, 0xA3, 0x17, 0x97, 0x1C, 0x9C],
+        );
+        assert_eq!(s.captured(), "sh> echo hi\nhi\n");
+    }
+
+    #[test]
+    fn backspace_mid_word_closes_the_gap() {
+        let mut s = Screen::default();
+        s.write_str("sh> ");
+        let mut prompt_start = s.cursor;
+        type_prompt(&mut s, &mut prompt_start, "prints");
+        for key in [Key::ArrowLeft, Key::ArrowLeft, Key::ArrowLeft, Key::Backspace] {
+            assert!(!edit_prompt(key, &mut s, &mut prompt_start));
+        }
+
+        assert_eq!(&prompt_line(&s, prompt_start)[..6], b"prnts\0");
+        assert_eq!(s.cursor - prompt_start, 2);
+        assert!(s.buffer[prompt_start..s.last_entry_index].iter().all(|cell| cell.ch() != 0));
+    }
+
+    #[test]
+    fn line_kills_stay_in_the_prompt() {
+        let mut s = Screen::with_capture();
+        // "xecho hiyy", Left twice, Ctrl+K, Home, Right, Ctrl+U, Ctrl+U, then Enter.
+        type_scancodes(
+            &mut s,
+            &[
+                0x2D, 0x12, 0x2E, 0x23, 0x18, 0x39, 0x23, 0x17, 0x15, 0x15, 0xE0, 0x4B, 0xE0, 0x4B, 0x1D, 0x25, 0x9D, 0xE0, 0x47, 0xE0, 0x4D, 0x1D, 0x16, 0x16,
+                0x9D, 0x1C,
+            ],
+        );
+        assert!(s.captured().ends_with("hi\n"));
+        let mut lines = s.lines().map(|line| line.iter().map(|cell| cell.ch()));
+        assert!(lines.next().unwrap().eq(*b"sh> echo hi"));
+    }
+
+    #[test]
+    fn ctrl_a_and_ctrl_e_jump_within_the_prompt() {
+        let mut s = Screen::with_capture();
+        // "cho", Ctrl+A, "e", Ctrl+E, " x", then Enter.
+        type_scancodes(
+            &mut s,
+            &[0x2E, 0x23, 0x18, 0x1D, 0x1E, 0x9E, 0x9D, 0x12, 0x92, 0x1D, 0x12, 0x92, 0x9D, 0x39, 0x2D, 0x1C],
+        );
+        assert!(s.captured().ends_with("x\n"));
+        let mut lines = s.lines().map(|line| line.iter().map(|cell| cell.ch()));
+        assert!(lines.next().unwrap().eq(*b"sh> echo x"));
+        assert!(lines.next().unwrap().eq(*b"x"));
+    }
+
+    #[test]
+    fn word_jumps_stop_at_word_boundaries() {
+        let line = b"prints 0x1000 extra";
+        let mut pos = 0;
+        for expected in [6, 13, 19, 19] {
+            pos = next_word_end(line, pos);
+            assert_eq!(pos, expected);
+        }
+        for expected in [14, 7, 0, 0] {
+            pos = previous_word_start(line, pos);
+            assert_eq!(pos, expected);
+        }
+        assert_eq!(next_word_end(line, 6), 13);
+        assert_eq!(previous_word_start(line, 9), 7);
+    }
+
+    #[test]
+    fn ctrl_arrows_stay_in_the_prompt() {
+        let mut s = Screen::default();
+        s.write_str("sh> ");
+        let prompt_start = s.cursor;
+        s.write_str("prints 0x1000 extra");
+
+        jump_word(&mut s, prompt_start, false);
+        assert_eq!(s.cursor, prompt_start + 14);
+        for _ in 0..3 {
+            jump_word(&mut s, prompt_start, false);
+        }
+        assert_eq!(s.cursor, prompt_start);
+        jump_word(&mut s, prompt_start, true);
+        assert_eq!(s.cursor, prompt_start + 6);
+    }
+
+    #[test]
+    fn ctrl_l_keeps_the_typed_prompt() {
+        let mut s = Screen::with_capture();
+        s.write_str("old output\n");
+        // "ech", Ctrl+L, "o hi", then Enter.
+        type_scancodes(
+            &mut s,
+            &[
+                0x12, 0x92, 0x2E, 0xAE, 0x23, 0xA3, 0x1D, 0x26, 0xA6, 0x9D, 0x18, 0x98, 0x39, 0xB9, 0x23, 0xA3, 0x17, 0x97, 0x1C, 0x9C,
+            ],
+        );
+        assert!(s.captured().ends_with("echo hi\nhi\n"));
+        let mut lines = s.lines().map(|line| line.iter().map(|cell| cell.ch()));
+        assert!(lines.next().unwrap().eq(*b"sh> echo hi"));
+        assert!(lines.next().unwrap().eq(*b"hi"));
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn shifted_and_edited_input() {
+        let mut s = Screen::with_capture();
+        // "ECHO" typed with Shift held, Home, Ctrl+C.
+        type_scancodes(
+            &mut s,
+            &[0x2A, 0x12, 0x92, 0x2E, 0xAE, 0x23, 0xA3, 0x18, 0x98, 0xAA, 0xE0, 0x47, 0xE0, 0xC7, 0x1D, 0x2E],
+        );
+        assert_eq!(s.captured(), "sh> ECHO^C\n");
+    }
+
+    #[test]
+    fn ctrl_c_abandons_the_line() {
+        let mut s = Screen::with_capture();
+        s.write_str("sh> ech");
+        s.handle_key(Key::ArrowLeft);
+        s.handle_key(Key::CtrlC);
+        cancel_prompt(&mut s);
+        assert_eq!(s.captured(), "sh> ech^C\n");
+        assert_eq!(s.cursor, s.last_entry_index);
+    }
+
+    #[test]
+    fn tab_lists_ambiguous_matches() {
+        let mut s = Screen::with_capture();
+        s.write_str("sh> ");
+        let mut prompt_start = s.cursor;
+
+        let mut listing = [0u8; 256];
+        let mut len = 0;
+        for command in registry::commands().iter().filter(|c| c.name.starts_with("sy")) {
+            for &b in command.name.as_bytes().iter().chain(b"  ") {
+                listing[len] = b;
+                len += 1;
+            }
+        }
+
+        type_prompt(&mut s, &mut prompt_start, "sy\t");
+        let captured = s.captured().as_bytes();
+        assert_eq!(&captured[..7], b"sh> sy\n");
+        assert_eq!(&captured[7..captured.len() - 7], &listing[..len]);
+        assert_eq!(&captured[captured.len() - 7..], b"\nsh> sy");
+        assert_eq!(&prompt_line(&s, prompt_start)[..3], b"sy\0");
+
+        type_prompt(&mut s, &mut prompt_start, "si\t");
+        assert_eq!(&prompt_line(&s, prompt_start)[..9], b"sysinfo \0");
+    }
+
+    #[test]
+    fn set_autoscroll() {
+        let mut s = Screen::with_capture();
+        assert_eq!(run(set_cmd, b"AUTOSCROLL never", &mut s), Ok(()));
+        assert_eq!(s.autoscroll, AutoScroll::Never);
+        assert!(run(set_cmd, b"AUTOSCROLL sometimes", &mut s).is_err());
+        assert_eq!(run(set_cmd, b"COLOR red", &mut s), Err(CmdError::NotFound));
+
+        assert_eq!(run(set_cmd, b"", &mut s), Ok(()));
+        assert!(s.captured().starts_with("AUTOSCROLL=never\n"));
+
+        assert_eq!(run(set_cmd, b"SAVER matrix", &mut s), Ok(()));
+        assert_eq!(*SAVER.lock(), SaverMode::Matrix);
+        assert!(run(set_cmd, b"SAVER stars", &mut s).is_err());
+        assert_eq!(run(set_cmd, b"SAVER off", &mut s), Ok(()));
+    }
+
+    fn assert_conv(literal: &[u8], expected: &str) {
+        let mut s = Screen::with_capture();
+        assert_eq!(run(conv_cmd, literal, &mut s), Ok(()));
+        assert_eq!(s.captured(), expected);
+    }
+
+    #[test]
+    fn conv_zero() {
+        assert_conv(
+            b"0",
+            "unsigned: 0\n\
+              signed:   0\n\
+              hex:      0x00000000\n\
+              binary:   0b0000_0000_0000_0000_0000_0000_0000_0000\n\
+              octal:    0o0\n",
+        );
+    }
+
+    #[test]
+    fn conv_all_ones() {
+        assert_conv(
+            b"0xFFFFFFFF",
+            "unsigned: 4294967295\n\
+              signed:   -1\n\
+              hex:      0xffffffff\n\
+              binary:   0b1111_1111_1111_1111_1111_1111_1111_1111\n\
+              octal:    0o37777777777\n",
+        );
+    }
+
+    #[test]
+    fn conv_i32_min() {
+        assert_conv(
+            b"0b1000_0000_0000_0000_0000_0000_0000_0000",
+            "unsigned: 2147483648\n\
+              signed:   -2147483648\n\
+              hex:      0x80000000\n\
+              binary:   0b1000_0000_0000_0000_0000_0000_0000_0000\n\
+              octal:    0o20000000000\n",
+        );
+    }
+
+    #[test]
+    fn conv_printable() {
+        assert_conv(
+            b"'A'",
+            "unsigned: 65\n\
+              signed:   65\n\
+              hex:      0x00000041\n\
+              binary:   0b0000_0000_0000_0000_0000_0000_0100_0001\n\
+              octal:    0o101\n\
+              ascii:    'A'\n",
+        );
+    }
+
+    #[test]
+    fn hints_rotate_every_fifth_empty_line() {
+        let mut hints = Hints::new();
+        for round in 0..HINTS_TABLE.len() + 1 {
+            for _ in 0..HINT_INTERVAL - 1 {
+                assert_eq!(hints.on_empty_line(), None);
+            }
+            assert_eq!(hints.on_empty_line(), Some(HINTS_TABLE[round % HINTS_TABLE.len()]));
+        }
+
+        hints.enabled = false;
+        for _ in 0..HINT_INTERVAL * 2 {
+            assert_eq!(hints.on_empty_line(), None);
+        }
+    }
+
+    #[test]
+    fn blank_lines_are_empty() {
+        let mut s = Screen::with_capture();
+        let mut prompt = [0u8; PROMPT_MAX_LENGTH];
+        prompt[..3].copy_from_slice(b"   ");
+        prompt_execute(&prompt, &mut s);
+        prompt_execute(&[0u8; PROMPT_MAX_LENGTH], &mut s);
+
+        assert!(!s.captured().contains("command not found"));
+    }
+
+    #[test]
+    fn leading_spaces_are_ignored() {
+        let mut s = Screen::with_capture();
+        let mut prompt = [0u8; PROMPT_MAX_LENGTH];
+        prompt[..9].copy_from_slice(b"  echo hi");
+        prompt_execute(&prompt, &mut s);
+
+        assert_eq!(s.captured(), "hi\n");
+    }
+
+    #[test]
+    fn echo_prints_args() {
+        let mut s = Screen::with_capture();
+        assert_eq!(run(echo_cmd, b"hello  world", &mut s), Ok(()));
+        assert_eq!(s.captured(), "hello  world\n");
+    }
+
+    #[test]
+    fn help_lists_every_command() {
+        let mut s = Screen::with_capture();
+        assert_eq!(run(help_cmd, b"", &mut s), Ok(()));
+
+        let help = s.captured();
+        assert!(help.starts_with("\nAvailable commands:\n\n"));
+        for command in registry::commands() {
+            assert!(help.lines().any(|line| line.trim_start().starts_with(command.usage)));
+        }
+        assert!(help.contains("    reboot               reboot the kernel (!)\n"));
+    }
+
+    #[test]
+    fn mode_expects_a_height() {
+        let mut s = Screen::default();
+        assert_eq!(run(mode_cmd, b"43", &mut s), Err(CmdError::BadArgs("expected 25 or 50")));
+        assert_eq!(s.view_height, vga::VIEW_HEIGHT);
+    }
+
+    #[test]
+    fn cursor_toggles_the_cursor() {
+        let mut s = Screen::default();
+        assert_eq!(run(cursor_cmd, b"off", &mut s), Ok(()));
+        assert!(!s.shows_cursor());
+        assert_eq!(run(cursor_cmd, b"on", &mut s), Ok(()));
+        assert!(s.shows_cursor());
+        assert_eq!(run(cursor_cmd, b"blink", &mut s), Err(CmdError::BadArgs("expected on, off or style <name>")));
+
+        assert_eq!(run(cursor_cmd, b"style halfblock", &mut s), Ok(()));
+        assert_eq!(s.cursor_style(), CursorStyle::HalfBlock);
+        assert_eq!(
+            run(cursor_cmd, b"style wide", &mut s),
+            Err(CmdError::BadArgs("expected underline, block, halfblock or hidden"))
+        );
+        assert_eq!(s.cursor_style(), CursorStyle::HalfBlock);
+    }
+
+    #[test]
+    fn demo_highlight_inverts_a_wrapped_region() {
+        let mut s = Screen::default();
+        let color = s.color();
+        assert_eq!(run(demo_cmd, b"highlight", &mut s), Ok(()));
+
+        let end = s.buffer.iter().position(|c| c.is_line_break()).unwrap();
+        assert!(end > vga::VIEW_WIDTH);
+        assert_eq!(s.buffer[10].color(), color);
+        assert!(s.buffer[11..end].iter().all(|c| c.color() == color.rotate_left(4)));
+        assert_eq!(run(demo_cmd, b"", &mut s), Err(CmdError::BadArgs("expected highlight")));
+    }
+
+    #[test]
+    fn blink_programs_the_attribute_controller() {
+        use crate::io::mock;
+
+        mock::clear();
+        let mut s = Screen::default();
+        assert_eq!(run(blink_cmd, b"off", &mut s), Ok(()));
+        assert_eq!(mock::bytes_to(0x3C0), [0x30, 0x00]);
+        assert_eq!(run(blink_cmd, b"fast", &mut s), Err(CmdError::BadArgs("expected on or off")));
+        assert_eq!(mock::writes_to(0x3C0), 2);
+    }
+
+    #[test]
+    fn palette_sets_one_entry_from_hex() {
+        use crate::io::mock;
+
+        mock::clear();
+        let mut s = Screen::default();
+        assert_eq!(run(palette_cmd, b"lightblue #00babc", &mut s), Ok(()));
+        assert_eq!(mock::writes(), [(0x3C8, 0x39), (0x3C9, 0x00), (0x3C9, 0x2E), (0x3C9, 0x2F)]);
+
+        let bad_color = Err(CmdError::BadArgs("expected a color name or 0..15, run color for the list"));
+        assert_eq!(run(palette_cmd, b"16 000000", &mut s), bad_color);
+        assert_eq!(
+            run(palette_cmd, b"red 0000", &mut s),
+            Err(CmdError::BadArgs("expected the color as rrggbb in hex"))
+        );
+        assert_eq!(
+            run(palette_cmd, b"red 00000g", &mut s),
+            Err(CmdError::BadArgs("expected the color as rrggbb in hex"))
+        );
+        assert_eq!(mock::writes_to(0x3C9), 3);
+    }
+
+    #[test]
+    fn palette_lists_every_color() {
+        let mut s = Screen::with_capture();
+        assert_eq!(run(palette_cmd, b"", &mut s), Ok(()));
+        let captured = s.captured();
+        assert_eq!(captured.lines().count(), 16);
+        assert!(captured.starts_with("black         000000\nblue "));
+    }
+
+    #[test]
+    fn beep_checks_its_arguments_and_silences_the_speaker() {
+        use crate::io::mock;
+
+        mock::clear();
+        let mut s = Screen::default();
+        assert_eq!(run(beep_cmd, b"", &mut s), Ok(()));
+        assert_eq!(mock::bytes_to(0x42), [0x4B, 0x05]);
+        assert_eq!(mock::bytes_to(0x61).last(), Some(&0x00));
+
+        mock::clear();
+        assert_eq!(run(beep_cmd, b"440 0", &mut s), Ok(()));
+        assert_eq!(mock::writes(), [(0x61, 0x00)]);
+
+        mock::clear();
+        assert_eq!(run(beep_cmd, b"0", &mut s), Err(CmdError::BadArgs("expected [freq] [ms] as numbers")));
+        assert_eq!(run(beep_cmd, b"440 x", &mut s), Err(CmdError::BadArgs("expected [freq] [ms] as numbers")));
+        assert_eq!(run(beep_cmd, b"440 5001", &mut s), Err(CmdError::OutOfRange { what: "duration", max: 5000 }));
+        assert!(mock::writes().is_empty());
+    }
+
+    #[test]
+    fn clear_erases_the_scrollback() {
+        let mut s = Screen::default();
+        for _ in 0..3 * vga::VIEW_HEIGHT {
+            s.write_str("line\n");
+        }
+        s.handle_key(Key::PageUp);
+        assert_eq!(run(clear_cmd, b"", &mut s), Ok(()));
+        assert_eq!((s.cursor, s.last_entry_index, s.rows_scrolled), (0, 0, 0));
+        assert!(s.buffer.iter().all(|cell| cell.ch() == b' '));
+
+        s.handle_key(Key::PageUp);
+        write_prompt(&mut s);
+        assert_eq!(s.lines().count(), 1);
+    }
+
+    #[test]
+    fn color_sets_the_drawing_color() {
+        let mut s = Screen::with_capture();
+        assert_eq!(run(color_cmd, b"red", &mut s), Ok(()));
+        assert_eq!(s.color(), 0x04);
+        assert_eq!(run(color_cmd, b"lightgreen blue", &mut s), Ok(()));
+        assert_eq!(s.color(), 0x1A);
+        // The background is kept when only the foreground is given.
+        assert_eq!(run(color_cmd, b"12", &mut s), Ok(()));
+        assert_eq!(s.color(), 0x1C);
+        assert!(run(color_cmd, b"pink", &mut s).is_err());
+        assert!(run(color_cmd, b"red 16", &mut s).is_err());
+        assert_eq!(s.color(), 0x1C);
+        assert_eq!(run(color_cmd, b"reset", &mut s), Ok(()));
+        assert_eq!(s.color(), Color::Default as u8);
+
+        assert_eq!(run(color_cmd, b"", &mut s), Ok(()));
+        let list = s.captured();
+        assert!(Hue::ALL.iter().all(|h| list.contains(h.name())));
+        let red = s.buffer[..s.last_entry_index]
+            .windows(3)
+            .find(|w| w.iter().map(|c| c.ch()).eq(*b"red"))
+            .unwrap();
+        assert_eq!(red[0].color(), Hue::Red.on(Hue::Black));
+    }
+
+    #[test]
+    fn conv_bad_args() {
+        let mut s = Screen::default();
+        assert!(matches!(run(conv_cmd, b"0x1_0000_0000", &mut s), Err(CmdError::BadArgs(_))));
+        assert!(matches!(run(conv_cmd, b"12z", &mut s), Err(CmdError::BadArgs(_))));
+        assert!(matches!(run(conv_cmd, b"", &mut s), Err(CmdError::BadArgs(_))));
+    }
+
+    #[test]
+    fn single_escape_does_not_abort() {
+        let mut detector = AbortDetector::new();
+        assert!(!detector.on_key(Key::Escape, 100));
+        assert!(!detector.on_key(Key::A, 110));
+    }
+
+    #[test]
+    fn slow_double_escape_does_not_abort() {
+        let mut detector = AbortDetector::new();
+        assert!(!detector.on_key(Key::Escape, 100));
+        assert!(!detector.on_key(Key::Escape, 100 + ABORT_WINDOW_TICKS + 1));
+    }
+
+    #[test]
+    fn fast_double_escape_aborts() {
+        let mut detector = AbortDetector::new();
+        assert!(!detector.on_key(Key::Escape, 100));
+        assert!(!detector.on_key(Key::A, 105));
+        assert!(detector.on_key(Key::Escape, 100 + ABORT_WINDOW_TICKS));
+    }
+
+    #[test]
+    fn slow_press_starts_a_new_window() {
+        let mut detector = AbortDetector::new();
+        assert!(!detector.on_key(Key::Escape, 0));
+        assert!(!detector.on_key(Key::Escape, 200));
+        assert!(detector.on_key(Key::Escape, 210));
+        assert!(!detector.on_key(Key::Escape, 215));
+    }
+}
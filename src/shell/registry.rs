@@ -0,0 +1,603 @@
+use crate::terminal::Screen;
+
+use super::{
+    banner_cmd, beep_cmd, bell_cmd, bench_cmd, blink_cmd, bootlog_cmd, clear_cmd, color_cmd,
+    complete::{self, Candidates},
+    conv_cmd, cursor_cmd, demo_cmd, diag_cmd, dmesg_cmd, dumpscreen_cmd, echo_cmd, files_cmd, halt_cmd, help_cmd, kbdstat_cmd, kbrate_cmd, mode_cmd,
+    modind_cmd, mouse_cmd, palette_cmd, panic_cmd, prints_cmd, ps2stats_cmd, random_cmd, reboot_cmd, record_cmd, replay_cmd, saver_cmd, secret_cmd,
+    selftest_cmd, serial_cmd, set_cmd, setkeymap_cmd, settings_cmd, showkeys_cmd, sort_cmd, split_args, srand_cmd, status_cmd, statusbar_cmd, stop_cmd,
+    symbols_cmd, sysinfo_cmd, CmdError,
+};
+
+pub type CommandFn = fn(args: &[u8], s: &mut Screen) -> Result<(), CmdError>;
+
+/// The command halts, reboots or otherwise disrupts the kernel.
+pub const DANGEROUS: u8 = 1 << 0;
+
+/// Accepts any number of arguments.
+const ANY: usize = usize::MAX;
+
+pub struct Command {
+    pub name: &'static str,
+    pub func: CommandFn,
+    /// Synopsis of the command, starting with its name.
+    pub usage: &'static str,
+    /// One-line description displayed by `help`.
+    pub help: &'static str,
+    /// Bounds of the number of space-separated arguments, checked before `func` is called.
+    pub min_args: usize,
+    pub max_args: usize,
+    pub flags: u8,
+    /// Candidates for tab completion of the first argument.
+    pub complete_arg: Option<Candidates>,
+}
+
+impl Command {
+    /// Validates the number of arguments and runs the command.
+    pub fn run(&self, args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+        let count = split_args(args).count();
+        if count < self.min_args || count > self.max_args {
+            return Err(CmdError::BadArgs(self.usage));
+        }
+        (self.func)(args, s)
+    }
+
+    pub fn is_dangerous(&self) -> bool {
+        self.flags & DANGEROUS != 0
+    }
+}
+
+/// Every shell command, sorted by name so that `find` can binary search it.
+static COMMANDS: &[Command] = &[
+    Command {
+        name: "banner",
+        func: banner_cmd,
+        usage: "banner",
+        help: "show the boot splash again",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "beep",
+        func: beep_cmd,
+        usage: "beep [freq] [ms]",
+        help: "sound the PC speaker, 880 Hz for 50 ms by default",
+        min_args: 0,
+        max_args: 2,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "bell",
+        func: bell_cmd,
+        usage: "bell [mode]",
+        help: "show the bell mode, or set it to speaker, visual or off",
+        min_args: 0,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::bell_modes),
+    },
+    Command {
+        name: "bench",
+        func: bench_cmd,
+        usage: "bench scroll",
+        help: "time a flood of 1000 lines of output",
+        min_args: 1,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::bench_names),
+    },
+    Command {
+        name: "blink",
+        func: blink_cmd,
+        usage: "blink on|off",
+        help: "blink text with bit 7 of the color, or use 16 backgrounds",
+        min_args: 1,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::on_off),
+    },
+    Command {
+        name: "bootlog",
+        func: bootlog_cmd,
+        usage: "bootlog",
+        help: "display the results of the boot stages",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "clear",
+        func: clear_cmd,
+        usage: "clear",
+        help: "erase the screen and its scrollback",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "color",
+        func: color_cmd,
+        usage: "color [fg] [bg]",
+        help: "list the colors, set the text color to <fg> on [bg], or reset it",
+        min_args: 0,
+        max_args: 2,
+        flags: 0,
+        complete_arg: Some(complete::color_names),
+    },
+    Command {
+        name: "conv",
+        func: conv_cmd,
+        usage: "conv <value>",
+        help: "display 0x.., 0b.., 'c' or decimal <value> in every base",
+        min_args: 1,
+        max_args: ANY,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "cursor",
+        func: cursor_cmd,
+        usage: "cursor on|off|style",
+        help: "show or hide the text cursor, or change how it looks",
+        min_args: 1,
+        max_args: 2,
+        flags: 0,
+        complete_arg: Some(complete::cursor_args),
+    },
+    Command {
+        name: "demo",
+        func: demo_cmd,
+        usage: "demo highlight",
+        help: "show a reverse video region wrapping onto the next row",
+        min_args: 1,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::demo_names),
+    },
+    Command {
+        name: "diag",
+        func: diag_cmd,
+        usage: "diag [reset]",
+        help: "display the counters of dropped events, or reset them",
+        min_args: 0,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::diag_actions),
+    },
+    Command {
+        name: "dmesg",
+        func: dmesg_cmd,
+        usage: "dmesg",
+        help: "display the kernel log",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "dumpscreen",
+        func: dumpscreen_cmd,
+        usage: "dumpscreen <file>",
+        help: "save the text of the scrollback to <file>",
+        min_args: 1,
+        max_args: 1,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "echo",
+        func: echo_cmd,
+        usage: "echo [text]",
+        help: "echoes input to the console",
+        min_args: 0,
+        max_args: ANY,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "files",
+        func: files_cmd,
+        usage: "files",
+        help: "list the ramfiles",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "halt",
+        func: halt_cmd,
+        usage: "halt",
+        help: "halt the kernel execution",
+        min_args: 0,
+        max_args: 0,
+        flags: DANGEROUS,
+        complete_arg: None,
+    },
+    Command {
+        name: "help",
+        func: help_cmd,
+        usage: "help",
+        help: "display this help message",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "kbdstat",
+        func: kbdstat_cmd,
+        usage: "kbdstat [reset]",
+        help: "show or reset the keyboard input statistics",
+        min_args: 0,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::kbdstat_actions),
+    },
+    Command {
+        name: "kbrate",
+        func: kbrate_cmd,
+        usage: "kbrate <r> <d>",
+        help: "set the key repeat rate <r> (0 fastest..31) and delay <d> (0..3)",
+        min_args: 2,
+        max_args: 2,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "mode",
+        func: mode_cmd,
+        usage: "mode 25|50",
+        help: "switch to the 80x25 or 80x50 text mode, clearing the screen",
+        min_args: 1,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::text_modes),
+    },
+    Command {
+        name: "modind",
+        func: modind_cmd,
+        usage: "modind on|off",
+        help: "toggle the modifier key indicator",
+        min_args: 1,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::on_off),
+    },
+    Command {
+        name: "mouse",
+        func: mouse_cmd,
+        usage: "mouse on|off",
+        help: "toggle the mouse pointer",
+        min_args: 1,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::on_off),
+    },
+    Command {
+        name: "palette",
+        func: palette_cmd,
+        usage: "palette [c] [rrggbb]",
+        help: "list the palette, or show color <c> as hex <rrggbb>",
+        min_args: 0,
+        max_args: 2,
+        flags: 0,
+        complete_arg: Some(complete::hue_names),
+    },
+    Command {
+        name: "panic",
+        func: panic_cmd,
+        usage: "panic",
+        help: "trigger a kernel panic",
+        min_args: 0,
+        max_args: 0,
+        flags: DANGEROUS,
+        complete_arg: None,
+    },
+    Command {
+        name: "prints",
+        func: prints_cmd,
+        usage: "prints [address]",
+        help: "display the stack boundaries, or 1024 bytes from <address>",
+        min_args: 0,
+        max_args: 1,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "ps2stats",
+        func: ps2stats_cmd,
+        usage: "ps2stats",
+        help: "count the protocol bytes sent by the keyboard",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "random",
+        func: random_cmd,
+        usage: "random [lo hi]",
+        help: "print a random number, in lo..hi if given",
+        min_args: 0,
+        max_args: 2,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "reboot",
+        func: reboot_cmd,
+        usage: "reboot",
+        help: "reboot the kernel",
+        min_args: 0,
+        max_args: 0,
+        flags: DANGEROUS,
+        complete_arg: None,
+    },
+    Command {
+        name: "record",
+        func: record_cmd,
+        usage: "record input <file>",
+        help: "record key presses into the ramfile <file>",
+        min_args: 2,
+        max_args: 2,
+        flags: 0,
+        complete_arg: Some(complete::record_sources),
+    },
+    Command {
+        name: "replay",
+        func: replay_cmd,
+        usage: "replay <file> [n]",
+        help: "replay the key presses of <file> at <n> times the speed",
+        min_args: 1,
+        max_args: 2,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "saver",
+        func: saver_cmd,
+        usage: "saver demo",
+        help: "run the screensaver until a key is pressed",
+        min_args: 1,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::saver_actions),
+    },
+    Command {
+        name: "secret",
+        func: secret_cmd,
+        usage: "secret",
+        help: "read hidden input and print its length",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "selftest",
+        func: selftest_cmd,
+        usage: "selftest",
+        help: "check the consistency of the command table",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "serial",
+        func: serial_cmd,
+        usage: "serial dump",
+        help: "send the scrollback to the serial port",
+        min_args: 1,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::serial_actions),
+    },
+    Command {
+        name: "set",
+        func: set_cmd,
+        usage: "set [name] [value]",
+        help: "show or change a setting (AUTOSCROLL, HINTS, SAVER)",
+        min_args: 0,
+        max_args: 2,
+        flags: 0,
+        complete_arg: Some(complete::setting_names),
+    },
+    Command {
+        name: "setkeymap",
+        func: setkeymap_cmd,
+        usage: "setkeymap [name]",
+        help: "show the keyboard layouts, or switch to qwerty, azerty or qwertz",
+        min_args: 0,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::keymap_names),
+    },
+    Command {
+        name: "settings",
+        func: settings_cmd,
+        usage: "settings reset",
+        help: "forget the settings stored across boots",
+        min_args: 1,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::settings_actions),
+    },
+    Command {
+        name: "showkeys",
+        func: showkeys_cmd,
+        usage: "showkeys",
+        help: "print raw scancodes until Escape",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "sort",
+        func: sort_cmd,
+        usage: "sort [-ru] <file>",
+        help: "print <file> sorted (-r descending, -u unique)",
+        min_args: 1,
+        max_args: 3,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "srand",
+        func: srand_cmd,
+        usage: "srand <seed>",
+        help: "reseed the random number generator",
+        min_args: 1,
+        max_args: 1,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "status",
+        func: status_cmd,
+        usage: "status",
+        help: "display the result of the last command",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "statusbar",
+        func: statusbar_cmd,
+        usage: "statusbar on|off",
+        help: "toggle the status bar on the bottom row",
+        min_args: 1,
+        max_args: 1,
+        flags: 0,
+        complete_arg: Some(complete::on_off),
+    },
+    Command {
+        name: "stop",
+        func: stop_cmd,
+        usage: "stop",
+        help: "stop recording",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "symbols",
+        func: symbols_cmd,
+        usage: "symbols",
+        help: "display the kernel memory layout",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+    Command {
+        name: "sysinfo",
+        func: sysinfo_cmd,
+        usage: "sysinfo",
+        help: "display kernel counters",
+        min_args: 0,
+        max_args: 0,
+        flags: 0,
+        complete_arg: None,
+    },
+];
+
+pub fn commands() -> &'static [Command] {
+    COMMANDS
+}
+
+/// Returns the command called `name`.
+pub fn find(name: &[u8]) -> Option<&'static Command> {
+    COMMANDS.binary_search_by(|c| c.name.as_bytes().cmp(name)).ok().map(|i| &COMMANDS[i])
+}
+
+/// Checks the invariants `find` and `help` rely on: names are unique and sorted, every usage starts
+/// with the command name and every command has help text.
+pub fn check(commands: &[Command]) -> Result<(), &'static str> {
+    for pair in commands.windows(2) {
+        if pair[0].name >= pair[1].name {
+            return Err("command names are not unique and sorted");
+        }
+    }
+    for c in commands {
+        let usage = c.usage.as_bytes();
+        let name = c.name.as_bytes();
+        if !usage.starts_with(name) || usage.get(name.len()).is_some_and(|&b| b != b' ') {
+            return Err("usage does not start with the command name");
+        }
+        if c.help.is_empty() {
+            return Err("command without help text");
+        }
+        if c.min_args > c.max_args {
+            return Err("invalid argument bounds");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[allow(unused)]
+    fn nop(args: &[u8], s: &mut Screen) -> Result<(), CmdError> {
+        Ok(())
+    }
+
+    const fn command(name: &'static str, usage: &'static str) -> Command {
+        Command {
+            name,
+            func: nop,
+            usage,
+            help: "does nothing",
+            min_args: 1,
+            max_args: 2,
+            flags: 0,
+            complete_arg: None,
+        }
+    }
+
+    #[test]
+    fn table_is_consistent() {
+        assert_eq!(check(commands()), Ok(()));
+    }
+
+    #[test]
+    fn check_rejects_broken_tables() {
+        assert!(check(&[command("b", "b"), command("a", "a")]).is_err());
+        assert!(check(&[command("a", "a"), command("a", "a")]).is_err());
+        assert!(check(&[command("a", "b")]).is_err());
+        assert!(check(&[command("a", "ab <x>")]).is_err());
+        assert_eq!(check(&[command("a", "a <x>"), command("b", "b")]), Ok(()));
+    }
+
+    #[test]
+    fn every_command_is_found() {
+        for c in commands() {
+            assert_eq!(find(c.name.as_bytes()).map(|f| f.name), Some(c.name));
+        }
+        assert!(find(b"").is_none());
+        assert!(find(b"ech").is_none());
+        assert!(find(b"echoo").is_none());
+        assert!(find(b"zzz").is_none());
+    }
+
+    #[test]
+    fn argument_count_is_validated() {
+        let c = command("a", "a <x> [y]");
+        let mut s = Screen::default();
+        assert_eq!(c.run(b"", &mut s), Err(CmdError::BadArgs("a <x> [y]")));
+        assert_eq!(c.run(b"1", &mut s), Ok(()));
+        assert_eq!(c.run(b" 1  2 ", &mut s), Ok(()));
+        assert_eq!(c.run(b"1 2 3", &mut s), Err(CmdError::BadArgs("a <x> [y]")));
+    }
+}
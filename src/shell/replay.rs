@@ -0,0 +1,291 @@
+use spin::Mutex;
+
+use crate::{
+    ramfs::{RamFsError, MAX_FILE_SIZE, MAX_NAME_LENGTH, RAMFS},
+    terminal::ps2::Key,
+};
+
+/// First byte of every recording, bumped whenever the event encoding changes.
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Size of an encoded event: the key followed by the little-endian tick delta.
+const EVENT_SIZE: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReplayError {
+    /// The recording is empty or starts with an unknown version byte.
+    BadVersion,
+    /// The recording ends in the middle of an event.
+    Truncated,
+    /// An event refers to a key that does not exist.
+    UnknownKey(u8),
+}
+
+/// A key press, `delta` ticks after the previous one.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Event {
+    pub key: Key,
+    pub delta: u16,
+}
+
+impl Event {
+    pub fn encode(&self) -> [u8; EVENT_SIZE] {
+        let delta = self.delta.to_le_bytes();
+        [self.key as u8, delta[0], delta[1]]
+    }
+}
+
+/// Bounds-checked iterator over the events of a recording.
+pub struct Events<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Events<'a> {
+    pub fn new(recording: &'a [u8]) -> Result<Self, ReplayError> {
+        match recording.split_first() {
+            Some((&FORMAT_VERSION, data)) => Ok(Events { data }),
+            _ => Err(ReplayError::BadVersion),
+        }
+    }
+
+    /// Checks that every event of `recording` can be decoded.
+    pub fn validate(recording: &[u8]) -> Result<(), ReplayError> {
+        for event in Events::new(recording)? {
+            event?;
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<Event, ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        if self.data.len() < EVENT_SIZE {
+            self.data = &[];
+            return Some(Err(ReplayError::Truncated));
+        }
+
+        let (event, rest) = self.data.split_at(EVENT_SIZE);
+        self.data = rest;
+
+        Some(match Key::from_u8(event[0]) {
+            Some(key) => Ok(Event {
+                key,
+                delta: u16::from_le_bytes([event[1], event[2]]),
+            }),
+            None => Err(ReplayError::UnknownKey(event[0])),
+        })
+    }
+}
+
+/// Appends key presses to a ramfile.
+pub struct Recorder {
+    file: [u8; MAX_NAME_LENGTH],
+    file_len: usize,
+    last_tick: u64,
+    /// Length of the recording before the keys of the line being typed.
+    line_start: usize,
+}
+
+impl Recorder {
+    /// Creates (or truncates) the ramfile `name` and writes the recording header.
+    pub fn start(name: &[u8], now: u64) -> Result<Self, RamFsError> {
+        let mut fs = RAMFS.lock();
+        let file = fs.create(name)?;
+        file.append(&[FORMAT_VERSION])?;
+
+        let mut recorder = Recorder {
+            file: [0; MAX_NAME_LENGTH],
+            file_len: name.len(),
+            last_tick: now,
+            line_start: 1,
+        };
+        recorder.file[..name.len()].copy_from_slice(name);
+        Ok(recorder)
+    }
+
+    pub fn file(&self) -> &[u8] {
+        &self.file[..self.file_len]
+    }
+
+    /// Records `key` as pressed at tick `now`.
+    pub fn record(&mut self, key: Key, now: u64) -> Result<(), RamFsError> {
+        let delta = now.saturating_sub(self.last_tick).min(u16::MAX as u64) as u16;
+        self.last_tick = now;
+
+        let mut fs = RAMFS.lock();
+        let file = fs.find_mut(self.file()).ok_or(RamFsError::InvalidName)?;
+        file.append(&Event { key, delta }.encode())?;
+        if key == Key::Enter {
+            self.line_start = file.data().len();
+        }
+        Ok(())
+    }
+
+    /// Ends the recording, dropping the keys of the line that is being typed (i.e. the `stop`
+    /// command itself).
+    pub fn stop(self) {
+        if let Some(file) = RAMFS.lock().find_mut(self.file()) {
+            file.truncate(self.line_start);
+        }
+    }
+}
+
+/// Feeds the events of a recording back at their recorded pace, divided by `speed`.
+pub struct Player {
+    recording: [u8; MAX_FILE_SIZE],
+    len: usize,
+    position: usize,
+    speed: u64,
+    last_tick: u64,
+}
+
+impl Player {
+    /// Loads `recording`, rejecting it if any of its events cannot be decoded.
+    pub fn new(recording: &[u8], speed: u64, now: u64) -> Result<Self, ReplayError> {
+        Events::validate(recording)?;
+
+        let mut player = Player {
+            recording: [0; MAX_FILE_SIZE],
+            len: recording.len(),
+            position: 1,
+            speed: speed.max(1),
+            last_tick: now,
+        };
+        player.recording[..recording.len()].copy_from_slice(recording);
+        Ok(player)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.len
+    }
+
+    /// Returns the next key if it is due at tick `now`.
+    pub fn poll(&mut self, now: u64) -> Option<Result<Key, ReplayError>> {
+        let remaining = &self.recording[self.position..self.len];
+        let mut events = Events { data: remaining };
+
+        match events.next()? {
+            Ok(event) => {
+                let due = self.last_tick + event.delta as u64 / self.speed;
+                if now < due {
+                    return None;
+                }
+                self.position += EVENT_SIZE;
+                self.last_tick = now;
+                Some(Ok(event.key))
+            }
+            Err(e) => {
+                self.position = self.len;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+pub static RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+pub static PLAYER: Mutex<Option<Player>> = Mutex::new(None);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn recording(events: &[Event]) -> ([u8; 64], usize) {
+        let mut buf = [0u8; 64];
+        buf[0] = FORMAT_VERSION;
+        let mut len = 1;
+        for e in events {
+            buf[len..len + EVENT_SIZE].copy_from_slice(&e.encode());
+            len += EVENT_SIZE;
+        }
+        (buf, len)
+    }
+
+    const EVENTS: [Event; 3] = [
+        Event { key: Key::A, delta: 0 },
+        Event {
+            key: Key::Backspace,
+            delta: 300,
+        },
+        Event {
+            key: Key::Enter,
+            delta: u16::MAX,
+        },
+    ];
+
+    #[test]
+    fn round_trip() {
+        let (buf, len) = recording(&EVENTS);
+        let mut events = Events::new(&buf[..len]).unwrap();
+        for expected in EVENTS {
+            assert_eq!(events.next(), Some(Ok(expected)));
+        }
+        assert_eq!(events.next(), None);
+    }
+
+    #[test]
+    fn corrupt_recordings() {
+        assert_eq!(Events::validate(&[]), Err(ReplayError::BadVersion));
+        assert_eq!(Events::validate(&[FORMAT_VERSION + 1]), Err(ReplayError::BadVersion));
+        assert_eq!(Events::validate(&[FORMAT_VERSION]), Ok(()));
+
+        let (buf, len) = recording(&EVENTS);
+        assert_eq!(Events::validate(&buf[..len - 1]), Err(ReplayError::Truncated));
+
+        let mut buf = buf;
+        buf[1] = 0xFF;
+        assert_eq!(Events::validate(&buf[..len]), Err(ReplayError::UnknownKey(0xFF)));
+        assert!(Player::new(&buf[..len], 1, 0).is_err());
+    }
+
+    #[test]
+    fn player_honors_timing() {
+        let (buf, len) = recording(&EVENTS[..2]);
+        let mut player = Player::new(&buf[..len], 1, 1000).unwrap();
+
+        assert_eq!(player.poll(1000), Some(Ok(Key::A)));
+        assert_eq!(player.poll(1299), None);
+        assert_eq!(player.poll(1300), Some(Ok(Key::Backspace)));
+        assert!(player.is_finished());
+        assert_eq!(player.poll(5000), None);
+    }
+
+    #[test]
+    fn player_speed_factor() {
+        let (buf, len) = recording(&EVENTS[..2]);
+        let mut player = Player::new(&buf[..len], 4, 0).unwrap();
+
+        assert_eq!(player.poll(0), Some(Ok(Key::A)));
+        assert_eq!(player.poll(74), None);
+        assert_eq!(player.poll(75), Some(Ok(Key::Backspace)));
+    }
+
+    #[test]
+    fn late_poll_delivers_one_key_at_a_time() {
+        let (buf, len) = recording(&[Event { key: Key::A, delta: 0 }, Event { key: Key::B, delta: 0 }]);
+        let mut player = Player::new(&buf[..len], 1, 0).unwrap();
+
+        assert_eq!(player.poll(10), Some(Ok(Key::A)));
+        assert_eq!(player.poll(10), Some(Ok(Key::B)));
+        assert_eq!(player.poll(10), None);
+    }
+
+    #[test]
+    fn recorder_drops_the_stop_line() {
+        let mut recorder = Recorder::start(b"rec-test", 100).unwrap();
+        recorder.record(Key::A, 110).unwrap();
+        recorder.record(Key::Enter, 120).unwrap();
+        recorder.record(Key::S, 500).unwrap();
+        recorder.stop();
+
+        let fs = RAMFS.lock();
+        let mut events = Events::new(fs.find(b"rec-test").unwrap().data()).unwrap();
+        assert_eq!(events.next(), Some(Ok(Event { key: Key::A, delta: 10 })));
+        assert_eq!(events.next(), Some(Ok(Event { key: Key::Enter, delta: 10 })));
+        assert_eq!(events.next(), None);
+    }
+}
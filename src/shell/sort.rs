@@ -0,0 +1,143 @@
+use core::cmp::Ordering;
+
+use crate::config;
+
+/// Maximum number of lines `LineTable` can index.
+pub const MAX_LINES: usize = config::SORT_MAX_LINES;
+
+/// How `LineTable::sort` orders the lines.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct SortOptions {
+    /// Sort in descending order.
+    pub reverse: bool,
+    /// Keep a single copy of identical lines.
+    pub unique: bool,
+}
+
+/// Byte-wise comparison of two lines over their full stored length.
+pub fn compare(a: &[u8], b: &[u8], options: SortOptions) -> Ordering {
+    let order = a.cmp(b);
+    if options.reverse {
+        order.reverse()
+    } else {
+        order
+    }
+}
+
+/// Start and end offsets of the lines of a text, without their line break. Sorting reorders the
+/// table and leaves the text untouched.
+pub struct LineTable {
+    lines: [(u16, u16); MAX_LINES],
+    len: usize,
+}
+
+impl LineTable {
+    /// Indexes the lines of `text`, whose length must fit in a `u16`. A final line break does not
+    /// start an empty line. Returns `None` if `text` has more than `MAX_LINES` lines.
+    pub fn new(text: &[u8]) -> Option<Self> {
+        let mut table = LineTable {
+            lines: [(0, 0); MAX_LINES],
+            len: 0,
+        };
+        if text.is_empty() {
+            return Some(table);
+        }
+        let text = text.strip_suffix(b"\n").unwrap_or(text);
+
+        let mut start = 0;
+        for line in text.split(|&c| c == b'\n') {
+            if table.len == MAX_LINES {
+                return None;
+            }
+            table.lines[table.len] = (start as u16, (start + line.len()) as u16);
+            table.len += 1;
+            start += line.len() + 1;
+        }
+        Some(table)
+    }
+
+    /// Sorts the table by the content of the lines of `text`, which must be the text it indexes.
+    pub fn sort(&mut self, text: &[u8], options: SortOptions) {
+        let line = |&(start, end): &(u16, u16)| &text[start as usize..end as usize];
+        let lines = &mut self.lines[..self.len];
+        lines.sort_unstable_by(|a, b| compare(line(a), line(b), options));
+
+        if options.unique {
+            let mut kept = 0;
+            for i in 0..lines.len() {
+                if kept == 0 || line(&lines[kept - 1]) != line(&lines[i]) {
+                    lines[kept] = lines[i];
+                    kept += 1;
+                }
+            }
+            self.len = kept;
+        }
+    }
+
+    /// Iterates over the lines of `text` in table order.
+    pub fn lines<'t>(&self, text: &'t [u8]) -> impl Iterator<Item = &'t [u8]> {
+        let lines = self.lines;
+        (0..self.len).map(move |i| &text[lines[i].0 as usize..lines[i].1 as usize])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sorted(text: &[u8], options: SortOptions) -> [&[u8]; 8] {
+        let mut table = LineTable::new(text).unwrap();
+        table.sort(text, options);
+        let mut out = [&b""[..]; 8];
+        for (o, line) in out.iter_mut().zip(table.lines(text)) {
+            *o = line;
+        }
+        out
+    }
+
+    #[test]
+    fn compare_is_byte_wise() {
+        let options = SortOptions::default();
+        assert_eq!(compare(b"B", b"a", options), Ordering::Less);
+        assert_eq!(compare(b"ab", b"abc", options), Ordering::Less);
+        assert_eq!(compare(b"ab", b"ab", options), Ordering::Equal);
+
+        let reverse = SortOptions { reverse: true, unique: false };
+        assert_eq!(compare(b"B", b"a", reverse), Ordering::Greater);
+    }
+
+    #[test]
+    fn line_table_bounds() {
+        assert_eq!(LineTable::new(b"").unwrap().len, 0);
+        assert_eq!(LineTable::new(b"\n").unwrap().len, 1);
+        assert_eq!(LineTable::new(b"a\n\nb").unwrap().len, 3);
+
+        let mut text = [b'\n'; MAX_LINES + 1];
+        assert_eq!(LineTable::new(&text[..MAX_LINES]).unwrap().len, MAX_LINES);
+        text[MAX_LINES] = b'x';
+        assert!(LineTable::new(&text).is_none());
+    }
+
+    #[test]
+    fn sorts_lines() {
+        let text = b"pear\napple\nfig\napple\n";
+        let options = SortOptions::default();
+        assert_eq!(&sorted(text, options)[..5], [&b"apple"[..], b"apple", b"fig", b"pear", b""]);
+
+        let options = SortOptions { reverse: true, unique: true };
+        assert_eq!(&sorted(text, options)[..4], [&b"pear"[..], b"fig", b"apple", b""]);
+    }
+
+    #[test]
+    fn long_lines_compare_in_full() {
+        let mut long = [b'x'; 120];
+        long[100] = b'a';
+        let mut text = [b'\n'; 241];
+        text[..120].copy_from_slice(&[b'x'; 120]);
+        text[121..].copy_from_slice(&long);
+
+        let options = SortOptions { reverse: false, unique: true };
+        let lines = sorted(&text, options);
+        assert_eq!((lines[0], lines[1]), (&long[..], &[b'x'; 120][..]));
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{
+    io::{inb, outb},
+    time::{self, PIT_CHANNEL_2_DATA_PORT, PIT_COMMAND_PORT, PIT_FREQUENCY_HZ, SPEAKER_CONTROL_PORT},
+};
+
+/// Starts a square wave of `freq_hz` on the PC speaker through PIT channel 2.
+pub fn start(freq_hz: u32) {
+    let divisor = (PIT_FREQUENCY_HZ / freq_hz.clamp(19, PIT_FREQUENCY_HZ)) as u16;
+
+    unsafe {
+        // Channel 2, lobyte/hibyte access, mode 3 (square wave), binary.
+        outb(PIT_COMMAND_PORT, 0b1011_0110);
+        outb(PIT_CHANNEL_2_DATA_PORT, (divisor & 0xFF) as u8);
+        outb(PIT_CHANNEL_2_DATA_PORT, (divisor >> 8) as u8);
+
+        // Bit 0 gates channel 2, bit 1 connects its output to the speaker.
+        outb(SPEAKER_CONTROL_PORT, inb(SPEAKER_CONTROL_PORT) | 0x03);
+    }
+}
+
+/// Disconnects the PC speaker from PIT channel 2.
+pub fn stop() {
+    unsafe { outb(SPEAKER_CONTROL_PORT, inb(SPEAKER_CONTROL_PORT) & !0x03) }
+}
+
+/// Beeps at `freq_hz` for `duration_ms`, busy-waiting on the calibrated TSC. Without calibration the
+/// beep is cut short, but the speaker is always stopped before returning.
+pub fn beep(freq_hz: u32, duration_ms: u32) {
+    if duration_ms > 0 {
+        start(freq_hz);
+        time::wait((duration_ms as u64 * time::TICKS_PER_SECOND).div_ceil(1000));
+    }
+    stop();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::mock;
+
+    #[test]
+    fn beep_programs_the_divisor_and_stops() {
+        mock::clear();
+        mock::set_input(SPEAKER_CONTROL_PORT, 0x30);
+        beep(1000, 50);
+        assert_eq!(mock::bytes_to(PIT_COMMAND_PORT), [0b1011_0110]);
+        assert_eq!(mock::bytes_to(PIT_CHANNEL_2_DATA_PORT), [0xA9, 0x04]);
+        assert_eq!(mock::bytes_to(SPEAKER_CONTROL_PORT).last(), Some(&0x30));
+    }
+
+    #[test]
+    fn zero_length_beep_only_stops_the_speaker() {
+        mock::clear();
+        mock::set_input(SPEAKER_CONTROL_PORT, 0x33);
+        beep(440, 0);
+        assert_eq!(mock::writes(), [(SPEAKER_CONTROL_PORT, 0x30)]);
+    }
+}
@@ -0,0 +1,203 @@
+use super::vga::Color;
+
+/// Starts an escape sequence.
+const ESC: u8 = 0x1B;
+
+/// Maximum number of parameters kept for a sequence, the others are ignored.
+const MAX_PARAMS: usize = 4;
+
+/// VGA color of each ANSI color, the ANSI order swapping red and blue.
+const PALETTE: [u8; 8] = [0x0, 0x4, 0x2, 0x6, 0x1, 0x5, 0x3, 0x7];
+
+/// What the byte fed to `Parser::feed` asks for.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    /// Display the character with `Parser::color`.
+    Print(u8),
+    /// Erase the screen (`ESC[2J`).
+    Clear,
+    /// Move the cursor to the top-left corner (`ESC[H`).
+    Home,
+    /// The byte is part of an escape sequence.
+    Consumed,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Ground,
+    Escape,
+    /// Inside a control sequence, reading its parameters.
+    Csi,
+}
+
+/// State machine for the subset of ANSI escape sequences the screen understands: the `m` color
+/// sequences, `ESC[2J` and `ESC[H`. Any other sequence is consumed without effect.
+#[derive(Clone, Copy)]
+pub struct Parser {
+    state: State,
+    params: [u16; MAX_PARAMS],
+    /// Number of parameters started, the last one being the one read.
+    len: usize,
+    color: u8,
+    /// Set by `ESC[1m`, which brightens the normal foreground colors selected afterwards too.
+    bold: bool,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Parser {
+            state: State::Ground,
+            params: [0; MAX_PARAMS],
+            len: 0,
+            color: Color::Default as u8,
+            bold: false,
+        }
+    }
+
+    /// VGA attribute selected by the color sequences read so far.
+    pub fn color(&self) -> u8 {
+        self.color
+    }
+
+    /// Selects `color` as if set by color sequences, without bold.
+    pub fn set_color(&mut self, color: u8) {
+        self.color = color;
+        self.bold = false;
+    }
+
+    pub fn feed(&mut self, byte: u8) -> Action {
+        match (self.state, byte) {
+            (State::Ground, ESC) => self.state = State::Escape,
+            (State::Ground, _) => return Action::Print(byte),
+            (State::Escape, b'[') => {
+                self.state = State::Csi;
+                self.params = [0; MAX_PARAMS];
+                self.len = 0;
+            }
+            // Two-byte sequences are all unsupported.
+            (State::Escape, _) => self.state = State::Ground,
+            (State::Csi, b'0'..=b'9') => {
+                if self.len == 0 {
+                    self.len = 1;
+                }
+                if let Some(param) = self.params.get_mut(self.len - 1) {
+                    *param = param.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                }
+            }
+            (State::Csi, b';') => self.len = self.len.max(1) + 1,
+            (State::Csi, 0x40..=0x7E) => {
+                self.state = State::Ground;
+                return self.dispatch(byte);
+            }
+            // Intermediate bytes carry nothing the supported sequences need.
+            (State::Csi, _) => {}
+        }
+        Action::Consumed
+    }
+
+    /// Applies the control sequence ended by `last`.
+    fn dispatch(&mut self, last: u8) -> Action {
+        let all = self.params;
+        let params = &all[..self.len.min(MAX_PARAMS)];
+        match last {
+            b'm' if params.is_empty() => self.select_graphic_rendition(0),
+            b'm' => {
+                for &param in params {
+                    self.select_graphic_rendition(param);
+                }
+            }
+            b'J' if params == [2] => return Action::Clear,
+            b'H' if params.iter().all(|&p| p <= 1) => return Action::Home,
+            _ => {}
+        }
+        Action::Consumed
+    }
+
+    fn select_graphic_rendition(&mut self, param: u16) {
+        let (foreground, background) = (self.color & 0x0F, self.color & 0xF0);
+        let default = Color::Default as u8;
+        let bright = if self.bold { 0x08 } else { 0 };
+        self.color = match param {
+            0 => {
+                self.bold = false;
+                default
+            }
+            // Bold is shown as the bright variant of the foreground.
+            1 => {
+                self.bold = true;
+                self.color | 0x08
+            }
+            22 => {
+                self.bold = false;
+                self.color & !0x08
+            }
+            30..=37 => background | PALETTE[(param - 30) as usize] | bright,
+            39 => background | (default & 0x0F) | bright,
+            40..=47 => PALETTE[(param - 40) as usize] << 4 | foreground,
+            49 => (default & 0xF0) | foreground,
+            90..=97 => background | PALETTE[(param - 90) as usize] | 0x08,
+            100..=107 => (PALETTE[(param - 100) as usize] | 0x08) << 4 | foreground,
+            _ => self.color,
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn feed_all(parser: &mut Parser, bytes: &[u8]) -> ([Action; 8], usize) {
+        let mut actions = [Action::Consumed; 8];
+        let mut len = 0;
+        for &b in bytes {
+            let action = parser.feed(b);
+            if action != Action::Consumed {
+                actions[len] = action;
+                len += 1;
+            }
+        }
+        (actions, len)
+    }
+
+    #[test]
+    fn plain_text_is_printed() {
+        let mut parser = Parser::new();
+        let (actions, len) = feed_all(&mut parser, b"ok\n");
+        assert_eq!(actions[..len], [Action::Print(b'o'), Action::Print(b'k'), Action::Print(b'\n')]);
+        assert_eq!(parser.color(), Color::Default as u8);
+    }
+
+    #[test]
+    fn colors_map_onto_the_vga_palette() {
+        let mut parser = Parser::new();
+        feed_all(&mut parser, b"\x1b[31m");
+        assert_eq!(parser.color(), 0x04);
+        feed_all(&mut parser, b"\x1b[44m");
+        assert_eq!(parser.color(), 0x14);
+        feed_all(&mut parser, b"\x1b[96m");
+        assert_eq!(parser.color(), 0x1B);
+        feed_all(&mut parser, b"\x1b[39;49m");
+        assert_eq!(parser.color(), Color::Default as u8);
+        feed_all(&mut parser, b"\x1b[1;37;41m");
+        assert_eq!(parser.color(), Color::Error as u8);
+        feed_all(&mut parser, b"\x1b[0m");
+        assert_eq!(parser.color(), Color::Default as u8);
+        feed_all(&mut parser, b"\x1b[32m\x1b[m");
+        assert_eq!(parser.color(), Color::Default as u8);
+    }
+
+    #[test]
+    fn clear_and_home() {
+        let mut parser = Parser::new();
+        let (actions, len) = feed_all(&mut parser, b"\x1b[2J\x1b[H\x1b[1;1Hx");
+        assert_eq!(actions[..len], [Action::Clear, Action::Home, Action::Home, Action::Print(b'x')]);
+    }
+
+    #[test]
+    fn unknown_sequences_are_consumed() {
+        let mut parser = Parser::new();
+        let (actions, len) = feed_all(&mut parser, b"\x1b[5;10H\x1b[K\x1b[?25l\x1b7\x1b[1;2;3;4;5;6ma");
+        assert_eq!(actions[..len], [Action::Print(b'a')]);
+        assert_eq!(parser.color(), Color::Default as u8 | 0x08);
+    }
+}
@@ -0,0 +1,102 @@
+/// Byte shown for characters code page 437 has no glyph for.
+pub const REPLACEMENT: u8 = b'?';
+
+/// Latin-1 characters that code page 437 also has, with their byte in it.
+const LATIN_1: [(char, u8); 53] = [
+    ('Ç', 0x80),
+    ('ü', 0x81),
+    ('é', 0x82),
+    ('â', 0x83),
+    ('ä', 0x84),
+    ('à', 0x85),
+    ('å', 0x86),
+    ('ç', 0x87),
+    ('ê', 0x88),
+    ('ë', 0x89),
+    ('è', 0x8A),
+    ('ï', 0x8B),
+    ('î', 0x8C),
+    ('ì', 0x8D),
+    ('Ä', 0x8E),
+    ('Å', 0x8F),
+    ('É', 0x90),
+    ('æ', 0x91),
+    ('Æ', 0x92),
+    ('ô', 0x93),
+    ('ö', 0x94),
+    ('ò', 0x95),
+    ('û', 0x96),
+    ('ù', 0x97),
+    ('ÿ', 0x98),
+    ('Ö', 0x99),
+    ('Ü', 0x9A),
+    ('¢', 0x9B),
+    ('£', 0x9C),
+    ('¥', 0x9D),
+    ('á', 0xA0),
+    ('í', 0xA1),
+    ('ó', 0xA2),
+    ('ú', 0xA3),
+    ('ñ', 0xA4),
+    ('Ñ', 0xA5),
+    ('ª', 0xA6),
+    ('º', 0xA7),
+    ('¿', 0xA8),
+    ('¬', 0xAA),
+    ('½', 0xAB),
+    ('¼', 0xAC),
+    ('¡', 0xAD),
+    ('«', 0xAE),
+    ('»', 0xAF),
+    ('ß', 0xE1),
+    ('µ', 0xE6),
+    ('±', 0xF1),
+    ('÷', 0xF6),
+    ('°', 0xF8),
+    ('·', 0xFA),
+    ('²', 0xFD),
+    ('\u{A0}', 0xFF),
+];
+
+/// Line style of the frames drawn by `Screen::draw_box`.
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BoxStyle {
+    Single,
+    Double,
+}
+
+impl BoxStyle {
+    /// Glyphs of the top-left, top-right, bottom-left and bottom-right corners, then of the
+    /// horizontal and vertical lines.
+    pub const fn glyphs(self) -> [u8; 6] {
+        match self {
+            BoxStyle::Single => [0xDA, 0xBF, 0xC0, 0xD9, 0xC4, 0xB3],
+            BoxStyle::Double => [0xC9, 0xBB, 0xC8, 0xBC, 0xCD, 0xBA],
+        }
+    }
+}
+
+/// Returns the code page 437 byte displaying `c`: ASCII as is, the Latin-1 characters the code
+/// page has, and `REPLACEMENT` for anything else, so that every character takes one cell.
+pub fn encode(c: char) -> u8 {
+    if c.is_ascii() {
+        return c as u8;
+    }
+    LATIN_1.iter().find(|&&(latin, _)| latin == c).map_or(REPLACEMENT, |&(_, byte)| byte)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ascii_and_latin_1_map_to_one_byte() {
+        assert_eq!(encode('a'), b'a');
+        assert_eq!(encode('\x1b'), 0x1B);
+        assert_eq!(encode('é'), 0x82);
+        assert_eq!(encode('É'), 0x90);
+        assert_eq!(encode('→'), REPLACEMENT);
+        assert_eq!(encode('🦀'), REPLACEMENT);
+    }
+}
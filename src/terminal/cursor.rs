@@ -1,5 +1,5 @@
-use super::vga::{VIEW_HEIGHT, VIEW_WIDTH};
-use core::arch::asm;
+use super::vga::{self, modify_crtc, write_crtc, VIEW_WIDTH};
+use spin::Mutex;
 
 /// Abstraction for managing the [Text-mode cursor](https://wiki.osdev.org/Text_Mode_Cursor).
 #[derive(Clone, Copy)]
@@ -13,81 +13,265 @@ impl Cursor {
     const LOCATION_REG_HIGH: u8 = 0x0E;
     const REG_START: u8 = 0x0A;
     const REG_END: u8 = 0x0B;
+    /// Bit of the cursor start register that hides the cursor.
+    const DISABLE_BIT: u8 = 1 << 5;
 
     pub fn new(x: u16, y: u16) -> Self {
         Cursor { x, y }
     }
 
-    /// Flushes the text-mode cursor position in the VGA buffer by setting the CRTC's
-    /// [location registers](http://www.osdever.net/FreeVGA/vga/crtcreg.htm#0F) (`0x0F` and `0x0D`)
-    /// to `x, y`.
-    ///
-    /// ## SAFETY
-    /// 1.  This function uses `Cursor::update`, which writes directly to the VGA buffer. In user-mode, this **will** result
-    ///     in invalid memory access.
-    ///
-    /// 2.  `flush_pos` may cause undefined behavior if called with `x` or `y` values outside of the range `0x00..=0x0F`.
-    pub unsafe fn flush_pos(&self) {
-        let out_of_bounds: bool = !(0..VIEW_HEIGHT).contains(&(self.y as usize)) || !(0..VIEW_WIDTH).contains(&(self.x as usize));
+    /// Returns the offset of the cursor in text memory, or `None` if it lies outside of the viewable
+    /// area.
+    fn linear_pos(&self) -> Option<u16> {
+        let out_of_bounds: bool = !(0..vga::view_height()).contains(&(self.y as usize)) || !(0..VIEW_WIDTH).contains(&(self.x as usize));
         if out_of_bounds {
-            return;
+            return None;
         }
 
-        let pos = self.y * VIEW_WIDTH as u16 + self.x;
-
-        Self::update(Cursor::LOCATION_REG_LOW, (pos & 0xFF) as u8);
-        Self::update(Cursor::LOCATION_REG_HIGH, ((pos >> 8) & 0xFF) as u8);
+        Some(vga::window_start() as u16 + self.y * VIEW_WIDTH as u16 + self.x)
     }
 
     /// Resizes the cursor by updating the [cursor end & start register](http://www.osdever.net/FreeVGA/vga/crtcreg.htm#0A)
     /// (`0x0A` and `0x0B`) to `start, end`. The values of `start` and `end` are expected to be in the range `0x00..=0x0F`.
     ///
     /// ## SAFETY
-    /// 1.  This function uses `Cursor::update`, which writes directly to the VGA buffer. In user-mode, this **will** result
+    /// 1.  This function uses `write_crtc`, which writes directly to the VGA buffer. In user-mode, this **will** result
     ///     in invalid memory access.
     ///
     /// 2.  `resize` may cause undefined behavior if called with `start` or `end` values outside of the range `0x00..=0x0F`.
-    pub unsafe fn resize(start: u8, end: u8) {
-        Self::update(Cursor::REG_START, start);
-        Self::update(Cursor::REG_END, end);
+    unsafe fn resize(start: u8, end: u8) {
+        write_crtc(Cursor::REG_START, start);
+        write_crtc(Cursor::REG_END, end);
     }
 
-    /// Abstraction for the ugliness behind updating the cursor.
-    ///
-    /// `0x3D4` is the I/O port address for the VGA's CRTC ([Cathode-ray tube](https://en.wikipedia.org/wiki/Cathode-ray_tube))'s
-    /// index register. The value being loaded into it defines which CRTC functionality we want to access.
-    /// The different indices that can be loaded into it are documented [here](http://www.osdever.net/FreeVGA/vga/crtcreg.htm#0A).
-    ///
-    /// After the index has been loaded into the `0x3D4`, `dx`, (where the index register is stored) can be incremented by
-    /// one. This will move it to `0x3D5`, the CRTC's data register, signifying the CRTC's readiness to receive the input values.
-    ///
-    /// ## SAFETY:
-    /// This writes to the VGA buffer directly, running this in a non-bare-metal environment
-    /// will result in invalid memory access.
-    unsafe fn update(index: u8, value: u8) {
-        asm!(
-            "mov dx, 0x3D4",
-            "mov al, {index}",
-            "out dx, al",
-            "inc dx",
-            "mov al, {value}",
-            "out dx, al",
-            index = in(reg_byte) (index),
-            value = in(reg_byte) (value),
-            out("dx") _,
-            out("al") _,
-        )
-    }
-
-    pub fn show() {
+    /// Shows the cursor with the scanlines of `style`, or hides it for `CursorStyle::Hidden`. This
+    /// is the only way to shape the cursor, so that no out-of-range scanline can be programmed.
+    pub fn set_style(style: CursorStyle) {
+        match style.scanlines() {
+            Some((start, end)) => unsafe { Self::resize(start, end) },
+            None => Self::disable(),
+        }
+    }
+
+    /// Shows the cursor again, with the scanlines it had when disabled.
+    #[allow(dead_code)]
+    pub fn enable() {
         unsafe {
-            Self::resize(0, 15);
+            modify_crtc(Cursor::REG_START, |start| start & !Cursor::DISABLE_BIT);
         }
     }
 
-    pub fn hide() {
+    /// Hides the cursor by setting the disable bit of the
+    /// [cursor start register](http://www.osdever.net/FreeVGA/vga/crtcreg.htm#0A), keeping the
+    /// scanline start so that `enable` restores the same shape.
+    pub fn disable() {
         unsafe {
-            Self::update(Cursor::REG_START, 1 << 5);
+            modify_crtc(Cursor::REG_START, |start| start | Cursor::DISABLE_BIT);
+        }
+    }
+}
+
+/// Look of the cursor. Insert and overwrite mode are told apart by using a different one.
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub enum CursorStyle {
+    /// The bottom two scanlines, the default while typed characters are inserted.
+    #[default]
+    Underline,
+    /// The whole cell, shown while typed characters replace the one under the cursor.
+    Block,
+    /// The bottom half of the cell.
+    HalfBlock,
+    /// No cursor at all.
+    Hidden,
+}
+
+impl CursorStyle {
+    pub const ALL: [CursorStyle; 4] = [CursorStyle::Underline, CursorStyle::Block, CursorStyle::HalfBlock, CursorStyle::Hidden];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CursorStyle::Underline => "underline",
+            CursorStyle::Block => "block",
+            CursorStyle::HalfBlock => "halfblock",
+            CursorStyle::Hidden => "hidden",
+        }
+    }
+
+    pub fn from_name(name: &[u8]) -> Option<CursorStyle> {
+        CursorStyle::ALL.into_iter().find(|style| style.name().as_bytes() == name)
+    }
+
+    /// First and last scanlines of the character cell covered by the cursor, `None` if hidden.
+    fn scanlines(self) -> Option<(u8, u8)> {
+        let last = vga::mode::current().char_height() - 1;
+        match self {
+            CursorStyle::Underline => Some((last - 1, last)),
+            CursorStyle::Block => Some((0, last)),
+            CursorStyle::HalfBlock => Some((last / 2 + 1, last)),
+            CursorStyle::Hidden => None,
+        }
+    }
+}
+
+/// Mirror of the cursor state last programmed into the CRTC.
+///
+/// Every flush reports the desired cursor through `sync`, which only touches the registers whose
+/// value actually changed. Typing on a single row therefore costs one register update per flush
+/// instead of four.
+pub struct CursorSync {
+    /// Last programmed location, `None` when unknown.
+    pos: Option<u16>,
+    /// Last programmed visibility, `None` when unknown.
+    visible: Option<bool>,
+    /// Style programmed when the cursor is shown.
+    style: CursorStyle,
+}
+
+impl CursorSync {
+    pub const fn new() -> Self {
+        CursorSync {
+            pos: None,
+            visible: None,
+            style: CursorStyle::Underline,
+        }
+    }
+
+    /// Changes the style of the cursor, right away if it is visible and on the next show otherwise.
+    pub fn set_style(&mut self, style: CursorStyle) {
+        if style == self.style {
+            return;
         }
+        self.style = style;
+        if self.visible == Some(true) {
+            Cursor::set_style(style);
+        }
+    }
+
+    /// Programs the CRTC so that the hardware cursor matches `cursor`, hiding it for `None`.
+    pub fn sync(&mut self, cursor: Option<Cursor>) {
+        match cursor.and_then(|c| c.linear_pos()) {
+            Some(pos) => {
+                let previous = self.pos;
+                if previous.map(|p| p & 0xFF) != Some(pos & 0xFF) {
+                    unsafe { write_crtc(Cursor::LOCATION_REG_LOW, (pos & 0xFF) as u8) };
+                }
+                if previous.map(|p| p >> 8) != Some(pos >> 8) {
+                    unsafe { write_crtc(Cursor::LOCATION_REG_HIGH, (pos >> 8) as u8) };
+                }
+                self.pos = Some(pos);
+
+                if self.visible != Some(true) {
+                    Cursor::set_style(self.style);
+                    self.visible = Some(true);
+                }
+            }
+            None => {
+                if cursor.is_none() && self.visible != Some(false) {
+                    Cursor::disable();
+                    self.visible = Some(false);
+                }
+            }
+        }
+    }
+}
+
+/// State of the hardware cursor shared by every `Buffer::flush`.
+pub static CURSOR_SYNC: Mutex<CursorSync> = Mutex::new(CursorSync::new());
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::mock;
+
+    fn index_writes() -> usize {
+        mock::writes_to(vga::CRTC_INDEX_PORT)
+    }
+
+    #[test]
+    fn first_sync_programs_everything() {
+        mock::clear();
+        let mut sync = CursorSync::new();
+        sync.sync(Some(Cursor::new(3, 1)));
+
+        // Location low and high, then cursor start and end.
+        assert_eq!(index_writes(), 4);
+    }
+
+    #[test]
+    fn unchanged_cursor_is_not_reprogrammed() {
+        let mut sync = CursorSync::new();
+        sync.sync(Some(Cursor::new(3, 1)));
+        mock::clear();
+
+        sync.sync(Some(Cursor::new(3, 1)));
+        assert_eq!(index_writes(), 0);
+    }
+
+    #[test]
+    fn only_changed_location_bytes_are_written() {
+        let mut sync = CursorSync::new();
+        sync.sync(Some(Cursor::new(0, 0)));
+        mock::clear();
+
+        sync.sync(Some(Cursor::new(1, 0)));
+        assert_eq!(index_writes(), 1);
+
+        // Row 4 starts at offset 320, which changes both bytes.
+        sync.sync(Some(Cursor::new(0, 4)));
+        assert_eq!(index_writes(), 3);
+    }
+
+    #[test]
+    fn shape_changes_reprogram_a_visible_cursor() {
+        let mut sync = CursorSync::new();
+        sync.sync(None);
+        mock::clear();
+
+        // Applied on the next show only.
+        sync.set_style(CursorStyle::Block);
+        assert_eq!(index_writes(), 0);
+        sync.sync(Some(Cursor::new(0, 0)));
+        assert_eq!(mock::bytes_to(vga::CRTC_DATA_PORT)[2..], [0, 15]);
+
+        mock::clear();
+        sync.set_style(CursorStyle::Block);
+        assert_eq!(index_writes(), 0);
+        sync.set_style(CursorStyle::Underline);
+        assert_eq!(mock::bytes_to(vga::CRTC_DATA_PORT), [14, 15]);
+    }
+
+    #[test]
+    fn disable_keeps_the_scanline_start() {
+        mock::clear();
+        mock::set_input(vga::CRTC_DATA_PORT, 14);
+        Cursor::disable();
+        mock::set_input(vga::CRTC_DATA_PORT, 14 | Cursor::DISABLE_BIT);
+        Cursor::enable();
+
+        assert_eq!(mock::bytes_to(vga::CRTC_INDEX_PORT), [Cursor::REG_START; 2]);
+        assert_eq!(mock::bytes_to(vga::CRTC_DATA_PORT), [14 | Cursor::DISABLE_BIT, 14]);
+    }
+
+    #[test]
+    fn hidden_style_only_sets_the_disable_bit() {
+        mock::clear();
+        Cursor::set_style(CursorStyle::HalfBlock);
+        assert_eq!(mock::bytes_to(vga::CRTC_DATA_PORT), [8, 15]);
+
+        mock::clear();
+        mock::set_input(vga::CRTC_DATA_PORT, 8);
+        Cursor::set_style(CursorStyle::Hidden);
+        assert_eq!(mock::bytes_to(vga::CRTC_DATA_PORT), [8 | Cursor::DISABLE_BIT]);
+    }
+
+    #[test]
+    fn hide_once() {
+        let mut sync = CursorSync::new();
+        sync.sync(Some(Cursor::new(0, 0)));
+        mock::clear();
+
+        sync.sync(None);
+        sync.sync(None);
+        assert_eq!(index_writes(), 1);
     }
 }
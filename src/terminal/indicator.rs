@@ -0,0 +1,526 @@
+use spin::Mutex;
+
+use crate::print::u64_to_base;
+
+use super::{
+    ps2::{self, ModifierState},
+    screen::visible_bytes,
+    vga::{Buffer, Cell, Color, VIEW_WIDTH},
+};
+
+/// Layout of the indicator. The lock badges are only displayed while the lock is active.
+const TEMPLATE: &[u8; INDICATOR_WIDTH] = b"[S C A] CAPS NUM";
+pub const INDICATOR_WIDTH: usize = 16;
+
+/// Black on light gray, used for the segments of active modifiers and locks.
+const ACTIVE_COLOR: u8 = 0x70;
+
+/// Compact display of the modifier and lock state in the top-right corner of the screen.
+///
+/// The cells are only recomputed when `update` receives a state that differs from the last one,
+/// and are composited over the screen content by `draw` without being stored in any `Screen`.
+pub struct ModIndicator {
+    pub enabled: bool,
+    /// Hides the indicator while `showkeys` prints raw scancodes and while the screensaver blanks
+    /// the screen, without changing `enabled`.
+    pub suppressed: bool,
+    state: ModifierState,
+    cells: [Cell; INDICATOR_WIDTH],
+}
+
+impl ModIndicator {
+    pub const fn new() -> Self {
+        ModIndicator {
+            enabled: false,
+            suppressed: false,
+            state: ModifierState::new(),
+            cells: [Cell::from_raw(0); INDICATOR_WIDTH],
+        }
+    }
+
+    /// Recomputes the indicator cells if `state` differs from the displayed one. Returns `true` if
+    /// the cells changed.
+    pub fn update(&mut self, state: ModifierState) -> bool {
+        if state == self.state && self.cells[0] != Cell::from_raw(0) {
+            return false;
+        }
+        self.state = state;
+
+        // (first cell, length, active, hidden while inactive)
+        let segments: [(usize, usize, bool, bool); 5] = [
+            (1, 1, state.shift(), false),
+            (3, 1, state.ctrl, false),
+            (5, 1, state.alt, false),
+            (8, 4, state.caps_lock, true),
+            (13, 3, state.num_lock, true),
+        ];
+
+        for (cell, &c) in self.cells.iter_mut().zip(TEMPLATE.iter()) {
+            *cell = Cell::new(c, Color::Default as u8);
+        }
+        for (start, len, active, badge) in segments {
+            let range = start..start + len;
+            for (cell, &c) in self.cells[range.clone()].iter_mut().zip(TEMPLATE[range].iter()) {
+                if active {
+                    *cell = Cell::new(c, ACTIVE_COLOR);
+                } else if badge {
+                    *cell = Cell::BLANK;
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns `true` if `draw` displays the indicator, i.e. if it is enabled and not suppressed.
+    pub fn is_shown(&self) -> bool {
+        self.enabled && !self.suppressed
+    }
+
+    /// The indicator cells in the colors of the status bar, where active segments are inverted
+    /// back to the default colors.
+    fn bar_cells(&self) -> [Cell; INDICATOR_WIDTH] {
+        self.cells.map(|cell| {
+            let color = if cell.color() == ACTIVE_COLOR {
+                Color::Default as u8
+            } else {
+                STATUS_BAR_COLOR
+            };
+            Cell::new(if cell.ch() == 0 { b' ' } else { cell.ch() }, color)
+        })
+    }
+
+    /// Composites the indicator over the top-right corner of `b` if it is shown. The caller keeps
+    /// it off that corner while the status bar, which shows it instead, or a banner is displayed.
+    pub fn draw(&self, b: &mut Buffer) {
+        if self.is_shown() {
+            b.overlay(VIEW_WIDTH - INDICATOR_WIDTH, &self.cells);
+        }
+    }
+
+    #[cfg(test)]
+    fn cells(&self) -> &[Cell; INDICATOR_WIDTH] {
+        &self.cells
+    }
+}
+
+pub static MOD_INDICATOR: Mutex<ModIndicator> = Mutex::new(ModIndicator::new());
+
+/// Width of the `[n/N]` marker drawn by `draw_screen_number`.
+const SCREEN_NUMBER_WIDTH: usize = 5;
+
+/// Composites `[n/N]` over the top-right corner of `b`, `n` being the active screen counted from 1
+/// and `N` the number of screens, both single digits.
+pub fn draw_screen_number(active: usize, count: usize, b: &mut Buffer) {
+    let digit = |n: usize| b'0' + (n % 10) as u8;
+    let text = [b'[', digit(active + 1), b'/', digit(count), b']'];
+    b.overlay(VIEW_WIDTH - SCREEN_NUMBER_WIDTH, &text.map(|c| Cell::new(c, Color::LightCyan as u8)));
+}
+
+/// Black on light gray, the inverse of the default colors.
+const STATUS_BAR_COLOR: u8 = 0x70;
+
+/// Fills the bottom row of `b` with the status bar: the active screen counted from 1 out of
+/// `count`, the `badges` of the modifiers and locks, then `text`, cut at the end of the row.
+pub fn draw_status_bar(active: usize, count: usize, badges: &[Cell], text: &[u8], b: &mut Buffer) {
+    let digit = |n: usize| b'0' + (n % 10) as u8;
+    let screen = [b' ', b'[', digit(active + 1), b'/', digit(count), b']', b' '];
+
+    let mut row = [Cell::new(b' ', STATUS_BAR_COLOR); VIEW_WIDTH];
+    let bar_cell = |&c: &u8| Cell::new(c, STATUS_BAR_COLOR);
+    let content = screen.iter().map(bar_cell).chain(badges.iter().copied()).chain(text.iter().map(bar_cell));
+    for (cell, c) in row.iter_mut().zip(content) {
+        *cell = c;
+    }
+    b.overlay(b.cells().len() - VIEW_WIDTH, &row);
+}
+
+/// State of the status bar drawn by `draw_status_bar`: whether it is shown, and its free text.
+pub struct StatusBar {
+    pub enabled: bool,
+    text: [u8; VIEW_WIDTH],
+    len: usize,
+}
+
+impl StatusBar {
+    pub const fn new() -> Self {
+        StatusBar {
+            enabled: false,
+            text: [0; VIEW_WIDTH],
+            len: 0,
+        }
+    }
+
+    /// Replaces the free text, encoded like the screen content. Only what fits on the row is kept.
+    pub fn set_text(&mut self, text: &str) {
+        self.len = 0;
+        for (place, b) in self.text.iter_mut().zip(visible_bytes(text)) {
+            *place = b;
+            self.len += 1;
+        }
+    }
+
+    pub fn text(&self) -> &[u8] {
+        &self.text[..self.len]
+    }
+
+    /// Composites the status bar over the bottom row of `b` if it is enabled, see `draw_status_bar`.
+    /// The bar shows `indicator` followed by a space when it is shown, and the badges of the locks
+    /// that are on otherwise.
+    pub fn draw(&self, active: usize, count: usize, indicator: &ModIndicator, b: &mut Buffer) {
+        if !self.enabled {
+            return;
+        }
+        let mut badges = [Cell::new(b' ', STATUS_BAR_COLOR); INDICATOR_WIDTH + 1];
+        let len = if indicator.is_shown() {
+            badges[..INDICATOR_WIDTH].copy_from_slice(&indicator.bar_cells());
+            INDICATOR_WIDTH + 1
+        } else {
+            let caps: &[u8] = if ps2::caps_lock() { b"CAPS " } else { b"" };
+            let num: &[u8] = if ps2::num_lock() { b"NUM " } else { b"" };
+            for (cell, &c) in badges.iter_mut().zip(caps.iter().chain(num)) {
+                *cell = Cell::new(c, STATUS_BAR_COLOR);
+            }
+            caps.len() + num.len()
+        };
+        draw_status_bar(active, count, &badges[..len], self.text(), b);
+    }
+}
+
+/// The status bar of the shell.
+pub static STATUS_BAR: Mutex<StatusBar> = Mutex::new(StatusBar::new());
+
+/// Maximum width of the badge drawn by `draw_pending_lines`.
+const BADGE_MAX_WIDTH: usize = 32;
+
+/// Formats the `+N new lines` badge into `cells` and returns its width.
+fn pending_lines_badge(pending: usize, cells: &mut [Cell; BADGE_MAX_WIDTH]) -> usize {
+    let Ok((digits, len)) = u64_to_base(pending as u64, 10) else {
+        return 0;
+    };
+    let text = [b" +", &digits[digits.len() - len..], b" new lines "];
+
+    let mut width = 0;
+    for (cell, &c) in cells.iter_mut().zip(text.iter().flat_map(|part| part.iter())) {
+        *cell = Cell::new(c, ACTIVE_COLOR);
+        width += 1;
+    }
+    width
+}
+
+/// Composites a `+N new lines` badge over the bottom-right corner of `b` while `pending` lines of
+/// output are hidden below the view.
+pub fn draw_pending_lines(pending: usize, b: &mut Buffer) {
+    if pending == 0 {
+        return;
+    }
+    let mut cells = [Cell::BLANK; BADGE_MAX_WIDTH];
+    let width = pending_lines_badge(pending, &mut cells);
+    b.overlay(b.cells().len() - width, &cells[..width]);
+}
+
+/// Maximum width of the marker drawn by `draw_discarded`.
+const MARKER_MAX_WIDTH: usize = 56;
+
+/// Formats the `--- N earlier lines discarded ---` marker into `cells` and returns its width.
+fn discarded_marker(discarded: u64, cells: &mut [Cell; MARKER_MAX_WIDTH]) -> usize {
+    let Ok((digits, len)) = u64_to_base(discarded, 10) else {
+        return 0;
+    };
+    let text = [b"--- ", &digits[digits.len() - len..], b" earlier lines discarded ---"];
+
+    let mut width = 0;
+    for (cell, &c) in cells.iter_mut().zip(text.iter().flat_map(|part| part.iter())) {
+        *cell = Cell::new(c, Color::Dim as u8);
+        width += 1;
+    }
+    width
+}
+
+/// Composites a marker centered over row `row` of `b` when the view reached the oldest retained
+/// line and `discarded` older lines were dropped from the scrollback.
+pub fn draw_discarded(discarded: u64, row: usize, b: &mut Buffer) {
+    if discarded == 0 || !b.shows_oldest() {
+        return;
+    }
+    let mut cells = [Cell::BLANK; MARKER_MAX_WIDTH];
+    let width = discarded_marker(discarded, &mut cells);
+    let mut cells_row = [Cell::BLANK; VIEW_WIDTH];
+    cells_row[(VIEW_WIDTH - width) / 2..][..width].copy_from_slice(&cells[..width]);
+    b.overlay(row * VIEW_WIDTH, &cells_row);
+}
+
+/// Fills the top row of `b` with the `-- SCROLLBACK (n lines up) --` banner while the view is
+/// `rows_up` rows above the live output, cut at the end of the row.
+pub fn draw_scrollback(rows_up: usize, b: &mut Buffer) {
+    if rows_up == 0 {
+        return;
+    }
+    let Ok((digits, len)) = u64_to_base(rows_up as u64, 10) else {
+        return;
+    };
+    let unit: &[u8] = if rows_up == 1 { b" line up) --" } else { b" lines up) --" };
+    let text = [b"-- SCROLLBACK (", &digits[digits.len() - len..], unit];
+
+    let mut row = [Cell::new(b' ', ACTIVE_COLOR); VIEW_WIDTH];
+    for (cell, &c) in row.iter_mut().zip(text.iter().flat_map(|part| part.iter())) {
+        *cell = Cell::new(c, ACTIVE_COLOR);
+    }
+    b.overlay(0, &row);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::terminal::Screen;
+
+    fn is_active(cell: Cell) -> bool {
+        cell.color() == ACTIVE_COLOR
+    }
+
+    #[test]
+    fn shift_lights_up_while_held() {
+        let mut mods = ModifierState::new();
+        let mut indicator = ModIndicator::new();
+        indicator.update(mods);
+        assert!(!is_active(indicator.cells()[1]));
+
+        mods.update(0x2A);
+        let change = mods.take_change().unwrap();
+        assert!(indicator.update(change));
+        assert!(is_active(indicator.cells()[1]));
+        assert_eq!(indicator.cells()[1].ch(), b'S');
+
+        mods.update(0xAA);
+        assert!(indicator.update(mods.take_change().unwrap()));
+        assert!(!is_active(indicator.cells()[1]));
+    }
+
+    #[test]
+    fn one_shift_released_keeps_segment_lit() {
+        let mut mods = ModifierState::new();
+        let mut indicator = ModIndicator::new();
+
+        mods.update(0x2A);
+        mods.update(0x36);
+        mods.update(0xAA);
+        indicator.update(mods.take_change().unwrap());
+        assert!(is_active(indicator.cells()[1]));
+    }
+
+    #[test]
+    fn ctrl_and_alt_segments() {
+        let mut mods = ModifierState::new();
+        let mut indicator = ModIndicator::new();
+
+        mods.update(0x1D);
+        mods.update(0x38);
+        indicator.update(mods.take_change().unwrap());
+        assert!(is_active(indicator.cells()[3]));
+        assert!(is_active(indicator.cells()[5]));
+        assert!(!is_active(indicator.cells()[1]));
+    }
+
+    #[test]
+    fn lock_badges_toggle_once_per_press() {
+        let mut mods = ModifierState::new();
+        let mut indicator = ModIndicator::new();
+        indicator.update(mods);
+        assert_eq!(indicator.cells()[8].ch(), b' ');
+
+        // Make, typematic repeat, break.
+        mods.update(0x3A);
+        mods.update(0x3A);
+        mods.update(0xBA);
+        indicator.update(mods.take_change().unwrap());
+        assert!(mods.caps_lock);
+        assert!(is_active(indicator.cells()[8]));
+        assert_eq!(indicator.cells()[8].ch(), b'C');
+
+        mods.update(0x45);
+        mods.update(0xC5);
+        indicator.update(mods.take_change().unwrap());
+        assert!(is_active(indicator.cells()[13]));
+        assert_eq!(indicator.cells()[13].ch(), b'N');
+    }
+
+    #[test]
+    fn redraw_only_on_change() {
+        let mut mods = ModifierState::new();
+        let mut indicator = ModIndicator::new();
+        assert!(indicator.update(mods));
+        assert!(!indicator.update(mods));
+
+        mods.update(0x2A);
+        mods.update(0x2A);
+        assert!(mods.take_change().is_some());
+        assert!(mods.take_change().is_none());
+    }
+
+    #[test]
+    fn suppressed_indicator_is_not_drawn() {
+        let mut s = Screen::default();
+        s.write_str("x");
+        let mut indicator = ModIndicator::new();
+        indicator.enabled = true;
+        indicator.update(ModifierState::new());
+
+        let mut b = Buffer::from_screen(&s);
+        let before = b;
+        indicator.suppressed = true;
+        indicator.draw(&mut b);
+        assert!(!indicator.is_shown());
+        assert!(b.cells() == before.cells());
+
+        indicator.suppressed = false;
+        indicator.draw(&mut b);
+        assert_eq!(b.cells()[VIEW_WIDTH - INDICATOR_WIDTH].ch(), b'[');
+    }
+
+    #[test]
+    fn non_modifier_keys_are_not_consumed() {
+        let mut mods = ModifierState::new();
+        assert!(!mods.update(0x1E));
+        assert!(mods.take_change().is_none());
+    }
+
+    #[test]
+    fn discarded_marker_text() {
+        let mut cells = [Cell::BLANK; MARKER_MAX_WIDTH];
+        let width = discarded_marker(728, &mut cells);
+
+        let mut text = [0u8; MARKER_MAX_WIDTH];
+        for (t, c) in text.iter_mut().zip(cells.iter()) {
+            *t = c.ch();
+        }
+        assert_eq!(&text[..width], b"--- 728 earlier lines discarded ---");
+    }
+
+    #[test]
+    fn pending_lines_badge_text() {
+        let mut cells = [Cell::BLANK; BADGE_MAX_WIDTH];
+        let width = pending_lines_badge(42, &mut cells);
+
+        let mut text = [0u8; BADGE_MAX_WIDTH];
+        for (t, c) in text.iter_mut().zip(cells.iter()) {
+            *t = c.ch();
+        }
+        assert_eq!(&text[..width], b" +42 new lines ");
+        assert!(is_active(cells[0]));
+    }
+
+    fn row_text(b: &Buffer) -> [u8; VIEW_WIDTH] {
+        let mut text = [0u8; VIEW_WIDTH];
+        for (t, c) in text.iter_mut().zip(b.cells().iter()) {
+            *t = c.ch();
+        }
+        text
+    }
+
+    #[test]
+    fn discarded_marker_at_the_top() {
+        let mut s = Screen::default();
+        for _ in 0..5000 {
+            s.write_str("0123456789\n");
+        }
+        assert!(s.lines_discarded > 0);
+        let mut marker = [Cell::BLANK; MARKER_MAX_WIDTH];
+        let width = discarded_marker(s.lines_discarded, &mut marker);
+        let start = (VIEW_WIDTH - width) / 2;
+
+        let mut b = Buffer::from_screen(&s);
+        draw_discarded(s.lines_discarded, 0, &mut b);
+        assert_eq!(&row_text(&b)[..10], b"0123456789");
+
+        s.scroll(10000);
+        let mut b = Buffer::from_screen(&s);
+        draw_discarded(s.lines_discarded, 0, &mut b);
+        assert_eq!(&b.cells()[start..start + width], &marker[..width]);
+        assert_eq!(b.cells()[start].color(), Color::Dim as u8);
+    }
+
+    #[test]
+    fn scrollback_banner_follows_the_view() {
+        let mut s = Screen::default();
+        for i in 0..100u32 {
+            s.write_dec(i);
+            s.write_str("\n");
+        }
+        let mut b = Buffer::from_screen(&s);
+        draw_scrollback(s.rows_scrolled, &mut b);
+        assert_ne!(b.cells()[0].color(), ACTIVE_COLOR);
+
+        s.scroll(3);
+        let mut b = Buffer::from_screen(&s);
+        draw_scrollback(s.rows_scrolled, &mut b);
+        let banner = b"-- SCROLLBACK (3 lines up) --";
+        assert_eq!(&row_text(&b)[..banner.len()], banner);
+        assert!(b.cells()[..VIEW_WIDTH].iter().all(|c| c.color() == ACTIVE_COLOR));
+
+        s.scroll(-2);
+        let mut b = Buffer::from_screen(&s);
+        draw_scrollback(s.rows_scrolled, &mut b);
+        assert_eq!(&row_text(&b)[..27], b"-- SCROLLBACK (1 line up) -");
+        assert_eq!(b.cells()[VIEW_WIDTH].ch(), Buffer::from_screen(&s).cells()[VIEW_WIDTH].ch());
+    }
+
+    #[test]
+    fn scrollback_banner_stays_on_the_top_row() {
+        let mut s = Screen::default();
+        s.write_str("x\ny");
+        let mut b = Buffer::from_screen(&s);
+        draw_scrollback(usize::MAX, &mut b);
+        let banner = b"-- SCROLLBACK (18446744073709551615 lines up) --";
+        assert_eq!(&row_text(&b)[..banner.len()], banner);
+        assert_eq!(b.cells()[VIEW_WIDTH - 1], Cell::new(b' ', ACTIVE_COLOR));
+        assert_ne!(b.cells()[VIEW_WIDTH].color(), ACTIVE_COLOR);
+    }
+
+    #[test]
+    fn status_bar_text_is_encoded_like_the_screen() {
+        let _keyboard = ps2::reset_for_test();
+        let mut bar = StatusBar::new();
+        bar.set_text("\x1b[31mcaf\u{e9}\x1b[0m");
+        assert_eq!(bar.text(), b"caf\x82");
+        bar.set_text(core::str::from_utf8(&[b'x'; VIEW_WIDTH + 10]).unwrap());
+        assert_eq!(bar.text().len(), VIEW_WIDTH);
+
+        bar.set_text("caf\u{e9}");
+        let s = Screen::default();
+        let mut b = Buffer::from_screen(&s);
+        let before = b;
+        let indicator = ModIndicator::new();
+        bar.draw(0, 1, &indicator, &mut b);
+        assert!(b.cells() == before.cells());
+
+        bar.enabled = true;
+        bar.draw(0, 1, &indicator, &mut b);
+        let bottom = &b.cells()[b.cells().len() - VIEW_WIDTH..];
+        assert!(bottom.iter().take(12).map(|cell| cell.ch()).eq(*b" [1/1] caf\x82 "));
+    }
+
+    #[test]
+    fn status_bar_shows_the_indicator_in_its_row() {
+        let _keyboard = ps2::reset_for_test();
+        let mut bar = StatusBar::new();
+        bar.enabled = true;
+        bar.set_text("text");
+        let mut indicator = ModIndicator::new();
+        indicator.enabled = true;
+        let mut state = ModifierState::new();
+        state.ctrl = true;
+        state.caps_lock = true;
+        indicator.update(state);
+
+        let mut b = Buffer::from_screen(&Screen::default());
+        bar.draw(0, 1, &indicator, &mut b);
+        let bottom = &b.cells()[b.cells().len() - VIEW_WIDTH..];
+        assert!(bottom.iter().take(28).map(|cell| cell.ch()).eq(*b" [1/1] [S C A] CAPS     text"));
+        let active: [bool; 14] = core::array::from_fn(|i| bottom[7 + i].color() == Color::Default as u8);
+        assert_eq!(
+            active,
+            [false, false, false, true, false, false, false, false, true, true, true, true, false, false]
+        );
+        // Nothing is left for the top-right corner.
+        assert!(b.cells()[..VIEW_WIDTH].iter().all(|&cell| cell == Cell::BLANK));
+    }
+}
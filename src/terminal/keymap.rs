@@ -0,0 +1,407 @@
+use super::{
+    ps2::{self, Key, SCANCODE_TO_KEY, SCANCODE_TO_KEY_SHIFTED},
+    vga::Buffer,
+    Screen,
+};
+use crate::{
+    cmos, settings,
+    time::{self, TICKS_PER_SECOND},
+};
+
+/// Physical arrangement of the letter keys.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum Layout {
+    #[default]
+    Qwerty,
+    Azerty,
+    Qwertz,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 3] = [Layout::Qwerty, Layout::Azerty, Layout::Qwertz];
+
+    pub fn from_name(name: &[u8]) -> Option<Layout> {
+        Layout::ALL.into_iter().find(|l| l.name().as_bytes() == name)
+    }
+
+    pub fn keymap(&self) -> &'static Keymap {
+        match self {
+            Layout::Qwerty => &US_QWERTY,
+            Layout::Azerty => &FR_AZERTY,
+            Layout::Qwertz => &DE_QWERTZ,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layout::Qwerty => "qwerty",
+            Layout::Azerty => "azerty",
+            Layout::Qwertz => "qwertz",
+        }
+    }
+
+    /// Value stored in `cmos::KEYMAP_REGISTER`. Zero means no layout was chosen yet.
+    fn to_cmos(self) -> u8 {
+        match self {
+            Layout::Qwerty => 1,
+            Layout::Azerty => 2,
+            Layout::Qwertz => 3,
+        }
+    }
+
+    fn from_cmos(value: u8) -> Option<Layout> {
+        match value {
+            1 => Some(Layout::Qwerty),
+            2 => Some(Layout::Azerty),
+            3 => Some(Layout::Qwertz),
+            _ => None,
+        }
+    }
+}
+
+/// Translation of scancodes into keys for one layout, indexed by scancode.
+pub struct Keymap {
+    pub normal: [Option<Key>; 256],
+    /// Keys produced while a Shift key is held.
+    pub shifted: [Option<Key>; 256],
+}
+
+/// Returns `table` with the keys of `overrides` replacing the ones at their scancode. Keys that
+/// cannot be represented, such as accented letters, are overridden with `None`.
+const fn remap(mut table: [Option<Key>; 256], overrides: &[(u8, Option<Key>)]) -> [Option<Key>; 256] {
+    let mut i = 0;
+    while i < overrides.len() {
+        table[overrides[i].0 as usize] = overrides[i].1;
+        i += 1;
+    }
+    table
+}
+
+pub static US_QWERTY: Keymap = Keymap {
+    normal: SCANCODE_TO_KEY,
+    shifted: SCANCODE_TO_KEY_SHIFTED,
+};
+
+pub static FR_AZERTY: Keymap = {
+    use Key::*;
+    Keymap {
+        normal: remap(
+            SCANCODE_TO_KEY,
+            &[
+                (0x02, Some(Ampersand)),
+                (0x03, None),
+                (0x04, Some(DoubleQuote)),
+                (0x05, Some(SingleQuote)),
+                (0x06, Some(ParenthesesOpen)),
+                (0x07, Some(Minus)),
+                (0x08, None),
+                (0x09, Some(Underscore)),
+                (0x0A, None),
+                (0x0B, None),
+                (0x0C, Some(ParenthesesClosed)),
+                (0x10, Some(A)),
+                (0x11, Some(Z)),
+                (0x1A, Some(Caret)),
+                (0x1B, Some(Dollar)),
+                (0x1E, Some(Q)),
+                (0x27, Some(M)),
+                (0x28, None),
+                (0x29, None),
+                (0x2B, Some(Star)),
+                (0x2C, Some(W)),
+                (0x32, Some(Comma)),
+                (0x33, Some(Semicolon)),
+                (0x34, Some(Colon)),
+                (0x35, Some(Exclamation)),
+            ],
+        ),
+        shifted: remap(
+            SCANCODE_TO_KEY_SHIFTED,
+            &[
+                (0x02, Some(N1)),
+                (0x03, Some(N2)),
+                (0x04, Some(N3)),
+                (0x05, Some(N4)),
+                (0x06, Some(N5)),
+                (0x07, Some(N6)),
+                (0x08, Some(N7)),
+                (0x09, Some(N8)),
+                (0x0A, Some(N9)),
+                (0x0B, Some(N0)),
+                (0x0C, None),
+                (0x10, Some(UpperA)),
+                (0x11, Some(UpperZ)),
+                (0x1A, None),
+                (0x1B, None),
+                (0x1E, Some(UpperQ)),
+                (0x27, Some(UpperM)),
+                (0x28, Some(Percent)),
+                (0x29, None),
+                (0x2B, None),
+                (0x2C, Some(UpperW)),
+                (0x32, Some(QuestionMark)),
+                (0x33, Some(Dot)),
+                (0x34, Some(Slash)),
+                (0x35, None),
+            ],
+        ),
+    }
+};
+
+pub static DE_QWERTZ: Keymap = {
+    use Key::*;
+    Keymap {
+        normal: remap(
+            SCANCODE_TO_KEY,
+            &[
+                (0x0C, None),
+                (0x0D, None),
+                (0x15, Some(Z)),
+                (0x1A, None),
+                (0x1B, Some(Plus)),
+                (0x27, None),
+                (0x28, None),
+                (0x29, Some(Caret)),
+                (0x2B, Some(Hash)),
+                (0x2C, Some(Y)),
+                (0x35, Some(Minus)),
+            ],
+        ),
+        shifted: remap(
+            SCANCODE_TO_KEY_SHIFTED,
+            &[
+                (0x03, Some(DoubleQuote)),
+                (0x04, None),
+                (0x07, Some(Ampersand)),
+                (0x08, Some(Slash)),
+                (0x09, Some(ParenthesesOpen)),
+                (0x0A, Some(ParenthesesClosed)),
+                (0x0B, Some(Equal)),
+                (0x0C, Some(QuestionMark)),
+                (0x0D, Some(Backtick)),
+                (0x15, Some(UpperZ)),
+                (0x1A, None),
+                (0x1B, Some(Star)),
+                (0x27, None),
+                (0x28, None),
+                (0x29, None),
+                (0x2B, Some(SingleQuote)),
+                (0x2C, Some(UpperY)),
+                (0x33, Some(Semicolon)),
+                (0x34, Some(Colon)),
+                (0x35, Some(Underscore)),
+            ],
+        ),
+    }
+};
+
+/// Time the user has to answer the detection prompt.
+pub const DETECT_TIMEOUT_TICKS: u64 = 10 * TICKS_PER_SECOND;
+
+pub const DETECT_PROMPT: &str = "keymap: press the key labelled Z (Enter to skip)";
+
+/// Scancodes are tied to the position of a key, not to its label. The key labelled Z sits at a
+/// different position on each layout, so its scancode tells them apart.
+const Z_ON_QWERTY: u8 = 0x2C;
+const Z_ON_AZERTY: u8 = 0x11;
+const Z_ON_QWERTZ: u8 = 0x15;
+const ENTER: u8 = 0x1C;
+
+/// Returns the layout on which the key labelled Z sends `code`.
+pub fn layout_from_z(code: u8) -> Option<Layout> {
+    match code {
+        Z_ON_QWERTY => Some(Layout::Qwerty),
+        Z_ON_AZERTY => Some(Layout::Azerty),
+        Z_ON_QWERTZ => Some(Layout::Qwertz),
+        _ => None,
+    }
+}
+
+/// Outcome of the detection prompt.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Detection {
+    Detected(Layout),
+    /// No usable answer, QWERTY is used. Holds the reason.
+    Fallback(&'static str),
+}
+
+impl Detection {
+    pub fn layout(&self) -> Layout {
+        match self {
+            Detection::Detected(layout) => *layout,
+            Detection::Fallback(_) => Layout::Qwerty,
+        }
+    }
+}
+
+/// Returns `true` for scancodes that cannot be an answer: releases, prefixes and modifiers.
+fn is_ignored(code: u8) -> bool {
+    code & 0x80 != 0 || matches!(code, 0x00 | 0x1D | 0x2A | 0x36 | 0x38 | 0x3A | 0x45)
+}
+
+/// Reads scancodes from `read` until a key is pressed or `timeout` ticks elapsed according to
+/// `now`, and infers the layout from it.
+pub fn detect(read: &mut impl FnMut() -> Option<u8>, now: &mut impl FnMut() -> u64, timeout: u64) -> Detection {
+    let deadline = now() + timeout;
+    while now() < deadline {
+        match read() {
+            Some(ENTER) => return Detection::Fallback("skipped"),
+            Some(code) if !is_ignored(code) => {
+                return match layout_from_z(code) {
+                    Some(layout) => Detection::Detected(layout),
+                    None => Detection::Fallback("unexpected key"),
+                };
+            }
+            _ => {}
+        }
+    }
+    Detection::Fallback("no answer")
+}
+
+/// Makes `layout` the active layout and stores it in the CMOS for the next boots.
+pub fn select(layout: Layout) {
+    ps2::set_layout(layout);
+    cmos::write(cmos::KEYMAP_REGISTER, layout.to_cmos());
+}
+
+/// Restores the layout chosen on a previous boot, or asks for the key labelled Z and stores the
+/// detected layout in the CMOS so that the next boots skip the prompt. In safe mode the stored
+/// layout is ignored and QWERTY is used without asking.
+pub fn first_boot(s: &mut Screen) {
+    if settings::is_safe_mode() {
+        ps2::set_layout(Layout::Qwerty);
+        return;
+    }
+    if let Some(layout) = Layout::from_cmos(cmos::read(cmos::KEYMAP_REGISTER)) {
+        ps2::set_layout(layout);
+        return;
+    }
+    if !time::is_calibrated() {
+        s.write_str("keymap: no timer to wait for an answer, using qwerty\n");
+        return;
+    }
+
+    s.write_str(DETECT_PROMPT);
+    s.write_str("\n");
+    Buffer::from_screen(s).flush();
+
+    let detection = detect(&mut ps2::read_scancode_if_ready, &mut time::ticks, DETECT_TIMEOUT_TICKS);
+    let layout = detection.layout();
+    select(layout);
+
+    s.write_str("keymap: ");
+    if let Detection::Fallback(reason) = detection {
+        s.write_str(reason);
+        s.write_str(", using ");
+    }
+    s.write_str(layout.name());
+    s.write_str("\n");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(codes: &[u8], answer_at: u64) -> Detection {
+        let clock = core::cell::Cell::new(0);
+        let mut codes = codes.iter();
+        let mut read = || if clock.get() >= answer_at { codes.next().copied() } else { None };
+        let mut now = || {
+            clock.set(clock.get() + 1);
+            clock.get()
+        };
+        detect(&mut read, &mut now, DETECT_TIMEOUT_TICKS)
+    }
+
+    #[test]
+    fn inference_table() {
+        assert_eq!(layout_from_z(0x2C), Some(Layout::Qwerty));
+        assert_eq!(layout_from_z(0x11), Some(Layout::Azerty));
+        assert_eq!(layout_from_z(0x15), Some(Layout::Qwertz));
+        assert_eq!(layout_from_z(0x1E), None);
+    }
+
+    #[test]
+    fn detects_each_layout() {
+        assert_eq!(run(&[0x2C], 0), Detection::Detected(Layout::Qwerty));
+        assert_eq!(run(&[0x11], 50), Detection::Detected(Layout::Azerty));
+        assert_eq!(run(&[0x15], 100), Detection::Detected(Layout::Qwertz));
+    }
+
+    #[test]
+    fn releases_and_modifiers_are_skipped() {
+        assert_eq!(run(&[0x9C, 0x2A, 0xE0, 0x15], 0), Detection::Detected(Layout::Qwertz));
+    }
+
+    #[test]
+    fn wrong_key_falls_back() {
+        let detection = run(&[0x1E], 0);
+        assert_eq!(detection, Detection::Fallback("unexpected key"));
+        assert_eq!(detection.layout(), Layout::Qwerty);
+    }
+
+    #[test]
+    fn enter_skips() {
+        let detection = run(&[0x1C], 0);
+        assert_eq!(detection, Detection::Fallback("skipped"));
+        assert_eq!(detection.layout(), Layout::Qwerty);
+    }
+
+    #[test]
+    fn timeout_falls_back() {
+        assert_eq!(run(&[0x2C], DETECT_TIMEOUT_TICKS + 1), Detection::Fallback("no answer"));
+        assert_eq!(run(&[], 0), Detection::Fallback("no answer"));
+    }
+
+    #[test]
+    fn cmos_encoding() {
+        for layout in [Layout::Qwerty, Layout::Azerty, Layout::Qwertz] {
+            assert_eq!(Layout::from_cmos(layout.to_cmos()), Some(layout));
+        }
+        assert_eq!(Layout::from_cmos(0), None);
+        assert_eq!(Layout::from_cmos(0xFF), None);
+    }
+
+    fn decode(layout: Layout, codes: &[u8]) -> Option<Key> {
+        let mut modifiers = ps2::ModifierState::new();
+        modifiers.layout = layout;
+        codes.iter().fold(None, |_, &code| ps2::decode_scancode(code, &mut modifiers))
+    }
+
+    #[test]
+    fn shift_goes_through_the_keymap() {
+        assert_eq!(decode(Layout::Qwerty, &[0x2A, 0x03]), Some(Key::At));
+        assert_eq!(decode(Layout::Azerty, &[0x2A, 0x03]), Some(Key::N2));
+        assert_eq!(decode(Layout::Azerty, &[0x02]), Some(Key::Ampersand));
+        assert_eq!(decode(Layout::Qwertz, &[0x2A, 0x03]), Some(Key::DoubleQuote));
+    }
+
+    #[test]
+    fn letters_follow_the_layout() {
+        assert_eq!(decode(Layout::Azerty, &[0x10]), Some(Key::A));
+        assert_eq!(decode(Layout::Azerty, &[0x2C]), Some(Key::W));
+        assert_eq!(decode(Layout::Qwertz, &[0x15]), Some(Key::Z));
+        assert_eq!(decode(Layout::Qwertz, &[0x2C]), Some(Key::Y));
+
+        // Caps Lock applies to the letters of the layout only.
+        assert_eq!(decode(Layout::Azerty, &[0x3A, 0xBA, 0x27]), Some(Key::UpperM));
+        assert_eq!(decode(Layout::Azerty, &[0x3A, 0xBA, 0x02]), Some(Key::Ampersand));
+    }
+
+    #[test]
+    fn keyboard_reset_keeps_the_layout() {
+        let mut modifiers = ps2::ModifierState::new();
+        modifiers.layout = Layout::Azerty;
+        ps2::decode_scancode(0xAA, &mut modifiers);
+        assert!(modifiers.take_reset());
+        assert_eq!(modifiers.layout, Layout::Azerty);
+    }
+
+    #[test]
+    fn names() {
+        for layout in Layout::ALL {
+            assert_eq!(Layout::from_name(layout.name().as_bytes()), Some(layout));
+        }
+        assert_eq!(Layout::from_name(b"dvorak"), None);
+    }
+}
@@ -1,8 +1,17 @@
+pub mod ansi;
+pub mod cp437;
 pub mod cursor;
+pub mod indicator;
+#[cfg_attr(test, allow(dead_code))]
+pub mod keymap;
 pub mod ps2;
+#[cfg_attr(test, allow(dead_code))]
+pub mod saver;
 mod screen;
+mod splash;
 #[allow(clippy::module_inception)]
 pub mod terminal;
 pub mod vga;
 
 pub use screen::*;
+pub use splash::splash;
@@ -1,8 +1,53 @@
-use core::arch::asm;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use super::keymap::{Keymap, Layout};
+use crate::{
+    diag::{self, Counter},
+    io::{inb, outb},
+    log,
+};
+
+pub mod mouse;
 
 pub const PS2_DATA_PORT: u16 = 0x60;
 pub const PS2_STATUS_PORT: u16 = 0x64;
+/// Commands for the controller itself are written to the status port.
+pub const PS2_COMMAND_PORT: u16 = 0x64;
 pub const PS2_OUTPUT_BUFFER_STATUS_BIT: u8 = 1;
+/// Set while the controller has not consumed the last byte written to it.
+pub const PS2_INPUT_BUFFER_STATUS_BIT: u8 = 1 << 1;
+
+/// Number of status polls after which a missing or stuck controller is given up on.
+const POLL_LIMIT: u32 = 100_000;
+/// Number of times a command is sent again when the keyboard asks for it.
+const MAX_RESENDS: usize = 3;
+/// Keyboard command setting the lock LEDs, followed by a `ModifierState::led_mask` byte.
+const SET_LEDS: u8 = 0xED;
+/// Keyboard command starting to send scancodes.
+const ENABLE_SCANNING: u8 = 0xF4;
+/// Keyboard command setting the key repeat, followed by the encoded delay and rate.
+const SET_TYPEMATIC: u8 = 0xF3;
+pub const MAX_TYPEMATIC_RATE: u8 = 31;
+pub const MAX_TYPEMATIC_DELAY: u8 = 3;
+
+/// Controller commands.
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const DISABLE_PORT_2: u8 = 0xA7;
+const TEST_CONTROLLER: u8 = 0xAA;
+const TEST_PORT_1: u8 = 0xAB;
+const DISABLE_PORT_1: u8 = 0xAD;
+const ENABLE_PORT_1: u8 = 0xAE;
+const PULSE_RESET: u8 = 0xFE;
+const CONTROLLER_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+/// Interrupt enable bits of both ports in the configuration byte. Input is polled.
+const CONFIG_INTERRUPTS: u8 = 0b11;
+/// Most bytes read while emptying the output buffer or waiting for an answer, in case a device
+/// keeps sending.
+const FLUSH_LIMIT: usize = 16;
 
 /// Reads from the PS2 data port if the PS2 status port is ready. Returns `Some(KeyScanCode)`
 /// if the converted scancode is a supported character.
@@ -15,53 +60,765 @@ pub const PS2_OUTPUT_BUFFER_STATUS_BIT: u8 = 1;
 ///     v.write_char(b'a');
 /// }
 pub fn read_if_ready() -> Option<Key> {
+    translate_from(&mut Keyboard)
+}
+
+/// Set in the status when the byte waiting in the data port was sent by the mouse.
+pub const PS2_AUX_DATA_STATUS_BIT: u8 = 1 << 5;
+
+/// Supplier of raw scancodes, so that the translation can run on other input than the keyboard.
+pub trait ScancodeSource {
+    fn next_scancode(&mut self) -> Option<u8>;
+}
+
+/// The keyboard: scancodes queued by the interrupt handler.
+pub struct Keyboard;
+
+impl ScancodeSource for Keyboard {
+    fn next_scancode(&mut self) -> Option<u8> {
+        // Until interrupts are enabled, the data port is polled into the queues here.
+        let status = status();
+        if status & PS2_OUTPUT_BUFFER_STATUS_BIT != 0 {
+            if status & PS2_AUX_DATA_STATUS_BIT != 0 {
+                mouse::on_interrupt();
+            } else {
+                on_interrupt();
+            }
+        }
+        SCANCODES.pop()
+    }
+}
+
+impl ScancodeSource for &ScancodeQueue {
+    fn next_scancode(&mut self) -> Option<u8> {
+        self.pop()
+    }
+}
+
+impl ScancodeSource for core::slice::Iter<'_, u8> {
+    fn next_scancode(&mut self) -> Option<u8> {
+        self.next().copied()
+    }
+}
+
+/// Reads a single scancode and returns it along with the key it completes, if any. Meant for
+/// debugging layouts, where the bytes sent by the controller matter more than the keys.
+#[cfg_attr(test, allow(dead_code))]
+pub fn read_raw_if_ready() -> Option<(u8, Option<Key>)> {
+    let code = Keyboard.next_scancode()?;
+    Some((code, translate(code)))
+}
+
+/// Translates scancodes from `source` with `modifiers` until one of them produces a key.
+#[allow(dead_code)]
+pub fn decode_from(source: &mut impl ScancodeSource, modifiers: &mut ModifierState) -> Option<Key> {
+    while let Some(code) = source.next_scancode() {
+        if let Some(key) = decode_scancode(code, modifiers) {
+            return Some(key);
+        }
+    }
+    None
+}
+
+/// Same as `decode_from`, with the global modifier state and its side effects (see `translate`).
+fn translate_from(source: &mut impl ScancodeSource) -> Option<Key> {
+    while let Some(code) = source.next_scancode() {
+        if let Some(key) = translate(code) {
+            return Some(key);
+        }
+    }
+    None
+}
+
+/// Body of the keyboard (IRQ1) handler: queues the scancode waiting in the data port. The caller
+/// acknowledges the interrupt.
+pub fn on_interrupt() {
+    queue_scancode(unsafe { inb(PS2_DATA_PORT) });
+}
+
+/// Queues `code` for the key translation, as if it was read by the interrupt handler.
+fn queue_scancode(code: u8) {
+    diag::bump(Counter::ScancodesRead);
+    SCANCODES.push(code);
+}
+
+/// Translates the queued scancodes until one of them produces a key.
+#[allow(dead_code)]
+pub fn pop_key() -> Option<Key> {
+    translate_from(&mut &SCANCODES)
+}
+
+/// Number of scancodes the queue holds between the interrupt handler and `pop_key`.
+const SCANCODE_QUEUE_SIZE: usize = 64;
+
+/// Lock-free ring of raw scancodes, filled by a single producer (the interrupt handler) and
+/// drained by the kernel. When full, the oldest scancode is dropped and counted.
+pub struct ScancodeQueue {
+    codes: [AtomicU8; SCANCODE_QUEUE_SIZE],
+    /// Total number of scancodes removed, either popped or dropped.
+    head: AtomicUsize,
+    /// Total number of scancodes pushed. Only written by the producer.
+    tail: AtomicUsize,
+}
+
+impl ScancodeQueue {
+    pub const fn new() -> Self {
+        ScancodeQueue {
+            codes: [const { AtomicU8::new(0) }; SCANCODE_QUEUE_SIZE],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn push(&self, code: u8) {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        // Moving the head invalidates a pop in progress on the oldest slot, which then retries.
+        if tail - head == SCANCODE_QUEUE_SIZE && self.head.compare_exchange(head, head + 1, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+            diag::bump(Counter::ScancodeOverruns);
+        }
+        self.codes[tail % SCANCODE_QUEUE_SIZE].store(code, Ordering::Relaxed);
+        self.tail.store(tail + 1, Ordering::Release);
+    }
+
+    pub fn pop(&self) -> Option<u8> {
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            if head == self.tail.load(Ordering::Acquire) {
+                return None;
+            }
+            let code = self.codes[head % SCANCODE_QUEUE_SIZE].load(Ordering::Relaxed);
+            if self.head.compare_exchange(head, head + 1, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+                return Some(code);
+            }
+        }
+    }
+}
+
+static SCANCODES: ScancodeQueue = ScancodeQueue::new();
+
+/// Decodes `code` with the global modifier state, logging keyboard resets and mirroring lock
+/// changes on the LEDs.
+fn translate(code: u8) -> Option<Key> {
+    let mut modifiers = MODIFIERS.lock();
+    let leds = modifiers.led_mask();
+    let key = decode_scancode(code, &mut modifiers);
+    if modifiers.take_reset() {
+        let leds = modifiers.led_mask();
+        drop(modifiers);
+        log::notice("keyboard reset detected, state cleared");
+        if reconfigure(leds).is_err() {
+            log::notice("keyboard did not accept its configuration after the reset");
+        }
+        return key;
+    }
+    if modifiers.led_mask() != leds {
+        let leds = modifiers.led_mask();
+        drop(modifiers);
+        if set_leds(leds).is_err() {
+            log::notice("keyboard did not acknowledge the LED update");
+        }
+    }
+    key
+}
+
+/// Failures of an exchange with a PS/2 device.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Ps2Error {
+    /// The controller did not take the byte within the allotted polls.
+    NotReady,
+    /// The device did not answer within the allotted polls.
+    NoResponse,
+    /// The device kept asking for the byte to be sent again.
+    ResendExhausted,
+    /// An argument is outside the range the device accepts.
+    OutOfRange,
+}
+
+impl Ps2Error {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Ps2Error::NotReady => "controller not ready",
+            Ps2Error::NoResponse => "no response",
+            Ps2Error::ResendExhausted => "too many resend requests",
+            Ps2Error::OutOfRange => "setting out of range",
+        }
+    }
+}
+
+impl From<Ps2Error> for &'static str {
+    fn from(e: Ps2Error) -> Self {
+        e.message()
+    }
+}
+
+/// Sends the configuration a keyboard loses when it resets itself, e.g. when plugged back in:
+/// scanning, the lock LEDs and the key repeat, if it was set.
+fn reconfigure(leds: u8) -> Result<(), Ps2Error> {
+    write_and_wait_ack(ENABLE_SCANNING, POLL_LIMIT)?;
+    set_leds(leds)?;
+    let typematic = *TYPEMATIC.lock();
+    match typematic {
+        Some((rate, delay)) => set_typematic(rate, delay),
+        None => Ok(()),
+    }
+}
+
+/// Writes `byte` to `port` once the controller is ready to take it.
+fn write_when_ready(port: u16, byte: u8) -> Result<(), Ps2Error> {
+    for _ in 0..POLL_LIMIT {
+        if unsafe { inb(PS2_STATUS_PORT) } & PS2_INPUT_BUFFER_STATUS_BIT == 0 {
+            unsafe { outb(port, byte) };
+            return Ok(());
+        }
+    }
+    Err(Ps2Error::NotReady)
+}
+
+/// Writes `byte` to the keyboard once the controller is ready to take it.
+pub fn write_data(byte: u8) -> Result<(), Ps2Error> {
+    write_when_ready(PS2_DATA_PORT, byte)
+}
+
+fn write_command(command: u8) -> Result<(), Ps2Error> {
+    write_when_ready(PS2_COMMAND_PORT, command)
+}
+
+/// Sends `command` to the controller and returns its answer.
+fn query(command: u8) -> Result<u8, Ps2Error> {
+    write_command(command)?;
+    read_with_timeout(POLL_LIMIT).ok_or(Ps2Error::NoResponse)
+}
+
+fn write_config(config: u8) -> Result<(), Ps2Error> {
+    write_command(WRITE_CONFIG)?;
+    write_data(config)
+}
+
+/// Brings up the 8042 controller instead of trusting the state the BIOS left it in: both ports
+/// are disabled while the controller and the first port are tested, then the keyboard is enabled
+/// with interrupts off, since input is polled.
+pub fn init() -> Result<(), &'static str> {
+    write_command(DISABLE_PORT_1)?;
+    write_command(DISABLE_PORT_2)?;
+    for _ in 0..FLUSH_LIMIT {
+        if unsafe { inb(PS2_STATUS_PORT) } & PS2_OUTPUT_BUFFER_STATUS_BIT == 0 {
+            break;
+        }
+        unsafe { inb(PS2_DATA_PORT) };
+    }
+
+    let config = query(READ_CONFIG)? & !CONFIG_INTERRUPTS;
+    write_config(config)?;
+    if query(TEST_CONTROLLER)? != CONTROLLER_TEST_PASSED {
+        return Err("controller self-test failed");
+    }
+    // The self-test may reset the controller.
+    write_config(config)?;
+    if query(TEST_PORT_1)? != PORT_TEST_PASSED {
+        return Err("keyboard port test failed");
+    }
+
+    write_command(ENABLE_PORT_1)?;
+    Ok(write_and_wait_ack(ENABLE_SCANNING, POLL_LIMIT)?)
+}
+
+/// Waits at most `max_polls` status reads for the next byte sent by a device.
+pub fn read_with_timeout(max_polls: u32) -> Option<u8> {
+    for _ in 0..max_polls {
+        if unsafe { inb(PS2_STATUS_PORT) } & PS2_OUTPUT_BUFFER_STATUS_BIT != 0 {
+            return Some(unsafe { inb(PS2_DATA_PORT) });
+        }
+    }
+    None
+}
+
+/// Sends `byte` to the keyboard until it is acknowledged, repeating it when the keyboard asks for
+/// a resend. Each answer is waited for at most `max_polls` status reads. Keys pressed in the
+/// meantime are queued instead of being taken for the answer.
+pub fn write_and_wait_ack(byte: u8, max_polls: u32) -> Result<(), Ps2Error> {
+    send_with_ack(byte, max_polls, write_data, queue_scancode)
+}
+
+/// Sends `byte` with `write` until it is acknowledged, see `write_and_wait_ack`. Other bytes the
+/// device sends before its answer are handed to `stray`, up to `FLUSH_LIMIT` of them.
+fn send_with_ack(byte: u8, max_polls: u32, write: impl Fn(u8) -> Result<(), Ps2Error>, stray: impl Fn(u8)) -> Result<(), Ps2Error> {
+    'resend: for _ in 0..=MAX_RESENDS {
+        write(byte)?;
+        for _ in 0..FLUSH_LIMIT {
+            match read_with_timeout(max_polls).ok_or(Ps2Error::NoResponse)? {
+                ACK => return Ok(()),
+                RESEND => continue 'resend,
+                other => stray(other),
+            }
+        }
+        return Err(Ps2Error::NoResponse);
+    }
+    Err(Ps2Error::ResendExhausted)
+}
+
+/// Resets the machine through the controller's reset line, once the pending input is drained.
+#[cfg_attr(test, allow(dead_code))]
+pub fn reboot() {
+    while read_if_ready().is_some() {}
+    let _ = write_command(PULSE_RESET);
+}
+
+/// Makes held keys repeat at `rate`, from 0 (fastest) to `MAX_TYPEMATIC_RATE`, once they were held
+/// for `delay`, from 0 (shortest) to `MAX_TYPEMATIC_DELAY`.
+pub fn set_typematic(rate: u8, delay: u8) -> Result<(), Ps2Error> {
+    if rate > MAX_TYPEMATIC_RATE || delay > MAX_TYPEMATIC_DELAY {
+        return Err(Ps2Error::OutOfRange);
+    }
+    write_and_wait_ack(SET_TYPEMATIC, POLL_LIMIT)?;
+    write_and_wait_ack(delay << 5 | rate, POLL_LIMIT)?;
+    *TYPEMATIC.lock() = Some((rate, delay));
+    Ok(())
+}
+
+/// Rate and delay of the key repeat last set with `set_typematic`.
+static TYPEMATIC: Mutex<Option<(u8, u8)>> = Mutex::new(None);
+
+/// Milliseconds between two repeats at typematic `rate`, rounded down.
+pub fn typematic_period_ms(rate: u8) -> u32 {
+    // The period is (8 + A) * 2^B * 4.17 ms, with A the low three bits and B the next two.
+    (8 + (rate & 0b111) as u32) * (1 << (rate >> 3 & 0b11)) * 417 / 100
+}
+
+/// Milliseconds a key is held before it repeats with typematic `delay`.
+pub fn typematic_delay_ms(delay: u8) -> u32 {
+    250 * (delay as u32 + 1)
+}
+
+/// Lights the lock LEDs of the keyboard according to `mask`.
+pub fn set_leds(mask: u8) -> Result<(), Ps2Error> {
+    write_and_wait_ack(SET_LEDS, POLL_LIMIT)?;
+    write_and_wait_ack(mask, POLL_LIMIT)
+}
+
+/// Reads a raw scancode from the PS2 data port if one is available, bypassing the decoding and
+/// the modifier state.
+#[cfg_attr(test, allow(dead_code))]
+pub fn read_scancode_if_ready() -> Option<u8> {
     if !is_ps2_data_available() {
         return None;
     }
+    Some(unsafe { inb(PS2_DATA_PORT) })
+}
 
-    let code = unsafe { read(PS2_DATA_PORT) };
+/// Sent by the keyboard when its self-test passed, e.g. after being plugged back in.
+const SELF_TEST_PASSED: u8 = 0xAA;
+/// Response to the echo command.
+const ECHO: u8 = 0xEE;
+/// Acknowledgement of a command.
+const ACK: u8 = 0xFA;
+/// Request to resend the last command.
+const RESEND: u8 = 0xFE;
+/// Key detection error or internal buffer overrun.
+const ERROR: u8 = 0xFF;
+/// Key detection error or internal buffer overrun (scancode set 1 variant).
+const ERROR_SET_1: u8 = 0x00;
 
-    SCANCODE_TO_KEY[code as usize]
+/// Running totals of the keyboard input, to tell whether keys get lost and where, with their
+/// names. They are derived from the diag counters, which wrap around instead of overflowing.
+pub fn input_stats() -> [(&'static str, u32); 4] {
+    let protocol_bytes = Counter::PS2_PROTOCOL.into_iter().fold(0, |sum: u32, c| sum.wrapping_add(diag::get(c)));
+    [
+        ("scancodes read", diag::get(Counter::ScancodesRead)),
+        ("protocol bytes", protocol_bytes),
+        ("unknown scancodes", diag::get(Counter::UnknownScancodes)),
+        ("queue overruns", diag::get(Counter::ScancodeOverruns)),
+    ]
 }
 
-/// Returns `true` if the PS2 input buffer has data ready to be read,
-/// meaning the least significant bit of the PS2 status port is set.
-fn is_ps2_data_available() -> bool {
-    status() & PS2_OUTPUT_BUFFER_STATUS_BIT != 0
+/// Resets the counters behind `input_stats`.
+pub fn reset_input_stats() {
+    let input = [Counter::ScancodesRead, Counter::UnknownScancodes, Counter::ScancodeOverruns];
+    for counter in input.into_iter().chain(Counter::PS2_PROTOCOL) {
+        diag::reset_counter(counter);
+    }
 }
 
-/// Reads from `PS2_STATUS_PORT` and returns the extracted value.
-fn status() -> u8 {
-    let res: u8;
+/// Translates a raw scancode into a `Key`, updating `modifiers` along the way.
+///
+/// Protocol bytes (ACK, resend, echo, errors) never produce a key. `0xAA` is both the break code of
+/// the left Shift and the keyboard's self-test result after a reset; it is treated as a break code
+/// only while the left Shift is held, and as a device reset clearing every modifier and lock
+/// otherwise. A keyboard replugged while the left Shift is held therefore only releases the Shift:
+/// the locks stay on until toggled, as telling the two apart would clear them on every release.
+pub fn decode_scancode(code: u8, modifiers: &mut ModifierState) -> Option<Key> {
+    decode_event(code, modifiers).map(|e| e.key)
+}
+
+/// Physical origin of a `KeyEvent` whose `key` was canonicalized.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum KeyOrigin {
+    /// The key itself.
+    Main,
+    /// The Enter key of the numeric keypad, delivered as `Key::Enter`.
+    KeypadEnter,
+    /// Ctrl+I, delivered as `Key::Tab`.
+    CtrlI,
+}
+
+/// A decoded key press.
+///
+/// `key` is canonical: keys that are equivalent in cooked input (keypad Enter and Enter, Ctrl+I and
+/// Tab) produce the same `key`, so that prompts only have to match one variant. Raw-mode consumers
+/// can tell them apart through `origin`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeyEvent {
+    pub key: Key,
+    /// Set for the typematic repeats the keyboard sends while the key is held down.
+    pub repeat: bool,
+    pub origin: KeyOrigin,
+    /// Set while a Ctrl key is held, for chords such as Ctrl+Alt+Del.
+    pub ctrl: bool,
+    /// Set while an Alt key is held, for chords such as Alt+Tab.
+    pub alt: bool,
+}
 
-    unsafe {
-        res = read(PS2_STATUS_PORT);
+/// Same as `decode_scancode`, but also reports whether the key press is a typematic repeat.
+pub fn decode_event(code: u8, modifiers: &mut ModifierState) -> Option<KeyEvent> {
+    // The Pause sequence contains a fake Ctrl press, so none of its bytes may be decoded on their own.
+    if modifiers.pause_remaining != 0 {
+        modifiers.pause_remaining -= 1;
+        if modifiers.pause_remaining != 0 {
+            return None;
+        }
+        return Some(KeyEvent {
+            key: Key::Pause,
+            repeat: false,
+            origin: KeyOrigin::Main,
+            ctrl: modifiers.ctrl,
+            alt: modifiers.alt,
+        });
+    }
+    let extended = core::mem::take(&mut modifiers.extended);
+    match code {
+        PAUSE_PREFIX => {
+            modifiers.pause_remaining = PAUSE_SEQUENCE_LENGTH - 1;
+            return None;
+        }
+        EXTENDED_PREFIX => {
+            modifiers.extended = true;
+            return None;
+        }
+        SELF_TEST_PASSED if !extended && !modifiers.left_shift => {
+            diag::bump(Counter::Ps2SelfTests);
+            modifiers.reset();
+            return None;
+        }
+        ECHO => {
+            diag::bump(Counter::Ps2Echoes);
+            return None;
+        }
+        ACK => {
+            diag::bump(Counter::Ps2Acks);
+            return None;
+        }
+        RESEND => {
+            diag::bump(Counter::Ps2Resends);
+            return None;
+        }
+        ERROR | ERROR_SET_1 => {
+            diag::bump(Counter::Ps2Errors);
+            return None;
+        }
+        _ => {}
     }
 
-    res
+    // Some extended keys are wrapped in fake Shift presses, which must not change the state. Print
+    // Screen is one of them, and is not otherwise supported.
+    if extended && matches!(code & !BREAK_BIT, LEFT_SHIFT | RIGHT_SHIFT | PRINT_SCREEN) {
+        return None;
+    }
+    if modifiers.update(code) {
+        return None;
+    }
+
+    let id = (extended as u16) << 8 | (code & !BREAK_BIT) as u16;
+    if code & BREAK_BIT != 0 {
+        if modifiers.held == Some(id) {
+            modifiers.held = None;
+        }
+        return None;
+    }
+    let repeat = modifiers.held == Some(id);
+    modifiers.held = Some(id);
+
+    let (key, origin) = match code {
+        ENTER if extended => (Key::Enter, KeyOrigin::KeypadEnter),
+        _ if extended && extended_key(code).is_some() => (extended_key(code)?, KeyOrigin::Main),
+        KEY_I if modifiers.ctrl => (Key::Tab, KeyOrigin::CtrlI),
+        KEY_C if modifiers.ctrl => (Key::CtrlC, KeyOrigin::Main),
+        KEY_L if modifiers.ctrl => (Key::CtrlL, KeyOrigin::Main),
+        KEY_U if modifiers.ctrl => (Key::CtrlU, KeyOrigin::Main),
+        KEY_K if modifiers.ctrl => (Key::CtrlK, KeyOrigin::Main),
+        KEY_A if modifiers.ctrl => (Key::CtrlA, KeyOrigin::Main),
+        KEY_E if modifiers.ctrl => (Key::CtrlE, KeyOrigin::Main),
+        ARROW_LEFT if extended && modifiers.ctrl => (Key::CtrlArrowLeft, KeyOrigin::Main),
+        ARROW_RIGHT if extended && modifiers.ctrl => (Key::CtrlArrowRight, KeyOrigin::Main),
+        _ => {
+            let keymap = modifiers.layout.keymap();
+            let table = if modifiers.shift() != (modifiers.caps_lock && is_letter(code, keymap)) {
+                &keymap.shifted
+            } else {
+                &keymap.normal
+            };
+            let Some(key) = table[code as usize] else {
+                diag::bump(Counter::UnknownScancodes);
+                return None;
+            };
+            (key, KeyOrigin::Main)
+        }
+    };
+    Some(KeyEvent {
+        key,
+        repeat,
+        origin,
+        ctrl: modifiers.ctrl,
+        alt: modifiers.alt,
+    })
 }
 
-/// Reads from `port` and returns the extracted value.
-/// ## SAFETY:
-/// `port` is assumed to be one of `PS2_STATUS_PORT` or `PS2_DATA_PORT`. Passing another value
-/// to this function will result in a panic.
-///
-unsafe fn read(port: u16) -> u8 {
-    assert!(port == PS2_DATA_PORT || port == PS2_STATUS_PORT);
+/// Returns `true` if `code` is the make code of a letter, the only keys affected by Caps Lock.
+fn is_letter(code: u8, keymap: &Keymap) -> bool {
+    keymap.normal[code as usize].is_some_and(|k| (k as u8).is_ascii_lowercase())
+}
+
+/// Keys of the navigation cluster that only exist behind `EXTENDED_PREFIX`, with their second byte.
+/// Without the prefix the same bytes are keys of the numeric keypad.
+const EXTENDED_KEYS: [(u8, Key); 6] = [(0x47, Home), (0x49, PageUp), (0x4F, End), (0x51, PageDown), (0x52, Insert), (0x53, Delete)];
+
+/// Returns the navigation key sent as `EXTENDED_PREFIX` followed by `code`.
+fn extended_key(code: u8) -> Option<Key> {
+    EXTENDED_KEYS.iter().find(|(c, _)| *c == code).map(|&(_, k)| k)
+}
+
+/// Announces that the next byte belongs to an extended key.
+const EXTENDED_PREFIX: u8 = 0xE0;
+/// Starts the Pause sequence, `E1 1D 45 E1 9D C5`, which has no break code.
+const PAUSE_PREFIX: u8 = 0xE1;
+const PAUSE_SEQUENCE_LENGTH: u8 = 6;
+/// Sent after `EXTENDED_PREFIX` by Print Screen, which shares it with the keypad `*`.
+const PRINT_SCREEN: u8 = 0x37;
+const ENTER: u8 = 0x1C;
+const KEY_I: u8 = 0x17;
+const KEY_C: u8 = 0x2E;
+const KEY_L: u8 = 0x26;
+const KEY_U: u8 = 0x16;
+const KEY_K: u8 = 0x25;
+const KEY_A: u8 = 0x1E;
+const KEY_E: u8 = 0x12;
+const ARROW_LEFT: u8 = 0x4B;
+const ARROW_RIGHT: u8 = 0x4D;
+
+const LEFT_SHIFT: u8 = 0x2A;
+const RIGHT_SHIFT: u8 = 0x36;
+const CTRL: u8 = 0x1D;
+const ALT: u8 = 0x38;
+const CAPS_LOCK: u8 = 0x3A;
+const NUM_LOCK: u8 = 0x45;
+const SCROLL_LOCK: u8 = 0x46;
+/// Set on the scancode of a key release (break code).
+const BREAK_BIT: u8 = 0x80;
+
+/// State of the modifier and lock keys, maintained from the raw scancode stream.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct ModifierState {
+    pub left_shift: bool,
+    pub right_shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+    caps_lock_held: bool,
+    num_lock_held: bool,
+    scroll_lock_held: bool,
+    changed: bool,
+    was_reset: bool,
+    /// Last pressed key until it is released, used to recognize typematic repeats. Extended keys
+    /// have bit 8 set.
+    held: Option<u16>,
+    /// Set after an `EXTENDED_PREFIX` byte.
+    extended: bool,
+    /// Bytes of the Pause sequence left to swallow after its `PAUSE_PREFIX`.
+    pause_remaining: u8,
+    /// Layout used to translate scancodes, kept across keyboard resets.
+    pub layout: Layout,
+}
+
+impl ModifierState {
+    pub const fn new() -> Self {
+        ModifierState {
+            left_shift: false,
+            right_shift: false,
+            ctrl: false,
+            alt: false,
+            caps_lock: false,
+            num_lock: false,
+            scroll_lock: false,
+            caps_lock_held: false,
+            num_lock_held: false,
+            scroll_lock_held: false,
+            changed: false,
+            was_reset: false,
+            held: None,
+            extended: false,
+            pause_remaining: 0,
+            layout: Layout::Qwerty,
+        }
+    }
 
-    let res: u8;
+    /// Clears every modifier and lock, as after a keyboard reset.
+    pub fn reset(&mut self) {
+        let before = *self;
+        *self = ModifierState::new();
+        self.layout = before.layout;
+        self.changed = before.changed || self.visible_state() != before.visible_state();
+        self.was_reset = true;
+    }
+
+    /// Returns `true` once after `reset` was called.
+    pub fn take_reset(&mut self) -> bool {
+        core::mem::take(&mut self.was_reset)
+    }
+
+    pub fn shift(&self) -> bool {
+        self.left_shift || self.right_shift
+    }
+
+    /// Updates the state from `code` and returns `true` if it belongs to a modifier or lock key,
+    /// in which case it must not be translated into a `Key`.
+    ///
+    /// Lock keys toggle on their first make code only, so that typematic repeats while the key is
+    /// held do not toggle them again.
+    pub fn update(&mut self, code: u8) -> bool {
+        let pressed = code & BREAK_BIT == 0;
+        let before = *self;
 
-    asm!(
-        "in al, dx",
-        in("dx") port,
-        out("al") res,
-    );
+        match code & !BREAK_BIT {
+            LEFT_SHIFT => self.left_shift = pressed,
+            RIGHT_SHIFT => self.right_shift = pressed,
+            CTRL => self.ctrl = pressed,
+            ALT => self.alt = pressed,
+            CAPS_LOCK => {
+                if pressed && !self.caps_lock_held {
+                    self.caps_lock = !self.caps_lock;
+                }
+                self.caps_lock_held = pressed;
+            }
+            NUM_LOCK => {
+                if pressed && !self.num_lock_held {
+                    self.num_lock = !self.num_lock;
+                }
+                self.num_lock_held = pressed;
+            }
+            SCROLL_LOCK => {
+                if pressed && !self.scroll_lock_held {
+                    self.scroll_lock = !self.scroll_lock;
+                }
+                self.scroll_lock_held = pressed;
+            }
+            _ => return false,
+        }
+
+        if self.visible_state() != before.visible_state() {
+            self.changed = true;
+        }
+        true
+    }
 
-    res
+    /// Returns the state if it changed since the last call, for consumers that only need to react
+    /// to modifier-change events.
+    pub fn take_change(&mut self) -> Option<ModifierState> {
+        if !self.changed {
+            return None;
+        }
+        self.changed = false;
+        Some(*self)
+    }
+
+    /// Returns `true` if the last scancode decoded starts a multi-byte sequence.
+    pub fn sequence_pending(&self) -> bool {
+        self.extended || self.pause_remaining != 0
+    }
+
+    /// Lock states in the layout of the `SET_LEDS` argument.
+    pub fn led_mask(&self) -> u8 {
+        self.scroll_lock as u8 | (self.num_lock as u8) << 1 | (self.caps_lock as u8) << 2
+    }
+
+    fn visible_state(&self) -> (bool, bool, bool, bool, bool) {
+        (self.shift(), self.ctrl, self.alt, self.caps_lock, self.num_lock)
+    }
+}
+
+static MODIFIERS: Mutex<ModifierState> = Mutex::new(ModifierState::new());
+
+/// Serializes the tests that change the global keyboard state, and resets that state for each.
+#[cfg(test)]
+pub fn reset_for_test() -> spin::MutexGuard<'static, ()> {
+    static KEYBOARD_TESTS: Mutex<()> = Mutex::new(());
+    let guard = KEYBOARD_TESTS.lock();
+    *MODIFIERS.lock() = ModifierState::new();
+    *TYPEMATIC.lock() = None;
+    crate::io::mock::clear();
+    guard
+}
+
+/// Selects the layout used to translate the following scancodes.
+pub fn set_layout(layout: Layout) {
+    MODIFIERS.lock().layout = layout;
+}
+
+/// Returns the layout used to translate scancodes.
+pub fn layout() -> Layout {
+    MODIFIERS.lock().layout
+}
+
+/// Returns `true` if the last scancode read starts a multi-byte sequence.
+#[cfg_attr(test, allow(dead_code))]
+pub fn sequence_pending() -> bool {
+    MODIFIERS.lock().sequence_pending()
+}
+
+/// Returns `true` while both a Ctrl and an Alt key are held down.
+pub fn ctrl_alt_held() -> bool {
+    let modifiers = MODIFIERS.lock();
+    modifiers.ctrl && modifiers.alt
+}
+
+/// Returns `true` while Caps Lock is on.
+pub fn caps_lock() -> bool {
+    MODIFIERS.lock().caps_lock
+}
+
+/// Returns `true` while Num Lock is on.
+pub fn num_lock() -> bool {
+    MODIFIERS.lock().num_lock
+}
+
+/// Returns the modifier state if it changed since the last call.
+pub fn take_modifier_change() -> Option<ModifierState> {
+    MODIFIERS.lock().take_change()
+}
+
+/// Returns `true` if the PS2 input buffer has data ready to be read,
+/// meaning the least significant bit of the PS2 status port is set.
+fn is_ps2_data_available() -> bool {
+    status() & PS2_OUTPUT_BUFFER_STATUS_BIT != 0
+}
+
+/// Reads from `PS2_STATUS_PORT` and returns the extracted value.
+fn status() -> u8 {
+    unsafe { inb(PS2_STATUS_PORT) }
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
 pub enum Key {
     Escape,
     Tab,
@@ -71,6 +828,36 @@ pub enum Key {
     ArrowDown,
     ArrowLeft,
     ArrowRight,
+    /// Ctrl+C, which cancels the prompt line.
+    CtrlC,
+    Home,
+    End,
+    Delete,
+    PageUp,
+    PageDown,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    /// Ctrl+L, which clears the screen.
+    CtrlL,
+    /// Ctrl+U, which erases the prompt line before the cursor.
+    CtrlU,
+    /// Ctrl+K, which erases the prompt line after the cursor.
+    CtrlK,
+    /// Ctrl+A, which moves to the start of the prompt line.
+    CtrlA,
+    /// Ctrl+E, which moves to the end of the prompt line.
+    CtrlE,
+    Pause,
     A = b'a',
     B = b'b',
     C = b'c',
@@ -120,11 +907,125 @@ pub enum Key {
     SingleQuote = b'\'',
     SquareBracketsOpen = b'[',
     SquareBracketsClosed = b']',
+    UpperA = b'A',
+    UpperB = b'B',
+    UpperC = b'C',
+    UpperD = b'D',
+    UpperE = b'E',
+    UpperF = b'F',
+    UpperG = b'G',
+    UpperH = b'H',
+    UpperI = b'I',
+    UpperJ = b'J',
+    UpperK = b'K',
+    UpperL = b'L',
+    UpperM = b'M',
+    UpperN = b'N',
+    UpperO = b'O',
+    UpperP = b'P',
+    UpperQ = b'Q',
+    UpperR = b'R',
+    UpperS = b'S',
+    UpperT = b'T',
+    UpperU = b'U',
+    UpperV = b'V',
+    UpperW = b'W',
+    UpperX = b'X',
+    UpperY = b'Y',
+    UpperZ = b'Z',
+    Exclamation = b'!',
+    At = b'@',
+    Hash = b'#',
+    Dollar = b'$',
+    Percent = b'%',
+    Caret = b'^',
+    Ampersand = b'&',
+    ParenthesesOpen = b'(',
+    ParenthesesClosed = b')',
+    Underscore = b'_',
+    Plus = b'+',
+    CurlyBracketsOpen = b'{',
+    CurlyBracketsClosed = b'}',
+    Colon = b':',
+    DoubleQuote = b'"',
+    Tilde = b'~',
+    Pipe = b'|',
+    LessThan = b'<',
+    GreaterThan = b'>',
+    QuestionMark = b'?',
+    /// Ctrl+Left, which moves to the previous word of the prompt line. Past the printable
+    /// characters, since the values below them are taken.
+    CtrlArrowLeft = 0x80,
+    /// Ctrl+Right, which moves to the next word of the prompt line.
+    CtrlArrowRight,
+    Insert,
+}
+
+impl Key {
+    /// Name of a key that does not print a character.
+    pub fn name(self) -> Option<&'static str> {
+        Some(match self {
+            Escape => "Escape",
+            Tab => "Tab",
+            Enter => "Enter",
+            ArrowUp => "Up",
+            Backspace => "Backspace",
+            ArrowDown => "Down",
+            ArrowLeft => "Left",
+            ArrowRight => "Right",
+            CtrlC => "Ctrl+C",
+            Home => "Home",
+            End => "End",
+            Delete => "Delete",
+            PageUp => "PageUp",
+            PageDown => "PageDown",
+            F1 => "F1",
+            F2 => "F2",
+            F3 => "F3",
+            F4 => "F4",
+            F5 => "F5",
+            F6 => "F6",
+            F7 => "F7",
+            F8 => "F8",
+            F9 => "F9",
+            F10 => "F10",
+            F11 => "F11",
+            F12 => "F12",
+            CtrlL => "Ctrl+L",
+            CtrlU => "Ctrl+U",
+            CtrlK => "Ctrl+K",
+            CtrlA => "Ctrl+A",
+            CtrlE => "Ctrl+E",
+            Pause => "Pause",
+            CtrlArrowLeft => "Ctrl+Left",
+            CtrlArrowRight => "Ctrl+Right",
+            Insert => "Insert",
+            Space => "Space",
+            _ => return None,
+        })
+    }
+
+    /// Returns `n` for the function key Fn.
+    pub fn function_number(&self) -> Option<u8> {
+        (F1..=F12).contains(self).then(|| *self as u8 - F1 as u8 + 1)
+    }
+
+    /// Returns the `Key` whose discriminant is `value`, if any.
+    pub fn from_u8(value: u8) -> Option<Key> {
+        SCANCODE_TO_KEY
+            .iter()
+            .chain(SCANCODE_TO_KEY_SHIFTED.iter())
+            .flatten()
+            .copied()
+            .chain(EXTENDED_KEYS.iter().map(|&(_, k)| k))
+            .chain([CtrlC, CtrlL, CtrlU, CtrlK, CtrlA, CtrlE, Pause, CtrlArrowLeft, CtrlArrowRight])
+            .find(|&k| k as u8 == value)
+    }
 }
 
 use Key::*;
 /// Conversion table for all characters currently supported by our kernel for PS2 input.
-const SCANCODE_TO_KEY: [Option<Key>; 256] = [
+pub const SCANCODE_TO_KEY: [Option<Key>; 256] = [
     None,
     Some(Escape),
     Some(N1),
@@ -184,6 +1085,71 @@ const SCANCODE_TO_KEY: [Option<Key>; 256] = [
     None,
     Some(Space),
     None,
+    Some(F1),
+    Some(F2),
+    Some(F3),
+    Some(F4),
+    Some(F5),
+    Some(F6),
+    Some(F7),
+    Some(F8),
+    Some(F9),
+    Some(F10),
+    None,
+    None,
+    None,
+    Some(ArrowUp),
+    None,
+    None,
+    Some(ArrowLeft),
+    None,
+    Some(ArrowRight),
+    None,
+    None,
+    Some(ArrowDown),
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    Some(F11),
+    Some(F12),
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
     None,
     None,
     None,
@@ -197,6 +1163,201 @@ const SCANCODE_TO_KEY: [Option<Key>; 256] = [
     None,
     None,
     None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+    None,
+];
+
+/// Same as `SCANCODE_TO_KEY`, for keys pressed while a Shift key is held.
+pub const SCANCODE_TO_KEY_SHIFTED: [Option<Key>; 256] = [
+    None,
+    Some(Escape),
+    Some(Exclamation),
+    Some(At),
+    Some(Hash),
+    Some(Dollar),
+    Some(Percent),
+    Some(Caret),
+    Some(Ampersand),
+    Some(Star),
+    Some(ParenthesesOpen),
+    Some(ParenthesesClosed),
+    Some(Underscore),
+    Some(Plus),
+    Some(Backspace),
+    Some(Tab),
+    Some(UpperQ),
+    Some(UpperW),
+    Some(UpperE),
+    Some(UpperR),
+    Some(UpperT),
+    Some(UpperY),
+    Some(UpperU),
+    Some(UpperI),
+    Some(UpperO),
+    Some(UpperP),
+    Some(CurlyBracketsOpen),
+    Some(CurlyBracketsClosed),
+    Some(Enter),
+    None,
+    Some(UpperA),
+    Some(UpperS),
+    Some(UpperD),
+    Some(UpperF),
+    Some(UpperG),
+    Some(UpperH),
+    Some(UpperJ),
+    Some(UpperK),
+    Some(UpperL),
+    Some(Colon),
+    Some(DoubleQuote),
+    Some(Tilde),
+    None,
+    Some(Pipe),
+    Some(UpperZ),
+    Some(UpperX),
+    Some(UpperC),
+    Some(UpperV),
+    Some(UpperB),
+    Some(UpperN),
+    Some(UpperM),
+    Some(LessThan),
+    Some(GreaterThan),
+    Some(QuestionMark),
+    None,
+    Some(Star),
+    None,
+    Some(Space),
+    None,
+    Some(F1),
+    Some(F2),
+    Some(F3),
+    Some(F4),
+    Some(F5),
+    Some(F6),
+    Some(F7),
+    Some(F8),
+    Some(F9),
+    Some(F10),
+    None,
+    None,
+    None,
     Some(ArrowUp),
     None,
     None,
@@ -212,8 +1373,8 @@ const SCANCODE_TO_KEY: [Option<Key>; 256] = [
     None,
     None,
     None,
-    None,
-    None,
+    Some(F11),
+    Some(F12),
     None,
     None,
     None,
@@ -382,3 +1543,493 @@ const SCANCODE_TO_KEY: [Option<Key>; 256] = [
     None,
     None,
 ];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::mock;
+
+    fn feed(bytes: &[u8], modifiers: &mut ModifierState) -> usize {
+        bytes.iter().filter_map(|&b| decode_scancode(b, modifiers)).count()
+    }
+
+    #[test]
+    fn input_stats_follow_the_diag_counters() {
+        let before = input_stats();
+        let queue = ScancodeQueue::new();
+        for code in 0..=SCANCODE_QUEUE_SIZE as u8 {
+            queue.push(code);
+        }
+        let mut modifiers = ModifierState::new();
+        decode_scancode(0x54, &mut modifiers);
+        decode_scancode(ECHO, &mut modifiers);
+        let after = input_stats();
+        // Protocol bytes, unknown scancodes and overruns; other tests may count too.
+        for i in 1..4 {
+            assert_eq!(after[i].0, before[i].0);
+            assert!(after[i].1 != before[i].1);
+        }
+    }
+
+    #[test]
+    fn protocol_bytes_never_produce_keys() {
+        let before = diag::get(Counter::Ps2Acks);
+        for prefix in [None, Some(0xE0)] {
+            for code in [0x00, 0xAA, 0xEE, 0xFA, 0xFE, 0xFF] {
+                let mut modifiers = ModifierState::new();
+                if let Some(prefix) = prefix {
+                    decode_scancode(prefix, &mut modifiers);
+                }
+                assert_eq!(decode_scancode(code, &mut modifiers), None);
+            }
+        }
+        assert!(diag::get(Counter::Ps2Acks) >= before + 2);
+    }
+
+    #[test]
+    fn keyboard_is_reconfigured_after_a_self_test() {
+        let _keyboard = reset_for_test();
+        mock::set_input(PS2_STATUS_PORT, PS2_OUTPUT_BUFFER_STATUS_BIT);
+        mock::set_input(PS2_DATA_PORT, ACK);
+        assert_eq!(translate(SELF_TEST_PASSED), None);
+        assert_eq!(mock::bytes_to(PS2_DATA_PORT), [ENABLE_SCANNING, SET_LEDS, 0]);
+
+        *TYPEMATIC.lock() = Some((0x0B, 2));
+        mock::clear();
+        mock::set_input(PS2_STATUS_PORT, PS2_OUTPUT_BUFFER_STATUS_BIT);
+        mock::set_input(PS2_DATA_PORT, ACK);
+        assert_eq!(translate(SELF_TEST_PASSED), None);
+        assert_eq!(mock::bytes_to(PS2_DATA_PORT)[3..], [SET_TYPEMATIC, 0b0100_1011]);
+    }
+
+    #[test]
+    fn multi_byte_sequences_leave_modifiers_alone() {
+        let cases: [(&[u8], Option<Key>); 4] = [
+            (&[0xE1, 0x1D, 0x45, 0xE1, 0x9D, 0xC5], Some(Key::Pause)),
+            // Print Screen pressed, then released.
+            (&[0xE0, 0x2A, 0xE0, 0x37], None),
+            (&[0xE0, 0xB7, 0xE0, 0xAA], None),
+            (&[0xE0, 0x47], Some(Key::Home)),
+        ];
+        for (bytes, expected) in cases {
+            let mut modifiers = ModifierState::new();
+            let mut keys = bytes.iter().filter_map(|&b| decode_scancode(b, &mut modifiers));
+            assert_eq!(keys.next(), expected);
+            assert_eq!(keys.next(), None);
+            assert_eq!(modifiers.visible_state(), ModifierState::new().visible_state());
+            assert!(!modifiers.sequence_pending());
+        }
+
+        // A key typed right after the Pause sequence decodes normally.
+        let mut modifiers = ModifierState::new();
+        feed(&[0xE1, 0x1D, 0x45, 0xE1, 0x9D, 0xC5], &mut modifiers);
+        assert_eq!(decode_scancode(0x1E, &mut modifiers), Some(Key::A));
+    }
+
+    #[test]
+    fn replug_during_right_shift_hold_clears_state() {
+        let mut modifiers = ModifierState::new();
+        // Caps Lock on, right Shift held, then the keyboard is unplugged and plugged back in.
+        feed(&[0x3A, 0xBA, 0x36], &mut modifiers);
+        assert!(modifiers.shift());
+        assert!(modifiers.caps_lock);
+
+        assert_eq!(feed(&[0xAA], &mut modifiers), 0);
+        assert!(!modifiers.shift());
+        assert!(!modifiers.caps_lock);
+        assert!(modifiers.take_reset());
+        assert!(!modifiers.take_reset());
+    }
+
+    #[test]
+    fn replug_during_left_shift_hold_only_releases_shift() {
+        let mut modifiers = ModifierState::new();
+        // Caps Lock on, left Shift held, then the keyboard is unplugged and plugged back in. Its
+        // self-test result reads as the left Shift break code.
+        feed(&[0x3A, 0xBA, 0x2A], &mut modifiers);
+        assert!(modifiers.shift());
+
+        assert_eq!(feed(&[0xAA], &mut modifiers), 0);
+        assert!(!modifiers.shift());
+        assert!(modifiers.caps_lock);
+        assert!(!modifiers.take_reset());
+    }
+
+    #[test]
+    fn left_shift_break_is_not_a_reset() {
+        let mut modifiers = ModifierState::new();
+        feed(&[0x1D, 0x2A, 0xAA], &mut modifiers);
+
+        assert!(!modifiers.shift());
+        assert!(modifiers.ctrl);
+        assert!(!modifiers.take_reset());
+    }
+
+    #[test]
+    fn protocol_bytes_produce_no_keys() {
+        let mut modifiers = ModifierState::new();
+        feed(&[0x2A], &mut modifiers);
+        let before = modifiers;
+
+        assert_eq!(feed(&[0xEE, 0xFA, 0xFE, 0xFF, 0x00], &mut modifiers), 0);
+        assert_eq!(modifiers, before);
+    }
+
+    #[test]
+    fn repeats_are_flagged_until_release() {
+        let mut modifiers = ModifierState::new();
+        let tab = |repeat| {
+            Some(KeyEvent {
+                key: Key::Tab,
+                repeat,
+                origin: KeyOrigin::Main,
+                ctrl: false,
+                alt: false,
+            })
+        };
+
+        assert_eq!(decode_event(0x0F, &mut modifiers), tab(false));
+        assert_eq!(decode_event(0x0F, &mut modifiers), tab(true));
+        // Modifiers pressed in between do not end the repeat.
+        assert_eq!(decode_event(0x2A, &mut modifiers), None);
+        assert_eq!(decode_event(0x0F, &mut modifiers), tab(true));
+        assert_eq!(decode_event(0x8F, &mut modifiers), None);
+        assert_eq!(decode_event(0x0F, &mut modifiers), tab(false));
+
+        // Rolling over to another key starts a new press.
+        assert!(!decode_event(0x1E, &mut modifiers).unwrap().repeat);
+        assert_eq!(decode_event(0x0F, &mut modifiers), tab(false));
+    }
+
+    #[test]
+    fn keypad_enter_is_canonicalized() {
+        let mut modifiers = ModifierState::new();
+        let enter = decode_event(0x1C, &mut modifiers).unwrap();
+        assert_eq!((enter.key, enter.origin), (Key::Enter, KeyOrigin::Main));
+        decode_event(0x9C, &mut modifiers);
+
+        assert_eq!(decode_event(0xE0, &mut modifiers), None);
+        let keypad = decode_event(0x1C, &mut modifiers).unwrap();
+        assert_eq!((keypad.key, keypad.origin), (Key::Enter, KeyOrigin::KeypadEnter));
+        assert!(!keypad.repeat);
+
+        // Releasing the keypad Enter does not leak a key, and the main Enter is not a repeat of it.
+        assert_eq!(decode_event(0xE0, &mut modifiers), None);
+        assert_eq!(decode_event(0x9C, &mut modifiers), None);
+        assert!(!decode_event(0x1C, &mut modifiers).unwrap().repeat);
+    }
+
+    #[test]
+    fn ctrl_i_is_canonicalized() {
+        let mut modifiers = ModifierState::new();
+        assert_eq!(decode_scancode(0x17, &mut modifiers), Some(Key::I));
+
+        decode_event(0x1D, &mut modifiers);
+        let event = decode_event(0x17, &mut modifiers).unwrap();
+        assert_eq!((event.key, event.origin), (Key::Tab, KeyOrigin::CtrlI));
+        decode_event(0x97, &mut modifiers);
+        decode_event(0x9D, &mut modifiers);
+
+        assert_eq!(decode_scancode(0x0F, &mut modifiers), Some(Key::Tab));
+    }
+
+    #[test]
+    fn extended_fake_shifts_are_ignored() {
+        let mut modifiers = ModifierState::new();
+        // Print Screen: E0 2A E0 37, then E0 B7 E0 AA on release.
+        for b in [0xE0, 0x2A, 0xE0, 0x37, 0xE0, 0xB7, 0xE0, 0xAA] {
+            decode_event(b, &mut modifiers);
+        }
+        assert!(!modifiers.shift());
+        assert!(!modifiers.take_reset());
+    }
+
+    #[test]
+    fn regular_keys_still_decode() {
+        let mut modifiers = ModifierState::new();
+        assert_eq!(decode_scancode(0x1E, &mut modifiers), Some(Key::A));
+        assert_eq!(decode_scancode(0x9E, &mut modifiers), None);
+    }
+
+    #[test]
+    fn shift_selects_the_shifted_table() {
+        let mut modifiers = ModifierState::new();
+        decode_scancode(0x2A, &mut modifiers);
+        assert_eq!(decode_scancode(0x1E, &mut modifiers), Some(Key::UpperA));
+        assert_eq!(decode_scancode(0x02, &mut modifiers), Some(Key::Exclamation));
+        assert_eq!(decode_scancode(0x1A, &mut modifiers), Some(Key::CurlyBracketsOpen));
+        assert_eq!(decode_scancode(0x1C, &mut modifiers), Some(Key::Enter));
+        decode_scancode(0xAA, &mut modifiers);
+        assert_eq!(decode_scancode(0x1E, &mut modifiers), Some(Key::A));
+    }
+
+    #[test]
+    fn one_shift_released_keeps_shift_active() {
+        let mut modifiers = ModifierState::new();
+        feed(&[0x2A, 0x36, 0xB6], &mut modifiers);
+        assert_eq!(decode_scancode(0x10, &mut modifiers), Some(Key::UpperQ));
+        feed(&[0x90, 0xAA], &mut modifiers);
+        assert_eq!(decode_scancode(0x10, &mut modifiers), Some(Key::Q));
+    }
+
+    #[test]
+    fn shifted_keys_map_to_their_character() {
+        for key in SCANCODE_TO_KEY_SHIFTED.iter().flatten() {
+            assert_eq!(Key::from_u8(*key as u8), Some(*key));
+        }
+        assert_eq!(Key::UpperZ as u8, b'Z');
+        assert_eq!(Key::QuestionMark as u8, b'?');
+    }
+
+    #[test]
+    fn caps_lock_only_affects_letters() {
+        let mut modifiers = ModifierState::new();
+        feed(&[0x3A, 0xBA], &mut modifiers);
+        assert_eq!(decode_scancode(0x1E, &mut modifiers), Some(Key::UpperA));
+        assert_eq!(decode_scancode(0x02, &mut modifiers), Some(Key::N1));
+        assert_eq!(decode_scancode(0x27, &mut modifiers), Some(Key::Semicolon));
+
+        // Shift cancels Caps Lock for letters only.
+        decode_scancode(0x36, &mut modifiers);
+        assert_eq!(decode_scancode(0x1E, &mut modifiers), Some(Key::A));
+        assert_eq!(decode_scancode(0x02, &mut modifiers), Some(Key::Exclamation));
+        decode_scancode(0xB6, &mut modifiers);
+
+        // The break code does not toggle, the next make code does.
+        feed(&[0x3A, 0xBA], &mut modifiers);
+        assert!(!modifiers.caps_lock);
+        assert_eq!(decode_scancode(0x1E, &mut modifiers), Some(Key::A));
+    }
+
+    #[test]
+    fn ctrl_c_is_a_distinct_key() {
+        let mut modifiers = ModifierState::new();
+        feed(&[0x1D], &mut modifiers);
+        assert_eq!(decode_scancode(0x2E, &mut modifiers), Some(Key::CtrlC));
+        feed(&[0xAE, 0x9D], &mut modifiers);
+        assert_eq!(decode_scancode(0x2E, &mut modifiers), Some(Key::C));
+        assert_eq!(Key::from_u8(Key::CtrlC as u8), Some(Key::CtrlC));
+
+        feed(&[0x1D], &mut modifiers);
+        assert_eq!(decode_scancode(0x26, &mut modifiers), Some(Key::CtrlL));
+        assert_eq!(Key::from_u8(Key::CtrlL as u8), Some(Key::CtrlL));
+
+        // Ctrl with the extended arrows, but not with the keypad ones.
+        feed(&[0x1D], &mut modifiers);
+        assert_eq!(decode_scancode(0xE0, &mut modifiers), None);
+        assert_eq!(decode_scancode(0x4B, &mut modifiers), Some(Key::CtrlArrowLeft));
+        feed(&[0xE0, 0xCB, 0xE0], &mut modifiers);
+        assert_eq!(decode_scancode(0x4D, &mut modifiers), Some(Key::CtrlArrowRight));
+        feed(&[0xE0, 0xCD], &mut modifiers);
+        assert_eq!(decode_scancode(0x4B, &mut modifiers), Some(Key::ArrowLeft));
+        feed(&[0xCB], &mut modifiers);
+        assert_eq!(Key::from_u8(Key::CtrlArrowRight as u8), Some(Key::CtrlArrowRight));
+
+        // Ctrl held across A and E, released in between the presses.
+        feed(&[0x1E, 0x9E], &mut modifiers);
+        assert_eq!(decode_scancode(0x12, &mut modifiers), Some(Key::CtrlE));
+        feed(&[0x92, 0x9D], &mut modifiers);
+        assert_eq!(decode_scancode(0x1E, &mut modifiers), Some(Key::A));
+    }
+
+    #[test]
+    fn break_codes_never_produce_keys() {
+        for code in 0x80..=0xFF {
+            let mut modifiers = ModifierState::new();
+            decode_scancode(code & !0x80, &mut modifiers);
+            assert_eq!(decode_scancode(code, &mut modifiers), None, "{code:#x}");
+        }
+    }
+
+    #[test]
+    fn navigation_cluster_needs_the_prefix() {
+        let mut modifiers = ModifierState::new();
+        let mut keys = [0xE0, 0x47, 0xE0, 0xC7, 0xE0, 0x4F, 0xE0, 0x53, 0xE0, 0x49, 0xE0, 0x51, 0xE0, 0x4B]
+            .into_iter()
+            .filter_map(|b| decode_scancode(b, &mut modifiers));
+        for expected in [Key::Home, Key::End, Key::Delete, Key::PageUp, Key::PageDown, Key::ArrowLeft] {
+            assert_eq!(keys.next(), Some(expected));
+        }
+        assert_eq!(keys.next(), None);
+        drop(keys);
+
+        // The keypad keys behind the same bytes are not decoded.
+        assert_eq!(decode_scancode(0x47, &mut modifiers), None);
+        assert_eq!(decode_scancode(0x53, &mut modifiers), None);
+        assert_eq!(Key::from_u8(Key::Delete as u8), Some(Key::Delete));
+    }
+
+    #[test]
+    fn function_keys() {
+        let mut modifiers = ModifierState::new();
+        assert_eq!(decode_scancode(0x3B, &mut modifiers), Some(Key::F1));
+        assert_eq!(decode_scancode(0x44, &mut modifiers), Some(Key::F10));
+        assert_eq!(decode_scancode(0x58, &mut modifiers), Some(Key::F12));
+        assert_eq!(Key::F7.function_number(), Some(7));
+        assert_eq!(Key::PageDown.function_number(), None);
+        assert_eq!(Key::A.function_number(), None);
+    }
+
+    #[test]
+    fn led_mask_follows_the_locks() {
+        let mut modifiers = ModifierState::new();
+        feed(&[0x3A, 0xBA, 0x46, 0xC6], &mut modifiers);
+        assert_eq!(modifiers.led_mask(), 0b101);
+        feed(&[0x45, 0x45, 0xC5, 0x46, 0xC6], &mut modifiers);
+        assert_eq!(modifiers.led_mask(), 0b110);
+    }
+
+    #[test]
+    fn set_leds_waits_for_each_ack() {
+        mock::clear();
+        mock::set_input(PS2_STATUS_PORT, PS2_OUTPUT_BUFFER_STATUS_BIT);
+        mock::set_input(PS2_DATA_PORT, ACK);
+        assert_eq!(set_leds(0b100), Ok(()));
+        assert_eq!(mock::bytes_to(PS2_DATA_PORT), [SET_LEDS, 0b100]);
+    }
+
+    #[test]
+    fn set_leds_gives_up_on_resends() {
+        mock::clear();
+        mock::set_input(PS2_STATUS_PORT, PS2_OUTPUT_BUFFER_STATUS_BIT);
+        mock::set_input(PS2_DATA_PORT, RESEND);
+        assert_eq!(set_leds(0), Err(Ps2Error::ResendExhausted));
+        assert_eq!(mock::bytes_to(PS2_DATA_PORT), [SET_LEDS; MAX_RESENDS + 1]);
+    }
+
+    #[test]
+    fn set_leds_times_out() {
+        mock::clear();
+        // No acknowledgement ever arrives.
+        assert_eq!(set_leds(0), Err(Ps2Error::NoResponse));
+
+        // The controller never empties its input buffer.
+        mock::clear();
+        mock::set_input(PS2_STATUS_PORT, PS2_INPUT_BUFFER_STATUS_BIT);
+        assert_eq!(set_leds(0), Err(Ps2Error::NotReady));
+        assert!(mock::bytes_to(PS2_DATA_PORT).is_empty());
+    }
+
+    #[test]
+    fn acknowledged_writes_retry_on_resend() {
+        mock::clear();
+        mock::set_input(PS2_STATUS_PORT, PS2_OUTPUT_BUFFER_STATUS_BIT);
+        mock::queue_inputs(PS2_DATA_PORT, &[RESEND, RESEND, ACK]);
+        assert_eq!(write_and_wait_ack(0xF4, 10), Ok(()));
+        assert_eq!(mock::bytes_to(PS2_DATA_PORT), [0xF4; 3]);
+    }
+
+    #[test]
+    fn keys_pressed_while_waiting_for_an_ack_are_kept() {
+        mock::clear();
+        mock::set_input(PS2_STATUS_PORT, PS2_OUTPUT_BUFFER_STATUS_BIT);
+        mock::queue_inputs(PS2_DATA_PORT, &[0x1E, RESEND, 0x9E, ACK]);
+        let queue = ScancodeQueue::new();
+        assert_eq!(send_with_ack(SET_LEDS, 10, write_data, |code| queue.push(code)), Ok(()));
+        assert_eq!(mock::bytes_to(PS2_DATA_PORT), [SET_LEDS; 2]);
+        assert_eq!((queue.pop(), queue.pop(), queue.pop()), (Some(0x1E), Some(0x9E), None));
+
+        // A device that keeps sending other bytes never answers.
+        mock::set_input(PS2_DATA_PORT, 0x1E);
+        assert_eq!(send_with_ack(SET_LEDS, 10, write_data, |_| ()), Err(Ps2Error::NoResponse));
+    }
+
+    #[test]
+    fn reads_give_up_after_max_polls() {
+        mock::clear();
+        assert_eq!(read_with_timeout(7), None);
+        assert_eq!(mock::reads_from(PS2_STATUS_PORT), 7);
+        assert_eq!(write_and_wait_ack(0xF4, 7), Err(Ps2Error::NoResponse));
+
+        mock::queue_inputs(PS2_STATUS_PORT, &[0, 0, PS2_OUTPUT_BUFFER_STATUS_BIT]);
+        mock::queue_inputs(PS2_DATA_PORT, &[0x1C]);
+        assert_eq!(read_with_timeout(3), Some(0x1C));
+    }
+
+    #[test]
+    fn init_brings_up_the_controller() {
+        mock::clear();
+        mock::set_input(PS2_STATUS_PORT, PS2_OUTPUT_BUFFER_STATUS_BIT);
+        // The status always reports a byte, so the flush reads FLUSH_LIMIT stale bytes before the
+        // configuration byte and the answers to both tests and to the enable command arrive.
+        mock::queue_inputs(PS2_DATA_PORT, &[0x1E; FLUSH_LIMIT]);
+        mock::queue_inputs(PS2_DATA_PORT, &[0x47, CONTROLLER_TEST_PASSED, PORT_TEST_PASSED, ACK]);
+
+        assert_eq!(init(), Ok(()));
+        assert_eq!(
+            mock::bytes_to(PS2_COMMAND_PORT),
+            [
+                DISABLE_PORT_1,
+                DISABLE_PORT_2,
+                READ_CONFIG,
+                WRITE_CONFIG,
+                TEST_CONTROLLER,
+                WRITE_CONFIG,
+                TEST_PORT_1,
+                ENABLE_PORT_1
+            ]
+        );
+        assert_eq!(mock::bytes_to(PS2_DATA_PORT), [0x44, 0x44, ENABLE_SCANNING]);
+    }
+
+    #[test]
+    fn init_reports_failures() {
+        mock::clear();
+        mock::set_input(PS2_STATUS_PORT, PS2_OUTPUT_BUFFER_STATUS_BIT);
+        mock::set_input(PS2_DATA_PORT, 0xFC);
+        assert_eq!(init(), Err("controller self-test failed"));
+
+        mock::clear();
+        mock::set_input(PS2_STATUS_PORT, PS2_OUTPUT_BUFFER_STATUS_BIT);
+        mock::queue_inputs(PS2_DATA_PORT, &[0; FLUSH_LIMIT]);
+        mock::queue_inputs(PS2_DATA_PORT, &[0x47, CONTROLLER_TEST_PASSED, 0x01]);
+        assert_eq!(init(), Err("keyboard port test failed"));
+
+        // No controller: the input buffer never empties.
+        mock::clear();
+        mock::set_input(PS2_STATUS_PORT, 0xFF);
+        assert_eq!(init(), Err("controller not ready"));
+    }
+
+    #[test]
+    fn scancode_queue_is_fifo() {
+        let queue = ScancodeQueue::new();
+        assert_eq!(queue.pop(), None);
+        for code in 1..=3 {
+            queue.push(code);
+        }
+        assert_eq!(queue.pop(), Some(1));
+        queue.push(4);
+        assert_eq!((queue.pop(), queue.pop(), queue.pop()), (Some(2), Some(3), Some(4)));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn full_scancode_queue_drops_the_oldest() {
+        let queue = ScancodeQueue::new();
+        for code in 0..SCANCODE_QUEUE_SIZE as u8 + 2 {
+            queue.push(code);
+        }
+        for code in 2..SCANCODE_QUEUE_SIZE as u8 + 2 {
+            assert_eq!(queue.pop(), Some(code));
+        }
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn typematic_encoding() {
+        let _keyboard = reset_for_test();
+        mock::set_input(PS2_STATUS_PORT, PS2_OUTPUT_BUFFER_STATUS_BIT);
+        mock::set_input(PS2_DATA_PORT, ACK);
+        assert_eq!(set_typematic(0x0B, 2), Ok(()));
+        assert_eq!(mock::bytes_to(PS2_DATA_PORT), [SET_TYPEMATIC, 0b0100_1011]);
+
+        assert!(set_typematic(32, 0).is_err());
+        assert!(set_typematic(0, 4).is_err());
+
+        assert_eq!(typematic_period_ms(0), 33);
+        assert_eq!(typematic_period_ms(MAX_TYPEMATIC_RATE), 500);
+        assert_eq!(typematic_delay_ms(0), 250);
+        assert_eq!(typematic_delay_ms(MAX_TYPEMATIC_DELAY), 1000);
+    }
+}
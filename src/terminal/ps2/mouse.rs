@@ -0,0 +1,273 @@
+use spin::Mutex;
+
+use super::{send_with_ack, write_command, write_data, Ps2Error, POLL_LIMIT, PS2_DATA_PORT};
+use crate::{
+    io::inb,
+    terminal::vga::{self, Buffer, VIEW_HEIGHT, VIEW_WIDTH},
+};
+
+/// Controller commands.
+const ENABLE_PORT_2: u8 = 0xA8;
+/// Routes the next byte written to the data port to the mouse instead of the keyboard.
+const WRITE_TO_PORT_2: u8 = 0xD4;
+/// Mouse commands.
+const ENABLE_REPORTING: u8 = 0xF4;
+const DISABLE_REPORTING: u8 = 0xF5;
+
+/// Bits of the first packet byte.
+pub const LEFT_BUTTON: u8 = 1;
+pub const RIGHT_BUTTON: u8 = 1 << 1;
+pub const MIDDLE_BUTTON: u8 = 1 << 2;
+/// Always set, which is how the first byte of a packet is recognized.
+const ALWAYS_SET: u8 = 1 << 3;
+const X_SIGN: u8 = 1 << 4;
+const Y_SIGN: u8 = 1 << 5;
+const X_OVERFLOW: u8 = 1 << 6;
+const Y_OVERFLOW: u8 = 1 << 7;
+const BUTTONS: u8 = LEFT_BUTTON | RIGHT_BUTTON | MIDDLE_BUTTON;
+
+/// Mouse movement counts needed to move the pointer by one cell.
+const COUNTS_PER_COLUMN: i32 = 8;
+const COUNTS_PER_ROW: i32 = 16;
+
+/// Movement reported by one packet. `dy` is positive when the mouse moves away from the user.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MouseEvent {
+    pub dx: i16,
+    pub dy: i16,
+    /// `LEFT_BUTTON`, `RIGHT_BUTTON` and `MIDDLE_BUTTON` of the buttons held.
+    pub buttons: u8,
+}
+
+/// Decodes a standard 3-byte movement packet. Returns `None` if `packet` does not start with a
+/// first byte. A delta whose overflow flag is set is meaningless and reported as 0.
+pub fn decode_packet(packet: [u8; 3]) -> Option<MouseEvent> {
+    let [flags, x, y] = packet;
+    if flags & ALWAYS_SET == 0 {
+        return None;
+    }
+    let delta = |value: u8, sign: u8, overflow: u8| {
+        if flags & overflow != 0 {
+            0
+        } else if flags & sign != 0 {
+            value as i16 - 0x100
+        } else {
+            value as i16
+        }
+    };
+    Some(MouseEvent {
+        dx: delta(x, X_SIGN, X_OVERFLOW),
+        dy: delta(y, Y_SIGN, Y_OVERFLOW),
+        buttons: flags & BUTTONS,
+    })
+}
+
+/// Gathers the bytes sent by the mouse into packets.
+pub struct PacketReader {
+    bytes: [u8; 3],
+    len: usize,
+}
+
+impl PacketReader {
+    pub const fn new() -> Self {
+        PacketReader { bytes: [0; 3], len: 0 }
+    }
+
+    /// Adds `byte` to the packet being read and returns its event once complete. A byte that
+    /// cannot start a packet is dropped, so that a reader which lost the packet alignment resyncs.
+    pub fn push(&mut self, byte: u8) -> Option<MouseEvent> {
+        if self.len == 0 && byte & ALWAYS_SET == 0 {
+            return None;
+        }
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        if self.len < self.bytes.len() {
+            return None;
+        }
+        self.len = 0;
+        decode_packet(self.bytes)
+    }
+}
+
+/// Position of the mouse pointer on the screen.
+pub struct Pointer {
+    pub enabled: bool,
+    pub column: usize,
+    pub row: usize,
+    /// Movement not yet worth a whole cell.
+    rest_x: i32,
+    rest_y: i32,
+    moved: bool,
+    reader: PacketReader,
+}
+
+impl Pointer {
+    pub const fn new() -> Self {
+        Pointer {
+            enabled: false,
+            column: VIEW_WIDTH / 2,
+            row: VIEW_HEIGHT / 2,
+            rest_x: 0,
+            rest_y: 0,
+            moved: false,
+            reader: PacketReader::new(),
+        }
+    }
+
+    /// Moves the pointer by `event`, keeping it on the screen.
+    pub fn apply(&mut self, event: MouseEvent) {
+        fn step(position: usize, rest: &mut i32, counts: i32, per_cell: i32, max: usize) -> usize {
+            *rest += counts;
+            let cells = *rest / per_cell;
+            *rest %= per_cell;
+            (position as i32 + cells).clamp(0, max as i32 - 1) as usize
+        }
+        let (column, row) = (self.column, self.row);
+        self.column = step(self.column, &mut self.rest_x, event.dx as i32, COUNTS_PER_COLUMN, VIEW_WIDTH);
+        // Rows grow downwards while `dy` grows upwards.
+        self.row = step(self.row, &mut self.rest_y, -(event.dy as i32), COUNTS_PER_ROW, vga::view_height());
+        self.moved |= (column, row) != (self.column, self.row);
+    }
+
+    /// Feeds a byte sent by the mouse.
+    pub fn push(&mut self, byte: u8) {
+        if let Some(event) = self.reader.push(byte) {
+            self.apply(event);
+        }
+    }
+
+    /// Returns `true` if the pointer moved since the last call.
+    pub fn take_moved(&mut self) -> bool {
+        core::mem::take(&mut self.moved) && self.enabled
+    }
+
+    /// Shows the pointer as a reverse-video cell.
+    pub fn draw(&self, b: &mut Buffer) {
+        if self.enabled {
+            b.invert(self.row * VIEW_WIDTH + self.column);
+        }
+    }
+}
+
+pub static POINTER: Mutex<Pointer> = Mutex::new(Pointer::new());
+
+/// Body of the mouse (IRQ12) handler: feeds the byte waiting in the data port to the pointer. The
+/// caller acknowledges the interrupt.
+pub fn on_interrupt() {
+    POINTER.lock().push(unsafe { inb(PS2_DATA_PORT) });
+}
+
+/// Sends `byte` to the mouse until it is acknowledged, repeating it when the mouse asks for a
+/// resend. Movements reported in the meantime go to the pointer.
+fn send_to_mouse(byte: u8) -> Result<(), Ps2Error> {
+    send_with_ack(
+        byte,
+        POLL_LIMIT,
+        |byte| {
+            write_command(WRITE_TO_PORT_2)?;
+            write_data(byte)
+        },
+        |byte| POINTER.lock().push(byte),
+    )
+}
+
+/// Enables the second port and has the mouse report its movements.
+pub fn enable() -> Result<(), Ps2Error> {
+    write_command(ENABLE_PORT_2)?;
+    send_to_mouse(ENABLE_REPORTING)?;
+    POINTER.lock().enabled = true;
+    Ok(())
+}
+
+/// Stops the movement reports and hides the pointer.
+pub fn disable() -> Result<(), Ps2Error> {
+    POINTER.lock().enabled = false;
+    send_to_mouse(DISABLE_REPORTING)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn packets_decode_positive_and_negative_deltas() {
+        let event = decode_packet([ALWAYS_SET | LEFT_BUTTON, 5, 3]).unwrap();
+        assert_eq!(
+            event,
+            MouseEvent {
+                dx: 5,
+                dy: 3,
+                buttons: LEFT_BUTTON
+            }
+        );
+
+        let event = decode_packet([ALWAYS_SET | X_SIGN | Y_SIGN, 0xFB, 0x00]).unwrap();
+        assert_eq!((event.dx, event.dy), (-5, -256));
+    }
+
+    #[test]
+    fn overflowed_deltas_are_dropped() {
+        let event = decode_packet([ALWAYS_SET | X_OVERFLOW | X_SIGN | RIGHT_BUTTON, 0x10, 7]).unwrap();
+        assert_eq!(
+            event,
+            MouseEvent {
+                dx: 0,
+                dy: 7,
+                buttons: RIGHT_BUTTON
+            }
+        );
+        let event = decode_packet([ALWAYS_SET | Y_OVERFLOW, 2, 0xFF]).unwrap();
+        assert_eq!((event.dx, event.dy), (2, 0));
+    }
+
+    #[test]
+    fn misaligned_bytes_are_skipped() {
+        assert_eq!(decode_packet([0, 1, 1]), None);
+
+        let mut reader = PacketReader::new();
+        // The tail of a packet whose start was lost.
+        assert_eq!(reader.push(0x02), None);
+        assert_eq!(reader.push(0x01), None);
+        assert_eq!(reader.push(ALWAYS_SET), None);
+        assert_eq!(reader.push(4), None);
+        assert_eq!(reader.push(0), Some(MouseEvent { dx: 4, dy: 0, buttons: 0 }));
+    }
+
+    #[test]
+    fn pointer_moves_by_whole_cells_and_stays_on_screen() {
+        let mut pointer = Pointer::new();
+        pointer.enabled = true;
+        let (column, row) = (pointer.column, pointer.row);
+
+        pointer.apply(MouseEvent {
+            dx: COUNTS_PER_COLUMN as i16 - 1,
+            dy: 0,
+            buttons: 0,
+        });
+        assert!(!pointer.take_moved());
+        pointer.apply(MouseEvent {
+            dx: 1,
+            dy: COUNTS_PER_ROW as i16,
+            buttons: 0,
+        });
+        assert!(pointer.take_moved());
+        assert_eq!((pointer.column, pointer.row), (column + 1, row - 1));
+
+        pointer.apply(MouseEvent {
+            dx: -255,
+            dy: -255,
+            buttons: 0,
+        });
+        pointer.apply(MouseEvent {
+            dx: -255,
+            dy: -255,
+            buttons: 0,
+        });
+        pointer.apply(MouseEvent {
+            dx: -255,
+            dy: -255,
+            buttons: 0,
+        });
+        assert_eq!((pointer.column, pointer.row), (0, VIEW_HEIGHT - 1));
+    }
+}
@@ -0,0 +1,222 @@
+use spin::Mutex;
+
+use crate::{
+    rand::{Pcg32, RNG},
+    time::{self, TICKS_PER_SECOND},
+};
+
+use super::{
+    indicator::MOD_INDICATOR,
+    ps2,
+    vga::{self, Buffer, Cell, MAX_VIEW_BUFFER_SIZE, VIEW_WIDTH},
+    Screen,
+};
+
+/// Ticks without input after which the screensaver starts.
+pub const IDLE_TIMEOUT_TICKS: u64 = 60 * TICKS_PER_SECOND;
+
+/// Ticks between two frames of the animation.
+const FRAME_TICKS: u64 = 5;
+
+/// What happens when the shell is left idle.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SaverMode {
+    /// Nothing, the screen stays as it is.
+    Off,
+    /// Falling green characters.
+    Matrix,
+}
+
+impl SaverMode {
+    pub fn from_name(name: &[u8]) -> Option<SaverMode> {
+        match name {
+            b"off" => Some(SaverMode::Off),
+            b"matrix" => Some(SaverMode::Matrix),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SaverMode::Off => "off",
+            SaverMode::Matrix => "matrix",
+        }
+    }
+}
+
+pub static SAVER: Mutex<SaverMode> = Mutex::new(SaverMode::Off);
+
+/// White on black, the character just written by a streak.
+const HEAD_COLOR: u8 = 0x0F;
+/// Light green, dark green then dark gray on black, the trail fading behind the head.
+const TRAIL_COLORS: [u8; 3] = [0x0A, 0x02, 0x08];
+
+/// A falling trail of characters in one column.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Streak {
+    /// Row of the head, negative while the streak waits above the screen.
+    head: i32,
+    /// Number of cells of the trail, including the head.
+    len: i32,
+}
+
+impl Streak {
+    fn spawn(rng: &mut Pcg32, rows: usize) -> Streak {
+        Streak {
+            head: -(rng.range(0, 2 * rows as u32) as i32),
+            len: rng.range(4, 16) as i32,
+        }
+    }
+}
+
+/// State of the matrix rain animation. Each frame only depends on the previous one and on the
+/// generator, so that a seed always produces the same animation.
+pub struct Rain {
+    streaks: [Streak; VIEW_WIDTH],
+    cells: [Cell; MAX_VIEW_BUFFER_SIZE],
+    /// Rows of the text mode the animation was started in.
+    rows: usize,
+}
+
+impl Rain {
+    pub fn new(rng: &mut Pcg32) -> Rain {
+        let rows = vga::view_height();
+        let mut streaks = [Streak { head: 0, len: 0 }; VIEW_WIDTH];
+        for streak in streaks.iter_mut() {
+            *streak = Streak::spawn(rng, rows);
+        }
+        Rain {
+            streaks,
+            cells: [Cell::from_raw(0); MAX_VIEW_BUFFER_SIZE],
+            rows,
+        }
+    }
+
+    /// Moves every streak down by one row and redraws the cells. Heads get a random CP437 glyph, the
+    /// trail keeps the glyphs left by the head and fades with the distance to it.
+    pub fn step(&mut self, rng: &mut Pcg32) {
+        for (x, streak) in self.streaks.iter_mut().enumerate() {
+            streak.head += 1;
+            if streak.head - streak.len >= self.rows as i32 {
+                *streak = Streak::spawn(rng, self.rows);
+            }
+
+            for y in 0..self.rows {
+                let cell = &mut self.cells[y * VIEW_WIDTH + x];
+                let distance = streak.head - y as i32;
+                *cell = if distance == 0 {
+                    Cell::new(rng.range(0x21, 0x100) as u8, HEAD_COLOR)
+                } else if distance > 0 && distance < streak.len {
+                    let step = (distance * TRAIL_COLORS.len() as i32 / streak.len) as usize;
+                    Cell::new(cell.ch(), TRAIL_COLORS[step])
+                } else {
+                    Cell::from_raw(0)
+                };
+            }
+        }
+    }
+
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells[..self.rows * VIEW_WIDTH]
+    }
+}
+
+/// Returns `true` if the screensaver is enabled and no key was pressed since `last_input`.
+pub fn is_due(last_input: u64) -> bool {
+    *SAVER.lock() != SaverMode::Off && time::is_calibrated() && time::ticks() >= last_input + IDLE_TIMEOUT_TICKS
+}
+
+/// Animates the matrix rain over the alternate buffer of `s` until a key is pressed. The key is
+/// consumed and the screen content is left untouched.
+///
+/// Returns an error if the timer is not calibrated, as the frames could not be paced.
+pub fn run(s: &mut Screen) -> Result<(), &'static str> {
+    if !time::is_calibrated() {
+        return Err("timer not calibrated");
+    }
+    s.enter_alt();
+    MOD_INDICATOR.lock().suppressed = true;
+    let mut rain = Rain::new(&mut RNG.lock());
+    let mut next_frame = time::ticks();
+
+    while ps2::read_if_ready().is_none() {
+        if time::ticks() < next_frame {
+            core::hint::spin_loop();
+            continue;
+        }
+        next_frame += FRAME_TICKS;
+        rain.step(&mut RNG.lock());
+        let mut b = Buffer::from_screen(s);
+        b.overlay(0, rain.cells());
+        b.flush();
+    }
+
+    MOD_INDICATOR.lock().suppressed = false;
+    s.leave_alt();
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::terminal::vga::VIEW_HEIGHT;
+
+    fn frames(seed: u64, n: usize) -> Rain {
+        let mut rng = Pcg32::from_seed(seed);
+        let mut rain = Rain::new(&mut rng);
+        for _ in 0..n {
+            rain.step(&mut rng);
+        }
+        rain
+    }
+
+    #[test]
+    fn same_seed_same_frames() {
+        assert!(frames(3, 40).cells() == frames(3, 40).cells());
+        assert!(frames(3, 40).cells() != frames(4, 40).cells());
+    }
+
+    #[test]
+    fn heads_fall_one_row_per_frame() {
+        let mut rng = Pcg32::from_seed(9);
+        let mut rain = Rain::new(&mut rng);
+        for _ in 0..200 {
+            let before = rain.streaks;
+            rain.step(&mut rng);
+            for (x, (old, new)) in before.iter().zip(rain.streaks.iter()).enumerate() {
+                assert!(new.head == old.head + 1 || new.head <= 0);
+                let heads = (0..VIEW_HEIGHT).filter(|y| rain.cells[y * VIEW_WIDTH + x].color() == HEAD_COLOR).count();
+                assert_eq!(heads, (0..VIEW_HEIGHT as i32).contains(&new.head) as usize);
+            }
+        }
+    }
+
+    #[test]
+    fn trail_keeps_glyph_and_fades() {
+        let mut rng = Pcg32::from_seed(11);
+        let mut rain = Rain::new(&mut rng);
+        rain.streaks[0] = Streak { head: 4, len: 9 };
+        rain.step(&mut rng);
+        let glyph = rain.cells[5 * VIEW_WIDTH].ch();
+        assert!(glyph >= 0x21);
+
+        let mut colors = [0u8; 8];
+        for color in colors.iter_mut() {
+            rain.step(&mut rng);
+            assert_eq!(rain.cells[5 * VIEW_WIDTH].ch(), glyph);
+            *color = rain.cells[5 * VIEW_WIDTH].color();
+        }
+        assert_eq!(colors, [0x0A, 0x0A, 0x02, 0x02, 0x02, 0x08, 0x08, 0x08]);
+
+        rain.step(&mut rng);
+        assert_eq!(rain.cells[5 * VIEW_WIDTH], Cell::from_raw(0));
+    }
+
+    #[test]
+    fn saver_mode_names() {
+        for mode in [SaverMode::Off, SaverMode::Matrix] {
+            assert_eq!(SaverMode::from_name(mode.name().as_bytes()), Some(mode));
+        }
+        assert_eq!(SaverMode::from_name(b"stars"), None);
+    }
+}
@@ -1,35 +1,352 @@
+use core::{fmt, ops::Range};
+
+use crate::{
+    bell, config,
+    conv::{self, I32_MAX_LENGTH},
+    diag::{self, Counter},
+    print::u64_to_base,
+    serial,
+};
+
 use super::{
+    ansi::{self, Action},
+    cp437::{self, BoxStyle},
+    cursor::CursorStyle,
     ps2::Key,
-    vga::{Color, Entry},
+    vga::{self, Buffer, Cell, Color, VIEW_WIDTH},
 };
 
-pub const BUFFER_SIZE: usize = 50000;
+pub const BUFFER_SIZE: usize = config::SCREEN_BUFFER_SIZE;
+
+/// Minimum number of cells freed at once when the oldest rows are discarded to make room, so that
+/// the scrollback is not shifted for every new line once full.
+const DISCARD_CELLS: usize = config::SCREEN_DISCARD_CELLS;
+
+/// The BEL control character, routed to the terminal bell instead of being displayed.
+const BELL: u8 = 0x07;
+
+/// Starts an ANSI escape sequence.
+const ESC: u8 = 0x1B;
+
+/// Moves the cursor one column left without erasing, when written.
+const BACKSPACE: u8 = 0x08;
+
+/// Columns between two tab stops.
+pub const TAB_WIDTH: usize = 8;
+
+/// Iterates over the code page 437 bytes of the characters of `text` that occupy a cell, skipping
+/// ANSI escape sequences such as the `ESC [ 31 m` color sequences.
+pub fn visible_bytes(text: &str) -> impl Iterator<Item = u8> + '_ {
+    let mut bytes = text.chars().map(cp437::encode);
+    core::iter::from_fn(move || loop {
+        match bytes.next()? {
+            ESC => {
+                if bytes.next()? == b'[' {
+                    bytes.find(|b| (0x40..=0x7E).contains(b))?;
+                }
+            }
+            b => return Some(b),
+        }
+    })
+}
+
+/// Number of columns `text` occupies once rendered: tabs are expanded to the next multiple of
+/// `tab_width` and escape sequences take no room.
+pub fn display_width(text: &str, tab_width: usize) -> usize {
+    visible_bytes(text).fold(0, |column, b| if b == b'\t' { next_tab_stop(column, tab_width.max(1)) } else { column + 1 })
+}
+
+fn next_tab_stop(column: usize, tab_width: usize) -> usize {
+    (column / tab_width + 1) * tab_width
+}
+
+/// Whether the view snaps back to the live output while scrolled back.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AutoScroll {
+    /// Any new output or key press snaps the view back.
+    Always,
+    /// Only key presses snap the view back, output arriving in the background keeps the place.
+    InputOnly,
+    /// The view only moves when scrolled explicitly.
+    Never,
+}
+
+impl AutoScroll {
+    pub fn from_name(name: &[u8]) -> Option<AutoScroll> {
+        match name {
+            b"always" => Some(AutoScroll::Always),
+            b"input-only" => Some(AutoScroll::InputOnly),
+            b"never" => Some(AutoScroll::Never),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AutoScroll::Always => "always",
+            AutoScroll::InputOnly => "input-only",
+            AutoScroll::Never => "never",
+        }
+    }
+}
+
+/// Number of characters kept while echo is off. Later ones are dropped.
+pub const HIDDEN_INPUT_SIZE: usize = 64;
+
+/// Size of the buffer of `Screen::with_capture`.
+#[cfg(test)]
+const CAPTURE_SIZE: usize = 8192;
+
+/// Copy of every byte written to a `Screen`, for tests asserting on printed text.
+#[cfg(test)]
+#[derive(Clone, Copy)]
+struct Capture {
+    bytes: [u8; CAPTURE_SIZE],
+    len: usize,
+}
+
+/// State of the primary buffer saved while the alternate buffer is active.
+#[derive(Clone, Copy)]
+struct PrimaryState {
+    cursor: usize,
+    last_entry_index: usize,
+    rows_scrolled: usize,
+    pending_lines: usize,
+}
 
 #[derive(Clone, Copy)]
 pub struct Screen {
-    pub buffer: [u16; BUFFER_SIZE],
+    pub buffer: [Cell; BUFFER_SIZE],
     pub cursor: usize,
     pub last_entry_index: usize,
     pub rows_scrolled: usize,
+    pub autoscroll: AutoScroll,
+    /// Number of lines written below the view since it was detached from the live output.
+    pub pending_lines: usize,
+    /// Set while the alternate buffer is active. Its content lives after the primary content.
+    primary: Option<PrimaryState>,
+    /// Blank columns kept on the left and right of the lines written, see `set_margins`.
+    margins: (usize, usize),
+    /// Number of line breaks ever written, including discarded ones.
+    pub lines_written: u64,
+    /// Number of rows dropped from the top of the scrollback to make room for new output.
+    pub lines_discarded: u64,
+    /// Number of cells dropped from the start of the buffer with those rows, wrapping around. Every
+    /// entry moved left by as many cells, so indices kept across writes have to follow.
+    pub cells_discarded: usize,
+    /// Copies the sequential output of the primary buffer to the serial port, a line at a time when
+    /// its line break is written, so that the edits made to the line before show as on screen. Row
+    /// writes and the alternate buffer are not mirrored, as they would garble the serial stream.
+    pub serial_mirror: bool,
+    /// Rows the screen is displayed on, the top ones of the view. Fewer than `vga::view_height()` while
+    /// the bottom rows are used by something else, like the status bar of `Terminal`.
+    pub view_height: usize,
+    /// Columns between two tab stops for the tabs written with `write`, at least 1.
+    pub tab_width: usize,
+    /// Reads the escape sequences written with `write`, and holds the color they selected.
+    ansi: ansi::Parser,
+    /// Set once a written `\r` or backspace moved the cursor back, so that the following output
+    /// replaces the cells of the line instead of being inserted before them.
+    replacing: bool,
+    /// Set while typed characters replace the one under the cursor, see `set_overwrite`.
+    overwrite: bool,
+    /// Cleared while typed characters are hidden, see `set_echo`.
+    echo: bool,
+    /// Cell shown for each hidden character, or nothing if `None`.
+    pub echo_mask: Option<u8>,
+    /// Cleared while the cursor is hidden, see `set_cursor_visible`.
+    cursor_visible: bool,
+    /// Look of the cursor in insert mode, see `set_cursor_style`.
+    cursor_style: CursorStyle,
+    /// Characters typed while echo is off, retrieved with `take_captured`.
+    hidden: [u8; HIDDEN_INPUT_SIZE],
+    hidden_len: usize,
+    /// Entries changed since the last `render`, as a start and end index.
+    damage: Option<(usize, usize)>,
+    /// View made by the last `render`, kept to only convert the damaged entries the next time.
+    rendered: Option<Buffer>,
+    #[cfg(test)]
+    capture: Option<Capture>,
 }
 
 impl Screen {
     pub fn default() -> Self {
         Screen {
-            buffer: [Entry::new(b' ').to_u16(); BUFFER_SIZE],
+            buffer: [Cell::BLANK; BUFFER_SIZE],
             cursor: 0,
             last_entry_index: 0,
             rows_scrolled: 0,
+            autoscroll: AutoScroll::InputOnly,
+            pending_lines: 0,
+            primary: None,
+            margins: (0, 0),
+            lines_written: 0,
+            lines_discarded: 0,
+            cells_discarded: 0,
+            serial_mirror: false,
+            view_height: vga::view_height(),
+            tab_width: TAB_WIDTH,
+            ansi: ansi::Parser::new(),
+            replacing: false,
+            overwrite: false,
+            echo: true,
+            echo_mask: Some(b'*'),
+            cursor_visible: true,
+            cursor_style: CursorStyle::Underline,
+            hidden: [0; HIDDEN_INPUT_SIZE],
+            hidden_len: 0,
+            damage: None,
+            rendered: None,
+            #[cfg(test)]
+            capture: None,
+        }
+    }
+
+    /// Creates a screen that also records the bytes written to it, see `captured`.
+    #[cfg(test)]
+    pub fn with_capture() -> Self {
+        Screen {
+            capture: Some(Capture {
+                bytes: [0; CAPTURE_SIZE],
+                len: 0,
+            }),
+            ..Screen::default()
+        }
+    }
+
+    /// Returns the text written since the screen was created by `with_capture`. Bytes past
+    /// `CAPTURE_SIZE` are dropped.
+    #[cfg(test)]
+    pub fn captured(&self) -> &str {
+        let capture = self.capture.as_ref().expect("screen created without capture");
+        core::str::from_utf8(&capture.bytes[..capture.len]).unwrap_or("<invalid utf-8>")
+    }
+
+    /// Returns the characters of line `row` of the active buffer and its length, for tests
+    /// asserting on where text ended up. Missing lines are empty.
+    #[cfg(test)]
+    pub fn line_text(&self, row: usize) -> ([u8; VIEW_WIDTH], usize) {
+        let line = self.lines().nth(row).unwrap_or(&[]);
+        let mut text = [0; VIEW_WIDTH];
+        for (t, c) in text.iter_mut().zip(line.iter()) {
+            *t = c.ch();
         }
+        (text, line.len())
+    }
+
+    /// Switches to the alternate buffer: a blank, single-page buffer without scrollback for
+    /// full-screen commands, whose output is not kept in the history.
+    pub fn enter_alt(&mut self) {
+        if self.primary.is_some() {
+            return;
+        }
+        self.primary = Some(PrimaryState {
+            cursor: self.cursor,
+            last_entry_index: self.last_entry_index,
+            rows_scrolled: self.rows_scrolled,
+            pending_lines: self.pending_lines,
+        });
+        self.cursor = self.last_entry_index;
+        self.rows_scrolled = 0;
+        self.pending_lines = 0;
+    }
+
+    /// Discards the alternate buffer and restores the primary buffer and cursor exactly as they were
+    /// before `enter_alt`.
+    pub fn leave_alt(&mut self) {
+        let Some(primary) = self.primary.take() else {
+            return;
+        };
+        self.cursor = primary.last_entry_index;
+        self.clear_to_eos();
+        self.cursor = primary.cursor;
+        self.last_entry_index = primary.last_entry_index;
+        self.rows_scrolled = primary.rows_scrolled;
+        self.pending_lines = primary.pending_lines;
+    }
+
+    /// Returns the index of the first entry of the alternate buffer if it is active.
+    pub fn alt_origin(&self) -> Option<usize> {
+        self.primary.map(|p| p.last_entry_index)
+    }
+
+    /// First index the cursor can move to.
+    fn origin(&self) -> usize {
+        self.alt_origin().unwrap_or(0)
+    }
+
+    /// Drops the oldest rows of the alternate buffer until it fits on the screen.
+    fn trim_alt(&mut self) {
+        let Some(origin) = self.alt_origin() else {
+            return;
+        };
+
+        loop {
+            let mut rows = 1;
+            let mut column = 0;
+            let mut first_row_len = None;
+            for (i, cell) in self.buffer[origin..self.last_entry_index].iter().enumerate() {
+                if cell.is_line_break() {
+                    column = 0;
+                } else {
+                    column += 1;
+                    if column < VIEW_WIDTH {
+                        continue;
+                    }
+                    column = 0;
+                }
+                rows += 1;
+                first_row_len.get_or_insert(i + 1);
+            }
+
+            let Some(len) = first_row_len.filter(|_| rows > self.view_height) else {
+                return;
+            };
+            self.buffer.copy_within(origin + len..self.last_entry_index, origin);
+            self.buffer[self.last_entry_index - len..self.last_entry_index].fill(Cell::BLANK);
+            self.touch(origin..self.last_entry_index);
+            self.last_entry_index -= len;
+            self.cursor = self.cursor.saturating_sub(len).max(origin);
+        }
+    }
+
+    /// Number of rows the content takes on screen, counting wrapped lines once per row.
+    fn row_count(&self) -> usize {
+        let mut rows = 1;
+        let mut column = 0;
+        for cell in &self.buffer[..self.last_entry_index] {
+            if !cell.is_line_break() {
+                column += 1;
+                if column < VIEW_WIDTH {
+                    continue;
+                }
+            }
+            column = 0;
+            rows += 1;
+        }
+        rows
     }
 
     pub fn handle_key(&mut self, key: Key) {
         use Key::*;
+        if !matches!(key, ArrowUp | ArrowDown | PageUp | PageDown) {
+            self.follow(true, 0);
+        }
+        if !self.echo && key != Enter {
+            self.handle_hidden_key(key);
+            return;
+        }
         match key {
-            Tab => {}
+            Tab | CtrlC | CtrlL | CtrlU | CtrlK | CtrlA | CtrlE | Pause | CtrlArrowLeft | CtrlArrowRight | Home => {}
+            _ if key.function_number().is_some() => {}
+            End => self.move_cursor_to_end(),
+            Delete => self.delete_forward(),
+            Insert => self.set_overwrite(!self.overwrite),
+            PageUp => self.scroll(self.view_height as isize - 1),
+            PageDown => self.scroll(1 - self.view_height as isize),
             Enter => self.write(b'\n'),
             Backspace => {
-                if self.cursor > 0 {
+                if self.cursor > self.origin() {
                     self.cursor -= 1;
                     self.remove_entry_at(self.cursor);
                 }
@@ -37,7 +354,7 @@ impl Screen {
             ArrowUp => self.scroll(1),
             ArrowDown => self.scroll(-1),
             ArrowLeft => {
-                if self.cursor > 0 {
+                if self.cursor > self.origin() {
                     self.cursor -= 1;
                 }
             }
@@ -46,70 +363,635 @@ impl Screen {
                     self.cursor += 1;
                 }
             }
+            _ if self.overwrite && self.cursor < self.last_entry_index && !self.buffer[self.cursor].is_line_break() => self.overwrite_at_cursor(key as u8),
             _ => self.write(key as u8),
         }
     }
 
+    /// Typed characters replace the one under the cursor if `overwrite`, instead of moving the
+    /// rest of the line right. Typing past the end of the text appends in both modes.
+    pub fn set_overwrite(&mut self, overwrite: bool) {
+        self.overwrite = overwrite;
+    }
+
+    #[allow(dead_code)]
+    pub fn overwrite(&self) -> bool {
+        self.overwrite
+    }
+
+    fn overwrite_at_cursor(&mut self, character: u8) {
+        self.record(character);
+        self.buffer[self.cursor] = Cell::new(character, self.color());
+        self.touch(self.cursor..self.cursor + 1);
+        self.cursor += 1;
+    }
+
+    /// Shows or hides the characters typed from now on. Hidden characters are kept for
+    /// `take_captured` and shown as `echo_mask`, if any.
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    /// Shows or hides the hardware cursor on the next flush.
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+    }
+
+    /// Selects the look of the cursor in insert mode. Overwrite mode always shows a block.
+    pub fn set_cursor_style(&mut self, style: CursorStyle) {
+        self.cursor_style = style;
+    }
+
+    /// Look of the cursor in the current editing mode.
+    pub fn cursor_style(&self) -> CursorStyle {
+        if self.overwrite {
+            CursorStyle::Block
+        } else {
+            self.cursor_style
+        }
+    }
+
+    /// Returns `true` if the cursor is to be shown: it is visible and the view follows the live
+    /// output, as the cursor means nothing while scrolled back.
+    pub fn shows_cursor(&self) -> bool {
+        self.cursor_visible && self.rows_scrolled == 0
+    }
+
+    /// Copies the characters typed while echo was off to `out`, forgets them, and returns how many
+    /// were copied.
+    pub fn take_captured(&mut self, out: &mut [u8]) -> usize {
+        let len = self.hidden_len.min(out.len());
+        out[..len].copy_from_slice(&self.hidden[..len]);
+        self.hidden_len = 0;
+        len
+    }
+
+    /// Records a key typed while echo is off. Only printable characters and Backspace are taken,
+    /// since moving the cursor would take the masks out of step with the hidden characters.
+    fn handle_hidden_key(&mut self, key: Key) {
+        if key == Key::Backspace {
+            if self.hidden_len == 0 {
+                return;
+            }
+            self.hidden_len -= 1;
+            if self.echo_mask.is_some() && self.cursor > self.origin() {
+                self.cursor -= 1;
+                self.remove_entry_at(self.cursor);
+            }
+        } else if key.name().is_none() || key == Key::Space {
+            if self.hidden_len == HIDDEN_INPUT_SIZE {
+                return;
+            }
+            self.hidden[self.hidden_len] = key as u8;
+            self.hidden_len += 1;
+            if let Some(mask) = self.echo_mask {
+                self.write(mask);
+            }
+        }
+    }
+
     pub fn scroll(&mut self, delta: isize) {
+        if self.primary.is_some() {
+            return;
+        }
         if delta >= 0 {
-            self.rows_scrolled += delta as usize;
+            // The view stops at the oldest buffered row.
+            let max = self.row_count().saturating_sub(self.view_height);
+            self.rows_scrolled = (self.rows_scrolled + delta as usize).min(max.max(self.rows_scrolled));
         } else if delta < 0 && delta.unsigned_abs() <= self.rows_scrolled {
             self.rows_scrolled -= delta.unsigned_abs();
         } else {
             self.rows_scrolled = 0;
         }
+        self.pending_lines = self.pending_lines.min(self.rows_scrolled);
+    }
+
+    /// Applies the `autoscroll` policy to `new_lines` lines appended to the screen, either by a key
+    /// press (`from_input`) or by output. Either the view snaps back to the live output, or it is
+    /// moved up by the new lines so that the displayed content stays in place.
+    fn follow(&mut self, from_input: bool, new_lines: usize) {
+        if self.rows_scrolled == 0 {
+            return;
+        }
+
+        let snap = match self.autoscroll {
+            AutoScroll::Always => true,
+            AutoScroll::InputOnly => from_input,
+            AutoScroll::Never => false,
+        };
+        if snap {
+            self.rows_scrolled = 0;
+            self.pending_lines = 0;
+        } else {
+            self.rows_scrolled += new_lines;
+            self.pending_lines += new_lines;
+        }
+    }
+
+    /// Color of the characters written with `write` and typed, until changed by `set_color` or a
+    /// color escape sequence.
+    pub fn color(&self) -> u8 {
+        self.ansi.color()
     }
 
+    pub fn set_color(&mut self, color: u8) {
+        self.ansi.set_color(color);
+    }
+
+    /// Writes `character`, interpreting the ANSI escape sequences understood by `ansi::Parser`.
     pub fn write(&mut self, character: u8) {
-        self.write_color(character, Color::Default as u8);
+        match self.ansi.feed(character) {
+            Action::Print(character) => self.write_color(character, self.color()),
+            Action::Clear => self.clear(),
+            Action::Home => {
+                self.cursor = self.origin();
+                self.replacing = true;
+            }
+            Action::Consumed => {}
+        }
     }
 
     pub fn write_color(&mut self, character: u8, color: u8) {
+        if character == BELL {
+            bell::ring();
+            return;
+        }
+        self.record(character);
+
+        match character {
+            b'\r' => {
+                self.cursor -= (self.cursor - self.line_start(self.cursor)) % VIEW_WIDTH;
+                self.replacing = true;
+                return;
+            }
+            BACKSPACE => {
+                if !(self.cursor - self.line_start(self.cursor)).is_multiple_of(VIEW_WIDTH) {
+                    self.cursor -= 1;
+                    self.replacing = true;
+                }
+                return;
+            }
+            b'\n' if self.replacing => {
+                self.cursor = self.line_end(self.cursor);
+                self.replacing = false;
+            }
+            _ => {}
+        }
+        if character == b'\n' && self.serial_mirror && self.primary.is_none() {
+            self.mirror_line();
+        }
+
+        if self.margins != (0, 0) && character != b'\n' {
+            let mut column = self.cursor - self.line_start(self.cursor);
+            if column >= VIEW_WIDTH - self.margins.1 {
+                self.insert(Cell::new(b'\n', color));
+                column = 0;
+            }
+            for _ in column..self.margins.0 {
+                self.insert(Cell::BLANK);
+            }
+        }
+        if character == b'\t' {
+            // Blanks up to the next tab stop, a stop past the end of the row wrapping to the next.
+            let column = (self.cursor - self.line_start(self.cursor)) % VIEW_WIDTH;
+            for _ in column..next_tab_stop(column, self.tab_width.max(1)).min(VIEW_WIDTH) {
+                self.put(Cell::new(b' ', color));
+            }
+            return;
+        }
+        self.put(Cell::new(character, color));
+    }
+
+    /// Writes `cell` at the cursor, over the cell there while `replacing` and inserted otherwise.
+    /// Line breaks are never replaced, so that output longer than the line extends it.
+    fn put(&mut self, cell: Cell) {
+        if self.replacing && self.cursor < self.last_entry_index && !self.buffer[self.cursor].is_line_break() {
+            self.buffer[self.cursor] = cell;
+            self.touch(self.cursor..self.cursor + 1);
+            self.cursor += 1;
+        } else {
+            self.replacing = false;
+            self.insert(cell);
+        }
+    }
+
+    /// Sends the line ended by a line break written at the cursor to the serial port, as it reads
+    /// on screen.
+    fn mirror_line(&self) {
+        for cell in &self.buffer[self.line_start(self.cursor)..self.cursor] {
+            serial::write_byte(cell.ch());
+        }
+        serial::write_byte(b'\n');
+    }
+
+    /// Appends `character` to the capture of a screen created by `with_capture`.
+    fn record(&mut self, _character: u8) {
+        #[cfg(test)]
+        if let Some(capture) = self.capture.as_mut().filter(|c| c.len < CAPTURE_SIZE) {
+            capture.bytes[capture.len] = _character;
+            capture.len += 1;
+        }
+    }
+
+    /// Inserts `cell` at the cursor.
+    fn insert(&mut self, cell: Cell) {
+        if self.cursor == BUFFER_SIZE - 1 && self.last_entry_index == self.cursor && self.primary.is_none() {
+            self.discard_oldest_rows();
+        }
         if self.cursor >= BUFFER_SIZE - 1 {
+            diag::bump(Counter::DroppedCells);
             return;
         }
-        let mut index = BUFFER_SIZE - 2;
-        while index + 1 > self.cursor && index > 0 {
-            self.buffer[index + 1] = self.buffer[index];
-            index -= 1;
+        // Output is almost always appended, in which case there is nothing to shift.
+        if self.cursor < self.last_entry_index {
+            if self.last_entry_index == BUFFER_SIZE - 1 {
+                diag::bump(Counter::DroppedCells);
+            }
+            let end = self.last_entry_index.min(BUFFER_SIZE - 2);
+            self.buffer.copy_within(self.cursor..end, self.cursor + 1);
         }
 
-        self.last_entry_index += 1;
-        self.buffer[self.cursor] = Entry::new_with_color(character, color).to_u16();
+        self.last_entry_index = (self.last_entry_index + 1).min(BUFFER_SIZE - 1);
+        self.buffer[self.cursor] = cell;
+        self.touch(self.cursor..self.last_entry_index);
 
         self.cursor += 1;
+        self.lines_written += cell.is_line_break() as u64;
+        self.follow(false, cell.is_line_break() as usize);
+        self.trim_alt();
+    }
+
+    /// Drops rows from the top of the scrollback until at least `DISCARD_CELLS` cells are free. A
+    /// row ends at a line break or after `VIEW_WIDTH` cells.
+    fn discard_oldest_rows(&mut self) {
+        let mut len = 0;
+        while len < DISCARD_CELLS && len < self.last_entry_index {
+            let row = &self.buffer[len..self.last_entry_index.min(len + VIEW_WIDTH)];
+            len += row.iter().position(|c| c.is_line_break()).map_or(row.len(), |pos| pos + 1);
+            self.lines_discarded += 1;
+        }
+        self.cells_discarded = self.cells_discarded.wrapping_add(len);
+        self.remove_range(0..len);
+    }
+
+    /// Constrains subsequent writes to the columns between `left` and `VIEW_WIDTH - right`: lines
+    /// start after `left` blank cells and break before reaching the right margin.
+    #[allow(dead_code)]
+    pub fn set_margins(&mut self, left: usize, right: usize) {
+        let left = left.min(VIEW_WIDTH - 1);
+        self.margins = (left, right.min(VIEW_WIDTH - 1 - left));
+    }
+
+    /// Replaces `row` with `text` centered between the margins.
+    pub fn write_centered(&mut self, row: usize, text: &str, color: u8) {
+        let (left, right) = self.margins;
+        let free = (VIEW_WIDTH - left - right).saturating_sub(display_width(text, self.tab_width));
+        self.write_at_row(row, left + free / 2, text, color);
+    }
+
+    /// Replaces `row` with `text` aligned against the right margin.
+    #[allow(dead_code)]
+    pub fn write_right_aligned(&mut self, row: usize, text: &str, color: u8) {
+        let (left, right) = self.margins;
+        let column = (VIEW_WIDTH - right).saturating_sub(display_width(text, self.tab_width));
+        self.write_at_row(row, column.max(left), text, color);
+    }
+
+    /// Replaces the content of `row`, counted from the first line of the active buffer, with `text`
+    /// starting at `column`. Missing rows are appended. A cursor after the row, or at the end of the
+    /// content, stays on the same content.
+    fn write_at_row(&mut self, row: usize, column: usize, text: &str, color: u8) {
+        let cursor = self.cursor;
+        let from_end = self.last_entry_index - cursor;
+        let start = self.append_rows_until(row);
+
+        self.clear_rows(row..row + 1);
+        self.cursor = start;
+        for _ in 0..column {
+            self.insert(Cell::BLANK);
+        }
+        let mut column = 0;
+        for b in visible_bytes(text) {
+            self.record(b);
+            if b == b'\t' {
+                let stop = next_tab_stop(column, self.tab_width.max(1));
+                for _ in column..stop {
+                    self.insert(Cell::new(b' ', color));
+                }
+                column = stop;
+            } else {
+                self.insert(Cell::new(b, color));
+                column += 1;
+            }
+        }
+
+        self.cursor = if cursor < start && from_end > 0 {
+            cursor
+        } else {
+            self.last_entry_index.saturating_sub(from_end).max(start)
+        };
     }
 
+    /// Returns the index of the first entry of `row`, counted from the first line of the active
+    /// buffer, appending empty lines until it exists. Leaves the cursor anywhere.
+    fn append_rows_until(&mut self, row: usize) -> usize {
+        loop {
+            if let Some(start) = self.row_start(row) {
+                return start;
+            }
+            self.cursor = self.last_entry_index;
+            self.insert(Cell::new(b'\n', Color::Default as u8));
+        }
+    }
+
+    /// Replaces the cells of `row`, counted in lines from the first line of the active buffer, from
+    /// `column` on with `cells`, padding the line with blanks if it is shorter. Missing rows are
+    /// appended as long as the buffer has fewer than `view_height` lines, so that a short buffer
+    /// can be drawn on like the view; other missing rows, and cells past the right edge, are
+    /// dropped. The cursor keeps its place on the screen.
+    fn put_cells(&mut self, column: usize, row: usize, cells: impl Iterator<Item = Cell>) {
+        if column >= VIEW_WIDTH || (row >= self.view_height && self.row_start(row).is_none()) {
+            return;
+        }
+        let cursor = self.cursor;
+        let start = self.append_rows_until(row);
+        let line_end = self.line_end(start);
+
+        let mut end = line_end;
+        for (index, cell) in (start + column..start + VIEW_WIDTH).zip(cells) {
+            while end < index {
+                self.cursor = end;
+                self.insert(Cell::BLANK);
+                end += 1;
+            }
+            if index < end {
+                self.buffer[index] = cell;
+                self.touch(index..index + 1);
+            } else {
+                self.cursor = index;
+                self.insert(cell);
+                end += 1;
+            }
+        }
+
+        // Cells are only inserted at the end of the row, which the cursor follows when past it.
+        self.cursor = if cursor <= line_end { cursor } else { cursor + (end - line_end) };
+    }
+
+    /// Writes `text` with the current color at `column` of `row` over what is there, without moving
+    /// the cursor. Rows are lines counted from the first line of the active buffer, which on the
+    /// alternate buffer are the rows of the view; see `put_cells` for the clipping.
+    #[allow(dead_code)]
+    pub fn write_str_at(&mut self, column: usize, row: usize, text: &str) {
+        let color = self.color();
+        self.put_cells(column, row, visible_bytes(text).map(|b| Cell::new(b, color)));
+    }
+
+    /// Draws a `width` by `height` frame with its top-left corner at `column` of `row`, counted
+    /// and clipped like `write_str_at`, in the current color. The inside of the frame is left as
+    /// is.
+    #[allow(dead_code)]
+    pub fn draw_box(&mut self, column: usize, row: usize, width: usize, height: usize, style: BoxStyle) {
+        if width < 2 || height < 2 {
+            return;
+        }
+        let [top_left, top_right, bottom_left, bottom_right, horizontal, vertical] = style.glyphs();
+        let color = self.color();
+        let edge = |left, right| {
+            let middle = core::iter::repeat_n(Cell::new(horizontal, color), width - 2);
+            core::iter::once(Cell::new(left, color))
+                .chain(middle)
+                .chain(core::iter::once(Cell::new(right, color)))
+        };
+
+        self.put_cells(column, row, edge(top_left, top_right));
+        for row in row + 1..row + height - 1 {
+            self.put_cells(column, row, core::iter::once(Cell::new(vertical, color)));
+            self.put_cells(column + width - 1, row, core::iter::once(Cell::new(vertical, color)));
+        }
+        self.put_cells(column, row + height - 1, edge(bottom_left, bottom_right));
+    }
+
+    /// Writes `string` one cell per character, see `cp437::encode`.
     pub fn write_str(&mut self, string: &str) {
-        for &c in string.as_bytes().iter() {
+        for c in string.chars() {
+            self.write(cp437::encode(c));
+        }
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &c in bytes {
             self.write(c);
         }
     }
 
+    /// Writes `line` followed by a line break, dropping its trailing spaces.
+    ///
+    /// The rest of the row is filled with blanks when the screen is rendered, so padding output to
+    /// the end of the row only bloats the scrollback and anything extracted from it.
+    pub fn write_line(&mut self, line: &[u8]) {
+        let len = line.iter().rposition(|&c| c != b' ').map_or(0, |pos| pos + 1);
+        self.write_bytes(&line[..len]);
+        self.write(b'\n');
+    }
+
+    /// Iterates over the logical lines of the scrollback, without their line break and trailing
+    /// blank cells.
+    pub fn lines(&self) -> impl Iterator<Item = &[Cell]> {
+        let content = &self.buffer[..self.last_entry_index];
+        let trailing_break = content.last().is_some_and(|e| e.is_line_break());
+        let content = if trailing_break { &content[..content.len() - 1] } else { content };
+
+        content.split(|e| e.is_line_break()).map(|line| {
+            let len = line.iter().rposition(|e| !e.is_empty()).map_or(0, |pos| pos + 1);
+            &line[..len]
+        })
+    }
+
     #[allow(dead_code)]
     pub fn write_color_str(&mut self, string: &str, color: u8) {
-        for &c in string.as_bytes().iter() {
-            self.write_color(c, color);
+        for c in string.chars() {
+            self.write_color(cp437::encode(c), color);
         }
     }
 
-    fn remove_entry_at(&mut self, mut index: usize) {
-        while (index + 1) < BUFFER_SIZE {
-            self.buffer[index] = self.buffer[index + 1];
-            index += 1;
+    /// Removes the character under the cursor, moving the rest of the text left by one.
+    pub fn delete_forward(&mut self) {
+        if self.cursor < self.last_entry_index {
+            self.remove_entry_at(self.cursor);
         }
+    }
+
+    /// Erases the entries from `from` to `to`, kept within the content of the active buffer, and
+    /// moves the text after them left. The cursor moves along with the text after it.
+    pub fn erase_range(&mut self, from: usize, to: usize) {
+        let to = to.min(self.last_entry_index);
+        let from = from.clamp(self.origin(), to);
+        self.remove_range(from..to);
+    }
+
+    fn remove_entry_at(&mut self, index: usize) {
+        self.buffer.copy_within(index + 1..self.last_entry_index, index);
+        self.touch(index..self.last_entry_index);
         self.last_entry_index -= 1;
-        self.buffer[index] = Entry::new(b' ').to_u16();
+        self.buffer[self.last_entry_index] = Cell::BLANK;
     }
 
-    pub fn move_cursor_to_end(&mut self) {
-        for _ in 0..BUFFER_SIZE {
-            self.handle_key(Key::ArrowRight);
+    /// Removes the entries in `range`. The cursor moves along with the content after it.
+    fn remove_range(&mut self, range: Range<usize>) {
+        let len = range.len();
+        if len == 0 {
+            return;
         }
+        self.buffer.copy_within(range.end..self.last_entry_index, range.start);
+        self.buffer[self.last_entry_index - len..self.last_entry_index].fill(Cell::BLANK);
+        self.touch(range.start..self.last_entry_index);
+        self.last_entry_index -= len;
+
+        if self.cursor >= range.end {
+            self.cursor -= len;
+        } else if self.cursor > range.start {
+            self.cursor = range.start;
+        }
+    }
+
+    /// Index of the first entry of the line containing `index`.
+    fn line_start(&self, index: usize) -> usize {
+        let origin = self.origin();
+        self.buffer[origin..index]
+            .iter()
+            .rposition(|c| c.is_line_break())
+            .map_or(origin, |pos| origin + pos + 1)
+    }
+
+    /// Index of the first entry of `row`, counted from the first line of the active buffer, if the
+    /// content has that many rows.
+    fn row_start(&self, row: usize) -> Option<usize> {
+        let mut start = Some(self.origin());
+        for _ in 0..row {
+            start = start.map(|s| self.line_end(s) + 1).filter(|&s| s <= self.last_entry_index);
+        }
+        start
+    }
+
+    /// Index of the line break ending the line containing `index`, or the end of the content.
+    fn line_end(&self, index: usize) -> usize {
+        self.buffer[index..self.last_entry_index]
+            .iter()
+            .position(|c| c.is_line_break())
+            .map_or(self.last_entry_index, |pos| index + pos)
+    }
+
+    /// Records that the entries in `range` changed, for the next `render`.
+    fn touch(&mut self, range: Range<usize>) {
+        self.damage = Some(match self.damage {
+            Some((start, end)) => (start.min(range.start), end.max(range.end)),
+            None => (range.start, range.end),
+        });
+    }
+
+    /// Converts the view for display like `Buffer::from_screen`. While the view does not move,
+    /// only the rows holding entries changed since the previous call are converted again, so that
+    /// typing does not go over the whole view.
+    pub fn render(&mut self) -> Buffer {
+        let damage = self.damage.take().map(|(start, end)| start..end);
+        let buffer = match self.rendered.take() {
+            Some(mut buffer) => {
+                buffer.refresh(self, damage);
+                buffer
+            }
+            None => Buffer::from_screen(self),
+        };
+        self.rendered = Some(buffer);
+        buffer
+    }
+
+    /// Erases the whole content of the active buffer, scrollback included, and moves the cursor
+    /// to its start.
+    pub fn clear(&mut self) {
+        let origin = self.origin();
+        self.buffer[origin..self.last_entry_index].fill(Cell::BLANK);
+        self.touch(origin..self.last_entry_index);
+        self.cursor = origin;
+        self.last_entry_index = origin;
         self.rows_scrolled = 0;
+        self.pending_lines = 0;
+        vga::invalidate();
+    }
+
+    /// Clears the line from the cursor to its end.
+    pub fn clear_to_eol(&mut self) {
+        let end = self.line_end(self.cursor);
+        self.remove_range(self.cursor..end);
+    }
+
+    /// Clears the line from its beginning to the cursor, which ends up at the beginning of the line.
+    #[allow(dead_code)]
+    pub fn clear_to_bol(&mut self) {
+        let start = self.line_start(self.cursor);
+        self.remove_range(start..self.cursor);
+    }
+
+    /// Clears everything from the cursor to the end of the content.
+    pub fn clear_to_eos(&mut self) {
+        self.remove_range(self.cursor..self.last_entry_index);
+    }
+
+    /// Empties the lines in `rows`, counted from the first line of the active buffer. The line
+    /// breaks are kept so that the following lines do not move.
+    pub fn clear_rows(&mut self, rows: Range<usize>) {
+        for row in rows.rev() {
+            if let Some(start) = self.row_start(row) {
+                let end = self.line_end(start);
+                self.remove_range(start..end);
+            }
+        }
+    }
+
+    /// Blanks line `y`, counted like `clear_rows`, with the current color. Unlike `clear_rows` the
+    /// cells stay in place, so that nothing moves, the cursor included.
+    #[allow(dead_code)]
+    pub fn clear_line(&mut self, y: usize) {
+        let Some(start) = self.row_start(y) else {
+            return;
+        };
+        let end = self.line_end(start);
+        let blank = Cell::new(b' ', self.color());
+        self.buffer[start..end].fill(blank);
+        self.touch(start..end);
+    }
+
+    /// Shows the `len` cells starting at buffer index `start` in reverse video. The region follows
+    /// the buffer, so it runs on across wrapped rows, and is cut at the end of the buffer.
+    pub fn invert_region(&mut self, start: usize, len: usize) {
+        let start = start.min(BUFFER_SIZE);
+        let end = start.saturating_add(len).min(BUFFER_SIZE);
+        for cell in &mut self.buffer[start..end] {
+            *cell = cell.inverted();
+        }
+        self.touch(start..end);
+    }
+
+    /// Undoes `invert_region` over the same region. Swapping the colors twice restores them.
+    #[allow(dead_code)]
+    pub fn uninvert_region(&mut self, start: usize, len: usize) {
+        self.invert_region(start, len);
+    }
+
+    /// Moves the cursor to the buffer position `index`, kept between the start of the active buffer
+    /// and the end of its content.
+    pub fn set_cursor(&mut self, index: usize) {
+        self.cursor = index.clamp(self.origin(), self.last_entry_index.min(BUFFER_SIZE - 1));
+    }
+
+    /// Moves the cursor after the last entry. Whether the view follows is up to the `autoscroll`
+    /// policy.
+    pub fn move_cursor_to_end(&mut self) {
+        self.cursor = self.last_entry_index.min(BUFFER_SIZE - 1);
     }
 
     /// Writes a single byte in hexadecimal notation (little-endian).
+    #[allow(dead_code)]
     pub fn write_hex_byte(&mut self, byte: u8) {
         let high_nibble = (byte >> 4) & 0xF;
         let low_nibble = byte & 0xF;
@@ -118,12 +1000,996 @@ impl Screen {
         self.write(if low_nibble < 10 { b'0' + low_nibble } else { b'a' + (low_nibble - 10) });
     }
 
-    /// Writes `value` in hexadecimal notation, left-padded with zeros.
-    pub fn write_hex(&mut self, val: u32) {
-        let mut nibble;
-        for i in (0..8).rev() {
-            nibble = ((val >> (i * 4)) & 0xF) as u8;
+    /// Writes `val` in binary notation, left-padded with zeros to 32 digits and grouped by nibbles
+    /// separated with `_`.
+    pub fn write_bin(&mut self, val: u32) {
+        for i in (0..32).rev() {
+            self.write(if (val >> i) & 1 == 1 { b'1' } else { b'0' });
+            if i % 4 == 0 && i != 0 {
+                self.write(b'_');
+            }
+        }
+    }
+
+    /// Writes `val` in `base` (2 to 16) without leading zeros.
+    pub fn write_base(&mut self, val: u64, base: u8) {
+        if let Ok((digits, len)) = u64_to_base(val, base) {
+            self.write_bytes(&digits[digits.len() - len..]);
+        }
+    }
+
+    /// Writes `val` in decimal without leading zeros.
+    pub fn write_dec(&mut self, val: u32) {
+        self.write_dec_u64(val as u64);
+    }
+
+    /// Writes `val` in decimal, preceded by `-` if negative.
+    pub fn write_i32(&mut self, val: i32) {
+        let mut buf = [0; I32_MAX_LENGTH];
+        for &c in conv::itoa(val, &mut buf) {
+            self.write(c);
+        }
+    }
+
+    /// Writes `val` in decimal without leading zeros.
+    pub fn write_dec_u64(&mut self, val: u64) {
+        self.write_dec_padded(val, 0);
+    }
+
+    /// Writes `val` in decimal, right-aligned in a field of `width` columns.
+    pub fn write_dec_padded(&mut self, val: u64, width: usize) {
+        if let Ok((digits, len)) = u64_to_base(val, 10) {
+            for _ in len..width {
+                self.write(b' ');
+            }
+            self.write_bytes(&digits[digits.len() - len..]);
+        }
+    }
+
+    /// Writes exactly `digits` hexadecimal digits of `val`: left-padded with zeros, or cut from the
+    /// top if `val` does not fit.
+    pub fn write_hex_padded(&mut self, val: u32, digits: u8) {
+        for i in (0..digits as u32).rev() {
+            let nibble = val.checked_shr(i * 4).unwrap_or(0) as u8 & 0xF;
             self.write(if nibble < 10 { b'0' + nibble } else { b'a' + (nibble - 10) });
         }
     }
+
+    /// Same as `write_hex_padded`, preceded by `0x`.
+    pub fn write_hex_prefixed(&mut self, val: u32, digits: u8) {
+        self.write_str("0x");
+        self.write_hex_padded(val, digits);
+    }
+}
+
+impl fmt::Write for Screen {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        Screen::write_str(self, string);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::terminal::vga::VIEW_HEIGHT;
+
+    fn chars(line: &[Cell]) -> [u8; 80] {
+        let mut out = [0u8; 80];
+        for (o, e) in out.iter_mut().zip(line.iter()) {
+            *o = e.ch();
+        }
+        out
+    }
+
+    #[test]
+    fn decimal_output() {
+        let mut s = Screen::default();
+        s.write_dec(0);
+        s.write(b'|');
+        s.write_dec(7);
+        s.write(b'|');
+        s.write_dec(u32::MAX);
+        s.write(b'|');
+        s.write_dec_u64(u64::MAX);
+        let mut lines = s.lines();
+        assert_eq!(&chars(lines.next().unwrap())[..35], b"0|7|4294967295|18446744073709551615");
+
+        let mut s = Screen::default();
+        s.write_dec_padded(42, 5);
+        s.write(b'|');
+        s.write_dec_padded(0, 1);
+        s.write(b'|');
+        s.write_dec_padded(123456, 3);
+        let mut lines = s.lines();
+        assert_eq!(&chars(lines.next().unwrap())[..14], b"   42|0|123456");
+    }
+
+    #[test]
+    fn hex_output_has_the_requested_width() {
+        let mut s = Screen::default();
+        for val in [0x0, 0xFF, 0xFFFF_FFFF] {
+            for digits in [2, 4, 8] {
+                s.write_hex_padded(val, digits);
+                s.write(b' ');
+            }
+        }
+        let mut lines = s.lines();
+        assert_eq!(&chars(lines.next().unwrap())[..50], b"00 0000 00000000 ff 00ff 000000ff ff ffff ffffffff");
+
+        let mut s = Screen::default();
+        s.write_hex_prefixed(0x1234, 2);
+        s.write(b' ');
+        s.write_hex_prefixed(0xAB, 10);
+        let mut lines = s.lines();
+        assert_eq!(&chars(lines.next().unwrap())[..17], b"0x34 0x00000000ab");
+    }
+
+    #[test]
+    fn signed_decimal_output() {
+        let mut s = Screen::default();
+        for val in [0, -1, 42, i32::MIN] {
+            s.write_i32(val);
+            s.write(b' ');
+        }
+        let mut lines = s.lines();
+        assert_eq!(&chars(lines.next().unwrap())[..19], b"0 -1 42 -2147483648");
+    }
+
+    #[test]
+    fn formatted_output_handles_line_breaks() {
+        use core::fmt::Write;
+
+        let mut s = Screen::default();
+        write!(s, "{:#06x}|{}\n{:>3}", 0x2a, -3, "ok").unwrap();
+
+        let mut lines = s.lines();
+        assert_eq!(&chars(lines.next().unwrap())[..9], b"0x002a|-3");
+        assert_eq!(&chars(lines.next().unwrap())[..3], b" ok");
+    }
+
+    #[test]
+    fn write_line_trims_trailing_spaces() {
+        let mut s = Screen::default();
+        s.write_line(b"name    size      ");
+        s.write_line(b"   ");
+
+        assert_eq!(s.last_entry_index, 14);
+        let mut lines = s.lines();
+        let first = lines.next().unwrap();
+        assert_eq!(&chars(first)[..first.len()], b"name    size");
+        assert_eq!(lines.next().unwrap().len(), 0);
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn lines_are_trimmed() {
+        let mut s = Screen::default();
+        s.write_str("a  \nb\n\nc ");
+
+        let mut lines = s.lines();
+        assert_eq!(lines.next().unwrap().len(), 1);
+        assert_eq!(lines.next().unwrap().len(), 1);
+        assert_eq!(lines.next().unwrap().len(), 0);
+        assert_eq!(&chars(lines.next().unwrap())[..1], b"c");
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn control_bytes_are_not_keys() {
+        let mut s = Screen::default();
+        s.write_str("ab");
+        // Scancodes of Backspace and Enter, and the discriminants of `Key::Backspace` and
+        // `Key::Enter`.
+        for byte in [14, 28, Key::Backspace as u8, Key::Enter as u8] {
+            s.write(byte);
+        }
+
+        assert_eq!(s.cursor, 6);
+        assert_eq!(s.last_entry_index, 6);
+        assert_eq!(s.lines().count(), 1);
+        assert_eq!(chars(s.lines().next().unwrap())[..2], *b"ab");
+    }
+
+    #[test]
+    fn keys_are_handled_by_variant() {
+        let mut s = Screen::default();
+        s.write_str("ab");
+        s.handle_key(Key::Backspace);
+        s.handle_key(Key::Enter);
+
+        assert_eq!(s.last_entry_index, 2);
+        assert_eq!(s.buffer[0].ch(), b'a');
+        assert!(s.buffer[1].is_line_break());
+    }
+
+    fn scrolled_back(mode: AutoScroll) -> Screen {
+        let mut s = Screen::default();
+        s.autoscroll = mode;
+        for _ in 0..40 {
+            s.write_str("line\n");
+        }
+        s.scroll(5);
+        s
+    }
+
+    #[test]
+    fn autoscroll_always() {
+        let mut s = scrolled_back(AutoScroll::Always);
+        s.write_str("log\n");
+        assert_eq!(s.rows_scrolled, 0);
+        assert_eq!(s.pending_lines, 0);
+    }
+
+    #[test]
+    fn autoscroll_input_only() {
+        let mut s = scrolled_back(AutoScroll::InputOnly);
+        s.write_str("log\nlog\n");
+        assert_eq!(s.rows_scrolled, 7);
+        assert_eq!(s.pending_lines, 2);
+
+        // Scrolling is not input that snaps back.
+        s.handle_key(Key::ArrowUp);
+        s.handle_key(Key::ArrowDown);
+        assert_eq!(s.rows_scrolled, 7);
+
+        s.handle_key(Key::A);
+        assert_eq!(s.rows_scrolled, 0);
+        assert_eq!(s.pending_lines, 0);
+    }
+
+    #[test]
+    fn autoscroll_never() {
+        let mut s = scrolled_back(AutoScroll::Never);
+        s.write_str("log\n");
+        s.handle_key(Key::A);
+        s.handle_key(Key::Enter);
+        assert_eq!(s.rows_scrolled, 7);
+        assert_eq!(s.pending_lines, 2);
+
+        // Scrolling down past the new lines drops them from the badge.
+        s.scroll(-6);
+        assert_eq!(s.pending_lines, 1);
+        s.scroll(-1);
+        assert_eq!(s.rows_scrolled, 0);
+        assert_eq!(s.pending_lines, 0);
+    }
+
+    #[test]
+    fn live_view_has_no_pending_lines() {
+        let mut s = Screen::default();
+        s.autoscroll = AutoScroll::Never;
+        s.write_str("a\nb\n");
+        assert_eq!(s.rows_scrolled, 0);
+        assert_eq!(s.pending_lines, 0);
+    }
+
+    #[test]
+    fn alt_buffer_leaves_no_trace() {
+        let mut s = Screen::default();
+        for _ in 0..40 {
+            s.write_str("history\n");
+        }
+        s.write_str("sh> ls");
+        s.handle_key(Key::ArrowLeft);
+        s.scroll(3);
+        let before = s;
+
+        s.enter_alt();
+        for _ in 0..100 {
+            s.write_str("junk junk junk\n");
+        }
+        s.handle_key(Key::ArrowUp);
+        assert_eq!(s.rows_scrolled, 0);
+        s.leave_alt();
+
+        assert!(s.buffer == before.buffer);
+        assert_eq!(s.cursor, before.cursor);
+        assert_eq!(s.last_entry_index, before.last_entry_index);
+        assert_eq!(s.rows_scrolled, 3);
+        assert!(s.lines().all(|line| chars(line)[..4] != *b"junk"));
+    }
+
+    #[test]
+    fn delete_removes_the_character_under_the_cursor() {
+        let mut s = Screen::default();
+        s.write_str("hello");
+        s.handle_key(Key::ArrowLeft);
+        s.handle_key(Key::ArrowLeft);
+        s.handle_key(Key::Delete);
+        assert_eq!(&text(&s)[..5], b"helo\0");
+        assert_eq!(s.cursor, 3);
+
+        // Nothing to delete at the end of the text.
+        s.move_cursor_to_end();
+        s.handle_key(Key::Delete);
+        assert_eq!(s.last_entry_index, 4);
+    }
+
+    #[test]
+    fn line_editing_chords_are_inert() {
+        let mut s = Screen::default();
+        s.write_str("hello");
+        s.set_cursor(2);
+        for key in [Key::CtrlA, Key::CtrlE, Key::CtrlU, Key::CtrlK] {
+            s.handle_key(key);
+        }
+        assert_eq!(&text(&s)[..6], b"hello\0");
+        assert_eq!(s.cursor, 2);
+    }
+
+    #[test]
+    fn overwrite_replaces_instead_of_shifting() {
+        let mut s = Screen::default();
+        s.write_str("hello\nx");
+        s.set_cursor(1);
+        s.handle_key(Key::A);
+        assert_eq!(&text(&s)[..8], b"haello\nx");
+
+        s.handle_key(Key::Insert);
+        assert!(s.overwrite());
+        for key in [Key::B, Key::C, Key::D, Key::E, Key::F] {
+            s.handle_key(key);
+        }
+        // The line break is never overwritten.
+        assert_eq!(&text(&s)[..10], b"habcdef\nx\0");
+        assert_eq!(s.cursor, 7);
+
+        s.set_overwrite(false);
+        s.set_cursor(0);
+        s.handle_key(Key::Z);
+        assert_eq!(&text(&s)[..4], b"zhab");
+    }
+
+    #[test]
+    fn hidden_input_is_masked_and_captured() {
+        let mut s = Screen::default();
+        s.write_str("pw: ");
+        s.set_echo(false);
+        for key in [Key::S, Key::E, Key::C, Key::Backspace, Key::ArrowLeft, Key::UpperX] {
+            s.handle_key(key);
+        }
+        assert_eq!(&text(&s)[..8], b"pw: ***\0");
+
+        let mut out = [0u8; 8];
+        assert_eq!(s.take_captured(&mut out), 3);
+        assert_eq!(&out[..3], b"seX");
+        assert_eq!(s.take_captured(&mut out), 0);
+
+        s.echo_mask = None;
+        s.handle_key(Key::A);
+        s.handle_key(Key::Backspace);
+        s.handle_key(Key::B);
+        s.set_echo(true);
+        s.handle_key(Key::C);
+        assert_eq!(&text(&s)[..9], b"pw: ***c\0");
+        assert_eq!(s.take_captured(&mut out), 1);
+        assert_eq!(out[0], b'b');
+    }
+
+    #[test]
+    fn erase_range_shifts_the_rest_left() {
+        let mut s = Screen::default();
+        s.write_str("hello world");
+        s.set_cursor(8);
+        s.erase_range(2, 8);
+        assert_eq!(&text(&s)[..6], b"herld\0");
+        assert_eq!(s.cursor, 2);
+        assert_eq!(s.buffer[5], Cell::BLANK);
+
+        s.erase_range(3, 40);
+        assert_eq!(&text(&s)[..4], b"her\0");
+        s.erase_range(2, 1);
+        assert_eq!(s.last_entry_index, 3);
+    }
+
+    #[test]
+    fn set_cursor_stays_in_the_content() {
+        let mut s = Screen::default();
+        s.write_str("hello");
+        s.set_cursor(2);
+        assert_eq!(s.cursor, 2);
+        s.set_cursor(40);
+        assert_eq!(s.cursor, 5);
+
+        s.enter_alt();
+        s.write_str("ab");
+        s.set_cursor(0);
+        assert_eq!(s.cursor, s.alt_origin().unwrap());
+    }
+
+    #[test]
+    fn page_keys_scroll_by_a_page() {
+        let mut s = Screen::default();
+        for _ in 0..3 * VIEW_HEIGHT {
+            s.write_str("line\n");
+        }
+        s.handle_key(Key::PageUp);
+        s.handle_key(Key::PageUp);
+        assert_eq!(s.rows_scrolled, 2 * (VIEW_HEIGHT - 1));
+        s.handle_key(Key::PageDown);
+        assert_eq!(s.rows_scrolled, VIEW_HEIGHT - 1);
+
+        // Clamped at the oldest row, so that one PageDown is enough to come back.
+        for _ in 0..10 {
+            s.handle_key(Key::PageUp);
+        }
+        assert_eq!(s.rows_scrolled, 2 * VIEW_HEIGHT + 1);
+        s.handle_key(Key::PageDown);
+        assert_eq!(s.rows_scrolled, VIEW_HEIGHT + 2);
+
+        // Typing snaps back to the live view.
+        s.handle_key(Key::A);
+        assert_eq!(s.rows_scrolled, 0);
+    }
+
+    #[test]
+    fn cursor_is_hidden_while_scrolled_back() {
+        let mut s = Screen::default();
+        for _ in 0..2 * VIEW_HEIGHT {
+            s.write_str("line\n");
+        }
+        s.handle_key(Key::PageUp);
+        assert!(!s.shows_cursor());
+        s.handle_key(Key::PageDown);
+        assert!(s.shows_cursor());
+    }
+
+    #[test]
+    fn short_content_does_not_scroll() {
+        let mut s = Screen::default();
+        s.write_str("line\n");
+        s.handle_key(Key::PageUp);
+        assert_eq!(s.rows_scrolled, 0);
+    }
+
+    #[test]
+    fn alt_buffer_holds_one_page() {
+        let mut s = Screen::default();
+        s.write_str("primary\n");
+        s.enter_alt();
+        for _ in 0..VIEW_HEIGHT + 10 {
+            s.write_str("junk\n");
+        }
+
+        let origin = s.alt_origin().unwrap();
+        let rows = s.buffer[origin..s.last_entry_index].iter().filter(|c| c.is_line_break()).count();
+        assert_eq!(rows, VIEW_HEIGHT - 1);
+
+        // The cursor cannot leave the alternate buffer.
+        s.enter_alt();
+        for _ in 0..BUFFER_SIZE {
+            s.handle_key(Key::Backspace);
+        }
+        assert_eq!(s.cursor, origin);
+        assert_eq!(s.last_entry_index, origin);
+        s.leave_alt();
+        assert_eq!(chars(s.lines().next().unwrap())[..7], *b"primary");
+    }
+
+    fn text(s: &Screen) -> [u8; 16] {
+        let mut out = [0u8; 16];
+        for (o, e) in out.iter_mut().zip(s.buffer[..s.last_entry_index].iter()) {
+            *o = e.ch();
+        }
+        out
+    }
+
+    #[test]
+    fn mid_line_insert_shifts_the_rest() {
+        let mut s = Screen::default();
+        s.write_str("helo\nworld");
+        for _ in 0..7 {
+            s.handle_key(Key::ArrowLeft);
+        }
+        s.write_str("l");
+
+        assert_eq!(&text(&s)[..11], b"hello\nworld");
+        assert_eq!(s.cursor, 4);
+        assert_eq!(s.last_entry_index, 11);
+    }
+
+    #[test]
+    fn insert_at_start_keeps_first_entry() {
+        let mut s = Screen::default();
+        s.write_str("bc");
+        s.cursor = 0;
+        s.write_str("a");
+
+        assert_eq!(&text(&s)[..3], b"abc");
+        assert_eq!(s.cursor, 1);
+        assert_eq!(s.buffer[3], Cell::BLANK);
+    }
+
+    #[test]
+    fn removal_closes_the_gap() {
+        let mut s = Screen::default();
+        s.write_str("abcd");
+        s.handle_key(Key::ArrowLeft);
+        s.handle_key(Key::Backspace);
+
+        assert_eq!(&text(&s)[..3], b"abd");
+        assert_eq!(s.last_entry_index, 3);
+        assert_eq!(s.buffer[3], Cell::BLANK);
+    }
+
+    #[test]
+    fn full_buffer_stays_in_bounds() {
+        let mut s = Screen::default();
+        for _ in 0..BUFFER_SIZE - 1 {
+            s.write(b'x');
+        }
+        assert_eq!(s.last_entry_index, BUFFER_SIZE - 1);
+
+        s.cursor = 0;
+        s.write(b'y');
+        assert_eq!(s.last_entry_index, BUFFER_SIZE - 1);
+        assert_eq!(s.buffer[0].ch(), b'y');
+        assert_eq!(s.buffer[BUFFER_SIZE - 1], Cell::BLANK);
+
+        s.move_cursor_to_end();
+        s.write(b'z');
+        assert_eq!(s.last_entry_index, BUFFER_SIZE - DISCARD_CELLS);
+        assert_eq!(s.lines_discarded as usize, DISCARD_CELLS / VIEW_WIDTH);
+        assert_eq!(s.buffer[s.last_entry_index - 1].ch(), b'z');
+    }
+
+    #[test]
+    fn full_buffer_discards_oldest_lines() {
+        let mut s = Screen::default();
+        for _ in 0..5000 {
+            s.write_str("0123456789\n");
+        }
+
+        assert_eq!(s.lines_written, 5000);
+        assert!(s.lines_discarded > 0);
+        assert_eq!(s.lines_discarded + s.lines().count() as u64, 5000);
+        assert!(s.lines().all(|line| line.len() == 10));
+        assert_eq!(s.cursor, s.last_entry_index);
+    }
+
+    #[test]
+    fn tabs_advance_to_the_next_stop() {
+        let mut s = Screen::default();
+        s.write_str("\tb\n1234567\tc\n");
+        for _ in 0..VIEW_WIDTH - 1 {
+            s.write(b'x');
+        }
+        s.write_str("\td");
+        let mut lines = s.lines();
+        assert_eq!(&chars(lines.next().unwrap())[..9], b"        b");
+        assert_eq!(&chars(lines.next().unwrap())[..9], b"1234567 c");
+        // The stop of column 79 is the end of the row, so `d` starts the next one.
+        let last = lines.next().unwrap();
+        assert_eq!(last.len(), VIEW_WIDTH + 1);
+        assert_eq!(last[VIEW_WIDTH - 1].ch(), b' ');
+        assert_eq!(last[VIEW_WIDTH].ch(), b'd');
+    }
+
+    #[test]
+    fn escape_sequences_color_the_output() {
+        let mut s = Screen::with_capture();
+        s.write_str("a\x1b[31mb\x1b[1;44mc\x1b[0md\x1b[Ke");
+        let colors: [(u8, u8); 5] = core::array::from_fn(|i| (s.buffer[i].ch(), s.buffer[i].color()));
+        assert_eq!(colors, [(b'a', 0x07), (b'b', 0x04), (b'c', 0x1C), (b'd', 0x07), (b'e', 0x07)]);
+        assert_eq!(s.captured(), "abcde");
+    }
+
+    #[test]
+    fn escape_sequences_clear_and_home() {
+        let mut s = Screen::default();
+        s.write_str("old\nlines\x1b[2Jnew\x1b[HN");
+        assert_eq!(&text(&s)[..4], b"New\0");
+    }
+
+    #[test]
+    fn carriage_return_overwrites_the_line() {
+        let mut s = Screen::default();
+        s.write_str("abc\rX");
+        assert_eq!(&text(&s)[..3], b"Xbc");
+        assert_eq!(s.cursor, 1);
+
+        // Progress output: the second counter replaces the first, and the line break ends the line.
+        let mut s = Screen::default();
+        s.write_str("ok\n10%\r20%\r\ndone");
+        assert_eq!(&text(&s)[..11], b"ok\n20%\ndone");
+    }
+
+    #[test]
+    fn backspace_moves_left_without_erasing() {
+        let mut s = Screen::default();
+        s.write_str("abc\x08\x08Y");
+        assert_eq!(&text(&s)[..3], b"aYc");
+
+        let mut s = Screen::default();
+        s.write_str("a\n\x08b\x08\x08");
+        assert_eq!(&text(&s)[..3], b"a\nb");
+        assert_eq!(s.cursor, 2);
+
+        // Replacing stops at the end of the line, which then grows.
+        let mut s = Screen::default();
+        s.write_str("ab\nc\x08xyz");
+        assert_eq!(&text(&s)[..6], b"ab\nxyz");
+    }
+
+    #[test]
+    fn backspace_at_column_0_after_a_full_row() {
+        let mut s = Screen::default();
+        s.write_str(&"a".repeat(VIEW_WIDTH));
+        s.render();
+        assert_eq!(s.cursor, VIEW_WIDTH);
+
+        s.handle_key(Key::Backspace);
+        s.render();
+        assert_eq!((s.cursor, s.last_entry_index), (VIEW_WIDTH - 1, VIEW_WIDTH - 1));
+        assert_eq!(s.line_text(0).1, VIEW_WIDTH - 1);
+    }
+
+    #[test]
+    fn backspace_at_column_0_of_the_last_buffered_row() {
+        let mut s = Screen::default();
+        for _ in 0..BUFFER_SIZE - 1 {
+            s.write(b'x');
+        }
+        s.render();
+
+        let last_row = (BUFFER_SIZE - 1) / VIEW_WIDTH * VIEW_WIDTH;
+        s.set_cursor(last_row);
+        s.handle_key(Key::Backspace);
+        s.render();
+        assert_eq!((s.cursor, s.last_entry_index), (last_row - 1, BUFFER_SIZE - 2));
+        assert!(s.buffer[..BUFFER_SIZE - 2].iter().all(|c| c.ch() == b'x'));
+    }
+
+    #[test]
+    fn moving_past_the_top_left_corner_stays_put() {
+        let mut s = Screen::default();
+        for key in [Key::ArrowUp, Key::ArrowLeft, Key::Backspace, Key::PageUp] {
+            s.handle_key(key);
+        }
+        assert_eq!((s.cursor, s.last_entry_index, s.rows_scrolled), (0, 0, 0));
+
+        // Left at the start of a line goes to the end of the previous one.
+        s.write_str("ab\ncd");
+        s.set_cursor(3);
+        s.handle_key(Key::ArrowLeft);
+        assert_eq!(s.cursor, 2);
+
+        // Up at the top of the view scrolls back until the oldest row is shown.
+        for _ in 0..VIEW_HEIGHT {
+            s.write_str("line\n");
+        }
+        for _ in 0..VIEW_HEIGHT {
+            s.handle_key(Key::ArrowUp);
+        }
+        assert_eq!(s.rows_scrolled, 2);
+    }
+
+    #[test]
+    fn tab_width_is_settable() {
+        let mut s = Screen::default();
+        s.tab_width = 4;
+        s.write_str("ab\tc\n");
+        s.tab_width = 12;
+        for _ in 0..75 {
+            s.write(b'x');
+        }
+        s.write_str("\td");
+        let mut lines = s.lines();
+        assert_eq!(&chars(lines.next().unwrap())[..5], b"ab  c");
+        // The stop at column 84 lies past the row, which is filled up to its end.
+        assert_eq!(lines.next().unwrap()[VIEW_WIDTH].ch(), b'd');
+    }
+
+    #[test]
+    fn newest_lines_survive_in_order() {
+        // 300 lines fill the buffer twice over, whatever its configured size.
+        let len = BUFFER_SIZE / 150;
+        let mut s = Screen::default();
+        for i in 0..300 {
+            s.write_dec_padded(i, 3);
+            for _ in 3..len {
+                s.write(b'x');
+            }
+            s.write(b'\n');
+        }
+
+        assert!(s.lines_discarded > 0);
+        let lines: usize = s.lines().count();
+        assert!(lines >= 100);
+        for (line, i) in s.lines().skip(lines - 100).zip(200..) {
+            let number = [i / 100, i / 10 % 10, i % 10].map(|digit: usize| b'0' + digit as u8);
+            assert_eq!(chars(line)[..3], number);
+            assert_eq!(line.len(), len);
+        }
+    }
+
+    #[test]
+    fn capture_records_written_bytes() {
+        let mut s = Screen::with_capture();
+        s.write_str("ab\x07c\n");
+        s.handle_key(Key::Backspace);
+        s.write_color_str("d", Color::Error as u8);
+
+        assert_eq!(s.captured(), "abc\nd");
+    }
+
+    fn cleared(content: &str, cursor: usize, clear: fn(&mut Screen)) -> ([u8; 16], usize, usize) {
+        let mut s = Screen::default();
+        s.write_str(content);
+        s.cursor = cursor;
+        clear(&mut s);
+        assert!(s.buffer[s.last_entry_index..].iter().all(|&c| c == Cell::BLANK));
+        (text(&s), s.cursor, s.last_entry_index)
+    }
+
+    #[test]
+    fn clear_to_eol() {
+        let (text, cursor, len) = cleared("abc\ndef\nghi", 5, Screen::clear_to_eol);
+        assert_eq!((&text[..len], cursor), (&b"abc\nd\nghi"[..], 5));
+
+        let (text, cursor, len) = cleared("abc\ndef", 0, Screen::clear_to_eol);
+        assert_eq!((&text[..len], cursor), (&b"\ndef"[..], 0));
+
+        // On the last cell nothing is left to clear.
+        let (text, cursor, len) = cleared("abc\ndef", 7, Screen::clear_to_eol);
+        assert_eq!((&text[..len], cursor), (&b"abc\ndef"[..], 7));
+    }
+
+    #[test]
+    fn clear_to_eol_on_the_last_column() {
+        let mut s = Screen::default();
+        s.write_str(&"a".repeat(VIEW_WIDTH + 20));
+        s.cursor = VIEW_WIDTH - 1;
+        s.clear_to_eol();
+        assert_eq!((s.cursor, s.last_entry_index), (VIEW_WIDTH - 1, VIEW_WIDTH - 1));
+    }
+
+    #[test]
+    fn clear_line_keeps_the_cursor() {
+        let mut s = Screen::default();
+        s.write_str("abc\ndef\x1b[44m");
+        s.cursor = 6;
+        s.clear_line(1);
+        assert_eq!(&text(&s)[..7], b"abc\n   ");
+        assert_eq!((s.cursor, s.last_entry_index), (6, 7));
+        assert!(s.buffer[4..7].iter().all(|cell| cell.color() == 0x17));
+        assert_eq!(s.buffer[0].color(), Color::Default as u8);
+
+        // Rows past the end are ignored.
+        s.clear_line(5);
+        assert_eq!(s.last_entry_index, 7);
+    }
+
+    #[test]
+    fn clear_to_bol() {
+        let (text, cursor, len) = cleared("abc\ndef\nghi", 6, Screen::clear_to_bol);
+        assert_eq!((&text[..len], cursor), (&b"abc\nf\nghi"[..], 4));
+
+        let (text, cursor, len) = cleared("abc\ndef", 2, Screen::clear_to_bol);
+        assert_eq!((&text[..len], cursor), (&b"c\ndef"[..], 0));
+
+        let (text, cursor, len) = cleared("abc\ndef", 7, Screen::clear_to_bol);
+        assert_eq!((&text[..len], cursor), (&b"abc\n"[..], 4));
+    }
+
+    #[test]
+    fn clear_to_eos() {
+        let (text, cursor, len) = cleared("abc\ndef\nghi", 5, Screen::clear_to_eos);
+        assert_eq!((&text[..len], cursor), (&b"abc\nd"[..], 5));
+
+        let (_, cursor, len) = cleared("abc\ndef", 0, Screen::clear_to_eos);
+        assert_eq!((cursor, len), (0, 0));
+
+        let (text, cursor, len) = cleared("abc\ndef", 7, Screen::clear_to_eos);
+        assert_eq!((&text[..len], cursor), (&b"abc\ndef"[..], 7));
+    }
+
+    #[test]
+    fn clear_rows() {
+        let (text, cursor, len) = cleared("abc\ndef\nghi\njk", 14, |s| s.clear_rows(1..3));
+        assert_eq!((&text[..len], cursor), (&b"abc\n\n\njk"[..], 8));
+
+        // Rows past the end are ignored.
+        let (text, _, len) = cleared("abc\ndef", 0, |s| s.clear_rows(1..10));
+        assert_eq!(&text[..len], b"abc\n");
+    }
+
+    #[test]
+    fn display_width_skips_escape_sequences() {
+        assert_eq!(display_width("kfs", TAB_WIDTH), 3);
+        assert_eq!(display_width("\x1b[31mkfs\x1b[0m", TAB_WIDTH), 3);
+        assert_eq!(display_width("\x1b[1;32mok\x1b[0m done", TAB_WIDTH), 7);
+        assert_eq!(display_width("a\tb", TAB_WIDTH), 9);
+        assert_eq!(display_width("\x1b[31m\tb", TAB_WIDTH), 9);
+        assert_eq!(display_width("", TAB_WIDTH), 0);
+        assert_eq!(display_width("a\tb", 4), 5);
+        assert_eq!(display_width("caf\u{e9} \u{2192} \u{1F980}", TAB_WIDTH), 8);
+        assert!(visible_bytes("\x1b[31mred").eq(b"red".iter().copied()));
+    }
+
+    #[test]
+    fn non_ascii_characters_take_one_cell() {
+        let mut s = Screen::default();
+        s.write_str("caf\u{e9} \u{2192} \u{1F980}!");
+        assert_eq!(s.last_entry_index, 9);
+        assert_eq!(&text(&s)[..9], b"caf\x82 ? ?!");
+
+        s.write_color_str("\u{c9}t\u{e9}", Color::Error as u8);
+        assert_eq!(&text(&s)[9..12], b"\x90t\x82");
+    }
+
+    #[test]
+    fn write_centered() {
+        let mut s = Screen::default();
+        s.write_str("top\n");
+        s.write_centered(2, "kfs", Color::Error as u8);
+
+        let (text, len) = s.line_text(2);
+        assert_eq!(len, 41);
+        assert_eq!(&text[38..41], b"kfs");
+        assert!(text[..38].iter().all(|&c| c == b' '));
+        assert_eq!(s.lines().nth(1).unwrap().len(), 0);
+        assert_eq!(s.buffer[38 + 5].color(), Color::Error as u8);
+        assert_eq!(s.cursor, s.last_entry_index);
+
+        let mut colored = Screen::default();
+        colored.write_str("top\n");
+        colored.write_centered(2, "\x1b[31mkfs\x1b[0m", Color::Error as u8);
+        assert_eq!(colored.line_text(2), (text, len));
+
+        // Tabs follow the tab width of the screen.
+        let mut tabbed = Screen::default();
+        tabbed.tab_width = 4;
+        tabbed.write_centered(0, "a\tb", Color::Default as u8);
+        let (text, len) = tabbed.line_text(0);
+        assert_eq!(&text[37..len], b"a   b");
+    }
+
+    #[test]
+    fn draw_box_frames_the_text_written_inside() {
+        let mut s = Screen::default();
+        s.write_str("top\nsh> ");
+        s.draw_box(2, 1, 10, 4, BoxStyle::Double);
+        s.write_str_at(4, 2, "hi");
+
+        let expected: [&[u8]; 5] = [
+            b"top",
+            b"sh\xC9\xCD\xCD\xCD\xCD\xCD\xCD\xCD\xCD\xBB",
+            b"  \xBA hi     \xBA",
+            b"  \xBA        \xBA",
+            b"  \xC8\xCD\xCD\xCD\xCD\xCD\xCD\xCD\xCD\xBC",
+        ];
+        for (i, line) in expected.iter().enumerate() {
+            let (text, len) = s.line_text(i);
+            assert_eq!(&text[..len], *line, "row {i}");
+        }
+        assert_eq!(s.lines().count(), 5);
+        // The cursor is still after the prompt, now covered by the top of the frame.
+        assert_eq!(s.cursor, 8);
+    }
+
+    #[test]
+    fn draw_box_clips_at_the_edges() {
+        let mut s = Screen::default();
+        s.draw_box(VIEW_WIDTH - 3, VIEW_HEIGHT - 2, 6, 4, BoxStyle::Single);
+        s.write_str_at(VIEW_WIDTH - 1, 0, "xyz");
+
+        assert_eq!(s.line_text(0).1, VIEW_WIDTH);
+        assert_eq!(s.line_text(0).0[VIEW_WIDTH - 1], b'x');
+        let (text, len) = s.line_text(VIEW_HEIGHT - 2);
+        assert_eq!(&text[VIEW_WIDTH - 3..len], b"\xDA\xC4\xC4");
+        let (text, len) = s.line_text(VIEW_HEIGHT - 1);
+        assert_eq!(&text[VIEW_WIDTH - 3..len], b"\xB3");
+        assert_eq!(s.lines().count(), VIEW_HEIGHT);
+
+        // Past the view, existing lines of the scrollback can still be written, but no more are added.
+        for _ in 0..10 {
+            s.write_str("\n");
+        }
+        s.write_str_at(0, VIEW_HEIGHT + 5, "ab");
+        s.write_str_at(0, VIEW_HEIGHT + 20, "cd");
+        assert_eq!(s.line_text(VIEW_HEIGHT + 5).1, 2);
+        assert_eq!(s.lines().count(), VIEW_HEIGHT + 10);
+    }
+
+    #[test]
+    fn invert_region_swaps_the_colors_across_a_wrap() {
+        let mut s = Screen::default();
+        s.write_color_str(&"x".repeat(VIEW_WIDTH + 4), 0x1E);
+        s.invert_region(VIEW_WIDTH - 2, 4);
+
+        let colors: [u8; 6] = core::array::from_fn(|i| s.buffer[VIEW_WIDTH - 3 + i].color());
+        assert_eq!(colors, [0x1E, 0xE1, 0xE1, 0xE1, 0xE1, 0x1E]);
+        assert_eq!(s.buffer[VIEW_WIDTH].ch(), b'x');
+
+        s.uninvert_region(VIEW_WIDTH - 2, 4);
+        assert!(s.buffer[..VIEW_WIDTH + 4].iter().all(|c| c.color() == 0x1E));
+    }
+
+    #[test]
+    fn invert_region_stops_at_the_end_of_the_buffer() {
+        let mut s = Screen::default();
+        let before = s.buffer[BUFFER_SIZE - 1];
+        s.invert_region(BUFFER_SIZE - 1, usize::MAX);
+        assert_eq!(s.buffer[BUFFER_SIZE - 1], before.inverted());
+        s.invert_region(BUFFER_SIZE + 5, 3);
+        s.uninvert_region(BUFFER_SIZE - 1, 10);
+        assert_eq!(s.buffer[BUFFER_SIZE - 1], before);
+    }
+
+    #[test]
+    fn write_right_aligned_replaces_the_row() {
+        let mut s = Screen::with_capture();
+        s.write_str("abc\ndef\nghi");
+        s.write_right_aligned(1, "v1.0", Color::Default as u8);
+
+        let (text, len) = s.line_text(1);
+        assert_eq!((&text[76..80], len), (&b"v1.0"[..], 80));
+        assert_eq!(&chars(s.lines().nth(2).unwrap())[..3], b"ghi");
+        assert_eq!(s.cursor, s.last_entry_index);
+        assert_eq!(s.captured(), "abc\ndef\nghiv1.0");
+    }
+
+    #[test]
+    fn serial_mirrors_sequential_output_only() {
+        use crate::io::mock;
+
+        mock::clear();
+        mock::set_input(serial::LINE_STATUS, serial::TRANSMIT_EMPTY);
+        let mut s = Screen::default();
+        s.serial_mirror = true;
+        s.write_str("sh> ");
+        s.handle_key(Key::L);
+        s.handle_key(Key::S);
+        s.handle_key(Key::Enter);
+        assert_eq!(mock::bytes_to(serial::COM1), b"sh> ls\r\n");
+
+        mock::clear();
+        mock::set_input(serial::LINE_STATUS, serial::TRANSMIT_EMPTY);
+        s.write_right_aligned(0, "12:00", Color::Default as u8);
+        s.write_centered(1, "title", Color::Default as u8);
+        s.enter_alt();
+        s.write_str("full screen\n");
+        s.leave_alt();
+        assert!(mock::bytes_to(serial::COM1).is_empty());
+
+        s.serial_mirror = false;
+        s.write_str("quiet");
+        assert!(mock::bytes_to(serial::COM1).is_empty());
+    }
+
+    #[test]
+    fn serial_mirrors_edited_lines_as_shown() {
+        use crate::io::mock;
+
+        mock::clear();
+        mock::set_input(serial::LINE_STATUS, serial::TRANSMIT_EMPTY);
+        let mut s = Screen::default();
+        s.serial_mirror = true;
+        s.write_str("sh> ");
+        for key in [Key::L, Key::S, Key::ArrowLeft, Key::Backspace, Key::X, Key::Y, Key::Delete] {
+            s.handle_key(key);
+        }
+        assert!(mock::bytes_to(serial::COM1).is_empty());
+
+        s.handle_key(Key::Enter);
+        s.write_str("50%\r100%\n");
+        assert_eq!(mock::bytes_to(serial::COM1), b"sh> xy\r\n100%\r\n");
+    }
+
+    #[test]
+    fn margins_constrain_wrapping() {
+        let mut s = Screen::default();
+        s.set_margins(2, 70);
+        s.write_str("abcdefghij\nk");
+
+        assert_eq!(&s.line_text(0).0[..10], b"  abcdefgh");
+        assert_eq!(&s.line_text(1).0[..4], b"  ij");
+        assert_eq!(&s.line_text(2).0[..3], b"  k");
+
+        s.set_margins(10, 60);
+        s.write_centered(3, "ab", Color::Default as u8);
+        assert_eq!(s.line_text(3).1, 16);
+    }
 }
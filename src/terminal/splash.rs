@@ -0,0 +1,91 @@
+use crate::time::{self, TICKS_PER_SECOND};
+
+use super::{
+    ps2,
+    vga::{Color, VIEW_WIDTH},
+    Screen,
+};
+
+/// The 42 logo, drawn in `LOGO_COLOR`.
+const LOGO: [&str; 5] = [r"   __ __  ___ ", r"  / // / |__ \", r" / // /_ __/ /", r"/__  __// __/ ", r"  /_/  /____/ "];
+const LOGO_COLOR: u8 = Color::LightCyan as u8;
+
+const VERSION: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+const HINT: &str = "press any key to continue";
+
+/// Ticks the splash stays up when no key is pressed.
+const SPLASH_TICKS: u64 = 3 * TICKS_PER_SECOND;
+
+/// Shows the splash on the alternate buffer of `s` until a key is pressed, or for `SPLASH_TICKS`
+/// when the timer is calibrated, then brings back what `s` displayed before.
+pub fn splash(s: &mut Screen) {
+    s.enter_alt();
+    draw(s, &LOGO);
+    s.render().flush();
+
+    let end = time::ticks() + SPLASH_TICKS;
+    while ps2::read_if_ready().is_none() && (!time::is_calibrated() || time::ticks() < end) {
+        core::hint::spin_loop();
+    }
+    s.leave_alt();
+}
+
+/// Draws `logo` centered in the view of `s`, the kernel name and version below it, and the hint to
+/// continue on the bottom row. Logo lines are clipped one column short of the right edge, as a
+/// full row would push its line break onto the next row.
+fn draw(s: &mut Screen, logo: &[&str]) {
+    let color = s.color();
+    let width = logo.iter().map(|line| line.len()).max().unwrap_or(0).min(VIEW_WIDTH - 1);
+    let column = VIEW_WIDTH.saturating_sub(width) / 2;
+    let top = s.view_height.saturating_sub(logo.len() + 2) / 2;
+
+    s.set_color(LOGO_COLOR);
+    for (row, line) in logo.iter().enumerate() {
+        s.write_str_at(column, top + row, line.get(..width).unwrap_or(line));
+    }
+    s.set_color(Color::Default as u8);
+    s.write_str_at((VIEW_WIDTH - VERSION.len()) / 2, top + logo.len() + 1, VERSION);
+    s.set_color(Color::Dim as u8);
+    s.write_str_at((VIEW_WIDTH - HINT.len()) / 2, s.view_height - 1, HINT);
+    s.set_color(color);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn logo_and_version_are_centered() {
+        let mut s = Screen::default();
+        s.enter_alt();
+        draw(&mut s, &LOGO);
+
+        let top = (s.view_height - LOGO.len() - 2) / 2;
+        let column = (VIEW_WIDTH - LOGO[0].len()) / 2;
+        let (text, len) = s.line_text(top + 1);
+        assert_eq!((&text[column..len], len), (LOGO[1].as_bytes(), column + LOGO[1].len()));
+        assert_eq!(s.lines().nth(top).unwrap()[column + 3].color(), LOGO_COLOR);
+
+        let (text, len) = s.line_text(top + LOGO.len() + 1);
+        let start = (VIEW_WIDTH - VERSION.len()) / 2;
+        assert_eq!((&text[start..len], len), (VERSION.as_bytes(), start + VERSION.len()));
+        assert!(VERSION.starts_with("kfs "));
+
+        let (text, len) = s.line_text(s.view_height - 1);
+        assert_eq!(&text[len - HINT.len()..len], HINT.as_bytes());
+        assert_eq!(s.color(), Color::Default as u8);
+    }
+
+    #[test]
+    fn wide_logos_are_clipped() {
+        let wide = "#".repeat(VIEW_WIDTH + 20);
+        let mut s = Screen::default();
+        s.enter_alt();
+        draw(&mut s, &[&wide, "42"]);
+
+        let top = (s.view_height - 4) / 2;
+        let (text, len) = s.line_text(top);
+        assert_eq!((&text[..len], len), (&[b'#'; VIEW_WIDTH - 1][..], VIEW_WIDTH - 1));
+        assert_eq!(&s.line_text(top + 1).0[..2], b"42");
+    }
+}
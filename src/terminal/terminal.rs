@@ -1,10 +1,19 @@
-use super::{ps2::Key, screen::Screen};
+use super::{
+    indicator::{self, StatusBar, MOD_INDICATOR},
+    ps2::{self, mouse::POINTER, Key, KeyEvent},
+    screen::Screen,
+    vga::{self, Buffer},
+};
 
 pub const NBR_OF_SCREENS_PER_TERMINAL: usize = 2;
 
 pub struct Terminal {
     pub active_screen_index: usize,
+    /// Screen that was active before the current one, toggled back to with Alt+Tab.
+    pub previous_screen_index: Option<usize>,
     screens: [Screen; NBR_OF_SCREENS_PER_TERMINAL],
+    /// Shown on the bottom row instead of the screen content while enabled.
+    status_bar: StatusBar,
 }
 
 impl Terminal {
@@ -16,30 +25,135 @@ impl Terminal {
     pub fn default() -> Terminal {
         Terminal {
             active_screen_index: 0,
+            previous_screen_index: None,
             screens: [Screen::default(); NBR_OF_SCREENS_PER_TERMINAL],
+            status_bar: StatusBar::new(),
         }
     }
 
     /// Handles a key press event by updating the terminal's state.
     ///
-    /// If the key is the `Tab` key, it switches to the next screen. Otherwise, the key event is passed
-    /// to the active screen for processing.
+    /// The emergency chords are handled first, see `handle_emergency`. F1 to F5 switch to the screen
+    /// of that number, if it exists. Other keys, including the other function keys, are passed to
+    /// the active screen for processing.
     ///
     /// # Parameters
     /// - `key`: The key that was pressed.
     #[allow(unused)]
     pub fn handle_key(&mut self, key: Key) {
-        match key {
-            Key::Tab => {
-                self.active_screen_index += 1;
-                if self.active_screen_index >= NBR_OF_SCREENS_PER_TERMINAL {
-                    self.active_screen_index = 0;
-                }
-            }
+        if self.handle_emergency(key, ps2::ctrl_alt_held()) {
+            return;
+        }
+        match key.function_number() {
+            Some(n @ 1..=5) => self.switch_to(n as usize - 1),
             _ => self.screens[self.active_screen_index].handle_key(key),
         }
     }
 
+    /// Handles a decoded key press. Alt+1 to Alt+9 jump to the screen of that number, if it exists,
+    /// and Alt+Tab toggles to the previous screen. Other Alt chords are ignored, as are typematic
+    /// repeats of the chords, so that holding them switches screens only once.
+    #[allow(unused)]
+    pub fn handle_event(&mut self, event: KeyEvent) {
+        if self.handle_emergency(event.key, event.ctrl && event.alt) {
+            return;
+        }
+        if !event.alt {
+            self.handle_key(event.key);
+            return;
+        }
+        if event.repeat {
+            return;
+        }
+        match event.key {
+            Key::Tab => self.switch_to_previous(),
+            key if (Key::N1..=Key::N9).contains(&key) => self.switch_to((key as u8 - b'1') as usize),
+            _ => {}
+        }
+    }
+
+    /// Runs the emergency action of `key` if `ctrl_alt`, telling that both Ctrl and Alt are held
+    /// right now, is set: Ctrl+Alt+Del reboots and Ctrl+Alt+Backspace starts the active screen
+    /// afresh. Returns `true` if `key` was consumed.
+    fn handle_emergency(&mut self, key: Key, ctrl_alt: bool) -> bool {
+        match emergency_action(key, ctrl_alt) {
+            Some(Emergency::Reboot) => ps2::reboot(),
+            Some(Emergency::ResetScreen) => {
+                let screen = &mut self.screens[self.active_screen_index];
+                let serial_mirror = screen.serial_mirror;
+                *screen = Screen::default();
+                screen.serial_mirror = serial_mirror;
+            }
+            None => return false,
+        }
+        true
+    }
+
+    /// Makes `index` the active screen and remembers the one it replaces. Every way of switching
+    /// screens goes through here, so that the previous screen is always tracked. Switching to the
+    /// active screen changes nothing.
+    pub fn switch_to(&mut self, index: usize) {
+        if index == self.active_screen_index || index >= NBR_OF_SCREENS_PER_TERMINAL {
+            return;
+        }
+        self.previous_screen_index = Some(self.active_screen_index);
+        self.active_screen_index = index;
+        vga::invalidate();
+    }
+
+    /// Switches back to the previously active screen, if any.
+    pub fn switch_to_previous(&mut self) {
+        if let Some(previous) = self.previous_screen_index {
+            self.switch_to(previous);
+        }
+    }
+
+    /// Shows the status bar on the bottom row, which the screens then leave free, or gives the row
+    /// back to them.
+    #[allow(unused)]
+    pub fn set_status_bar(&mut self, enabled: bool) {
+        self.status_bar.enabled = enabled;
+        for screen in self.screens.iter_mut() {
+            screen.view_height = vga::view_height() - enabled as usize;
+        }
+    }
+
+    /// Replaces the free text of the status bar. Only what fits on the row is kept.
+    #[allow(unused)]
+    pub fn set_status(&mut self, text: &str) {
+        self.status_bar.set_text(text);
+    }
+
+    /// Composes the active screen with the screen number, or the status bar if enabled, and the
+    /// mouse pointer drawn over it, ready to be flushed.
+    #[allow(unused)]
+    pub fn render(&self) -> Buffer {
+        let screen = &self.screens[self.active_screen_index];
+        let mut b = Buffer::from_screen(screen);
+        indicator::draw_scrollback(screen.rows_scrolled, &mut b);
+        if self.status_bar.enabled {
+            let indicator = MOD_INDICATOR.lock();
+            self.status_bar.draw(self.active_screen_index, NBR_OF_SCREENS_PER_TERMINAL, &indicator, &mut b);
+        } else {
+            indicator::draw_screen_number(self.active_screen_index, NBR_OF_SCREENS_PER_TERMINAL, &mut b);
+        }
+        POINTER.lock().draw(&mut b);
+        b
+    }
+
+    /// Displays the active screen.
+    #[allow(unused)]
+    pub fn flush(&self) {
+        self.render().flush();
+    }
+
+    /// Color the active screen writes with. Every screen keeps its own, so switching screens does
+    /// not carry it over.
+    #[allow(unused)]
+    pub fn active_screen_color(&self) -> u8 {
+        self.screens[self.active_screen_index].color()
+    }
+
     #[allow(unused)]
     pub fn write_str(&mut self, string: &str) {
         self.screens[self.active_screen_index].write_str(string);
@@ -50,3 +164,243 @@ impl Terminal {
         self.screens[self.active_screen_index].write_color_str(string, color);
     }
 }
+
+/// Actions bound to the Ctrl+Alt chords, which work whatever the shell is doing.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Emergency {
+    Reboot,
+    ResetScreen,
+}
+
+/// Returns the action bound to `key`, if any, given whether Ctrl and Alt are both held.
+pub fn emergency_action(key: Key, ctrl_alt: bool) -> Option<Emergency> {
+    match key {
+        _ if !ctrl_alt => None,
+        Key::Delete => Some(Emergency::Reboot),
+        Key::Backspace => Some(Emergency::ResetScreen),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::terminal::{
+        ps2::{decode_event, ModifierState},
+        vga::{view_size, Color, VIEW_HEIGHT, VIEW_WIDTH},
+    };
+
+    fn feed(t: &mut Terminal, bytes: &[u8], modifiers: &mut ModifierState) {
+        for &b in bytes {
+            if let Some(event) = decode_event(b, modifiers) {
+                t.handle_event(event);
+            }
+        }
+    }
+
+    #[test]
+    fn alt_digit_jumps_to_the_screen() {
+        let mut t = Terminal::default();
+        let mut modifiers = ModifierState::new();
+
+        // Alt held, 2 held down, everything released.
+        feed(&mut t, &[0x38, 0x03, 0x03, 0x03, 0x83, 0xB8], &mut modifiers);
+        assert_eq!(t.active_screen_index, 1);
+        assert_eq!(t.screens[1].last_entry_index, 0);
+
+        feed(&mut t, &[0x38, 0x02, 0x82, 0xB8], &mut modifiers);
+        assert_eq!(t.active_screen_index, 0);
+    }
+
+    #[test]
+    fn out_of_range_alt_digit_is_ignored() {
+        let mut t = Terminal::default();
+        let mut modifiers = ModifierState::new();
+
+        // Alt+9, then Alt+0, which is not a screen number.
+        feed(&mut t, &[0x38, 0x0A, 0x8A, 0x0B, 0x8B, 0xB8], &mut modifiers);
+        assert_eq!(t.active_screen_index, 0);
+        assert_eq!(t.screens[0].last_entry_index, 0);
+    }
+
+    fn line_text(line: &[crate::terminal::vga::Cell]) -> impl Iterator<Item = u8> + '_ {
+        line.iter().map(|cell| cell.ch())
+    }
+
+    #[test]
+    fn typing_edits_the_active_screen() {
+        let mut t = Terminal::default();
+        let mut modifiers = ModifierState::new();
+
+        // a, b, Backspace, Enter, c, then Alt+2 and d on the second screen.
+        feed(&mut t, &[0x1E, 0x9E, 0x30, 0xB0, 0x0E, 0x8E, 0x1C, 0x9C, 0x2E, 0xAE], &mut modifiers);
+        feed(&mut t, &[0x38, 0x03, 0x83, 0xB8, 0x20, 0xA0], &mut modifiers);
+
+        let mut lines = t.screens[0].lines();
+        assert!(line_text(lines.next().unwrap()).eq(*b"a"));
+        assert!(line_text(lines.next().unwrap()).eq(*b"c"));
+        assert!(lines.next().is_none());
+        assert!(line_text(t.screens[1].lines().next().unwrap()).eq(*b"d"));
+    }
+
+    #[test]
+    fn emergency_chords_need_ctrl_and_alt() {
+        assert_eq!(emergency_action(Key::Delete, true), Some(Emergency::Reboot));
+        assert_eq!(emergency_action(Key::Backspace, true), Some(Emergency::ResetScreen));
+        assert_eq!(emergency_action(Key::Delete, false), None);
+        assert_eq!(emergency_action(Key::A, true), None);
+
+        let mut t = Terminal::default();
+        let mut modifiers = ModifierState::new();
+        t.write_str("wedged");
+        // Ctrl+Backspace without Alt: an ordinary Backspace reaches the screen.
+        feed(&mut t, &[0x1D, 0x0E, 0x8E, 0x9D], &mut modifiers);
+        assert_eq!(t.screens[0].last_entry_index, 5);
+
+        feed(&mut t, &[0x1D, 0x38, 0x0E, 0x8E, 0xB8, 0x9D], &mut modifiers);
+        assert_eq!(t.screens[0].last_entry_index, 0);
+        assert_eq!(t.screens[0].cursor, 0);
+    }
+
+    #[test]
+    fn screen_number_is_drawn_over_the_content() {
+        let mut t = Terminal::default();
+        for screen in t.screens.iter_mut() {
+            screen.write_str("x");
+        }
+        let corner = |b: &Buffer, text: &[u8]| {
+            b.cells()[VIEW_WIDTH - text.len()..VIEW_WIDTH]
+                .iter()
+                .map(|cell| cell.ch())
+                .eq(text.iter().copied())
+        };
+
+        let b = t.render();
+        assert!(corner(&b, b"[1/2]"));
+        assert_eq!(b.cells()[0].ch(), b'x');
+        assert_eq!(b.cells()[VIEW_WIDTH - 1].color(), Color::LightCyan as u8);
+        assert_eq!(t.screens[0].last_entry_index, 1);
+
+        t.switch_to(1);
+        assert!(corner(&t.render(), b"[2/2]"));
+    }
+
+    #[test]
+    fn status_bar_takes_the_bottom_row() {
+        let mut t = Terminal::default();
+        t.set_status_bar(true);
+        t.set_status(&"status ".repeat(12));
+        for _ in 0..VIEW_HEIGHT {
+            t.write_str("line\n");
+        }
+        t.write_str("last");
+
+        let b = t.render();
+        let row = |r: usize| &b.cells()[r * VIEW_WIDTH..(r + 1) * VIEW_WIDTH];
+        let text = |r: usize| row(r).iter().map(|cell| cell.ch());
+        assert!(text(VIEW_HEIGHT - 2).take(4).eq(*b"last"));
+        assert!(text(VIEW_HEIGHT - 1).take(15).eq(*b" [1/2] status s"));
+        assert!(row(VIEW_HEIGHT - 1).iter().all(|cell| cell.color() == 0x70));
+        assert_eq!(row(VIEW_HEIGHT - 1)[VIEW_WIDTH - 1].ch(), b'a');
+        assert_ne!(b.cells()[VIEW_WIDTH - 1].ch(), b']');
+
+        t.set_status_bar(false);
+        let b = t.render();
+        assert!(b.cells()[view_size() - VIEW_WIDTH..].iter().take(4).map(|cell| cell.ch()).eq(*b"last"));
+    }
+
+    #[test]
+    fn colors_stay_with_their_screen() {
+        let mut t = Terminal::default();
+        t.write_str("\x1b[31ma");
+        t.switch_to(1);
+        assert_eq!(t.active_screen_color(), Color::Default as u8);
+        t.write_str("b\x1b[44mc");
+        t.switch_to(0);
+        assert_eq!(t.active_screen_color(), 0x04);
+        t.write_str("d");
+        t.switch_to(1);
+        assert_eq!(t.active_screen_color(), 0x17);
+        t.write_str("e");
+
+        let attributes = |screen: &Screen| -> [(u8, u8); 3] { core::array::from_fn(|i| (screen.buffer[i].ch(), screen.buffer[i].color())) };
+        assert_eq!(attributes(&t.screens[0])[..2], [(b'a', 0x04), (b'd', 0x04)]);
+        assert_eq!(attributes(&t.screens[1]), [(b'b', 0x07), (b'c', 0x17), (b'e', 0x17)]);
+    }
+
+    #[test]
+    fn tab_reaches_the_active_screen() {
+        let mut t = Terminal::default();
+        let mut modifiers = ModifierState::new();
+
+        feed(&mut t, &[0x1E, 0x9E, 0x0F, 0x8F], &mut modifiers);
+        assert_eq!(t.active_screen_index, 0);
+        assert_eq!(t.screens[0].last_entry_index, 1);
+    }
+
+    #[test]
+    fn held_letters_still_repeat() {
+        let mut t = Terminal::default();
+        let mut modifiers = ModifierState::new();
+
+        feed(&mut t, &[0x1E, 0x1E, 0x1E, 0x9E], &mut modifiers);
+        assert_eq!(t.screens[0].last_entry_index, 3);
+    }
+
+    #[test]
+    fn switching_tracks_the_previous_screen() {
+        let mut t = Terminal::default();
+        assert_eq!(t.previous_screen_index, None);
+        t.switch_to_previous();
+        assert_eq!(t.active_screen_index, 0);
+
+        t.switch_to(1);
+        assert_eq!((t.active_screen_index, t.previous_screen_index), (1, Some(0)));
+
+        t.switch_to(1);
+        assert_eq!(t.previous_screen_index, Some(0));
+        t.switch_to(NBR_OF_SCREENS_PER_TERMINAL);
+        assert_eq!((t.active_screen_index, t.previous_screen_index), (1, Some(0)));
+
+        t.switch_to(0);
+        assert_eq!((t.active_screen_index, t.previous_screen_index), (0, Some(1)));
+    }
+
+    #[test]
+    fn alt_tab_toggles() {
+        let mut t = Terminal::default();
+        let mut modifiers = ModifierState::new();
+        feed(&mut t, &[0x38, 0x03, 0x83, 0xB8], &mut modifiers);
+        assert_eq!(t.active_screen_index, 1);
+
+        // Alt held, Tab pressed three times, Alt released.
+        for expected in [0, 1, 0] {
+            feed(&mut t, &[0x38, 0x0F, 0x8F], &mut modifiers);
+            assert_eq!(t.active_screen_index, expected);
+        }
+        feed(&mut t, &[0xB8], &mut modifiers);
+        assert_eq!(t.previous_screen_index, Some(1));
+
+        // Holding the chord toggles once.
+        feed(&mut t, &[0x38, 0x0F, 0x0F, 0x0F, 0x8F, 0xB8], &mut modifiers);
+        assert_eq!(t.active_screen_index, 1);
+    }
+
+    #[test]
+    fn function_keys_select_screens() {
+        let mut t = Terminal::default();
+        t.handle_key(Key::F2);
+        assert_eq!(t.active_screen_index, 1);
+        t.handle_key(Key::F1);
+        assert_eq!(t.active_screen_index, 0);
+
+        // Only NBR_OF_SCREENS_PER_TERMINAL screens exist, F3 to F5 are ignored.
+        t.handle_key(Key::F3);
+        assert_eq!(t.active_screen_index, 0);
+        assert_eq!(t.screens[0].last_entry_index, 0);
+
+        t.handle_key(Key::F7);
+        assert_eq!(t.active_screen_index, 0);
+        assert_eq!(t.screens[0].last_entry_index, 0);
+    }
+}
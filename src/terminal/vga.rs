@@ -1,33 +1,89 @@
-use core::ptr::{read_volatile, write_volatile};
+use core::{
+    ops::Range,
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use spin::Mutex;
+
+use crate::{
+    conv,
+    io::{inb, outb},
+    time,
+};
 
 use super::{
-    cursor::Cursor,
+    cursor::{Cursor, CursorStyle, CursorSync, CURSOR_SYNC},
     screen::{Screen, BUFFER_SIZE},
 };
 
 /// The `width` of the viewable area of the VGA Buffer in chars
 pub const VIEW_WIDTH: usize = 80;
 
-/// The `height` of the viewable area of the VGA Buffer in chars
+/// The `height` of the viewable area of the VGA Buffer in chars in the standard 80x25 mode. The
+/// current height is `view_height()`.
 pub const VIEW_HEIGHT: usize = 25;
 
-/// The total number of character positions in the viewable area (width x height).
-pub const VIEW_BUFFER_SIZE: usize = VIEW_WIDTH * VIEW_HEIGHT;
+/// The `height` of the viewable area in the tallest text mode, 80x50.
+pub const MAX_VIEW_HEIGHT: usize = 50;
+
+/// Number of character positions of the viewable area in the tallest text mode.
+pub const MAX_VIEW_BUFFER_SIZE: usize = VIEW_WIDTH * MAX_VIEW_HEIGHT;
 
 /// The base memory address of the VGA buffer for text mode display.
 const VGA_BUFFER_ADDR: *mut u16 = 0xB8000 as *mut u16;
 
+/// Number of cells in the 32 KiB of text memory, of which the viewable area is a window.
+const TEXT_MEMORY_CELLS: usize = 0x4000;
+
+pub mod mode;
+
+/// Returns the number of rows of the viewable area in the current text mode.
+pub fn view_height() -> usize {
+    mode::current().rows()
+}
+
+/// Returns the number of character positions of the viewable area in the current text mode.
+pub fn view_size() -> usize {
+    VIEW_WIDTH * view_height()
+}
+
+/// Offset in text memory of the cell displayed in the top-left corner, as programmed in the CRTC
+/// start address registers.
+static WINDOW_START: AtomicUsize = AtomicUsize::new(0);
+
 /// A struct representing a screen buffer for VGA entry handling and cursor management.
 ///
 /// The `Buffer` holds a 2D array representing the screen's character data,
 /// a cursor position (`cursor_x` and `cursor_y`), and provides methods for creating
 /// a new buffer from a screen and flushing its contents to a device.
+#[derive(Clone, Copy)]
 pub struct Buffer {
-    /// A fixed-size array to hold screen data, representing characters and their colors.
-    buffer: [u16; VIEW_BUFFER_SIZE],
+    /// A fixed-size array to hold screen data, representing characters and their colors. Only the
+    /// first `rows` rows are displayed.
+    buffer: [Cell; MAX_VIEW_BUFFER_SIZE],
+
+    /// Rows of the text mode the buffer was made for.
+    rows: usize,
+
+    /// Rows converted from the screen, its `view_height`.
+    screen_rows: usize,
 
     /// Cursor
     cursor: Option<Cursor>,
+
+    /// Shape of the cursor, following the editing mode of the screen.
+    cursor_style: CursorStyle,
+
+    /// Index of the `Screen` entry displayed in the top-left corner.
+    view_start: usize,
+
+    /// Index of the `Screen` entry displayed first on each row, `usize::MAX` for the rows past
+    /// the end of the screen buffer.
+    row_starts: [usize; MAX_VIEW_HEIGHT],
+
+    /// Index of the first `Screen` entry past the view.
+    view_end: usize,
 }
 
 impl Buffer {
@@ -43,45 +99,125 @@ impl Buffer {
     /// # Returns
     /// A new `Buffer` with the formatted data from the `Screen` and the updated cursor position.
     pub fn from_screen(s: &Screen) -> Self {
-        let mut view_padding_whitespace: usize = 0;
-
+        let view_start = view_start_index(s);
         let mut vga_buffer: Buffer = Buffer {
-            buffer: [0; VIEW_BUFFER_SIZE],
+            buffer: [Cell::from_raw(0); MAX_VIEW_BUFFER_SIZE],
+            rows: view_height(),
+            screen_rows: s.view_height,
             cursor: None,
+            cursor_style: s.cursor_style(),
+            view_start,
+            row_starts: [usize::MAX; MAX_VIEW_HEIGHT],
+            view_end: view_start,
         };
+        vga_buffer.convert(s, view_start, 0, BUFFER_SIZE);
+        vga_buffer.place_cursor(s);
+        vga_buffer
+    }
 
-        let view_start_index = calculate_view_start_index(s);
-        for (relative_index, &entry) in s.buffer.iter().skip(view_start_index).enumerate() {
-            let padded_relative_index = relative_index + view_padding_whitespace;
-            let index_after_viewport = padded_relative_index >= VIEW_BUFFER_SIZE;
-            if index_after_viewport {
-                break;
+    /// Brings a buffer made from `s` up to date, `damage` being the range of entries changed since,
+    /// see `Screen::render`. Only the entries from the start of the damage are converted, up to the
+    /// first row past it that starts on the same entry as before, the rows after it being
+    /// unchanged. Everything is converted again if the view moved.
+    ///
+    /// Returns the number of entries converted.
+    pub fn refresh(&mut self, s: &Screen, damage: Option<Range<usize>>) -> usize {
+        let view_start = view_start_index(s);
+        if view_start != self.view_start || self.rows != view_height() || self.screen_rows != s.view_height {
+            *self = Buffer::from_screen(s);
+            return self.view_end - self.view_start;
+        }
+
+        let mut converted = 0;
+        if let Some(damage) = damage.filter(|d| d.start < self.view_end && d.end > view_start) {
+            let start = damage.start.max(view_start);
+            let row = self.row_of(start);
+            let position = row * VIEW_WIDTH + start - self.row_starts[row];
+            converted = self.convert(s, start, position, damage.end);
+        }
+        self.cursor_style = s.cursor_style();
+        self.place_cursor(s);
+        converted
+    }
+
+    /// Converts the entries of `s` from `index` on, displayed from `position` on, until the end of
+    /// the view. Stops early at a row that starts on the same entry as before, once past `resync`,
+    /// as the rest of the view already shows the unchanged entries from there.
+    ///
+    /// Returns the number of entries converted.
+    fn convert(&mut self, s: &Screen, mut index: usize, mut position: usize, resync: usize) -> usize {
+        let first = index;
+        let size = s.view_height * VIEW_WIDTH;
+        while position < size && index < BUFFER_SIZE {
+            if position.is_multiple_of(VIEW_WIDTH) {
+                let row = position / VIEW_WIDTH;
+                if index >= resync && self.row_starts[row] == index {
+                    return index - first;
+                }
+                self.row_starts[row] = index;
             }
 
-            let relative_cursor = s.cursor - view_start_index;
-            let padded_relative_cursor = relative_cursor + view_padding_whitespace;
-            if relative_cursor == relative_index {
-                vga_buffer.cursor = Some(Cursor::new(
-                    (padded_relative_cursor % VIEW_WIDTH) as u16,
-                    (padded_relative_cursor / VIEW_WIDTH) as u16,
-                ));
+            let entry = s.buffer[index];
+            if entry.is_line_break() {
+                let padding = VIEW_WIDTH - (position % VIEW_WIDTH) - 1;
+                self.buffer[position] = Cell::BLANK;
+                self.buffer[position + 1..=position + padding].fill(Cell::from_raw(0));
+                position += padding;
+            } else {
+                self.buffer[position] = entry;
             }
+            position += 1;
+            index += 1;
+        }
 
-            match (entry & 0xFF) as u8 {
-                b'\n' => {
-                    let padding = VIEW_WIDTH - (padded_relative_index % VIEW_WIDTH) - 1;
-                    view_padding_whitespace += padding;
+        self.buffer[position.min(size)..size].fill(Cell::from_raw(0));
+        self.row_starts[position.div_ceil(VIEW_WIDTH).min(MAX_VIEW_HEIGHT)..].fill(usize::MAX);
+        self.view_end = index;
+        index - first
+    }
 
-                    for _ in 0..(padding + 1) {
-                        vga_buffer.buffer[padded_relative_index] = Entry::new(b' ').to_u16()
-                    }
-                }
-                _ => vga_buffer.buffer[padded_relative_index] = entry, // _ => write_entry_to_vga(padded_relative_index, entry).unwrap(),
-            }
+    /// Returns the row displaying the entry `index`, which must be in the view.
+    fn row_of(&self, index: usize) -> usize {
+        self.row_starts.iter().rposition(|&start| start <= index).unwrap_or(0)
+    }
+
+    /// Displays the cursor of `s` if the view shows it.
+    fn place_cursor(&mut self, s: &Screen) {
+        self.cursor = None;
+        if !s.shows_cursor() || !(self.view_start..self.view_end).contains(&s.cursor) {
+            return;
         }
+        let row = self.row_of(s.cursor);
+        self.cursor = Some(Cursor::new((s.cursor - self.row_starts[row]) as u16, row as u16));
+    }
 
-        vga_buffer
+    /// Returns `true` if the view starts at the oldest entry of the scrollback.
+    pub fn shows_oldest(&self) -> bool {
+        self.view_start == 0
     }
+
+    /// Returns the displayed cells, `VIEW_WIDTH` per row.
+    pub fn cells(&self) -> &[Cell] {
+        &self.buffer[..self.rows * VIEW_WIDTH]
+    }
+
+    /// Composites `cells` over the buffer starting at `start`, clipping anything past the end of
+    /// the viewable area. Used for indicators that must not be stored in the `Screen`.
+    pub fn overlay(&mut self, start: usize, cells: &[Cell]) {
+        let size = self.rows * VIEW_WIDTH;
+        for (dst, src) in self.buffer[..size].iter_mut().skip(start).zip(cells.iter()) {
+            *dst = *src;
+        }
+    }
+
+    /// Swaps the foreground and background colors of the cell at `index`, if it is visible.
+    pub fn invert(&mut self, index: usize) {
+        let size = self.rows * VIEW_WIDTH;
+        if let Some(cell) = self.buffer[..size].get_mut(index) {
+            *cell = cell.inverted();
+        }
+    }
+
     /// Flushes the contents of the buffer to the hardware VGA device.
     ///
     /// This function writes the entries in the buffer to the VGA display,
@@ -93,84 +229,365 @@ impl Buffer {
     /// buffer.flush();
     /// ```
     pub fn flush(&self) {
-        for (i, e) in self.buffer.iter().enumerate() {
+        let mut shadow = SHADOW.lock();
+        self.write_view(&mut shadow, view_height(), store_entry);
+        drop(shadow);
+        let mut sync = CURSOR_SYNC.lock();
+        sync.set_style(self.cursor_style);
+        sync.sync(self.cursor);
+    }
+
+    /// Flushes the buffer and reprograms the hardware cursor unconditionally, without taking any
+    /// lock. Meant for the panic path, where the screen freezes and the cursor must not be stale.
+    #[cfg_attr(test, allow(dead_code))]
+    pub fn flush_immediate(&self) {
+        assert_eq!(self.rows, view_height(), "buffer made for another text mode");
+        self.write_entries();
+        let mut sync = CursorSync::new();
+        sync.set_style(self.cursor_style);
+        sync.sync(self.cursor);
+    }
+
+    fn write_entries(&self) {
+        for (i, e) in self.cells().iter().enumerate() {
             write_entry_to_vga(i, *e).unwrap();
         }
-        match self.cursor {
-            Some(c) => {
-                unsafe {
-                    c.flush_pos();
-                }
-                Cursor::show();
-            }
-            None => Cursor::hide(),
+    }
+
+    /// Passes the cells that changed since `shadow` to `write`. The buffer must have been made for
+    /// the `rows` of the view: `mode::set` flushes the last frame of the old mode itself, and the
+    /// next `Screen::render` makes one for the new mode.
+    fn write_view(&self, shadow: &mut Shadow, rows: usize, write: impl FnMut(usize, Cell)) {
+        assert_eq!(self.rows, rows, "buffer made for another text mode");
+        self.scroll_window(shadow);
+        shadow.update(self.cells(), write);
+    }
+
+    /// Moves the viewable window over text memory when the buffer is the displayed content
+    /// scrolled by whole rows, so that only the rows entering the view have to be written.
+    fn scroll_window(&self, shadow: &mut Shadow) {
+        if shadow.dirty_rows != 0 {
+            return;
+        }
+        let start = window_start();
+        let next = window_start_after(start, rows_scrolled(&shadow.cells[..shadow.rows * VIEW_WIDTH], self.cells()));
+        if next != start {
+            set_window_start(next);
+            shadow.scroll((next as isize - start as isize) / VIEW_WIDTH as isize);
         }
     }
 }
 
-fn calculate_view_start_index(t: &Screen) -> usize {
-    let mut rows: [(usize, usize); BUFFER_SIZE] = [(0, 0); BUFFER_SIZE];
-    let mut index_rows = 0;
-
-    let mut current_line = (0, 0);
-    for (i, e) in t.buffer.iter().enumerate() {
-        if current_line == (0, 0) {
-            current_line.0 = i;
-        }
-        if current_line.1 >= current_line.0 && (current_line.1 - current_line.0) == (VIEW_WIDTH - 1) {
-            rows[index_rows] = current_line;
-            index_rows += 1;
-            current_line = (0, 0);
-            continue;
+/// Bits of `Shadow::dirty_rows` for every row of the tallest viewable area.
+const ALL_ROWS: u64 = (1 << MAX_VIEW_HEIGHT) - 1;
+
+/// Copy of the cells last written to the viewable window, so that a flush only writes the cells
+/// that changed, without reading text memory back.
+pub struct Shadow {
+    cells: [Cell; MAX_VIEW_BUFFER_SIZE],
+    /// Rows of the viewable area last written.
+    rows: usize,
+    /// One bit per row whose cells may not match text memory and are written regardless.
+    dirty_rows: u64,
+    /// Cells written to text memory, wrapping around.
+    pub cells_written: u32,
+}
+
+impl Shadow {
+    pub const fn new() -> Self {
+        Shadow {
+            cells: [Cell::BLANK; MAX_VIEW_BUFFER_SIZE],
+            rows: VIEW_HEIGHT,
+            dirty_rows: ALL_ROWS,
+            cells_written: 0,
         }
-        match (e & 0xFF) as u8 {
-            b'\n' => {
-                current_line.1 = i;
-                rows[index_rows] = current_line;
-                index_rows += 1;
-                current_line = (0, 0);
-            }
-            _ => {
-                current_line.1 = i;
+    }
+
+    /// Has the next flush write every cell, for when the whole view changes anyway or text memory
+    /// was written behind the shadow's back.
+    pub fn invalidate(&mut self) {
+        self.dirty_rows = ALL_ROWS;
+    }
+
+    /// Follows the window moving by `rows`, positive when it moves down in text memory. The rows
+    /// entering the view hold whatever text memory had there and are dirty.
+    fn scroll(&mut self, rows: isize) {
+        let count = rows.unsigned_abs().min(self.rows);
+        let shift = count * VIEW_WIDTH;
+        let size = self.rows * VIEW_WIDTH;
+        let all_rows = ALL_ROWS >> (MAX_VIEW_HEIGHT - self.rows);
+        if rows > 0 {
+            self.cells.copy_within(shift..size, 0);
+            self.dirty_rows = (self.dirty_rows >> count) | (all_rows & !(all_rows >> count));
+        } else {
+            self.cells.copy_within(..size - shift, shift);
+            self.dirty_rows = ((self.dirty_rows << count) & all_rows) | ((1 << count) - 1);
+        }
+    }
+
+    /// Passes the cells of `next` that differ from the copy, or lie in a dirty row, to `write` and
+    /// records them. Returns the number of cells written.
+    fn update(&mut self, next: &[Cell], mut write: impl FnMut(usize, Cell)) -> usize {
+        self.rows = next.len() / VIEW_WIDTH;
+        let mut written = 0;
+        for (index, (shown, &cell)) in self.cells.iter_mut().zip(next.iter()).enumerate() {
+            if *shown != cell || self.dirty_rows & (1 << (index / VIEW_WIDTH)) != 0 {
+                write(index, cell);
+                *shown = cell;
+                written += 1;
             }
         }
+        self.dirty_rows = 0;
+        self.cells_written = self.cells_written.wrapping_add(written as u32);
+        written
     }
-    let mut row_position_last = 0;
-    for (i, (start, end)) in rows.iter().enumerate() {
-        if *start <= t.last_entry_index && t.last_entry_index <= *end {
-            row_position_last = i;
+}
+
+/// What `Buffer::flush` last wrote to the viewable window.
+static SHADOW: Mutex<Shadow> = Mutex::new(Shadow::new());
+
+/// Has the next flush rewrite every cell of the view.
+pub fn invalidate() {
+    SHADOW.lock().invalidate();
+}
+
+/// Returns the number of cells written to text memory by `Buffer::flush`, wrapping around.
+pub fn cells_written() -> u32 {
+    SHADOW.lock().cells_written
+}
+
+/// Returns by how many rows `next` is `shown` scrolled, positive when the content moved up, or 0
+/// when rewriting the changed cells in place is cheaper than writing the rows entering the view.
+fn rows_scrolled(shown: &[Cell], next: &[Cell]) -> isize {
+    if shown.len() != next.len() {
+        return 0;
+    }
+    let changed = shown.iter().zip(next.iter()).filter(|(a, b)| a != b).count();
+    for rows in 1..next.len() / VIEW_WIDTH {
+        if rows * VIEW_WIDTH >= changed {
             break;
         }
+        let kept = next.len() - rows * VIEW_WIDTH;
+        if shown[rows * VIEW_WIDTH..] == next[..kept] {
+            return rows as isize;
+        }
+        if shown[..kept] == next[rows * VIEW_WIDTH..] {
+            return -(rows as isize);
+        }
+    }
+    0
+}
+
+/// Returns the window start after scrolling by `rows` from `start`. A window that would leave text
+/// memory goes back to its beginning, where every cell is rewritten like a plain flush.
+fn window_start_after(start: usize, rows: isize) -> usize {
+    if rows == 0 {
+        return start;
+    }
+    match start.checked_add_signed(rows * VIEW_WIDTH as isize) {
+        Some(next) if next + view_size() <= TEXT_MEMORY_CELLS => next,
+        _ => 0,
+    }
+}
+
+/// Returns the offset in text memory of the cell displayed in the top-left corner.
+pub fn window_start() -> usize {
+    WINDOW_START.load(Ordering::Relaxed)
+}
+
+/// Displays text memory from `start` on by programming the CRTC start address registers.
+pub(super) fn set_window_start(start: usize) {
+    unsafe {
+        write_crtc(CRTC_START_ADDRESS_HIGH, (start >> 8) as u8);
+        write_crtc(CRTC_START_ADDRESS_LOW, start as u8);
+    }
+    WINDOW_START.store(start, Ordering::Relaxed);
+}
+
+/// Returns the index of the entry displayed in the top-left corner of `s`.
+fn view_start_index(s: &Screen) -> usize {
+    s.alt_origin().unwrap_or_else(|| calculate_view_start_index(s))
+}
+
+/// Returns the start of the row `rows_scrolled + view_height - 1` rows above the row of the last
+/// entry, so that the view ends `rows_scrolled` rows above it, or 0 if the content is shorter.
+///
+/// A row starts after a line break, or `VIEW_WIDTH` entries after the start of the previous row of
+/// its line, so the rows are walked backwards from the last entry, without going over the
+/// scrollback above the view.
+fn calculate_view_start_index(t: &Screen) -> usize {
+    let line_start = |end: usize| t.buffer[..end].iter().rposition(|c| c.is_line_break()).map_or(0, |i| i + 1);
+
+    // The row is kept as the start of its line and its number within the line.
+    let mut start = line_start(t.last_entry_index);
+    let mut row = (t.last_entry_index - start) / VIEW_WIDTH;
+    for _ in 1..t.rows_scrolled + t.view_height {
+        if row > 0 {
+            row -= 1;
+            continue;
+        }
+        if start == 0 {
+            return 0;
+        }
+        let line_break = start - 1;
+        start = line_start(line_break);
+        row = (line_break - start) / VIEW_WIDTH;
     }
-    if row_position_last < t.rows_scrolled {
-        row_position_last = 0;
-    } else {
-        row_position_last -= t.rows_scrolled;
+    start + row * VIEW_WIDTH
+}
+
+/// Inverts the colors of every visible cell for `duration` ticks, used as the visual bell.
+#[cfg_attr(test, allow(dead_code))]
+pub fn flash(duration: u64) {
+    invert_vga();
+    time::wait(duration);
+    invert_vga();
+}
+
+#[cfg_attr(test, allow(dead_code))]
+fn invert_vga() {
+    for index in 0..view_size() {
+        let entry = read_entry_from_vga(index).unwrap();
+        write_entry_to_vga(index, Cell::new(entry.ch(), entry.color().rotate_left(4))).unwrap();
+    }
+}
+
+const ATTRIBUTE_PORT: u16 = 0x3C0;
+/// The attribute controller registers are read back through a port of their own.
+const ATTRIBUTE_READ_PORT: u16 = 0x3C1;
+/// Reading the input status register resets the attribute controller to its index state.
+const INPUT_STATUS_PORT: u16 = 0x3DA;
+/// `0x3D4` selects the CRTC register accessed through `0x3D5`.
+pub const CRTC_INDEX_PORT: u16 = 0x3D4;
+pub const CRTC_DATA_PORT: u16 = 0x3D5;
+/// CRTC indices of the start address registers.
+const CRTC_START_ADDRESS_HIGH: u8 = 0x0C;
+const CRTC_START_ADDRESS_LOW: u8 = 0x0D;
+/// Attribute controller index of the mode control register, with the palette address source bit
+/// set so that the display stays enabled.
+const ATTRIBUTE_MODE_CONTROL: u8 = 0x10 | 0x20;
+/// Text mode with line graphics enabled and blinking disabled.
+const MODE_CONTROL_NO_BLINK: u8 = 0x04;
+/// Mode control bit making bit 7 of the attribute byte blink the cell instead of brightening its
+/// background.
+const MODE_CONTROL_BLINK: u8 = 0x08;
+/// CRTC index of the cursor start register.
+const CRTC_CURSOR_START: u8 = 0x0A;
+
+/// Brings the VGA registers touched by the kernel back into a known state, for paths that cannot
+/// trust them anymore, like the panic handler.
+///
+/// An interrupted register update may have left the attribute controller flip-flop on a data write
+/// or the CRTC index on an arbitrary register. The sequence is:
+/// 1. read `0x3DA` to reset the attribute controller flip-flop,
+/// 2. write the mode control register (`0x30`, `0x04`) to `0x3C0`, disabling blinking,
+/// 3. select the cursor start register (`0x0A`) on `0x3D4`, so that `0x3D5` no longer targets
+///    whatever register was selected before.
+///
+/// None of this relies on any `Screen` or cursor state.
+pub fn emergency_reset() {
+    unsafe {
+        inb(INPUT_STATUS_PORT);
+        outb(ATTRIBUTE_PORT, ATTRIBUTE_MODE_CONTROL);
+        outb(ATTRIBUTE_PORT, MODE_CONTROL_NO_BLINK);
+        outb(CRTC_INDEX_PORT, CRTC_CURSOR_START);
+    }
+}
+
+/// Chooses what bit 7 of the attribute byte does: blink the cell if `enabled`, otherwise select
+/// the bright half of the palette for the background, giving 16 background colors.
+///
+/// The mode control register is read back first so that its other bits are kept. The index is
+/// written with the palette address source bit set, as leaving it clear blanks the display.
+pub fn set_blink(enabled: bool) {
+    unsafe {
+        inb(INPUT_STATUS_PORT);
+        outb(ATTRIBUTE_PORT, ATTRIBUTE_MODE_CONTROL);
+        let value = inb(ATTRIBUTE_READ_PORT);
+        let value = if enabled { value | MODE_CONTROL_BLINK } else { value & !MODE_CONTROL_BLINK };
+        outb(ATTRIBUTE_PORT, value);
     }
-    if row_position_last < VIEW_HEIGHT {
-        0
-    } else {
-        rows[row_position_last - (VIEW_HEIGHT - 1)].0
+}
+
+/// `0x3C7` and `0x3C8` select the DAC entry read or written through `0x3C9`, one 6-bit component
+/// at a time in red, green, blue order.
+const DAC_READ_INDEX_PORT: u16 = 0x3C7;
+const DAC_WRITE_INDEX_PORT: u16 = 0x3C8;
+const DAC_DATA_PORT: u16 = 0x3C9;
+/// DAC entries shown by the 16 text colors through the default attribute controller palette.
+const TEXT_COLOR_DAC_INDEX: [u8; 16] = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x14, 0x07, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F];
+
+/// Changes the color shown for the text color `index` (0 to 15) to the 8-bit components `r`, `g`
+/// and `b`. The DAC only keeps the top 6 bits of each.
+pub fn set_palette_entry(index: u8, r: u8, g: u8, b: u8) -> Result<(), OutOfBoundsErr> {
+    let entry = *TEXT_COLOR_DAC_INDEX.get(index as usize).ok_or(OutOfBoundsErr)?;
+    unsafe {
+        outb(DAC_WRITE_INDEX_PORT, entry);
+        for component in [r, g, b] {
+            outb(DAC_DATA_PORT, component >> 2);
+        }
     }
+    Ok(())
+}
+
+/// Reads the color shown for the text color `index` (0 to 15), as 8-bit `(r, g, b)` components.
+pub fn palette_entry(index: u8) -> Result<(u8, u8, u8), OutOfBoundsErr> {
+    let entry = *TEXT_COLOR_DAC_INDEX.get(index as usize).ok_or(OutOfBoundsErr)?;
+    let [r, g, b] = unsafe {
+        outb(DAC_READ_INDEX_PORT, entry);
+        // Copies the top bits down, so that a full 6-bit component reads back as 0xFF.
+        [(); 3].map(|_| {
+            let component = inb(DAC_DATA_PORT) & 0x3F;
+            component << 2 | component >> 4
+        })
+    };
+    Ok((r, g, b))
+}
+
+/// Writes `value` to the [CRTC register](http://www.osdever.net/FreeVGA/vga/crtcreg.htm) `index`.
+///
+/// `0x3D4` is the I/O port address for the VGA's CRTC ([Cathode-ray tube](https://en.wikipedia.org/wiki/Cathode-ray_tube))'s
+/// index register. The value being loaded into it defines which CRTC functionality we want to access.
+///
+/// After the index has been loaded into the `0x3D4`, `dx`, (where the index register is stored) can be incremented by
+/// one. This will move it to `0x3D5`, the CRTC's data register, signifying the CRTC's readiness to receive the input values.
+///
+/// ## SAFETY:
+/// This writes to the VGA registers directly, running this in a non-bare-metal environment
+/// will result in invalid memory access.
+pub unsafe fn write_crtc(index: u8, value: u8) {
+    outb(CRTC_INDEX_PORT, index);
+    outb(CRTC_DATA_PORT, value);
+}
+
+/// Replaces the value of the CRTC register `index` by `f` applied to it, selecting the register
+/// once for both the read and the write.
+///
+/// ## SAFETY:
+/// Same as `write_crtc`.
+pub unsafe fn modify_crtc(index: u8, f: impl FnOnce(u8) -> u8) {
+    outb(CRTC_INDEX_PORT, index);
+    let value = inb(CRTC_DATA_PORT);
+    outb(CRTC_DATA_PORT, f(value));
 }
 
 #[derive(Debug)]
 pub struct OutOfBoundsErr;
 
-/// Writes an entry (a `u16` value) to the VGA buffer at the specified index.
+/// Writes a `Cell` to the VGA buffer at the specified index of the viewable window.
 ///
 /// This function ensures that an entry is only written if it's different from the existing one at that index.
 /// It checks for the current value at the index and only performs the write if there's a change.
 ///
 /// ### Parameters:
 /// - `index`: The index in the VGA buffer to which the entry should be written.
-/// - `entry`: The `u16` entry to be written to the VGA buffer.
+/// - `entry`: The `Cell` to be written to the VGA buffer.
 ///
 /// ### Returns:
 /// - `Ok(())` if the write is successful.
 /// - `Err(OutOfBoundsErr)` if the index is out of bounds.
-fn write_entry_to_vga(index: usize, entry: u16) -> Result<(), OutOfBoundsErr> {
-    if index >= VIEW_BUFFER_SIZE {
+fn write_entry_to_vga(index: usize, entry: Cell) -> Result<(), OutOfBoundsErr> {
+    if index >= view_size() {
         return Err(OutOfBoundsErr);
     }
 
@@ -179,73 +596,79 @@ fn write_entry_to_vga(index: usize, entry: u16) -> Result<(), OutOfBoundsErr> {
         return Ok(());
     }
 
-    unsafe { write_volatile(VGA_BUFFER_ADDR.add(index), entry) }
+    store_entry(index, entry);
     Ok(())
 }
 
-/// Reads an entry (a `u16` value) from the VGA buffer at the specified index.
+/// Writes `entry` at `index` of the viewable window, which must be in bounds.
+fn store_entry(index: usize, entry: Cell) {
+    unsafe { write_volatile(VGA_BUFFER_ADDR.add(window_start() + index), entry.to_raw()) }
+}
+
+/// Reads a `Cell` from the VGA buffer at the specified index of the viewable window.
 ///
 /// ### Parameters:
 /// - `index`: The index in the VGA buffer to read from.
 ///
 /// ### Returns:
-/// - `Ok(u16)` if the read is successful.
+/// - `Ok(Cell)` if the read is successful.
 /// - `Err(OutOfBoundsErr)` if the index is out of bounds.
-fn read_entry_from_vga(index: usize) -> Result<u16, OutOfBoundsErr> {
-    if index >= VIEW_BUFFER_SIZE {
+fn read_entry_from_vga(index: usize) -> Result<Cell, OutOfBoundsErr> {
+    if index >= view_size() {
         return Err(OutOfBoundsErr);
     }
-    let e: u16 = unsafe { read_volatile(VGA_BUFFER_ADDR.add(index)) };
-    Ok(e)
+    let e: u16 = unsafe { read_volatile(VGA_BUFFER_ADDR.add(window_start() + index)) };
+    Ok(Cell::from_raw(e))
 }
 
-/// Represents a single character entry for the Screen buffer.
+/// A single character cell of the screen, laid out like a VGA text-mode entry: the color attribute
+/// in the upper 8 bits and the character in the lower 8 bits.
 ///
-/// Each `Entry` consists of a character and a color attribute. The color is set to the default color (light gray on black)
-/// by default, but it can be customized. Each `Entry` can be converted into a `u16` value, which is the format used for
-/// writing to the VGA buffer.
-pub struct Entry {
-    color: u8,
-    character: u8,
-}
+/// Cells are only converted to raw `u16` values at the volatile read/write boundary with the VGA
+/// memory, everything else goes through the accessors.
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cell(u16);
 
-impl Entry {
-    /// Creates a new `Entry` with the specified character and the default color.
-    ///
-    /// The default color is light gray (`0x07`).
-    ///
-    /// ### Parameters:
-    /// - `character`: The character to be storedy.
-    pub fn new(character: u8) -> Self {
-        Entry {
-            color: Color::Default as u8,
-            character,
-        }
+impl Cell {
+    /// A space in the default color.
+    pub const BLANK: Cell = Cell::new(b' ', Color::Default as u8);
+
+    /// Creates a new `Cell` holding `ch` displayed with the `color` attribute.
+    pub const fn new(ch: u8, color: u8) -> Self {
+        Cell(((color as u16) << 8) | ch as u16)
     }
 
-    /// Creates a new `Entry` with the specified character and custom color.
-    ///
-    /// This function allows the creation of a `Entry` with a specific character and color,
-    /// where the color is passed as a parameter. The color is represented as an 8-bit value,
-    /// allowing for a wide range of color codes (e.g., for screen colors). The character
-    /// is displayed with this color when rendered to the VGA buffer.
-    ///
-    /// ### Parameters:
-    /// - `character`: The character to be displayed (e.g., an ASCII value representing a letter or symbol).
-    /// - `color`: The color code for the character (an 8-bit value that determines the character's color).
-    ///   - The value should correspond to a color in the VGA color palette (for example, `0x0F` for white, `0x01` for blue, etc.).
-    pub fn new_with_color(character: u8, color: u8) -> Self {
-        Entry { color, character }
+    pub const fn ch(self) -> u8 {
+        (self.0 & 0xFF) as u8
     }
 
-    /// Converts this `Entry` into a `u16` value that can be written to the VGA buffer.
-    ///
-    /// The `u16` format stores the color in the upper 8 bits and the character in the lower 8 bits.
-    ///
-    /// ### Returns:
-    /// A `u16` value representing this `Entry`.
-    pub fn to_u16(&self) -> u16 {
-        ((self.color as u16) << 8) | (self.character as u16)
+    pub const fn color(self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    /// Returns `true` if the cell displays nothing, i.e. holds a space or a NUL.
+    pub const fn is_empty(self) -> bool {
+        self.ch() == b' ' || self.ch() == 0
+    }
+
+    /// Returns `true` if the cell marks the end of a line in a `Screen`.
+    pub const fn is_line_break(self) -> bool {
+        self.ch() == b'\n'
+    }
+
+    /// Returns the cell in reverse video, its foreground and background colors swapped.
+    pub const fn inverted(self) -> Self {
+        Cell::new(self.ch(), self.color().rotate_left(4))
+    }
+
+    /// Returns the raw VGA text-mode entry.
+    pub const fn to_raw(self) -> u16 {
+        self.0
+    }
+
+    pub const fn from_raw(raw: u16) -> Self {
+        Cell(raw)
     }
 }
 
@@ -260,6 +683,87 @@ pub enum Color {
     /// White on Red
     #[allow(unused)]
     Error = 0x4F,
+    /// Dark gray on black
+    Dim = 0x08,
+    /// Light cyan on black
+    LightCyan = 0x0B,
+}
+
+/// The 16 colors of the VGA palette. A `Color` is a foreground hue on a background hue.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Hue {
+    Black = 0x0,
+    Blue = 0x1,
+    Green = 0x2,
+    Cyan = 0x3,
+    Red = 0x4,
+    Magenta = 0x5,
+    Brown = 0x6,
+    LightGray = 0x7,
+    DarkGray = 0x8,
+    LightBlue = 0x9,
+    LightGreen = 0xA,
+    LightCyan = 0xB,
+    LightRed = 0xC,
+    LightMagenta = 0xD,
+    Yellow = 0xE,
+    White = 0xF,
+}
+
+impl Hue {
+    /// Every hue, indexed by its value.
+    pub const ALL: [Hue; 16] = [
+        Hue::Black,
+        Hue::Blue,
+        Hue::Green,
+        Hue::Cyan,
+        Hue::Red,
+        Hue::Magenta,
+        Hue::Brown,
+        Hue::LightGray,
+        Hue::DarkGray,
+        Hue::LightBlue,
+        Hue::LightGreen,
+        Hue::LightCyan,
+        Hue::LightRed,
+        Hue::LightMagenta,
+        Hue::Yellow,
+        Hue::White,
+    ];
+
+    /// Parses a hue given by its name or by its value from 0 to 15.
+    pub fn from_name(name: &[u8]) -> Option<Hue> {
+        if let Some(value) = conv::dtou(name) {
+            return Hue::ALL.get(value as usize).copied();
+        }
+        Hue::ALL.into_iter().find(|h| h.name().as_bytes() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hue::Black => "black",
+            Hue::Blue => "blue",
+            Hue::Green => "green",
+            Hue::Cyan => "cyan",
+            Hue::Red => "red",
+            Hue::Magenta => "magenta",
+            Hue::Brown => "brown",
+            Hue::LightGray => "lightgray",
+            Hue::DarkGray => "darkgray",
+            Hue::LightBlue => "lightblue",
+            Hue::LightGreen => "lightgreen",
+            Hue::LightCyan => "lightcyan",
+            Hue::LightRed => "lightred",
+            Hue::LightMagenta => "lightmagenta",
+            Hue::Yellow => "yellow",
+            Hue::White => "white",
+        }
+    }
+
+    /// Color attribute of this hue on `background`.
+    pub const fn on(self, background: Hue) -> u8 {
+        (background as u8) << 4 | self as u8
+    }
 }
 
 #[cfg(test)]
@@ -268,16 +772,28 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn cursor_is_hidden_while_scrolled_back() {
+        let mut s = Screen::default();
+        for _ in 0..3 * VIEW_HEIGHT {
+            s.write_str("line\n");
+        }
+        s.handle_key(Key::PageUp);
+        assert!(Buffer::from_screen(&s).cursor.is_none());
+        s.handle_key(Key::PageDown);
+        assert!(Buffer::from_screen(&s).cursor.is_some());
+    }
+
     #[test]
     fn hello_world() {
         let test_string = "Hello World";
         let mut s = Screen::default();
-        s.write_str(&test_string);
+        s.write_str(test_string);
         let b = Buffer::from_screen(&s);
         assert_eq!(b.cursor.unwrap().x, 11);
         assert_eq!(b.cursor.unwrap().y, 0);
         for (i, c) in test_string.as_bytes().iter().enumerate() {
-            assert_eq!(b.buffer[i], Entry::new(*c).to_u16())
+            assert_eq!(b.buffer[i], Cell::new(*c, Color::Default as u8))
         }
     }
 
@@ -293,7 +809,7 @@ mod test {
             }
         }
         let b = Buffer::from_screen(&s);
-        assert_eq!(b.buffer[0], Entry::new(b' ').to_u16());
+        assert_eq!(b.buffer[0], Cell::BLANK);
 
         assert_eq!(b.cursor.unwrap().x, 0);
         assert_eq!(b.cursor.unwrap().y, (VIEW_HEIGHT - 1) as u16)
@@ -305,17 +821,17 @@ mod test {
         let test_string_1 = "Coka";
         let test_string_2 = "Cola";
 
-        s.write_str(&test_string_1);
+        s.write_str(test_string_1);
         s.handle_key(Key::Enter);
-        s.write_str(&test_string_2);
+        s.write_str(test_string_2);
 
         let b = Buffer::from_screen(&s);
 
         for (i, c) in test_string_1.as_bytes().iter().enumerate() {
-            assert_eq!(b.buffer[i], Entry::new(*c).to_u16());
+            assert_eq!(b.buffer[i], Cell::new(*c, Color::Default as u8));
         }
         for (i, c) in test_string_2.as_bytes().iter().enumerate() {
-            assert_eq!(b.buffer[VIEW_WIDTH + i], Entry::new(*c).to_u16());
+            assert_eq!(b.buffer[VIEW_WIDTH + i], Cell::new(*c, Color::Default as u8));
         }
 
         assert_eq!(b.cursor.unwrap().x, test_string_2.len() as u16);
@@ -334,11 +850,118 @@ mod test {
         assert_eq!(b.cursor.unwrap().y, 1);
     }
 
+    /// Types `n` keys cycling through the alphabet, so that every cell can be told apart.
+    fn type_letters(s: &mut Screen, n: usize) {
+        const LETTERS: [Key; 5] = [Key::A, Key::B, Key::C, Key::D, Key::E];
+        for i in 0..n {
+            s.handle_key(LETTERS[i % LETTERS.len()]);
+        }
+    }
+
+    fn assert_letters(b: &Buffer, len: usize) {
+        for (i, cell) in b.cells().iter().enumerate() {
+            let expected = if i < len { b"abcde"[i % 5] } else { b' ' };
+            assert_eq!(cell.ch(), expected, "cell {i}");
+        }
+    }
+
+    #[test]
+    fn inserting_into_a_wrapped_block_pushes_every_row() {
+        let mut s = Screen::default();
+        type_letters(&mut s, 170);
+        s.write_str("\nnext");
+        s.set_cursor(85);
+        s.handle_key(Key::X);
+
+        let mut block = [0; 171];
+        for (i, c) in block.iter_mut().enumerate() {
+            *c = b"abcde"[(i - (i > 85) as usize) % 5];
+        }
+        block[85] = b'x';
+        let b = Buffer::from_screen(&s);
+        for (i, (cell, &expected)) in b.cells().iter().zip(block.iter()).enumerate() {
+            assert_eq!(cell.ch(), expected, "cell {i}");
+        }
+        // The rest of the third row is padding, whatever the cells hold.
+        assert!(b.cells()[3 * VIEW_WIDTH..].iter().take(4).map(|cell| cell.ch()).eq(*b"next"));
+        assert!(b.cells()[3 * VIEW_WIDTH + 4..].iter().all(|cell| cell.ch() == b' '));
+        assert_eq!((b.cursor.unwrap().x, b.cursor.unwrap().y), (6, 1));
+    }
+
+    #[test]
+    fn a_block_growing_past_the_bottom_scrolls_the_view() {
+        let mut s = Screen::default();
+        for _ in 0..VIEW_HEIGHT - 2 {
+            s.write_str("line\n");
+        }
+        type_letters(&mut s, 155);
+        s.set_cursor(s.last_entry_index - 100);
+        for _ in 0..10 {
+            s.handle_key(Key::X);
+        }
+
+        let b = Buffer::from_screen(&s);
+        let rows = |r: usize| &b.cells()[r * VIEW_WIDTH..(r + 1) * VIEW_WIDTH];
+        assert!(rows(0).iter().take(4).map(|cell| cell.ch()).eq(*b"line"));
+        assert!(rows(VIEW_HEIGHT - 4).iter().take(4).map(|cell| cell.ch()).eq(*b"line"));
+        assert_eq!(rows(VIEW_HEIGHT - 3)[55..65].iter().filter(|cell| cell.ch() == b'x').count(), 10);
+        assert_eq!(rows(VIEW_HEIGHT - 1)[4].ch(), b"abcde"[(155 - 1) % 5]);
+        assert_eq!(rows(VIEW_HEIGHT - 1)[5].ch(), b' ');
+    }
+
+    #[test]
+    fn backspacing_a_wrapped_line() {
+        let mut s = Screen::default();
+        type_letters(&mut s, 85);
+        for _ in 0..10 {
+            s.handle_key(Key::Backspace);
+        }
+
+        let b = Buffer::from_screen(&s);
+        assert_letters(&b, 75);
+        assert_eq!((b.cursor.unwrap().x, b.cursor.unwrap().y), (75, 0));
+    }
+
+    #[test]
+    fn backspacing_across_the_soft_wrap() {
+        let mut s = Screen::default();
+        type_letters(&mut s, 85);
+        for _ in 0..5 {
+            s.handle_key(Key::Backspace);
+        }
+        let b = Buffer::from_screen(&s);
+        assert_letters(&b, 80);
+        assert_eq!((b.cursor.unwrap().x, b.cursor.unwrap().y), (0, 1));
+
+        // The last cell of the first row is removed, the cursor stays on the row.
+        s.handle_key(Key::Backspace);
+        let b = Buffer::from_screen(&s);
+        assert_letters(&b, 79);
+        assert_eq!((b.cursor.unwrap().x, b.cursor.unwrap().y), (79, 0));
+    }
+
+    #[test]
+    fn backspacing_mid_line_across_the_soft_wrap() {
+        let mut s = Screen::default();
+        type_letters(&mut s, 85);
+        for _ in 0..5 {
+            s.handle_key(Key::ArrowLeft);
+        }
+        s.handle_key(Key::Backspace);
+
+        let b = Buffer::from_screen(&s);
+        assert_eq!((b.cursor.unwrap().x, b.cursor.unwrap().y), (79, 0));
+        assert_eq!(b.buffer[78].ch(), b"abcde"[78 % 5]);
+        assert_eq!(b.buffer[79].ch(), b"abcde"[80 % 5]);
+        assert_eq!(b.buffer[83].ch(), b"abcde"[84 % 5]);
+        assert_eq!(b.buffer[84].ch(), b' ');
+    }
+
     #[test]
     fn backspacing() {
         let mut s = Screen::default();
         let test_string = "123";
-        s.write_str(&test_string);
+        s.write_str(test_string);
         s.handle_key(Key::Backspace);
 
         let b = Buffer::from_screen(&s);
@@ -347,10 +970,298 @@ mod test {
             if test_string.len() - 1 == i {
                 break;
             }
-            assert_eq!(b.buffer[i], Entry::new(*c).to_u16());
+            assert_eq!(b.buffer[i], Cell::new(*c, Color::Default as u8));
         }
 
         assert_eq!(b.cursor.unwrap().x, test_string.len() as u16 - 1);
         assert_eq!(b.cursor.unwrap().y, 0);
     }
+
+    #[test]
+    fn cursor_style_follows_the_editing_mode() {
+        let mut s = Screen::default();
+        s.write_str("sh> ");
+        assert_eq!(Buffer::from_screen(&s).cursor_style, CursorStyle::Underline);
+        s.handle_key(Key::Insert);
+        assert_eq!(Buffer::from_screen(&s).cursor_style, CursorStyle::Block);
+    }
+
+    #[test]
+    fn typing_burst_is_coalesced() {
+        use crate::io::mock;
+
+        let mut s = Screen::default();
+        s.write_str("sh> ");
+        let mut sync = CursorSync::new();
+        sync.sync(Buffer::from_screen(&s).cursor);
+        mock::clear();
+
+        let mut flushes = 0;
+        for _ in 0..20 {
+            s.handle_key(Key::A);
+            sync.sync(Buffer::from_screen(&s).cursor);
+            flushes += 1;
+        }
+        assert!(mock::writes_to(0x3D4) <= flushes);
+
+        mock::clear();
+        sync.sync(Buffer::from_screen(&s).cursor);
+        assert_eq!(mock::writes_to(0x3D4), 0);
+    }
+
+    #[test]
+    fn cell_accessors() {
+        let c = Cell::new(b'x', Color::Error as u8);
+        assert_eq!(c.ch(), b'x');
+        assert_eq!(c.color(), Color::Error as u8);
+        assert!(!c.is_empty());
+        assert!(!c.is_line_break());
+
+        assert!(Cell::BLANK.is_empty());
+        assert!(Cell::from_raw(0).is_empty());
+        assert!(Cell::new(b'\n', 0x70).is_line_break());
+        assert_eq!(c.inverted(), Cell::new(b'x', 0xF4));
+    }
+
+    #[test]
+    fn cell_raw_layout() {
+        let c = Cell::new(b'A', 0x4F);
+        assert_eq!(c.to_raw(), 0x4F41);
+        assert_eq!(Cell::from_raw(0x4F41), c);
+        assert_eq!(core::mem::size_of::<Cell>(), core::mem::size_of::<u16>());
+    }
+
+    /// Fills the rows of a buffer with the letters `rows`, one per row.
+    fn rows_of(rows: &[u8]) -> [Cell; VIEW_WIDTH * VIEW_HEIGHT] {
+        core::array::from_fn(|i| Cell::new(rows.get(i / VIEW_WIDTH).copied().unwrap_or(b' '), Color::Default as u8))
+    }
+
+    #[test]
+    fn scrolled_content_is_detected() {
+        let shown = rows_of(b"abcdefghijklmnopqrstuvwxy");
+        assert_eq!(rows_scrolled(&shown, &rows_of(b"cdefghijklmnopqrstuvwxyAB")), 2);
+        assert_eq!(rows_scrolled(&shown, &rows_of(b"Zabcdefghijklmnopqrstuvwx")), -1);
+        assert_eq!(rows_scrolled(&shown, &rows_of(b"ZZZdefghijklmnopqrstuvwxy")), 0);
+        assert_eq!(rows_scrolled(&shown, &shown), 0);
+    }
+
+    #[test]
+    fn small_changes_are_not_scrolled() {
+        let shown = rows_of(b"aaaa");
+        let mut next = shown;
+        next[0] = Cell::new(b'x', Color::Default as u8);
+        // Matches the content shifted by any number of blank rows, but one write is cheaper.
+        assert_eq!(rows_scrolled(&shown, &next), 0);
+    }
+
+    #[test]
+    fn window_stays_in_text_memory() {
+        assert_eq!(window_start_after(0, 0), 0);
+        assert_eq!(window_start_after(0, 3), 3 * VIEW_WIDTH);
+        assert_eq!(window_start_after(3 * VIEW_WIDTH, -2), VIEW_WIDTH);
+        assert_eq!(window_start_after(VIEW_WIDTH, -2), 0);
+
+        let last = TEXT_MEMORY_CELLS - view_size();
+        let below_last = last / VIEW_WIDTH * VIEW_WIDTH;
+        assert_eq!(window_start_after(below_last - VIEW_WIDTH, 1), below_last);
+        assert_eq!(window_start_after(below_last, 1), 0);
+    }
+
+    #[test]
+    fn typing_writes_only_the_changed_cells() {
+        let mut shadow = Shadow::new();
+        let mut s = Screen::default();
+        s.write_str("ab");
+        assert_eq!(shadow.update(Buffer::from_screen(&s).cells(), |_, _| {}), view_size());
+
+        s.handle_key(Key::C);
+        let mut written = [0; 4];
+        let count = shadow.update(Buffer::from_screen(&s).cells(), |index, _| written[index.min(3)] += 1);
+        assert_eq!((count, written), (1, [0, 0, 1, 0]));
+        assert_eq!(shadow.update(Buffer::from_screen(&s).cells(), |_, _| {}), 0);
+        assert_eq!(shadow.cells_written, view_size() as u32 + 1);
+
+        shadow.invalidate();
+        assert_eq!(shadow.update(Buffer::from_screen(&s).cells(), |_, _| {}), view_size());
+    }
+
+    /// Renders `s`, which only converts what changed since its previous render, and checks the
+    /// result against a full conversion.
+    fn assert_render_is_complete(s: &mut Screen) {
+        let rendered = s.render();
+        let full = Buffer::from_screen(s);
+        assert!(rendered.cells() == full.cells());
+        assert_eq!(rendered.cursor.map(|c| (c.x, c.y)), full.cursor.map(|c| (c.x, c.y)));
+        assert_eq!(rendered.view_start, full.view_start);
+    }
+
+    #[test]
+    fn render_matches_a_full_conversion() {
+        let mut s = Screen::default();
+        assert_render_is_complete(&mut s);
+        for i in 0..10 {
+            s.write_str("line ");
+            s.write_dec(i);
+            s.write_str("\n");
+        }
+        assert_render_is_complete(&mut s);
+        for key in [Key::A, Key::B, Key::ArrowLeft, Key::C, Key::Backspace, Key::ArrowRight] {
+            s.handle_key(key);
+            assert_render_is_complete(&mut s);
+        }
+
+        // A line growing past the right edge, then a line break inserted into earlier output.
+        s.write_str(&"x".repeat(VIEW_WIDTH));
+        assert_render_is_complete(&mut s);
+        s.set_cursor(3);
+        s.handle_key(Key::Enter);
+        assert_render_is_complete(&mut s);
+
+        // Output going past the bottom, then the scrollback.
+        s.move_cursor_to_end();
+        for _ in 0..2 * VIEW_HEIGHT {
+            s.write_str("more\n");
+            assert_render_is_complete(&mut s);
+        }
+        s.handle_key(Key::PageUp);
+        assert_render_is_complete(&mut s);
+        s.handle_key(Key::PageDown);
+        assert_render_is_complete(&mut s);
+
+        s.invert_region(s.cursor - 8, 6);
+        assert_render_is_complete(&mut s);
+        s.enter_alt();
+        s.write_str("alt");
+        assert_render_is_complete(&mut s);
+        s.leave_alt();
+        assert_render_is_complete(&mut s);
+        s.clear();
+        assert_render_is_complete(&mut s);
+    }
+
+    #[test]
+    fn typing_converts_and_writes_a_few_cells() {
+        let mut s = Screen::default();
+        for i in 0..3 * VIEW_HEIGHT as u32 {
+            s.write_dec(i);
+            s.write_str(" some output\n");
+        }
+        s.write_str("$ ");
+        let mut shadow = Shadow::new();
+        shadow.update(s.render().cells(), |_, _| {});
+
+        s.handle_key(Key::C);
+        assert!(shadow.update(s.render().cells(), |_, _| {}) < 16);
+
+        let mut b = s.render();
+        s.handle_key(Key::D);
+        assert!(b.refresh(&s, Some(s.cursor - 1..s.cursor)) <= VIEW_WIDTH);
+        assert!(b.cells() == Buffer::from_screen(&s).cells());
+    }
+
+    #[test]
+    fn scrolling_the_shadow_dirties_the_entering_rows() {
+        let shown = rows_of(b"abcdefghijklmnopqrstuvwxy");
+        let mut shadow = Shadow::new();
+        shadow.update(&shown, |_, _| {});
+
+        shadow.scroll(2);
+        let mut rows = [0; VIEW_HEIGHT];
+        shadow.update(&rows_of(b"cdefghijklmnopqrstuvwxyAB"), |index, _| rows[index / VIEW_WIDTH] += 1);
+        assert!(rows[..VIEW_HEIGHT - 2].iter().all(|&n| n == 0));
+        assert_eq!(rows[VIEW_HEIGHT - 2..], [VIEW_WIDTH, VIEW_WIDTH]);
+
+        shadow.scroll(-1);
+        let mut rows = [0; VIEW_HEIGHT];
+        shadow.update(&rows_of(b"ZcdefghijklmnopqrstuvwxyA"), |index, _| rows[index / VIEW_WIDTH] += 1);
+        assert_eq!(rows[0], VIEW_WIDTH);
+        assert!(rows[1..].iter().all(|&n| n == 0));
+    }
+
+    #[test]
+    fn shadow_follows_the_height_of_the_view() {
+        let tall = [Cell::new(b'a', Color::Default as u8); MAX_VIEW_BUFFER_SIZE];
+        let mut shadow = Shadow::new();
+        assert_eq!(shadow.update(&tall, |_, _| {}), MAX_VIEW_BUFFER_SIZE);
+
+        shadow.scroll(1);
+        let mut rows = [0; MAX_VIEW_HEIGHT];
+        shadow.update(&tall, |index, _| rows[index / VIEW_WIDTH] += 1);
+        assert!(rows[..MAX_VIEW_HEIGHT - 1].iter().all(|&n| n == 0));
+        assert_eq!(rows[MAX_VIEW_HEIGHT - 1], VIEW_WIDTH);
+
+        // Back to the short view, whose rows were all written with the tall one.
+        assert_eq!(shadow.update(&tall[..VIEW_WIDTH * VIEW_HEIGHT], |_, _| {}), 0);
+        assert_eq!(rows_scrolled(&tall, &tall[..VIEW_WIDTH * VIEW_HEIGHT]), 0);
+    }
+
+    #[test]
+    fn hues_parse_from_names_and_numbers() {
+        assert_eq!(Hue::from_name(b"red"), Some(Hue::Red));
+        assert_eq!(Hue::from_name(b"lightgreen"), Some(Hue::LightGreen));
+        assert_eq!(Hue::from_name(b"0"), Some(Hue::Black));
+        assert_eq!(Hue::from_name(b"15"), Some(Hue::White));
+        assert_eq!(Hue::from_name(b"16"), None);
+        assert_eq!(Hue::from_name(b"pink"), None);
+        assert_eq!(Hue::from_name(b""), None);
+        assert!(Hue::ALL.iter().enumerate().all(|(i, &h)| h as usize == i));
+        assert_eq!(Hue::White.on(Hue::Red), Color::Error as u8);
+    }
+
+    #[test]
+    fn set_blink_keeps_the_other_mode_control_bits() {
+        use crate::io::mock;
+
+        mock::clear();
+        mock::set_input(ATTRIBUTE_READ_PORT, 0x0C);
+        set_blink(false);
+        assert_eq!(mock::reads_from(INPUT_STATUS_PORT), 1);
+        assert_eq!(mock::bytes_to(ATTRIBUTE_PORT), [0x30, 0x04]);
+
+        mock::clear();
+        mock::set_input(ATTRIBUTE_READ_PORT, 0x04);
+        set_blink(true);
+        assert_eq!(mock::bytes_to(ATTRIBUTE_PORT), [0x30, 0x0C]);
+    }
+
+    #[test]
+    fn palette_entries_are_scaled_to_6_bits() {
+        use crate::io::mock;
+
+        mock::clear();
+        assert!(set_palette_entry(Hue::Brown as u8, 0xFF, 0x80, 0x03).is_ok());
+        assert_eq!(mock::writes(), [(0x3C8, 0x14), (0x3C9, 0x3F), (0x3C9, 0x20), (0x3C9, 0x00)]);
+
+        mock::clear();
+        mock::queue_inputs(DAC_DATA_PORT, &[0x3F, 0x20, 0x00]);
+        assert_eq!(palette_entry(Hue::White as u8).ok(), Some((0xFF, 0x82, 0x00)));
+        assert_eq!(mock::bytes_to(DAC_READ_INDEX_PORT), [0x3F]);
+    }
+
+    #[test]
+    fn palette_indices_past_15_are_rejected() {
+        use crate::io::mock;
+
+        mock::clear();
+        assert!(set_palette_entry(16, 0, 0, 0).is_err());
+        assert!(palette_entry(255).is_err());
+        assert!(mock::writes().is_empty());
+    }
+
+    #[test]
+    fn emergency_reset_sequence() {
+        use crate::io::mock;
+
+        // A mode control update interrupted between its index and data writes, and a CRTC index
+        // left on the cursor location register.
+        unsafe {
+            outb(ATTRIBUTE_PORT, ATTRIBUTE_MODE_CONTROL);
+            outb(CRTC_INDEX_PORT, 0x0F);
+        }
+        mock::clear();
+
+        emergency_reset();
+        assert_eq!(mock::reads_from(INPUT_STATUS_PORT), 1);
+        assert_eq!(mock::writes(), [(0x3C0, 0x30), (0x3C0, 0x04), (0x3D4, 0x0A)]);
+    }
 }
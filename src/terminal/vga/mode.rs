@@ -0,0 +1,259 @@
+use core::{
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::io::outb;
+
+use super::{modify_crtc, set_window_start, store_entry, Buffer, Cell, Shadow, MAX_VIEW_HEIGHT, SHADOW, VIEW_HEIGHT};
+use crate::terminal::cursor::{CursorSync, CURSOR_SYNC};
+
+/// `0x3C4` selects the sequencer register accessed through `0x3C5`.
+const SEQUENCER_INDEX_PORT: u16 = 0x3C4;
+const SEQUENCER_DATA_PORT: u16 = 0x3C5;
+/// `0x3CE` selects the graphics controller register accessed through `0x3CF`.
+const GRAPHICS_INDEX_PORT: u16 = 0x3CE;
+const GRAPHICS_DATA_PORT: u16 = 0x3CF;
+
+/// Sequencer indices.
+const SEQUENCER_MAP_MASK: u8 = 0x02;
+const SEQUENCER_CHARACTER_MAP_SELECT: u8 = 0x03;
+const SEQUENCER_MEMORY_MODE: u8 = 0x04;
+/// Graphics controller indices.
+const GRAPHICS_READ_MAP_SELECT: u8 = 0x04;
+const GRAPHICS_MODE: u8 = 0x05;
+const GRAPHICS_MISC: u8 = 0x06;
+/// CRTC index of the register whose low 5 bits hold the last scanline of a character row.
+const CRTC_MAX_SCAN_LINE: u8 = 0x09;
+const MAX_SCAN_LINE_MASK: u8 = 0x1F;
+
+/// Plane 2, which holds the fonts, while mapped by `map_font_plane`.
+const FONT_PLANE_ADDR: *mut u8 = 0xA0000 as *mut u8;
+/// Bytes between two glyphs of a font, whatever its height.
+const GLYPH_STRIDE: usize = 32;
+const GLYPH_COUNT: usize = 256;
+/// Offset in plane 2 of font block 1, where the 8x8 font is built.
+const HALF_FONT_OFFSET: usize = 0x4000;
+
+/// Text modes the screen can be switched to, all 80 columns wide.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextMode {
+    /// The mode set up by the BIOS, with its 8x16 font.
+    Rows25,
+    /// Twice as many rows, with an 8x8 font squeezed from the 8x16 one.
+    Rows50,
+}
+
+impl TextMode {
+    /// Parses the number of rows of a mode, `25` or `50`.
+    pub fn from_name(name: &[u8]) -> Option<TextMode> {
+        match name {
+            b"25" => Some(TextMode::Rows25),
+            b"50" => Some(TextMode::Rows50),
+            _ => None,
+        }
+    }
+
+    pub const fn rows(self) -> usize {
+        match self {
+            TextMode::Rows25 => VIEW_HEIGHT,
+            TextMode::Rows50 => MAX_VIEW_HEIGHT,
+        }
+    }
+
+    /// Number of scanlines of a character row.
+    pub const fn char_height(self) -> u8 {
+        match self {
+            TextMode::Rows25 => 16,
+            TextMode::Rows50 => 8,
+        }
+    }
+
+    /// Value of the character map select register using the font of the mode for every character:
+    /// block 0 holds the BIOS font, block 1 the one built by `build_half_font`.
+    const fn character_map(self) -> u8 {
+        match self {
+            TextMode::Rows25 => 0x00,
+            TextMode::Rows50 => 0x05,
+        }
+    }
+}
+
+/// Rows of the current mode.
+#[cfg(not(test))]
+static ROWS: AtomicUsize = AtomicUsize::new(VIEW_HEIGHT);
+
+#[cfg(test)]
+extern crate std;
+
+// Per thread in tests, so that a test switching modes leaves the others in 80x25.
+#[cfg(test)]
+std::thread_local! {
+    static ROWS: AtomicUsize = const { AtomicUsize::new(VIEW_HEIGHT) };
+}
+
+fn with_rows<T>(f: impl FnOnce(&AtomicUsize) -> T) -> T {
+    #[cfg(not(test))]
+    return f(&ROWS);
+    #[cfg(test)]
+    return ROWS.with(f);
+}
+
+/// Returns the current text mode.
+pub fn current() -> TextMode {
+    if with_rows(|rows| rows.load(Ordering::Relaxed)) == MAX_VIEW_HEIGHT {
+        TextMode::Rows50
+    } else {
+        TextMode::Rows25
+    }
+}
+
+/// Reprograms the VGA for `mode`, after flushing `last`, the frame rendered for the current mode,
+/// so that no output pending in it is lost. The text memory is then left as is, so the caller has
+/// to redraw the screen, which every flush does in full after a switch.
+///
+/// The switch holds the lock `Buffer::flush` writes under, so that a flush either completes in the
+/// old mode or starts in the new one, where a buffer made for the old one is a bug.
+pub fn set(mode: TextMode, last: &Buffer) {
+    let mut shadow = SHADOW.lock();
+    switch(mode, last, &mut shadow, store_entry);
+}
+
+fn switch(mode: TextMode, last: &Buffer, shadow: &mut Shadow, write: impl FnMut(usize, Cell)) {
+    last.write_view(shadow, current().rows(), write);
+    unsafe {
+        if mode == TextMode::Rows50 {
+            build_half_font();
+        }
+        write_sequencer(SEQUENCER_CHARACTER_MAP_SELECT, mode.character_map());
+        modify_crtc(CRTC_MAX_SCAN_LINE, |value| (value & !MAX_SCAN_LINE_MASK) | (mode.char_height() - 1));
+    }
+    with_rows(|rows| rows.store(mode.rows(), Ordering::Relaxed));
+
+    // A window placed for the shorter view may not leave room for the taller one.
+    set_window_start(0);
+    shadow.invalidate();
+    // The cursor scanlines depend on the character height.
+    *CURSOR_SYNC.lock() = CursorSync::new();
+}
+
+/// Squeezes a glyph of 16 scanlines into 8, each keeping the pixels of the two it replaces so that
+/// thin strokes are not lost.
+fn halve_glyph(glyph: &[u8; 16]) -> [u8; 8] {
+    core::array::from_fn(|i| glyph[2 * i] | glyph[2 * i + 1])
+}
+
+/// Fills font block 1 with the glyphs of the BIOS font in block 0 halved in height.
+///
+/// ## SAFETY
+/// Accesses plane 2 through `0xA0000`, which must be VGA memory.
+unsafe fn build_half_font() {
+    map_font_plane(true);
+    // Plane 2 is not mapped in tests.
+    let glyphs = if cfg!(test) { 0 } else { GLYPH_COUNT };
+    for glyph in 0..glyphs {
+        let source = FONT_PLANE_ADDR.add(glyph * GLYPH_STRIDE);
+        let mut full = [0; 16];
+        for (i, line) in full.iter_mut().enumerate() {
+            *line = read_volatile(source.add(i));
+        }
+
+        let target = FONT_PLANE_ADDR.add(HALF_FONT_OFFSET + glyph * GLYPH_STRIDE);
+        for (i, line) in halve_glyph(&full).into_iter().enumerate() {
+            write_volatile(target.add(i), line);
+        }
+    }
+    map_font_plane(false);
+}
+
+/// Maps plane 2 alone at `0xA0000` when `font` is set, or restores the text mapping at `0xB8000`
+/// with planes 0 and 1 interleaved.
+unsafe fn map_font_plane(font: bool) {
+    let (map_mask, memory_mode, read_map, mode, misc) = if font {
+        (0x04, 0x07, 0x02, 0x00, 0x04)
+    } else {
+        (0x03, 0x03, 0x00, 0x10, 0x0E)
+    };
+    write_sequencer(SEQUENCER_MAP_MASK, map_mask);
+    write_sequencer(SEQUENCER_MEMORY_MODE, memory_mode);
+    write_graphics(GRAPHICS_READ_MAP_SELECT, read_map);
+    write_graphics(GRAPHICS_MODE, mode);
+    write_graphics(GRAPHICS_MISC, misc);
+}
+
+unsafe fn write_sequencer(index: u8, value: u8) {
+    outb(SEQUENCER_INDEX_PORT, index);
+    outb(SEQUENCER_DATA_PORT, value);
+}
+
+unsafe fn write_graphics(index: u8, value: u8) {
+    outb(GRAPHICS_INDEX_PORT, index);
+    outb(GRAPHICS_DATA_PORT, value);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::mock;
+    use crate::terminal::{
+        screen::Screen,
+        vga::{CRTC_DATA_PORT, MAX_VIEW_BUFFER_SIZE, VIEW_WIDTH},
+    };
+
+    #[test]
+    fn modes_parse_from_their_rows() {
+        assert_eq!(TextMode::from_name(b"25"), Some(TextMode::Rows25));
+        assert_eq!(TextMode::from_name(b"50").map(TextMode::rows), Some(50));
+        assert_eq!(TextMode::from_name(b"43"), None);
+        assert_eq!(current(), TextMode::Rows25);
+    }
+
+    #[test]
+    fn halved_glyphs_keep_every_stroke() {
+        let mut glyph = [0; 16];
+        glyph[3] = 0xFF;
+        glyph[10] = 0x18;
+        glyph[11] = 0x24;
+        assert_eq!(halve_glyph(&glyph), [0, 0xFF, 0, 0, 0, 0x3C, 0, 0]);
+    }
+
+    #[test]
+    fn switching_flushes_the_old_frame_before_reprogramming() {
+        mock::clear();
+        let mut s = Screen::default();
+        for _ in 0..3 * VIEW_HEIGHT {
+            s.write_str("line\n");
+        }
+        let mut shadow = Shadow::new();
+        let mut frame = [Cell::from_raw(0); MAX_VIEW_BUFFER_SIZE];
+
+        // The frame rendered in 80x25 goes out before the switch, at the old size.
+        let last = s.render();
+        switch(TextMode::Rows50, &last, &mut shadow, |i, cell| frame[i] = cell);
+        assert!(frame[..VIEW_HEIGHT * VIEW_WIDTH] == *last.cells());
+        assert!(frame[VIEW_HEIGHT * VIEW_WIDTH..].iter().all(|&cell| cell == Cell::from_raw(0)));
+        assert_eq!(current(), TextMode::Rows50);
+        assert!(mock::bytes_to(CRTC_DATA_PORT).contains(&7));
+
+        // The next frame fills the taller view whole.
+        s.view_height = MAX_VIEW_HEIGHT;
+        s.write_str("more\n");
+        let b = s.render();
+        let mut next = [Cell::from_raw(0); MAX_VIEW_BUFFER_SIZE];
+        b.write_view(&mut shadow, current().rows(), |i, cell| next[i] = cell);
+        assert!(next == *b.cells());
+
+        mock::clear();
+        switch(TextMode::Rows25, &b, &mut shadow, |_, _| ());
+        assert_eq!(current(), TextMode::Rows25);
+        assert!(mock::bytes_to(CRTC_DATA_PORT).contains(&15));
+    }
+
+    #[test]
+    #[should_panic(expected = "buffer made for another text mode")]
+    fn frames_made_for_another_mode_are_refused() {
+        let b = Screen::default().render();
+        with_rows(|rows| rows.store(MAX_VIEW_HEIGHT, Ordering::Relaxed));
+        b.write_view(&mut Shadow::new(), current().rows(), |_, _| ());
+    }
+}
@@ -0,0 +1,103 @@
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::io::{inb, outb};
+
+/// Number of ticks per second returned by `ticks`.
+pub const TICKS_PER_SECOND: u64 = 100;
+
+pub const PIT_FREQUENCY_HZ: u32 = 1_193_182;
+pub const PIT_CHANNEL_2_DATA_PORT: u16 = 0x42;
+pub const PIT_COMMAND_PORT: u16 = 0x43;
+/// Controls the gate of PIT channel 2 (bit 0) and exposes its output (bit 5).
+pub const SPEAKER_CONTROL_PORT: u16 = 0x61;
+const CALIBRATION_MAX_POLLS: u32 = 10_000_000;
+
+/// Number of TSC cycles per tick, zero until `calibrate` succeeded.
+static TSC_PER_TICK: AtomicU32 = AtomicU32::new(0);
+
+/// Reads the CPU's time-stamp counter.
+pub fn rdtsc() -> u64 {
+    let low: u32;
+    let high: u32;
+
+    unsafe {
+        asm!(
+            "rdtsc",
+            out("eax") low,
+            out("edx") high,
+        );
+    }
+
+    ((high as u64) << 32) | low as u64
+}
+
+/// Returns the number of ticks elapsed since the CPU was reset.
+///
+/// Always returns `0` if the TSC was not calibrated with `calibrate`.
+pub fn ticks() -> u64 {
+    let tsc_per_tick = TSC_PER_TICK.load(Ordering::Relaxed);
+    if tsc_per_tick == 0 {
+        return 0;
+    }
+    rdtsc() / tsc_per_tick as u64
+}
+
+/// Returns `true` once `calibrate` succeeded, i.e. when `ticks` advances.
+#[cfg_attr(test, allow(dead_code))]
+pub fn is_calibrated() -> bool {
+    TSC_PER_TICK.load(Ordering::Relaxed) != 0
+}
+
+/// Busy-waits for `n` ticks. Returns immediately if the TSC was not calibrated.
+#[cfg_attr(test, allow(dead_code))]
+pub fn wait(n: u64) {
+    if TSC_PER_TICK.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let end = ticks() + n;
+    while ticks() < end {
+        core::hint::spin_loop();
+    }
+}
+
+/// Measures the TSC frequency by counting cycles during a one-tick one-shot countdown of PIT
+/// channel 2.
+///
+/// Returns an error if the PIT output never went high, in which case `ticks` keeps returning `0`.
+pub fn calibrate() -> Result<(), &'static str> {
+    let divisor = (PIT_FREQUENCY_HZ / TICKS_PER_SECOND as u32) as u16;
+
+    unsafe {
+        // Enable the channel 2 gate, keep the speaker disconnected.
+        let control = (inb(SPEAKER_CONTROL_PORT) & !0x02) | 0x01;
+        outb(SPEAKER_CONTROL_PORT, control & !0x01);
+
+        // Channel 2, lobyte/hibyte access, mode 0 (interrupt on terminal count), binary.
+        outb(PIT_COMMAND_PORT, 0b1011_0000);
+        outb(PIT_CHANNEL_2_DATA_PORT, (divisor & 0xFF) as u8);
+        outb(PIT_CHANNEL_2_DATA_PORT, (divisor >> 8) as u8);
+
+        // Raising the gate starts the countdown.
+        outb(SPEAKER_CONTROL_PORT, control);
+        let start = rdtsc();
+
+        for _ in 0..CALIBRATION_MAX_POLLS {
+            if inb(SPEAKER_CONTROL_PORT) & 0x20 != 0 {
+                let cycles = rdtsc() - start;
+                outb(SPEAKER_CONTROL_PORT, control & !0x01);
+                if cycles == 0 || cycles > u32::MAX as u64 {
+                    return Err("implausible tsc frequency");
+                }
+                TSC_PER_TICK.store(cycles as u32, Ordering::Relaxed);
+                return Ok(());
+            }
+        }
+
+        outb(SPEAKER_CONTROL_PORT, control & !0x01);
+    }
+
+    Err("pit calibration timeout")
+}